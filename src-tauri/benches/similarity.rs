@@ -0,0 +1,40 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use dead_frames_lib::similarity::{ssim_luma_scalar, ssim_luma_simd};
+use image::GrayImage;
+
+/// Deterministic noise so both implementations see the same frames.
+fn noise_frame(width: u32, height: u32, seed: u32) -> GrayImage {
+    let mut state = seed.wrapping_mul(2_654_435_761).max(1);
+    GrayImage::from_fn(width, height, |_, _| {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        image::Luma([(state >> 24) as u8])
+    })
+}
+
+fn bench_ssim(c: &mut Criterion) {
+    let mut group = c.benchmark_group("ssim_luma");
+    group.sample_size(20);
+
+    for (name, width, height) in [("1080p", 1920, 1080), ("4k", 3840, 2160)] {
+        let a = noise_frame(width, height, 1);
+        let b = noise_frame(width, height, 2);
+
+        group.bench_with_input(
+            BenchmarkId::new("scalar", name),
+            &(&a, &b),
+            |bench, (a, b)| bench.iter(|| ssim_luma_scalar(a, b).unwrap()),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("simd", name),
+            &(&a, &b),
+            |bench, (a, b)| bench.iter(|| ssim_luma_simd(a, b).unwrap()),
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_ssim);
+criterion_main!(benches);
@@ -1,3 +1,18 @@
 fn main() {
-    tauri_build::build()
+    // `embedded_ffmpeg` when the binary is compiled in, `bundled_ffmpeg` when
+    // it is compiled in or downloaded; download-ffmpeg builds leave it out
+    println!("cargo::rustc-check-cfg=cfg(embedded_ffmpeg, bundled_ffmpeg)");
+    let embedded = std::env::var_os("CARGO_FEATURE_EMBEDDED_FFMPEG").is_some();
+    let download = std::env::var_os("CARGO_FEATURE_DOWNLOAD_FFMPEG").is_some();
+    if embedded && !download {
+        println!("cargo::rustc-cfg=embedded_ffmpeg");
+    }
+    if embedded || download {
+        println!("cargo::rustc-cfg=bundled_ffmpeg");
+    }
+
+    // headless builds have no app to generate a context for
+    if std::env::var_os("CARGO_FEATURE_GUI").is_some() {
+        tauri_build::build()
+    }
 }
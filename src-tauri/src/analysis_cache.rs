@@ -0,0 +1,286 @@
+//! Analyses of earlier jobs, kept in the app data dir so a file queued
+//! again with the same options need not have its frames compared again:
+//! its plan is offered as soon as it is queued, and the job only extracts
+//! and encodes.
+//!
+//! Files are recognised by a [`Fingerprint`] of their size, modification
+//! time and first [`HEAD_BYTES`], which is quick to take even for large
+//! files on slow disks. Analyses decided in part by a script, plugins or a
+//! registered comparator are not kept, as those can change without the
+//! options doing so.
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::UNIX_EPOCH;
+use tracing::{debug, warn};
+
+use crate::similarity::Metric;
+use crate::video_fixer::{Analysis, ProcessOptions};
+
+/// Bytes from the start of a file that go into its fingerprint.
+pub const HEAD_BYTES: u64 = 1 << 20;
+
+/// Analyses kept; the least recently stored go first.
+pub const MAX_ENTRIES: usize = 500;
+
+/// Options left out of the key: where the output goes and how the work is
+/// done, none of which change what is removed. `low_memory` is not among
+/// them, as it compares luma only and at every frame.
+const IGNORED_OPTIONS: &[&str] = &[
+    "output",
+    "preset",
+    "reuse_analysis",
+    "keep_removed",
+    "export_removed",
+    "verify_quality",
+    "cache_remote",
+    "hardware_decode",
+    "deterministic",
+];
+
+/// The cache the engine and app of this process keep analyses in.
+static CACHE: Lazy<Arc<AnalysisCache>> = Lazy::new(Arc::default);
+
+/// What a file is recognised by.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Fingerprint {
+    pub size: u64,
+    /// Modification time in nanoseconds since the Unix epoch.
+    pub modified: u128,
+    /// SHA-256 of the first [`HEAD_BYTES`], in hex.
+    pub head: String,
+}
+
+impl Fingerprint {
+    /// Fingerprints the file at `path`.
+    pub fn of(path: &Path) -> io::Result<Fingerprint> {
+        let file = File::open(path)?;
+        let metadata = file.metadata()?;
+        if !metadata.is_file() {
+            return Err(io::Error::other("not a file"));
+        }
+        let modified = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_nanos());
+        let mut hasher = Sha256::new();
+        io::copy(&mut file.take(HEAD_BYTES), &mut hasher)?;
+        Ok(Fingerprint {
+            size: metadata.len(),
+            modified,
+            head: hasher
+                .finalize()
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect(),
+        })
+    }
+}
+
+/// A cached analysis of a queued file, as the app offers it.
+#[derive(Debug, Clone, Serialize)]
+pub struct CachedPlan {
+    pub frames_total: usize,
+    pub frames_removed: usize,
+    /// RFC 3339 timestamp of when the analysis was made.
+    pub analysed_at: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    analysed_at: String,
+    analysis: Analysis,
+}
+
+/// Whether what `options` remove is decided by the options alone.
+fn cacheable(options: &ProcessOptions) -> bool {
+    let custom = |metric: Metric| matches!(metric, Metric::Custom(_));
+    #[cfg(feature = "plugins")]
+    let plugins = !crate::plugins::enabled().is_empty();
+    #[cfg(not(feature = "plugins"))]
+    let plugins = false;
+    options.script.is_none()
+        && options.plugins.is_empty()
+        && !plugins
+        && !custom(options.metric)
+        && !options
+            .sections
+            .iter()
+            .any(|section| section.metric.is_some_and(custom))
+}
+
+/// The file in `dir` that `input` analysed with `options` is cached under,
+/// if it can be.
+fn entry_path(dir: &Path, input: &Path, options: &ProcessOptions) -> Option<PathBuf> {
+    if !cacheable(options) {
+        return None;
+    }
+    let fingerprint = Fingerprint::of(input)
+        .map_err(|e| debug!("Not caching the analysis of {}: {}", input.display(), e))
+        .ok()?;
+    let mut options = serde_json::to_value(options).ok()?;
+    if let Some(options) = options.as_object_mut() {
+        options.retain(|name, _| !IGNORED_OPTIONS.contains(&name.as_str()));
+    }
+    let key = serde_json::json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "fingerprint": fingerprint,
+        "options": options,
+    });
+    let hash: String = Sha256::digest(key.to_string().as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    Some(dir.join(hash).with_extension("json"))
+}
+
+fn read(path: &Path) -> Option<Entry> {
+    let json = fs::read(path).ok()?;
+    serde_json::from_slice(&json)
+        .map_err(|e| warn!("Ignoring malformed analysis {}: {}", path.display(), e))
+        .ok()
+}
+
+fn entries(dir: &Path) -> Vec<PathBuf> {
+    fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn prune(dir: &Path) {
+    let mut entries: Vec<_> = entries(dir)
+        .into_iter()
+        .filter_map(|path| Some((fs::metadata(&path).ok()?.modified().ok()?, path)))
+        .collect();
+    if entries.len() <= MAX_ENTRIES {
+        return;
+    }
+    entries.sort();
+    for (_, path) in &entries[..entries.len() - MAX_ENTRIES] {
+        let _ = fs::remove_file(path);
+    }
+}
+
+/// Analyses kept in a directory once [`AnalysisCache::open`] gives one.
+#[derive(Default)]
+pub struct AnalysisCache {
+    dir: Mutex<Option<PathBuf>>,
+}
+
+impl AnalysisCache {
+    /// Keeps analyses in the `analyses` directory under `app_data_dir` from
+    /// now on. Until then nothing is cached.
+    pub fn open(&self, app_data_dir: &Path) {
+        let dir = app_data_dir.join("analyses");
+        if let Err(e) = fs::create_dir_all(&dir) {
+            warn!("Failed to create {}: {}", dir.display(), e);
+            return;
+        }
+        *self.dir.lock().unwrap() = Some(dir);
+    }
+
+    fn dir(&self) -> Option<PathBuf> {
+        self.dir.lock().unwrap().clone()
+    }
+
+    fn entry_path(&self, input: &Path, options: &ProcessOptions) -> Option<PathBuf> {
+        entry_path(&self.dir()?, input, options)
+    }
+
+    /// The analysis of `input` with `options` an earlier job left, if the file
+    /// has not changed since.
+    pub fn lookup(&self, input: &Path, options: &ProcessOptions) -> Option<Analysis> {
+        if !options.reuse_analysis {
+            return None;
+        }
+        read(&self.entry_path(input, options)?).map(|entry| entry.analysis)
+    }
+
+    /// What the cached analysis of `input` with `options` removes, for the app
+    /// to offer when the file is queued.
+    pub fn peek(&self, input: &Path, options: &ProcessOptions) -> Option<CachedPlan> {
+        if !options.reuse_analysis {
+            return None;
+        }
+        let entry = read(&self.entry_path(input, options)?)?;
+        Some(CachedPlan {
+            frames_total: entry.analysis.frames_total(),
+            frames_removed: entry.analysis.frames_removed(),
+            analysed_at: entry.analysed_at,
+        })
+    }
+
+    /// Keeps `analysis` of `input` with `options` for [`AnalysisCache::lookup`], dropping the
+    /// oldest analyses beyond [`MAX_ENTRIES`].
+    pub fn store(&self, input: &Path, options: &ProcessOptions, analysis: &Analysis) {
+        let Some(path) = self.entry_path(input, options) else {
+            return;
+        };
+        let entry = Entry {
+            analysed_at: chrono::Local::now().to_rfc3339(),
+            analysis: analysis.clone(),
+        };
+        let temp = path.with_extension("json.tmp");
+        let result = serde_json::to_vec(&entry)
+            .map_err(io::Error::from)
+            .and_then(|json| fs::write(&temp, json))
+            .and_then(|()| fs::rename(&temp, &path));
+        if let Err(e) = result {
+            warn!("Failed to cache the analysis of {}: {}", input.display(), e);
+            return;
+        }
+        if let Some(dir) = path.parent() {
+            prune(dir);
+        }
+    }
+
+    /// Forgets every cached analysis and returns how many there were.
+    pub fn clear(&self) -> Result<usize, String> {
+        let Some(dir) = self.dir() else {
+            return Ok(0);
+        };
+        let entries = entries(&dir);
+        for path in &entries {
+            fs::remove_file(path)
+                .map_err(|e| format!("Failed to remove {}: {}", path.display(), e))?;
+        }
+        Ok(entries.len())
+    }
+}
+
+/// The process's cache, shared with [`crate::state::AppState`].
+pub fn cache() -> Arc<AnalysisCache> {
+    CACHE.clone()
+}
+
+/// Keeps analyses under `app_data_dir` from now on.
+pub fn init(app_data_dir: &Path) {
+    CACHE.open(app_data_dir);
+}
+
+/// The analysis of `input` with `options` an earlier job left, if the file
+/// has not changed since.
+pub fn lookup(input: &Path, options: &ProcessOptions) -> Option<Analysis> {
+    CACHE.lookup(input, options)
+}
+
+/// What the cached analysis of `input` with `options` removes.
+pub fn peek(input: &Path, options: &ProcessOptions) -> Option<CachedPlan> {
+    CACHE.peek(input, options)
+}
+
+/// Keeps `analysis` of `input` with `options` for [`lookup`].
+pub fn store(input: &Path, options: &ProcessOptions, analysis: &Analysis) {
+    CACHE.store(input, options, analysis);
+}
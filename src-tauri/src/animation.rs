@@ -0,0 +1,139 @@
+//! Animated GIF, WebP and APNG files: reading how often they loop, and
+//! decoding animated WebP, which ffmpeg cannot read.
+
+use image::codecs::webp::WebPDecoder;
+use image::AnimationDecoder;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+use crate::control::JobControl;
+use crate::error::ProcessError;
+use crate::sequence;
+
+/// Loop metadata is near the start of every format, so only this much of a
+/// file is read to find it.
+const HEADER_LEN: u64 = 64 * 1024;
+
+fn read_header(path: &Path) -> Option<Vec<u8>> {
+    let mut header = Vec::new();
+    File::open(path)
+        .ok()?
+        .take(HEADER_LEN)
+        .read_to_end(&mut header)
+        .ok()?;
+    Some(header)
+}
+
+/// How many times the animation in `path` plays, with 0 meaning forever.
+/// `None` when the file is not an animation this can read.
+pub fn plays(path: &Path) -> Option<u16> {
+    let header = read_header(path)?;
+    if header.starts_with(b"GIF8") {
+        Some(gif_plays(&header))
+    } else if header.starts_with(b"\x89PNG\r\n\x1a\n") {
+        apng_plays(&header)
+    } else if header.starts_with(b"RIFF") && header.get(8..12) == Some(b"WEBP") {
+        webp_plays(&header)
+    } else {
+        None
+    }
+}
+
+/// GIFs loop through the NETSCAPE2.0 extension, which counts repeats after
+/// the first play; without it they play once.
+fn gif_plays(header: &[u8]) -> u16 {
+    const NETSCAPE: &[u8] = b"NETSCAPE2.0";
+    let Some(at) = header
+        .windows(NETSCAPE.len())
+        .position(|window| window == NETSCAPE)
+    else {
+        return 1;
+    };
+    match header.get(at + NETSCAPE.len()..at + NETSCAPE.len() + 4) {
+        Some(&[3, 1, lo, hi]) => match u16::from_le_bytes([lo, hi]) {
+            0 => 0,
+            repeats => repeats.saturating_add(1),
+        },
+        _ => 1,
+    }
+}
+
+/// APNGs carry their play count in the `acTL` chunk; a PNG without one is a
+/// still image.
+fn apng_plays(header: &[u8]) -> Option<u16> {
+    let mut at = 8;
+    while let Some(chunk) = header.get(at..at + 8) {
+        let len = u32::from_be_bytes(chunk[..4].try_into().unwrap()) as usize;
+        match &chunk[4..] {
+            b"acTL" => {
+                let plays = header.get(at + 12..at + 16)?;
+                let plays = u32::from_be_bytes(plays.try_into().unwrap());
+                return Some(plays.min(u16::MAX as u32) as u16);
+            }
+            b"IDAT" => return None,
+            _ => at += 12 + len,
+        }
+    }
+    None
+}
+
+/// Animated WebPs set the animation flag in `VP8X` and their loop count in
+/// the `ANIM` chunk.
+fn webp_plays(header: &[u8]) -> Option<u16> {
+    let mut at = 12;
+    let mut animated = false;
+    while let Some(chunk) = header.get(at..at + 8) {
+        let len = u32::from_le_bytes(chunk[4..].try_into().unwrap()) as usize;
+        let data = header.get(at + 8..at + 8 + len);
+        match &chunk[..4] {
+            b"VP8X" => animated = data?.first()? & 0x02 != 0,
+            b"ANIM" if animated => {
+                let count = data?.get(4..6)?;
+                return Some(u16::from_le_bytes([count[0], count[1]]));
+            }
+            _ => {}
+        }
+        // chunks are padded to an even length
+        at += 8 + len + (len & 1);
+    }
+    None
+}
+
+pub fn is_animated_webp(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("webp"))
+        && plays(path).is_some()
+}
+
+/// Decodes the animated WebP `input` into `frame_00000001.png`, ... in `dir` and
+/// returns its average frame rate.
+pub fn decode_webp(input: &Path, dir: &Path, control: &JobControl) -> Result<f64, ProcessError> {
+    let decode_error = |e: image::ImageError| {
+        ProcessError::new(format!("Failed to decode {}: {}", input.display(), e))
+    };
+    let file = File::open(input)
+        .map_err(|e| ProcessError::new(format!("Failed to open {}: {}", input.display(), e)))?;
+    let decoder = WebPDecoder::new(BufReader::new(file)).map_err(decode_error)?;
+
+    let mut count = 0;
+    let mut duration_ms = 0.0;
+    for frame in decoder.into_frames() {
+        control.check()?;
+        let frame = frame.map_err(decode_error)?;
+        let (numer, denom) = frame.delay().numer_denom_ms();
+        duration_ms += numer as f64 / denom as f64;
+        count += 1;
+        let path = dir.join(sequence::frame_name(count, sequence::FRAME_DIGITS, "png"));
+        frame
+            .buffer()
+            .save(&path)
+            .map_err(|e| ProcessError::new(format!("Failed to write {}: {}", path.display(), e)))?;
+    }
+
+    Ok(if duration_ms > 0.0 {
+        count as f64 * 1000.0 / duration_ms
+    } else {
+        30.0
+    })
+}
@@ -0,0 +1,962 @@
+// Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
+
+use crate::error::ProcessError;
+#[cfg(feature = "download-ffmpeg")]
+use crate::ffmpeg_download;
+#[cfg(feature = "plugins")]
+use crate::plugins;
+use crate::processed::{self, ProcessedPolicy};
+use crate::queue::{Job, JobQueue, QueueEvent};
+use crate::state::{AppState, SessionMetrics};
+use crate::video_fixer::{JobSummary, ProcessOptions};
+use crate::{
+    analysis_cache, benchmark, capabilities, compare, ffmpeg, history, hooks, ingest, logging,
+    notify, postaction, presets, settings, streams, supervisor, synthetic, timeline, undo,
+    video_fixer, watch, webhook, workspace,
+};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tauri::{Emitter, Manager, RunEvent, State, Url, WindowEvent};
+use tauri_plugin_deep_link::DeepLinkExt;
+use tauri_plugin_opener::OpenerExt;
+use tracing::warn;
+
+/// URL scheme registered for the app; `dead-frames://open?path=<file>` opens
+/// a video, and `path` may be repeated.
+const URL_SCHEME: &str = "dead-frames";
+
+/// How long exiting waits for the running job to stop.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The app's job queue, reporting to the frontend as `queue-event` and
+/// recording finished jobs in the history. Unfinished jobs are kept in
+/// `store`. Events only arrive once the queue is part of the managed
+/// [`AppState`].
+fn create_queue(app: tauri::AppHandle, store: PathBuf) -> JobQueue {
+    JobQueue::persistent(store, move |event| {
+        let state = app.state::<AppState>();
+        match event {
+            QueueEvent::JobStarted { job } => {
+                if let Some(job) = state.queue.job(*job) {
+                    job_started(&state, job.id, &job.input);
+                }
+            }
+            QueueEvent::JobFinished { job, summary } => {
+                state.metrics.job_finished(summary);
+                if let Some(job) = state.queue.job(*job) {
+                    job_finished(&app, &state, job.id, &job.input, &job.options, summary);
+                }
+            }
+            QueueEvent::JobFailed { job, error } => {
+                state.metrics.job_failed();
+                if let Some(job) = state.queue.job(*job) {
+                    job_failed(&app, &state, job.id, &job.input, error);
+                }
+            }
+            QueueEvent::QueueDrained => {
+                postaction::after_queue(&state.settings.current().post_actions)
+            }
+            _ => {}
+        }
+        let _ = app.emit("queue-event", event);
+    })
+}
+
+/// Runs the `before_job` hook of the job `job` on `input`, queued or run
+/// directly, and waits for it.
+fn job_started(state: &AppState, job: u64, input: &Path) {
+    hooks::before_job(&state.settings.current().hooks, job, input);
+}
+
+/// Records, announces and follows up the job `job` that turned `input` into
+/// the output of `summary`: history, notification, webhook, `after_job`
+/// hook and post-processing.
+fn job_finished(
+    app: &tauri::AppHandle,
+    state: &AppState,
+    job: u64,
+    input: &Path,
+    options: &ProcessOptions,
+    summary: &JobSummary,
+) {
+    let settings = state.settings.current();
+    let name = input.to_string_lossy();
+    state.history.record(&name, options, summary);
+    notify::job_finished(app, &name, summary);
+    webhook::job_finished(&settings.webhook, job, input, summary);
+    hooks::after_job(&settings.hooks, job, input, summary);
+    postaction::after_job(&settings.post_actions, input, summary);
+}
+
+/// Announces the job `job` on `input` that failed with `error`.
+fn job_failed(
+    app: &tauri::AppHandle,
+    state: &AppState,
+    job: u64,
+    input: &Path,
+    error: &ProcessError,
+) {
+    let settings = state.settings.current();
+    notify::job_failed(app, &input.to_string_lossy(), error);
+    webhook::job_failed(&settings.webhook, job, input, error);
+    hooks::job_failed(&settings.hooks, job, input, error);
+}
+
+/// Queues videos the app was opened with, using the default preset. They
+/// start right away with `auto_start_opened` and are held otherwise.
+fn open_paths(state: &AppState, paths: Vec<PathBuf>) {
+    if paths.is_empty() {
+        return;
+    }
+    let settings = state.settings.current();
+    let options = match settings
+        .default_preset
+        .as_deref()
+        .and_then(|name| state.presets.get(name))
+    {
+        Some(preset) => preset.options(),
+        None => ProcessOptions::default(),
+    };
+    for path in paths {
+        if path.is_file() && ingest::is_video(&path) {
+            state
+                .queue
+                .enqueue(path, options.clone(), !settings.auto_start_opened);
+        } else {
+            warn!("Not opening {}: not a video file", path.display());
+        }
+    }
+}
+
+/// File arguments of a launch, resolved against the launching process's
+/// working directory. Flags and URLs are left to Tauri and the deep link
+/// handler.
+fn launch_paths(args: &[String], cwd: &Path) -> Vec<PathBuf> {
+    args.iter()
+        .filter(|arg| !arg.starts_with('-') && !arg.contains("://"))
+        .map(|arg| cwd.join(arg))
+        .collect()
+}
+
+fn deep_link_paths(urls: &[Url]) -> Vec<PathBuf> {
+    urls.iter()
+        .filter(|url| url.scheme() == URL_SCHEME && url.host_str() == Some("open"))
+        .flat_map(|url| {
+            url.query_pairs()
+                .filter(|(key, _)| key == "path")
+                .map(|(_, path)| PathBuf::from(path.into_owned()))
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+#[tauri::command]
+fn greet(name: &str) -> String {
+    format!("Hello, {}! You've been greeted from Rust!", name)
+}
+
+/// Runs `work` on the blocking pool of Tauri's runtime. Commands that wait
+/// on ffmpeg, the disk or another thread go through this, so neither the
+/// IPC thread nor the async workers stall while they run. Fails only when
+/// `work` panics.
+async fn blocking<T: Send + 'static>(
+    work: impl FnOnce() -> T + Send + 'static,
+) -> Result<T, String> {
+    tauri::async_runtime::spawn_blocking(work)
+        .await
+        .map_err(|e| format!("Command failed: {}", e))
+}
+
+/// Removes dead frames from `input` and reports what was done. The job
+/// runs outside the queue, but its hooks, webhook and post-processing are
+/// those of a queued job.
+#[tauri::command]
+async fn process_video(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    input: String,
+    options: Option<ProcessOptions>,
+) -> Result<JobSummary, ProcessError> {
+    let options = options.unwrap_or_default();
+    let input = PathBuf::from(input);
+    let _in_flight = state.in_flight.enter();
+    let job = state.queue.reserve_id();
+    // the hook may run for as long as its timeout
+    let starting = app.clone();
+    let started = input.clone();
+    blocking(move || job_started(&starting.state::<AppState>(), job, &started))
+        .await
+        .map_err(ProcessError::new)?;
+    let result = video_fixer::process_video(&input, &options).await;
+    match &result {
+        Ok(summary) => {
+            state.metrics.job_finished(summary);
+            job_finished(&app, &state, job, &input, &options, summary);
+        }
+        Err(e) => {
+            state.metrics.job_failed();
+            job_failed(&app, &state, job, &input, e);
+        }
+    }
+    result
+}
+
+/// Renders a side-by-side clip of `original` and `processed` and returns
+/// its path.
+#[tauri::command]
+async fn render_comparison(
+    original: String,
+    processed: String,
+    start: f64,
+    duration: f64,
+) -> Result<String, ProcessError> {
+    let processed = PathBuf::from(processed);
+    let output = compare::default_output(&processed);
+    blocking(move || {
+        compare::render(Path::new(&original), &processed, start, duration, &output)
+            .map(|_| output.to_string_lossy().into_owned())
+    })
+    .await
+    .map_err(ProcessError::new)?
+}
+
+/// Puts removed frames of a job run with `keep_removed` back and encodes its
+/// output again.
+#[tauri::command]
+async fn restore_frames(
+    job_id: String,
+    indices: Vec<usize>,
+) -> Result<undo::Restored, ProcessError> {
+    blocking(move || undo::restore_frames(&job_id, &indices))
+        .await
+        .map_err(ProcessError::new)?
+}
+
+/// Deletes the frames a job kept for undoing.
+#[tauri::command]
+fn discard_removed_frames(job_id: String) -> Result<(), String> {
+    undo::discard(&job_id)
+}
+
+#[tauri::command]
+fn get_queue(state: State<AppState>) -> Vec<Job> {
+    state.queue.jobs()
+}
+
+/// A dropped file and what became of it.
+#[derive(serde::Serialize)]
+struct AddedFile {
+    path: PathBuf,
+    /// The queued job, when the file was accepted.
+    job: Option<u64>,
+    error: Option<String>,
+}
+
+/// Queues the video files among `paths`, searching directories
+/// recursively, and reports on each file found. Accepted files are held
+/// unless `start` is set; files processed before are turned away when the
+/// settings say to skip them.
+#[tauri::command]
+async fn add_files(
+    state: State<'_, AppState>,
+    paths: Vec<String>,
+    options: Option<ProcessOptions>,
+    start: bool,
+) -> Result<Vec<AddedFile>, String> {
+    let paths: Vec<PathBuf> = paths.into_iter().map(PathBuf::from).collect();
+    let options = options.unwrap_or_default();
+    let if_processed = state.settings.current().if_processed;
+    let active: Vec<PathBuf> = state
+        .queue
+        .jobs()
+        .into_iter()
+        .filter(|job| !job.state.is_done())
+        .map(|job| job.input)
+        .collect();
+
+    let checks = blocking(move || ingest::check_paths(&paths)).await?;
+    Ok(checks
+        .into_iter()
+        .map(|check| {
+            let error = check
+                .error
+                .or_else(|| {
+                    active
+                        .contains(&check.path)
+                        .then(|| "Already in the queue".to_string())
+                })
+                .or_else(|| {
+                    processed::skip(&check.path, &options, if_processed).map(|processed| {
+                        format!("Already processed into {}", processed.output().display())
+                    })
+                });
+            let job = error.is_none().then(|| {
+                state
+                    .queue
+                    .enqueue(check.path.clone(), options.clone(), !start)
+            });
+            AddedFile {
+                path: check.path,
+                job,
+                error,
+            }
+        })
+        .collect())
+}
+
+/// Starts held jobs, such as videos opened with the app.
+#[tauri::command]
+fn start_jobs(state: State<AppState>, ids: Vec<u64>) -> Result<(), String> {
+    ids.into_iter().try_for_each(|id| state.queue.release(id))
+}
+
+#[tauri::command]
+fn cancel_job(state: State<AppState>, id: u64) -> Result<(), String> {
+    state.queue.cancel(id)
+}
+
+/// Holds a running job, suspending its ffmpeg children, to free the CPU.
+#[tauri::command]
+fn pause_job(state: State<AppState>, id: u64) -> Result<(), String> {
+    state.queue.pause(id)
+}
+
+#[tauri::command]
+fn resume_job(state: State<AppState>, id: u64) -> Result<(), String> {
+    state.queue.resume(id)
+}
+
+/// Totals over the jobs run since the app started.
+#[tauri::command]
+fn get_metrics(state: State<AppState>) -> SessionMetrics {
+    state.metrics.snapshot()
+}
+
+/// Opens a finished video in the default player.
+#[tauri::command]
+fn open_output(app: tauri::AppHandle, path: String) -> Result<(), String> {
+    app.opener()
+        .open_path(path, None::<&str>)
+        .map_err(|e| format!("Failed to open output: {}", e))
+}
+
+/// Shows a finished video selected in the system file manager.
+#[tauri::command]
+fn reveal_in_folder(app: tauri::AppHandle, path: String) -> Result<(), String> {
+    app.opener()
+        .reveal_item_in_dir(path)
+        .map_err(|e| format!("Failed to reveal output: {}", e))
+}
+
+#[tauri::command]
+fn get_settings(state: State<AppState>) -> settings::AppSettings {
+    state.settings.current()
+}
+
+/// Replaces all settings at once and returns what was saved.
+#[tauri::command]
+fn update_settings(
+    state: State<AppState>,
+    new_settings: settings::AppSettings,
+) -> Result<settings::AppSettings, String> {
+    new_settings.validate()?;
+    let old = state.settings.current();
+    if let Some(dir) = &new_settings.temp_dir {
+        if old.temp_dir.as_ref() != Some(dir) {
+            workspace::validate_work_dir(dir)?;
+        }
+    }
+    state
+        .settings
+        .update(|s| *s = new_settings)
+        .map_err(|e| format!("Failed to save settings: {}", e))?;
+
+    let new = state.settings.current();
+    if new.prefer_system_ffmpeg != old.prefer_system_ffmpeg || new.ffmpeg_path != old.ffmpeg_path {
+        state.ffmpeg.reset();
+    }
+    Ok(new)
+}
+
+/// Up to `limit` of the most recent log records at `level` ("error",
+/// "warn", "info", ...) or more severe, oldest first.
+#[tauri::command]
+fn get_recent_logs(
+    state: State<'_, AppState>,
+    level: Option<String>,
+    limit: Option<usize>,
+) -> Result<Vec<logging::LogRecord>, String> {
+    let level = match level {
+        Some(level) => level
+            .parse()
+            .map_err(|_| format!("Unknown log level: {}", level))?,
+        None => tracing::Level::INFO,
+    };
+    Ok(state.logging.recent(level, limit.unwrap_or(500)))
+}
+
+/// The transcript of a job, identified by the `job_id` of its summary or
+/// error.
+#[tauri::command]
+fn get_job_log(state: State<'_, AppState>, job_id: String) -> Result<String, String> {
+    state.logging.job_log(&job_id)
+}
+
+/// The similarity scores of a job for graphing, downsampled when there are
+/// many, with its threshold and removed spans.
+#[tauri::command]
+fn get_score_timeline(job_id: String) -> Result<timeline::ScoreTimeline, String> {
+    timeline::load(&job_id)
+}
+
+/// The video streams of `path`, for choosing which one a job processes.
+#[tauri::command]
+async fn list_video_streams(path: String) -> Result<Vec<streams::VideoStream>, ProcessError> {
+    blocking(move || streams::list(Path::new(&path)))
+        .await
+        .map_err(ProcessError::new)?
+}
+
+/// The most recent completed jobs, newest first.
+#[tauri::command]
+fn get_history(
+    state: State<'_, AppState>,
+    limit: Option<usize>,
+) -> Result<Vec<history::HistoryEntry>, String> {
+    state
+        .history
+        .recent(limit.unwrap_or(history::DEFAULT_LIMIT))
+}
+
+/// Completed jobs whose input or output path contains `query`.
+#[tauri::command]
+fn search_history(
+    state: State<'_, AppState>,
+    query: String,
+    limit: Option<usize>,
+) -> Result<Vec<history::HistoryEntry>, String> {
+    state
+        .history
+        .search(&query, limit.unwrap_or(history::DEFAULT_LIMIT))
+}
+
+#[tauri::command]
+fn clear_history(state: State<'_, AppState>) -> Result<(), String> {
+    state.history.clear()
+}
+
+/// Forgets the analyses kept for files queued again, returning how many.
+#[tauri::command]
+fn clear_analysis_cache(state: State<'_, AppState>) -> Result<usize, String> {
+    state.analysis_cache.clear()
+}
+
+#[tauri::command]
+fn list_presets(state: State<'_, AppState>) -> Vec<presets::Preset> {
+    state.presets.list()
+}
+
+#[tauri::command]
+fn create_preset(state: State<'_, AppState>, preset: presets::Preset) -> Result<(), String> {
+    state.presets.create(preset)
+}
+
+#[tauri::command]
+fn rename_preset(state: State<'_, AppState>, name: String, new_name: String) -> Result<(), String> {
+    state.presets.rename(&name, &new_name)
+}
+
+#[tauri::command]
+fn delete_preset(state: State<'_, AppState>, name: String) -> Result<(), String> {
+    state.presets.delete(&name)
+}
+
+/// Exports the named presets, or all user presets when `names` is empty.
+#[tauri::command]
+fn export_presets(
+    state: State<'_, AppState>,
+    path: String,
+    names: Vec<String>,
+) -> Result<(), String> {
+    state.presets.export(Path::new(&path), &names)
+}
+
+/// Imports presets from a JSON file and returns how many were added.
+#[tauri::command]
+fn import_presets(state: State<'_, AppState>, path: String) -> Result<usize, String> {
+    state.presets.import(Path::new(&path))
+}
+
+/// The detector plugins in the plugins directory and whether each is
+/// enabled.
+#[cfg(feature = "plugins")]
+#[tauri::command]
+fn list_plugins(state: State<'_, AppState>) -> Result<Vec<plugins::PluginInfo>, String> {
+    state.plugins.list()
+}
+
+/// Enables or disables a detector plugin for every job.
+#[cfg(feature = "plugins")]
+#[tauri::command]
+fn enable_plugin(state: State<'_, AppState>, name: String, enabled: bool) -> Result<(), String> {
+    state.plugins.enable(&name, enabled)
+}
+
+/// Times each metric and way of running the analysis on a synthetic clip,
+/// sending each measurement as `benchmark-result` as it is taken.
+#[tauri::command]
+async fn run_benchmark(
+    app: tauri::AppHandle,
+    clip: Option<benchmark::BenchmarkClip>,
+) -> Result<benchmark::BenchmarkReport, ProcessError> {
+    blocking(move || {
+        benchmark::run(clip.unwrap_or_default(), |measurement| {
+            let _ = app.emit("benchmark-result", measurement);
+        })
+    })
+    .await
+    .map_err(ProcessError::new)?
+}
+
+/// Renders a clip with a known pattern of unique, duplicated and black
+/// frames into `output` and returns which frames the default settings
+/// remove, to check settings against.
+#[tauri::command]
+async fn generate_test_video(
+    output: PathBuf,
+    pattern: synthetic::Pattern,
+) -> Result<Vec<bool>, ProcessError> {
+    blocking(move || {
+        pattern.generate(&output)?;
+        Ok(pattern.expected_removed())
+    })
+    .await
+    .map_err(ProcessError::new)?
+}
+
+/// Lists job directories left behind by crashed runs and the space they use.
+#[tauri::command]
+async fn scan_workspace() -> Result<workspace::WorkspaceReport, String> {
+    blocking(workspace::scan).await
+}
+
+/// Deletes stale job directories and returns the number of bytes freed.
+#[tauri::command]
+async fn cleanup_workspace() -> Result<u64, String> {
+    blocking(workspace::cleanup).await
+}
+
+#[tauri::command]
+fn get_work_dir() -> String {
+    workspace::work_dir().to_string_lossy().into_owned()
+}
+
+/// Sets the directory used for intermediate frames. An empty path resets it
+/// to the OS temp dir.
+#[tauri::command]
+fn set_work_dir(state: State<AppState>, path: String) -> Result<(), String> {
+    let work_dir = if path.is_empty() {
+        None
+    } else {
+        let path = PathBuf::from(path);
+        workspace::validate_work_dir(&path)?;
+        Some(path)
+    };
+    state
+        .settings
+        .update(|s| s.temp_dir = work_dir)
+        .map_err(|e| format!("Failed to save settings: {}", e))
+}
+
+/// Limits the comparison threads and concurrent ffmpeg processes. `None`
+/// restores the default of using every core without a process limit.
+#[tauri::command]
+fn set_parallelism(
+    state: State<AppState>,
+    threads: Option<usize>,
+    max_ffmpeg_processes: Option<usize>,
+) -> Result<(), String> {
+    state
+        .settings
+        .update(|s| {
+            s.threads = threads;
+            s.max_ffmpeg_processes = max_ffmpeg_processes;
+        })
+        .map_err(|e| format!("Failed to save settings: {}", e))
+}
+
+/// Toggles background mode, which lowers the OS priority of processing.
+#[tauri::command]
+fn set_low_priority(state: State<AppState>, enabled: bool) -> Result<(), String> {
+    state
+        .settings
+        .update(|s| s.low_priority = enabled)
+        .map_err(|e| format!("Failed to save settings: {}", e))
+}
+
+/// Turns the desktop notification for finished and failed jobs on or off.
+#[tauri::command]
+fn set_notifications(state: State<AppState>, enabled: bool) -> Result<(), String> {
+    state
+        .settings
+        .update(|s| s.mute_notifications = !enabled)
+        .map_err(|e| format!("Failed to save settings: {}", e))
+}
+
+/// Configures the ffmpeg supervisor. A timeout of 0 disables it and `None`
+/// restores the default.
+#[tauri::command]
+fn set_ffmpeg_supervision(
+    state: State<AppState>,
+    timeout_secs: Option<u64>,
+    retries: u32,
+) -> Result<(), String> {
+    state
+        .settings
+        .update(|s| {
+            s.ffmpeg_timeout_secs = timeout_secs;
+            s.ffmpeg_retries = retries;
+        })
+        .map_err(|e| format!("Failed to save settings: {}", e))
+}
+
+/// Limits what ffmpeg processes may use; see [`crate::sandbox`]. `None`
+/// lifts a limit.
+#[tauri::command]
+fn set_ffmpeg_sandbox(
+    state: State<AppState>,
+    memory_limit_mb: Option<u64>,
+    cpu_limit_percent: Option<u32>,
+    isolate_network: bool,
+) -> Result<(), String> {
+    state
+        .settings
+        .update(|s| {
+            s.ffmpeg_memory_limit_mb = memory_limit_mb;
+            s.ffmpeg_cpu_limit_percent = cpu_limit_percent;
+            s.isolate_ffmpeg_network = isolate_network;
+        })
+        .map_err(|e| format!("Failed to save settings: {}", e))
+}
+
+/// Reports watch folder activity to the frontend as `watch-event` and
+/// through notifications.
+fn start_watching(
+    app: tauri::AppHandle,
+    watch_settings: &watch::WatchSettings,
+) -> Result<(), String> {
+    let state = app.state::<AppState>();
+    let events = app.clone();
+    state.watch.start(watch_settings, move |event| {
+        let metrics = &events.state::<AppState>().metrics;
+        match &event {
+            watch::WatchEvent::Finished { input, summary } => {
+                metrics.job_finished(summary);
+                notify::job_finished(&events, &input.to_string_lossy(), summary)
+            }
+            watch::WatchEvent::Failed { input, error } => {
+                metrics.job_failed();
+                notify::job_failed(&events, &input.to_string_lossy(), error)
+            }
+            _ => {}
+        }
+        let _ = events.emit("watch-event", event);
+    })
+}
+
+/// Starts processing videos that appear in `folder` with `preset`, and
+/// keeps doing so across restarts until [`stop_watch`] is called.
+#[tauri::command]
+async fn start_watch(
+    app: tauri::AppHandle,
+    folder: String,
+    preset: Option<String>,
+    archive_originals: bool,
+    if_processed: Option<ProcessedPolicy>,
+) -> Result<watch::WatchStatus, String> {
+    let watch_settings = watch::WatchSettings {
+        enabled: true,
+        folder: Some(PathBuf::from(folder)),
+        preset,
+        archive_originals,
+        if_processed: if_processed.unwrap_or_default(),
+    };
+    // waits for an earlier watch to stop
+    blocking(move || {
+        start_watching(app.clone(), &watch_settings)?;
+        let state = app.state::<AppState>();
+        state
+            .settings
+            .update(|s| s.watch = watch_settings)
+            .map_err(|e| format!("Failed to save settings: {}", e))?;
+        Ok(state.watch.status())
+    })
+    .await?
+}
+
+#[tauri::command]
+async fn stop_watch(app: tauri::AppHandle) -> Result<(), String> {
+    // waits for the file being processed to be abandoned
+    let stopping = app.clone();
+    blocking(move || stopping.state::<AppState>().watch.stop()).await?;
+    app.state::<AppState>()
+        .settings
+        .update(|s| s.watch.enabled = false)
+        .map_err(|e| format!("Failed to save settings: {}", e))
+}
+
+#[tauri::command]
+fn get_watch_status(state: State<AppState>) -> watch::WatchStatus {
+    state.watch.status()
+}
+
+#[tauri::command]
+async fn get_ffmpeg_info(state: State<'_, AppState>) -> Result<ffmpeg::FfmpegInfo, String> {
+    let ffmpeg = state.ffmpeg.clone();
+    blocking(move || ffmpeg.info()).await?
+}
+
+#[tauri::command]
+async fn get_ffmpeg_capabilities() -> Result<capabilities::FfmpegCapabilities, String> {
+    blocking(capabilities::get_ffmpeg_capabilities).await?
+}
+
+/// Chooses which ffmpeg binary to use. A custom path must pass the version
+/// probe; otherwise the embedded binary remains the fallback.
+#[tauri::command]
+async fn set_ffmpeg_override(
+    state: State<'_, AppState>,
+    prefer_system: bool,
+    path: Option<String>,
+) -> Result<ffmpeg::FfmpegInfo, String> {
+    let path = path.filter(|p| !p.is_empty()).map(PathBuf::from);
+    let settings = state.settings.clone();
+    let ffmpeg = state.ffmpeg.clone();
+    blocking(move || {
+        if let Some(path) = &path {
+            ffmpeg::probe_version(path)?;
+        }
+        settings
+            .update(|s| {
+                s.prefer_system_ffmpeg = prefer_system;
+                s.ffmpeg_path = path;
+            })
+            .map_err(|e| format!("Failed to save settings: {}", e))?;
+        ffmpeg.reset();
+        ffmpeg.info()
+    })
+    .await?
+}
+
+#[cfg(feature = "download-ffmpeg")]
+#[derive(Clone, serde::Serialize)]
+struct DownloadProgress {
+    downloaded: u64,
+    total: Option<u64>,
+}
+
+/// Downloads ffmpeg in the background, reporting through
+/// `ffmpeg-download-progress`, `ffmpeg-download-finished` and
+/// `ffmpeg-download-failed` events.
+#[cfg(feature = "download-ffmpeg")]
+fn spawn_ffmpeg_download(app: tauri::AppHandle) {
+    std::thread::spawn(move || {
+        let progress_app = app.clone();
+        let mut last_emitted = 0;
+        let result = ffmpeg_download::download(move |downloaded, total| {
+            // one event per 256 KiB is plenty for a progress bar
+            if downloaded - last_emitted >= 256 * 1024 || Some(downloaded) == total {
+                last_emitted = downloaded;
+                let _ = progress_app.emit(
+                    "ffmpeg-download-progress",
+                    DownloadProgress { downloaded, total },
+                );
+            }
+        });
+        let _ = match result {
+            Ok(_) => app.emit("ffmpeg-download-finished", ()),
+            Err(e) => app.emit("ffmpeg-download-failed", e),
+        };
+    });
+}
+
+/// Retries the first-run ffmpeg download.
+#[cfg(feature = "download-ffmpeg")]
+#[tauri::command]
+fn download_ffmpeg(app: tauri::AppHandle) {
+    spawn_ffmpeg_download(app);
+}
+
+#[derive(Clone, serde::Serialize)]
+struct ExitRequested {
+    active_jobs: usize,
+}
+
+/// Closing the window while jobs are queued or running asks first: the close
+/// is held back and `exit-requested` sent, and the frontend calls this once
+/// the user agrees.
+#[tauri::command]
+fn confirm_exit(app: tauri::AppHandle) {
+    app.exit(0);
+}
+
+/// Stops everything still running before the process exits. ffmpeg children
+/// are killed, the watch folder and the queue stopped, with unfinished jobs
+/// kept for the next launch, the history closed, and job directories no job
+/// holds any more removed.
+fn shut_down(state: &AppState) {
+    supervisor::shut_down();
+    state.watch.stop();
+    if !state.queue.shut_down(SHUTDOWN_TIMEOUT) {
+        warn!(
+            "The running job did not stop within {}s",
+            SHUTDOWN_TIMEOUT.as_secs()
+        );
+    }
+    state.history.close();
+    workspace::cleanup();
+}
+
+#[cfg_attr(mobile, tauri::mobile_entry_point)]
+pub fn run() {
+    let builder = tauri::Builder::default();
+    // must come first so a second launch hands over its arguments and exits
+    // before doing anything else
+    #[cfg(desktop)]
+    let builder = builder.plugin(tauri_plugin_single_instance::init(|app, argv, cwd| {
+        if let Some(window) = app.get_webview_window("main") {
+            let _ = window.unminimize();
+            let _ = window.set_focus();
+        }
+        open_paths(
+            &app.state::<AppState>(),
+            launch_paths(argv.get(1..).unwrap_or_default(), Path::new(&cwd)),
+        );
+    }));
+    builder
+        .plugin(tauri_plugin_deep_link::init())
+        .plugin(tauri_plugin_opener::init())
+        .plugin(tauri_plugin_notification::init())
+        .setup(|app| {
+            logging::init(&app.path().app_log_dir()?);
+            let config_dir = app.path().app_config_dir()?;
+            settings::init(config_dir.join("settings.json"));
+            presets::init(config_dir.join("presets.json"));
+            history::init(&app.path().app_data_dir()?);
+            analysis_cache::init(&app.path().app_data_dir()?);
+            ffmpeg::init(&app.path().app_data_dir()?);
+            #[cfg(feature = "plugins")]
+            plugins::init(&app.path().app_data_dir()?);
+            let store = app.path().app_data_dir()?.join("queue.json");
+            app.manage(AppState::new(create_queue(app.handle().clone(), store)));
+            let state = app.state::<AppState>();
+            // jobs left from the last run wait for the user to start them
+            // again with start_jobs
+            let restored = state.queue.restore();
+            if !restored.is_empty() {
+                let _ = app.emit("queue-restored", restored);
+            }
+            let args: Vec<String> = std::env::args().skip(1).collect();
+            open_paths(&state, launch_paths(&args, &std::env::current_dir()?));
+            let handle = app.handle().clone();
+            app.deep_link().on_open_url(move |event| {
+                open_paths(&handle.state::<AppState>(), deep_link_paths(&event.urls()))
+            });
+            if let Some(urls) = app.deep_link().get_current()? {
+                open_paths(&state, deep_link_paths(&urls));
+            }
+            let watch_settings = state.settings.current().watch;
+            if watch_settings.enabled {
+                if let Err(e) = start_watching(app.handle().clone(), &watch_settings) {
+                    warn!("Failed to resume watching: {}", e);
+                }
+            }
+            let handle = app.handle().clone();
+            std::thread::spawn(move || {
+                let report = workspace::scan();
+                if !report.stale_dirs.is_empty() {
+                    let _ = handle.emit("workspace-stale", report);
+                }
+            });
+            #[cfg(feature = "download-ffmpeg")]
+            {
+                ffmpeg_download::init(&app.path().app_data_dir()?);
+                if ffmpeg_download::installed_path().is_err() {
+                    spawn_ffmpeg_download(app.handle().clone());
+                }
+            }
+            Ok(())
+        })
+        .on_window_event(|window, event| {
+            if let WindowEvent::CloseRequested { api, .. } = event {
+                let active_jobs = window.state::<AppState>().active_jobs();
+                if active_jobs > 0 {
+                    api.prevent_close();
+                    let _ = window.emit("exit-requested", ExitRequested { active_jobs });
+                }
+            }
+        })
+        .invoke_handler(tauri::generate_handler![
+            greet,
+            process_video,
+            render_comparison,
+            restore_frames,
+            discard_removed_frames,
+            get_queue,
+            add_files,
+            start_jobs,
+            cancel_job,
+            pause_job,
+            resume_job,
+            get_metrics,
+            open_output,
+            reveal_in_folder,
+            get_settings,
+            update_settings,
+            get_recent_logs,
+            get_job_log,
+            get_score_timeline,
+            list_video_streams,
+            get_history,
+            search_history,
+            clear_history,
+            clear_analysis_cache,
+            list_presets,
+            create_preset,
+            rename_preset,
+            delete_preset,
+            export_presets,
+            import_presets,
+            scan_workspace,
+            cleanup_workspace,
+            get_work_dir,
+            set_work_dir,
+            set_parallelism,
+            set_low_priority,
+            set_notifications,
+            get_ffmpeg_info,
+            set_ffmpeg_override,
+            get_ffmpeg_capabilities,
+            set_ffmpeg_supervision,
+            set_ffmpeg_sandbox,
+            start_watch,
+            stop_watch,
+            get_watch_status,
+            confirm_exit,
+            run_benchmark,
+            generate_test_video,
+            #[cfg(feature = "plugins")]
+            list_plugins,
+            #[cfg(feature = "plugins")]
+            enable_plugin,
+            #[cfg(feature = "download-ffmpeg")]
+            download_ffmpeg
+        ])
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            if let RunEvent::Exit = event {
+                shut_down(&app.state::<AppState>());
+            }
+        });
+}
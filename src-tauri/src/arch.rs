@@ -0,0 +1,115 @@
+//! Which CPU architecture an executable was built for, read from its header,
+//! so an ffmpeg for the wrong machine is turned down with a clear message
+//! rather than failing to start with an exec format error.
+
+use std::env::consts::{ARCH, OS};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// Bytes read from the start of the file; enough for every header below.
+const HEADER_LEN: u64 = 4096;
+
+/// The architectures the executable at `path` contains code for, as named by
+/// [`std::env::consts::ARCH`]; a universal macOS binary can have several.
+/// `None` for files that are not ELF, PE or Mach-O executables, such as the
+/// scripts some package managers put in front of ffmpeg.
+pub fn executable_archs(path: &Path) -> std::io::Result<Option<Vec<&'static str>>> {
+    let mut header = Vec::new();
+    File::open(path)?
+        .take(HEADER_LEN)
+        .read_to_end(&mut header)?;
+    Ok(parse(&header))
+}
+
+fn parse(header: &[u8]) -> Option<Vec<&'static str>> {
+    let u16_le = |at: usize| Some(u16::from_le_bytes(header.get(at..at + 2)?.try_into().ok()?));
+    let u32_le = |at: usize| Some(u32::from_le_bytes(header.get(at..at + 4)?.try_into().ok()?));
+    let u32_be = |at: usize| Some(u32::from_be_bytes(header.get(at..at + 4)?.try_into().ok()?));
+
+    match header.get(..4)? {
+        [0x7f, b'E', b'L', b'F'] => {
+            let machine = match header.get(5)? {
+                1 => u16_le(18)?,
+                _ => u16::from_be_bytes(header.get(18..20)?.try_into().ok()?),
+            };
+            Some(vec![elf_arch(machine)?])
+        }
+        [b'M', b'Z', ..] => {
+            let pe = u32_le(0x3c)? as usize;
+            if header.get(pe..pe + 4)? != b"PE\0\0" {
+                return None;
+            }
+            Some(vec![pe_arch(u16_le(pe + 4)?)?])
+        }
+        // 64-bit Mach-O, little-endian
+        [0xcf, 0xfa, 0xed, 0xfe] => Some(vec![mach_arch(u32_le(4)?)?]),
+        // universal binary: a big-endian list of the slices inside
+        [0xca, 0xfe, 0xba, 0xbe] => {
+            let count = (u32_be(4)? as usize).min(header.len() / 20);
+            let archs: Vec<_> = (0..count)
+                .filter_map(|i| mach_arch(u32_be(8 + i * 20)?))
+                .collect();
+            (!archs.is_empty()).then_some(archs)
+        }
+        _ => None,
+    }
+}
+
+fn elf_arch(machine: u16) -> Option<&'static str> {
+    match machine {
+        3 => Some("x86"),
+        40 => Some("arm"),
+        62 => Some("x86_64"),
+        183 => Some("aarch64"),
+        243 => Some("riscv64"),
+        _ => None,
+    }
+}
+
+fn pe_arch(machine: u16) -> Option<&'static str> {
+    match machine {
+        0x14c => Some("x86"),
+        0x1c4 => Some("arm"),
+        0x8664 => Some("x86_64"),
+        0xaa64 => Some("aarch64"),
+        _ => None,
+    }
+}
+
+fn mach_arch(cpu_type: u32) -> Option<&'static str> {
+    match cpu_type {
+        0x0100_0007 => Some("x86_64"),
+        0x0100_000c => Some("aarch64"),
+        _ => None,
+    }
+}
+
+/// Whether this machine runs code for `arch`, natively or through the
+/// emulation the OS ships: Rosetta 2 on Apple Silicon, and x86 emulation on
+/// Windows on ARM.
+fn runs_here(arch: &str) -> bool {
+    arch == ARCH
+        || match (OS, ARCH) {
+            ("macos", "aarch64") => arch == "x86_64",
+            ("windows", "aarch64") => matches!(arch, "x86" | "x86_64"),
+            ("windows", "x86_64") | ("linux", "x86_64") => arch == "x86",
+            _ => false,
+        }
+}
+
+/// Checks that the executable at `path` can run on this machine. Files whose
+/// architecture cannot be told are given the benefit of the doubt.
+pub fn check(path: &Path) -> Result<(), String> {
+    let archs =
+        executable_archs(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    match archs {
+        Some(archs) if !archs.iter().any(|arch| runs_here(arch)) => Err(format!(
+            "{} is built for {}, but this machine is {}",
+            path.display(),
+            archs.join(" and "),
+            ARCH
+        )),
+        _ => Ok(()),
+    }
+}
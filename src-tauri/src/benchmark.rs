@@ -0,0 +1,256 @@
+//! How fast this machine gets through frames with each metric and each way
+//! of running the analysis, measured on a synthetic clip, to help pick the
+//! fastest settings that suit the footage.
+//!
+//! The clip is ffmpeg's `testsrc2` pattern with every image shown three
+//! times, so two frames in three are dead and the analysis has spans to
+//! find. Metrics are timed on decoded frames alone; the pipeline variants
+//! time the whole analysis, extraction included.
+
+use image::GrayImage;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::time::Instant;
+use tracing::info;
+
+use crate::comparator;
+use crate::concurrency;
+use crate::error::ProcessError;
+use crate::ffmpeg;
+use crate::fixer::VideoFixer;
+use crate::paths;
+use crate::similarity::{self, Metric};
+use crate::supervisor;
+use crate::video_fixer::{FrameFormat, ProcessOptions};
+use crate::workspace;
+use crate::y4m;
+
+/// Frames decoded for timing the metrics, which cycle through them.
+const METRIC_FRAMES: usize = 60;
+
+/// The synthetic clip the benchmark runs on.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BenchmarkClip {
+    pub width: u32,
+    pub height: u32,
+    /// Length in frames, at 30 fps.
+    pub frames: usize,
+}
+
+impl Default for BenchmarkClip {
+    fn default() -> Self {
+        BenchmarkClip {
+            width: 1280,
+            height: 720,
+            frames: 300,
+        }
+    }
+}
+
+/// The throughput of one metric or pipeline variant.
+#[derive(Debug, Clone, Serialize)]
+pub struct Measurement {
+    pub name: String,
+    pub frames: usize,
+    pub elapsed_secs: f64,
+    pub frames_per_sec: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BenchmarkReport {
+    pub clip: BenchmarkClip,
+    pub results: Vec<Measurement>,
+}
+
+/// Generates `clip` and times every metric and pipeline variant on it,
+/// calling `on_result` with each measurement as it is taken.
+pub fn run(
+    clip: BenchmarkClip,
+    mut on_result: impl FnMut(&Measurement),
+) -> Result<BenchmarkReport, ProcessError> {
+    let even = clip.width.is_multiple_of(2) && clip.height.is_multiple_of(2);
+    if clip.width < 16 || clip.height < 16 || !even {
+        return Err(ProcessError::new(
+            "The clip must be at least 16x16 with even dimensions",
+        ));
+    }
+    if clip.frames < 2 {
+        return Err(ProcessError::new("The clip must be at least 2 frames long"));
+    }
+    let dir = tempfile::Builder::new()
+        .prefix("dfr-benchmark-")
+        .tempdir_in(workspace::work_dir())
+        .map_err(|e| ProcessError::new(format!("Failed to create a work directory: {}", e)))?;
+    let video = dir.path().join("clip.mp4");
+    generate(&clip, &video)?;
+
+    let mut results = Vec::new();
+    let mut measured = |measurement: Measurement| {
+        info!(
+            "{}: {:.1} frames/s",
+            measurement.name, measurement.frames_per_sec
+        );
+        on_result(&measurement);
+        results.push(measurement);
+    };
+
+    let frames = decode(&video, &dir.path().join("frames.y4m"))?;
+    for metric in metrics() {
+        measured(time_metric(metric, &frames, clip.frames)?);
+    }
+    drop(frames);
+
+    let base = ProcessOptions::default();
+    let variants = [
+        (
+            "PNG frames",
+            ProcessOptions {
+                frame_format: FrameFormat::Png,
+                ..base.clone()
+            },
+        ),
+        (
+            "JPEG frames",
+            ProcessOptions {
+                frame_format: FrameFormat::Jpeg(90),
+                ..base.clone()
+            },
+        ),
+        (
+            "y4m frames",
+            ProcessOptions {
+                frame_format: FrameFormat::Y4m,
+                ..base.clone()
+            },
+        ),
+        (
+            "low memory",
+            ProcessOptions {
+                low_memory: true,
+                ..base.clone()
+            },
+        ),
+        (
+            "analysis step 4",
+            ProcessOptions {
+                frame_format: FrameFormat::Png,
+                analysis_step: Some(4),
+                ..base.clone()
+            },
+        ),
+    ];
+    for (name, options) in variants {
+        let started = Instant::now();
+        let analysis = VideoFixer::new(&video).options(options).analyze()?;
+        measured(measurement(
+            name.to_string(),
+            analysis.frames_total(),
+            started,
+        ));
+    }
+    Ok(BenchmarkReport { clip, results })
+}
+
+/// The metrics this build can run: the built-in ones, the learned one if
+/// its model loads and the registered comparators.
+fn metrics() -> Vec<Metric> {
+    let mut metrics = vec![Metric::Ssim, Metric::MeanAbsDiff];
+    #[cfg(feature = "onnx")]
+    if crate::learned::ready().is_ok() {
+        metrics.push(Metric::Learned);
+    }
+    metrics.extend(
+        comparator::names()
+            .into_iter()
+            .filter_map(Metric::from_name),
+    );
+    metrics
+}
+
+/// Renders `clip` into `output`.
+fn generate(clip: &BenchmarkClip, output: &Path) -> Result<(), ProcessError> {
+    let source = format!(
+        "testsrc2=size={}x{}:rate=10,fps=30",
+        clip.width, clip.height
+    );
+    supervisor::run(|| {
+        let mut command = ffmpeg::command()?;
+        command
+            .args(["-f", "lavfi", "-i", &source])
+            .args(["-frames:v", &clip.frames.to_string()])
+            .args(["-c:v", "libx264", "-preset", "ultrafast", "-crf", "18"])
+            .args(["-pix_fmt", "yuv420p", "-y"])
+            .arg(paths::ffmpeg_arg(output));
+        Ok(command)
+    })
+    .map(|_| ())
+    .map_err(|e| ProcessError::ffmpeg("Failed to generate the benchmark clip", e))
+}
+
+/// The luma of the first [`METRIC_FRAMES`] frames of `video`, decoded by
+/// way of `stream`.
+fn decode(video: &Path, stream: &Path) -> Result<Vec<GrayImage>, ProcessError> {
+    supervisor::run(|| {
+        let mut command = ffmpeg::command()?;
+        command
+            .arg("-i")
+            .arg(paths::ffmpeg_arg(video))
+            .args(["-frames:v", &METRIC_FRAMES.to_string()])
+            .args(["-pix_fmt", "yuv420p", "-f", "yuv4mpegpipe", "-y"])
+            .arg(paths::ffmpeg_arg(stream));
+        Ok(command)
+    })
+    .map_err(|e| ProcessError::ffmpeg("Failed to decode the benchmark clip", e))?;
+    let read_error = |e: std::io::Error| {
+        ProcessError::new(format!("Failed to read the benchmark frames: {}", e))
+    };
+    let mut reader = y4m::Y4mReader::new(BufReader::new(File::open(stream).map_err(read_error)?))
+        .map_err(read_error)?;
+    let mut frames = Vec::new();
+    while let Some(frame) = reader.next_frame().map_err(read_error)? {
+        frames.push(reader.luma(&frame));
+    }
+    Ok(frames)
+}
+
+/// Scores `pairs` pairs of consecutive `frames` under `metric`, cycling
+/// through them, in parallel as the analysis does.
+fn time_metric(
+    metric: Metric,
+    frames: &[GrayImage],
+    pairs: usize,
+) -> Result<Measurement, ProcessError> {
+    if frames.len() < 2 {
+        return Err(ProcessError::new(
+            "The benchmark clip decoded to too few frames",
+        ));
+    }
+    let started = Instant::now();
+    concurrency::thread_pool().install(|| {
+        (0..pairs).into_par_iter().try_for_each(|pair| {
+            let first = pair % (frames.len() - 1);
+            similarity::score(metric, &frames[first], &frames[first + 1])
+                .map(|_| ())
+                .map_err(|e| ProcessError::new(format!("{} failed: {}", metric.name(), e)))
+        })
+    })?;
+    Ok(measurement(
+        format!("metric {}", metric.name()),
+        pairs,
+        started,
+    ))
+}
+
+fn measurement(name: String, frames: usize, started: Instant) -> Measurement {
+    let elapsed_secs = started.elapsed().as_secs_f64();
+    Measurement {
+        name,
+        frames,
+        elapsed_secs,
+        frames_per_sec: frames as f64 / elapsed_secs.max(f64::EPSILON),
+    }
+}
@@ -0,0 +1,837 @@
+//! Headless front end to the dead frame removal engine, for servers and
+//! batch jobs. Options and their values match the desktop app's.
+
+use clap::{Args, Parser, Subcommand};
+use dead_frames_lib::benchmark::{self, BenchmarkClip};
+use dead_frames_lib::crop::CropMode;
+use dead_frames_lib::cutlist::CutListFormat;
+use dead_frames_lib::estimate::SizeEstimate;
+use dead_frames_lib::glitch::GlitchAction;
+use dead_frames_lib::mezzanine::{DnxhrProfile, ProResProfile};
+use dead_frames_lib::output::CollisionPolicy;
+use dead_frames_lib::processed::{self, ProcessedPolicy};
+use dead_frames_lib::representative::Representative;
+use dead_frames_lib::sections::Section;
+use dead_frames_lib::similarity::{ColorSpace, Metric};
+use dead_frames_lib::synthetic::Pattern;
+use dead_frames_lib::timelapse::Timelapse;
+use dead_frames_lib::video_fixer::{
+    self, Analysis, Deinterlace, FrameFormat, ProcessOptions, SequenceFormat, VideoCodec,
+};
+use dead_frames_lib::{analysis_cache, compare, presets, serve, settings, streams, watch};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+#[derive(Parser)]
+#[command(
+    name = "dfr-cli",
+    version,
+    about = "Remove dead frames from videos",
+    arg_required_else_help = true,
+    args_conflicts_with_subcommands = true
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// Take newline-delimited JSON-RPC requests on stdin and write responses
+    /// and job events to stdout.
+    #[arg(long)]
+    serve: bool,
+    /// Log ffmpeg invocations and frame decisions to stderr.
+    #[arg(short, long, global = true)]
+    verbose: bool,
+    /// Keep settings, presets and cached analyses in this directory rather
+    /// than the desktop app's.
+    #[arg(long, global = true, value_name = "DIR")]
+    config_dir: Option<PathBuf>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Score every frame and print its decision without writing a video.
+    Analyze {
+        input: PathBuf,
+        #[command(flatten)]
+        options: OptionArgs,
+    },
+    /// Remove dead frames and write the processed videos. An input may also
+    /// be a directory of numbered images, encoded at --fps (30 by default),
+    /// or an http(s) or smb URL.
+    Process {
+        #[arg(required = true)]
+        inputs: Vec<PathBuf>,
+        /// What to do with inputs whose output already exists: reprocess
+        /// or skip.
+        #[arg(long, value_parser = by_name::<ProcessedPolicy>, default_value = "reprocess")]
+        if_processed: ProcessedPolicy,
+        #[command(flatten)]
+        options: OptionArgs,
+    },
+    /// Encode a directory of frame_0001.png, frame_0002.png, ... into a video.
+    /// The numbers may have any width.
+    Stitch {
+        folder: PathBuf,
+        #[arg(short, long)]
+        output: PathBuf,
+        #[command(flatten)]
+        options: OptionArgs,
+    },
+    /// Render the original and processed videos side by side over a stretch
+    /// of the original, to check the result.
+    Compare {
+        original: PathBuf,
+        processed: PathBuf,
+        /// Where the stretch starts in the original, in seconds.
+        #[arg(long, default_value_t = 0.0)]
+        start: f64,
+        /// Length of the stretch in seconds.
+        #[arg(long, default_value_t = 5.0)]
+        duration: f64,
+        /// The clip to write; <processed>_compare.mp4 by default.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Process videos as they appear in a folder until interrupted.
+    Watch {
+        folder: PathBuf,
+        /// Process new files with this preset instead of the settings.
+        #[arg(long)]
+        preset: Option<String>,
+        /// Move originals to an `archive` subfolder once processed.
+        #[arg(long)]
+        archive: bool,
+        /// What to do with files whose output already exists: reprocess
+        /// or skip.
+        #[arg(long, value_parser = by_name::<ProcessedPolicy>, default_value = "reprocess")]
+        if_processed: ProcessedPolicy,
+    },
+    /// List the video streams of a file, for --video-stream.
+    Streams { input: PathBuf },
+    /// Print a JSON summary of what processing would remove.
+    Report {
+        input: PathBuf,
+        #[command(flatten)]
+        options: OptionArgs,
+    },
+    /// Time each metric and way of running the analysis on a synthetic
+    /// clip and print their throughput in frames per second.
+    Benchmark {
+        #[arg(long, default_value_t = 1280)]
+        width: u32,
+        #[arg(long, default_value_t = 720)]
+        height: u32,
+        /// Length of the clip in frames, at 30 fps.
+        #[arg(long, default_value_t = 300)]
+        frames: usize,
+    },
+    /// Generate a clip with a known pattern of unique, duplicated and black
+    /// frames, to check which of them the options remove, and print how
+    /// many the default settings remove.
+    Generate {
+        /// Segments like 10u,4d,6b: counts of unique frames, duplicates of
+        /// the frame before and black frames.
+        pattern: String,
+        #[arg(short, long)]
+        output: PathBuf,
+        #[arg(long, default_value_t = 320)]
+        width: u32,
+        #[arg(long, default_value_t = 240)]
+        height: u32,
+        #[arg(long, default_value_t = 30)]
+        fps: u32,
+        /// Add a sine tone.
+        #[arg(long)]
+        audio: bool,
+    },
+}
+
+/// Parses a value by its serialised name, so the CLI accepts exactly the
+/// names the app uses in its settings and presets.
+fn by_name<T: DeserializeOwned>(value: &str) -> Result<T, String> {
+    serde_json::from_value(serde_json::Value::String(value.to_string()))
+        .map_err(|_| format!("unknown value \"{}\"", value))
+}
+
+/// `png`, `webp`, `y4m`, or `jpeg` with an optional quality as `jpeg:85`.
+fn frame_format(value: &str) -> Result<FrameFormat, String> {
+    match value.split_once(':') {
+        Some(("jpeg", quality)) => quality
+            .parse()
+            .map(FrameFormat::Jpeg)
+            .map_err(|_| format!("invalid JPEG quality \"{}\"", quality)),
+        None if value == "jpeg" => Ok(FrameFormat::Jpeg(90)),
+        None => serde_json::from_value(serde_json::json!({ "kind": value }))
+            .map_err(|_| format!("unknown frame format \"{}\"", value)),
+        _ => Err(format!("unknown frame format \"{}\"", value)),
+    }
+}
+
+/// `START-END:THRESHOLD[:METRIC]` in seconds, where either override may be
+/// left empty, as in `0-5:0.9` or `30-90::ssim`.
+fn section(value: &str) -> Result<Section, String> {
+    let invalid = || format!("invalid section \"{}\"", value);
+    let mut parts = value.split(':');
+    let (start, end) = parts
+        .next()
+        .and_then(|range| range.split_once('-'))
+        .ok_or_else(invalid)?;
+    let threshold = match parts.next().unwrap_or_default() {
+        "" => None,
+        threshold => Some(threshold.parse().map_err(|_| invalid())?),
+    };
+    let metric = match parts.next().unwrap_or_default() {
+        "" => None,
+        metric => Some(by_name(metric)?),
+    };
+    if parts.next().is_some() || (threshold.is_none() && metric.is_none()) {
+        return Err(invalid());
+    }
+    Ok(Section {
+        start_secs: start.parse().map_err(|_| invalid())?,
+        end_secs: end.parse().map_err(|_| invalid())?,
+        threshold,
+        metric,
+    })
+}
+
+/// `luma`, or `yuv` or `rgb` with optional weights as `rgb:1,2,1`.
+fn color_space(value: &str) -> Result<ColorSpace, String> {
+    let invalid = || format!("invalid color space \"{}\"", value);
+    let (kind, weights) = match value.split_once(':') {
+        Some((kind, weights)) => {
+            let weights: Vec<f32> = weights
+                .split(',')
+                .map(|weight| weight.trim().parse().map_err(|_| invalid()))
+                .collect::<Result<_, _>>()?;
+            (kind, weights.try_into().map_err(|_| invalid())?)
+        }
+        None => (value, [1.0; 3]),
+    };
+    let color_space = match kind {
+        "luma" if weights == [1.0; 3] => ColorSpace::Luma,
+        "yuv" => ColorSpace::Yuv { weights },
+        "rgb" => ColorSpace::Rgb { weights },
+        _ => return Err(invalid()),
+    };
+    color_space.validate()?;
+    Ok(color_space)
+}
+
+#[derive(Args)]
+struct OptionArgs {
+    /// Start from a named preset; other options override it.
+    #[arg(long)]
+    preset: Option<String>,
+    /// Frames scoring above this against their successor are removed.
+    #[arg(long)]
+    threshold: Option<f32>,
+    /// Once a frame is removed, keep removing frames scoring above this
+    /// lower threshold, e.g. 0.93 with a threshold of 0.97.
+    #[arg(long)]
+    exit_threshold: Option<f32>,
+    /// ssim or mean-abs-diff, or learned in builds with the onnx feature.
+    #[arg(long, value_parser = by_name::<Metric>)]
+    metric: Option<Metric>,
+    /// Compare frames in luma, yuv or rgb, the latter two with optional
+    /// channel weights as yuv:4,1,1; equal weights by default.
+    #[arg(long, value_parser = color_space)]
+    color_space: Option<ColorSpace>,
+    /// Analyse a time range with its own threshold or metric, as
+    /// START-END:THRESHOLD[:METRIC] in seconds; may be repeated.
+    #[arg(long = "section", value_parser = section)]
+    sections: Vec<Section>,
+    /// Compare each frame with this many of the last kept frames, to catch
+    /// frames alternating between near-identical images.
+    #[arg(long, value_parser = clap::value_parser!(u16).range(1..))]
+    window: Option<u16>,
+    /// Compare only every this many frames first, then frame by frame around
+    /// the dead spans found; faster on mostly live footage, but misses dead
+    /// spans shorter than the step.
+    #[arg(long)]
+    analysis_step: Option<usize>,
+    /// Merge removed spans with fewer than this many kept frames between
+    /// them, so frozen sections are not broken up by single kept frames.
+    #[arg(long)]
+    merge_gap: Option<usize>,
+    /// first, last or sharpest: which frame of each run of alike frames is
+    /// kept; by default the last, or the first under --window.
+    #[arg(long, value_parser = by_name::<Representative>)]
+    keep: Option<Representative>,
+    /// Also remove blurred frames, whose variance of the Laplacian is below
+    /// this; sharp footage tends to score in the hundreds.
+    #[arg(long)]
+    blur_threshold: Option<f64>,
+    /// remove or replace: what to do with single frames unlike both their
+    /// neighbours, such as green frames from capture cards.
+    #[arg(long, value_parser = by_name::<GlitchAction>)]
+    glitches: Option<GlitchAction>,
+    /// Rhai script whose decide(frame) function has the last word on
+    /// whether each frame is removed; needs the scripting feature.
+    #[arg(long)]
+    script: Option<PathBuf>,
+    /// WebAssembly detector plugin run on every frame before the script;
+    /// may be repeated. Needs the plugins feature.
+    #[arg(long = "plugin")]
+    plugins: Vec<PathBuf>,
+    /// h264, h265, vp9, av1, ffv1, prores, dnxhr, or gif, webp and apng
+    /// for animations.
+    #[arg(long, value_parser = by_name::<VideoCodec>)]
+    codec: Option<VideoCodec>,
+    /// proxy, lt, standard, hq, 4444 or 4444xq.
+    #[arg(long, value_parser = by_name::<ProResProfile>)]
+    prores_profile: Option<ProResProfile>,
+    /// lb, sq, hq, hqx or 444.
+    #[arg(long, value_parser = by_name::<DnxhrProfile>)]
+    dnxhr_profile: Option<DnxhrProfile>,
+    /// Encode h264 or h265 in two passes at this bitrate in kbit/s, to hit
+    /// an output size.
+    #[arg(long)]
+    target_bitrate: Option<u32>,
+    /// Encode h264 or h265 in two passes at the bitrate that keeps the
+    /// output within this many MB, e.g. 25 for an upload limit.
+    #[arg(long)]
+    target_size: Option<f64>,
+    /// Intermediate frames: png, webp, jpeg[:quality] or y4m.
+    #[arg(long, value_parser = frame_format)]
+    frame_format: Option<FrameFormat>,
+    /// Write png, tiff or exr frames to a directory instead of a video.
+    #[arg(long, value_parser = by_name::<SequenceFormat>)]
+    image_sequence: Option<SequenceFormat>,
+    /// Copy untouched GOPs and re-encode only around cuts, keeping the
+    /// source's codec and container.
+    #[arg(long)]
+    smart_cut: bool,
+    /// Also write <output>.timestamps.json mapping output frames to source
+    /// times.
+    #[arg(long)]
+    timestamp_map: bool,
+    /// Compare the output with the source frames afterwards and report SSIM,
+    /// plus VMAF when ffmpeg has libvmaf.
+    #[arg(long)]
+    verify: bool,
+    /// Estimate the output size with a short test encode rather than the
+    /// codec's typical bitrate.
+    #[arg(long)]
+    test_encode: bool,
+    /// Keep removed frames so they can be restored from the app afterwards.
+    #[arg(long)]
+    keep_removed: bool,
+    /// Decode frames as a stream and compare a pair at a time, for sources
+    /// too large for the memory at hand.
+    #[arg(long)]
+    low_memory: bool,
+    /// Give the same output and removed frames on every run: no hardware
+    /// encoding or decoding, a fixed number of encoder threads and no
+    /// version strings or random IDs in the output.
+    #[arg(long)]
+    deterministic: bool,
+    /// Compare the frames again even when an earlier job on the same file
+    /// with the same options left its analysis.
+    #[arg(long)]
+    reanalyze: bool,
+    /// Encode at 8 bits even when the source has more.
+    #[arg(long = "force-8bit")]
+    force_8bit: bool,
+    /// auto, off, yadif or bwdif; auto deinterlaces sources flagged as
+    /// interlaced.
+    #[arg(long, value_parser = by_name::<Deinterlace>)]
+    deinterlace: Option<Deinterlace>,
+    /// off, compare or output: detect black bars and leave them out of the
+    /// comparison, or crop them from the output as well.
+    #[arg(long, value_parser = by_name::<CropMode>)]
+    crop: Option<CropMode>,
+    /// Process this video stream, counted from 0 among the video streams;
+    /// see the streams command.
+    #[arg(long)]
+    video_stream: Option<usize>,
+    /// Download http(s) and smb URL inputs before processing them rather
+    /// than reading them over the network on every pass.
+    #[arg(long)]
+    cache_remote: bool,
+    /// Encode h264, h265 and av1 with NVENC when ffmpeg has it.
+    #[arg(long)]
+    hardware_encode: bool,
+    /// Decode with cuda, d3d11va, videotoolbox or vaapi while extracting
+    /// frames, where ffmpeg has one.
+    #[arg(long)]
+    hardware_decode: bool,
+    /// Also copy every removed frame into this directory, named by its index
+    /// and time in the source.
+    #[arg(long)]
+    export_removed: Option<PathBuf>,
+    /// Write an edl, concat or csv cut list, or an fcpxml or premiere-markers
+    /// list of the dead sections, instead of encoding.
+    #[arg(long, value_parser = by_name::<CutListFormat>)]
+    cut_list: Option<CutListFormat>,
+    /// Output frame rate; the source's by default, 30 for image directories.
+    #[arg(long)]
+    fps: Option<f64>,
+    /// Play the kept frames at this frame rate and keep the source's audio,
+    /// its tempo changed to match.
+    #[arg(long)]
+    retime: Option<f64>,
+    /// Remove only frames the encoder repeated, for screen recordings.
+    #[arg(long)]
+    encoder_duplicates: bool,
+    /// Build a timelapse of the frames that change by over this percentage,
+    /// 1 by default, played at --fps or 30.
+    #[arg(long, num_args = 0..=1, default_missing_value = "1")]
+    timelapse: Option<f32>,
+    /// Directory for processed videos; next to the input by default.
+    #[arg(short = 'd', long)]
+    output_dir: Option<PathBuf>,
+    /// Output file name template, e.g. "{stem}_{date}.{ext}".
+    #[arg(long)]
+    template: Option<String>,
+    /// overwrite, auto-increment, skip or error.
+    #[arg(long, value_parser = by_name::<CollisionPolicy>)]
+    collision: Option<CollisionPolicy>,
+    /// Comparison and ffmpeg threads; all cores by default.
+    #[arg(long)]
+    threads: Option<usize>,
+}
+
+impl OptionArgs {
+    fn into_options(self) -> Result<ProcessOptions, String> {
+        let mut options = match &self.preset {
+            Some(name) => presets::get(name)
+                .ok_or_else(|| format!("No preset named \"{}\"", name))?
+                .options(),
+            None => ProcessOptions::default(),
+        };
+        if let Some(threshold) = self.threshold {
+            options.threshold = threshold;
+        }
+        if self.exit_threshold.is_some() {
+            options.exit_threshold = self.exit_threshold;
+        }
+        if let Some(metric) = self.metric {
+            options.metric = metric;
+        }
+        if let Some(color_space) = self.color_space {
+            options.color_space = color_space;
+        }
+        if !self.sections.is_empty() {
+            options.sections = self.sections;
+        }
+        if let Some(window) = self.window {
+            options.comparison_window = window.into();
+        }
+        if self.analysis_step.is_some() {
+            options.analysis_step = self.analysis_step;
+        }
+        if let Some(gap) = self.merge_gap {
+            options.merge_gap = gap;
+        }
+        if self.keep.is_some() {
+            options.representative = self.keep;
+        }
+        if self.blur_threshold.is_some() {
+            options.blur_threshold = self.blur_threshold;
+        }
+        if self.glitches.is_some() {
+            options.glitches = self.glitches;
+        }
+        if self.script.is_some() {
+            options.script = self.script;
+        }
+        options.plugins.extend(self.plugins);
+        if let Some(codec) = self.codec {
+            options.codec = codec;
+        }
+        if let Some(profile) = self.prores_profile {
+            options.mezzanine.prores = profile;
+        }
+        if let Some(profile) = self.dnxhr_profile {
+            options.mezzanine.dnxhr = profile;
+        }
+        if self.target_bitrate.is_some() {
+            options.target_bitrate = self.target_bitrate;
+        }
+        if self.target_size.is_some() {
+            options.target_size_mb = self.target_size;
+        }
+        if let Some(frame_format) = self.frame_format {
+            options.frame_format = frame_format;
+        }
+        if self.image_sequence.is_some() {
+            options.image_sequence = self.image_sequence;
+        }
+        if self.smart_cut {
+            options.smart_cut = true;
+        }
+        if self.timestamp_map {
+            options.timestamp_map = true;
+        }
+        if self.verify {
+            options.verify_quality = true;
+        }
+        if self.test_encode {
+            options.test_encode = true;
+        }
+        if self.keep_removed {
+            options.keep_removed = true;
+        }
+        if self.low_memory {
+            options.low_memory = true;
+        }
+        if self.deterministic {
+            options.deterministic = true;
+        }
+        if self.reanalyze {
+            options.reuse_analysis = false;
+        }
+        if self.force_8bit {
+            options.force_8bit = true;
+        }
+        if let Some(deinterlace) = self.deinterlace {
+            options.deinterlace = deinterlace;
+        }
+        if let Some(crop) = self.crop {
+            options.crop = crop;
+        }
+        if self.video_stream.is_some() {
+            options.video_stream_index = self.video_stream;
+        }
+        if self.cache_remote {
+            options.cache_remote = true;
+        }
+        if self.hardware_encode {
+            options.hardware_encode = true;
+        }
+        if self.hardware_decode {
+            options.hardware_decode = true;
+        }
+        if self.export_removed.is_some() {
+            options.export_removed = self.export_removed;
+        }
+        if self.cut_list.is_some() {
+            options.cut_list = self.cut_list;
+        }
+        if self.fps.is_some() {
+            options.framerate = self.fps;
+        }
+        if self.retime.is_some() {
+            options.retime = self.retime;
+        }
+        if self.encoder_duplicates {
+            options.encoder_duplicates = true;
+        }
+        if let Some(min_change_percent) = self.timelapse {
+            let defaults = Timelapse::default();
+            options.timelapse = Some(Timelapse {
+                min_change_percent,
+                fps: self.fps.unwrap_or(defaults.fps),
+            });
+        }
+        if self.output_dir.is_some() {
+            options.output.dir = self.output_dir;
+        }
+        if let Some(template) = self.template {
+            options.output.template = template;
+        }
+        if let Some(collision) = self.collision {
+            options.output.collision = collision;
+        }
+        if self.threads.is_some() {
+            // only for this run; the settings file keeps the app's own value
+            settings::apply(|s| s.threads = self.threads);
+        }
+        Ok(options)
+    }
+}
+
+#[derive(Serialize)]
+struct Report {
+    input: PathBuf,
+    frames_total: usize,
+    frames_removed: usize,
+    score_min: Option<f32>,
+    score_mean: Option<f32>,
+    score_max: Option<f32>,
+    /// Inclusive frame index ranges that are removed.
+    removed_ranges: Vec<(usize, usize)>,
+    estimated_size: Option<SizeEstimate>,
+}
+
+impl Report {
+    fn new(input: PathBuf, analysis: &Analysis) -> Report {
+        let scores = &analysis.scores;
+        let mut removed_ranges: Vec<(usize, usize)> = Vec::new();
+        for (index, _) in analysis.removed.iter().enumerate().filter(|(_, &d)| d) {
+            match removed_ranges.last_mut() {
+                Some((_, end)) if *end + 1 == index => *end = index,
+                _ => removed_ranges.push((index, index)),
+            }
+        }
+        Report {
+            input,
+            frames_total: analysis.frames_total(),
+            frames_removed: analysis.frames_removed(),
+            score_min: scores.iter().copied().reduce(f32::min),
+            score_mean: (!scores.is_empty())
+                .then(|| scores.iter().sum::<f32>() / scores.len() as f32),
+            score_max: scores.iter().copied().reduce(f32::max),
+            removed_ranges,
+            estimated_size: analysis.estimated_size,
+        }
+    }
+}
+
+async fn run(command: Command) -> Result<(), String> {
+    match command {
+        Command::Analyze { input, options } => {
+            let options = options.into_options()?;
+            let analysis = video_fixer::analyze_video(&input, &options)
+                .await
+                .map_err(|e| e.to_string())?;
+            for (index, &dead) in analysis.removed.iter().enumerate() {
+                let score = analysis
+                    .score(index)
+                    .map_or("-".to_string(), |s| format!("{:.4}", s));
+                println!(
+                    "{}\t{}\t{}",
+                    index,
+                    score,
+                    if dead { "remove" } else { "keep" }
+                );
+            }
+        }
+        Command::Process {
+            inputs,
+            if_processed,
+            options,
+        } => {
+            let options = options.into_options()?;
+            let mut failed = 0;
+            for input in &inputs {
+                if let Some(processed) = processed::skip(input, &options, if_processed) {
+                    println!(
+                        "{}: skipped, already processed into {}",
+                        input.display(),
+                        processed.output().display()
+                    );
+                    continue;
+                }
+                match video_fixer::process_video(input, &options).await {
+                    Ok(summary) if summary.skipped => {
+                        println!("{}: skipped, {} exists", input.display(), summary.output)
+                    }
+                    Ok(summary) => {
+                        println!(
+                            "{}: removed {} of {} frames -> {}",
+                            input.display(),
+                            summary.frames_removed,
+                            summary.frames_total,
+                            summary.output
+                        );
+                        if let Some(speed_up) = summary.speed_up {
+                            println!("    {:.1}x speed-up", speed_up);
+                        }
+                        if summary.copied {
+                            println!("    nothing removed, video copied without re-encoding");
+                        }
+                        if let Some(quality) = &summary.quality {
+                            match quality.vmaf {
+                                Some(vmaf) => {
+                                    println!("    SSIM {:.4}, VMAF {:.2}", quality.ssim, vmaf)
+                                }
+                                None => println!("    SSIM {:.4}", quality.ssim),
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        failed += 1;
+                        eprintln!("{}: {}", input.display(), e);
+                        for line in &e.ffmpeg_stderr {
+                            eprintln!("    {}", line);
+                        }
+                    }
+                }
+            }
+            if failed > 0 {
+                return Err(format!("{} of {} inputs failed", failed, inputs.len()));
+            }
+        }
+        Command::Stitch {
+            folder,
+            output,
+            options,
+        } => {
+            let options = options.into_options()?;
+            video_fixer::stitch_frames(&folder, &options, &output).map_err(|e| e.to_string())?;
+        }
+        Command::Compare {
+            original,
+            processed,
+            start,
+            duration,
+            output,
+        } => {
+            let output = output.unwrap_or_else(|| compare::default_output(&processed));
+            compare::render(&original, &processed, start, duration, &output)
+                .map_err(|e| e.to_string())?;
+            println!("{}", output.display());
+        }
+        Command::Watch {
+            folder,
+            preset,
+            archive,
+            if_processed,
+        } => {
+            let watch_settings = watch::WatchSettings {
+                enabled: true,
+                folder: Some(folder),
+                preset,
+                archive_originals: archive,
+                if_processed,
+            };
+            let watch = watch::WatchFolder::default();
+            watch.start(&watch_settings, |event| match event {
+                watch::WatchEvent::Finished { input, summary } => println!(
+                    "{}: removed {} of {} frames -> {}",
+                    input.display(),
+                    summary.frames_removed,
+                    summary.frames_total,
+                    summary.output
+                ),
+                watch::WatchEvent::Skipped { input, processed } => println!(
+                    "{}: skipped, already processed into {}",
+                    input.display(),
+                    processed.output().display()
+                ),
+                watch::WatchEvent::Failed { input, error } => {
+                    eprintln!("{}: {}", input.display(), error)
+                }
+                _ => {}
+            })?;
+            std::future::pending::<()>().await;
+        }
+        Command::Streams { input } => {
+            for stream in streams::list(&input).map_err(|e| e.to_string())? {
+                println!(
+                    "{}\t{}\t{}x{}\t{}{}{}",
+                    stream.index,
+                    stream.codec,
+                    stream.width,
+                    stream.height,
+                    stream
+                        .fps
+                        .map_or("-".to_string(), |fps| format!("{} fps", fps)),
+                    if stream.attached_pic { "\tpicture" } else { "" },
+                    stream
+                        .title
+                        .map(|title| format!("\t{}", title))
+                        .unwrap_or_default()
+                );
+            }
+        }
+        Command::Report { input, options } => {
+            let options = options.into_options()?;
+            let analysis = video_fixer::analyze_video(&input, &options)
+                .await
+                .map_err(|e| e.to_string())?;
+            let report = Report::new(input, &analysis);
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&report).map_err(|e| e.to_string())?
+            );
+        }
+        Command::Benchmark {
+            width,
+            height,
+            frames,
+        } => {
+            let clip = BenchmarkClip {
+                width,
+                height,
+                frames,
+            };
+            benchmark::run(clip, |measurement| {
+                println!(
+                    "{}\t{:.1} frames/s",
+                    measurement.name, measurement.frames_per_sec
+                )
+            })
+            .map_err(|e| e.to_string())?;
+        }
+        Command::Generate {
+            pattern,
+            output,
+            width,
+            height,
+            fps,
+            audio,
+        } => {
+            let pattern = Pattern {
+                width,
+                height,
+                fps,
+                audio,
+                ..Pattern::parse(&pattern)?
+            };
+            pattern.generate(&output).map_err(|e| e.to_string())?;
+            println!(
+                "{} frames, {} removed at the default settings",
+                pattern.frames(),
+                pattern.expected_removed_count()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// The desktop app's identifier in tauri.conf.json, which names its config
+/// and data directories.
+const APP_IDENTIFIER: &str = "com.dead-frames.app";
+
+/// Opens the settings, presets and analysis cache the desktop app uses, or
+/// the ones in `config_dir` when it is given.
+fn init_stores(config_dir: Option<PathBuf>) {
+    let (config_dir, data_dir) = match config_dir {
+        Some(dir) => (Some(dir.clone()), Some(dir)),
+        None => (
+            dirs::config_dir().map(|dir| dir.join(APP_IDENTIFIER)),
+            dirs::data_dir().map(|dir| dir.join(APP_IDENTIFIER)),
+        ),
+    };
+    match config_dir {
+        Some(dir) => {
+            settings::init(dir.join("settings.json"));
+            presets::init(dir.join("presets.json"));
+        }
+        None => tracing::warn!("No config directory, using the default settings"),
+    }
+    match data_dir {
+        Some(dir) => analysis_cache::init(&dir),
+        None => tracing::warn!("No data directory, analyses will not be cached"),
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .with_max_level(if cli.verbose {
+            tracing::Level::DEBUG
+        } else {
+            tracing::Level::WARN
+        })
+        .init();
+    init_stores(cli.config_dir);
+
+    let Some(command) = cli.command else {
+        serve::serve(std::io::stdin().lock(), std::io::stdout());
+        return ExitCode::SUCCESS;
+    };
+    match run(command).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("dfr-cli: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
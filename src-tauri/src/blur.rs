@@ -0,0 +1,38 @@
+//! Blurred frames: out-of-focus shots and motion blur, which can be removed
+//! along with the dead frames. A frame's sharpness is the variance of the
+//! Laplacian of its luma, high where edges are crisp and falling as blur
+//! spreads them out. What counts as blurred depends on the footage; sharp
+//! detailed video tends to score in the hundreds, flat animation far less.
+
+use image::GrayImage;
+
+/// How sharp `luma` is: the variance of its Laplacian.
+pub fn sharpness(luma: &GrayImage) -> f64 {
+    let (width, height) = luma.dimensions();
+    if width < 3 || height < 3 {
+        return 0.0;
+    }
+    let pixel = |x: u32, y: u32| luma.get_pixel(x, y)[0] as f64;
+    let (mut sum, mut sum_sq) = (0.0, 0.0);
+    for y in 1..height - 1 {
+        for x in 1..width - 1 {
+            let laplacian = pixel(x - 1, y) + pixel(x + 1, y) + pixel(x, y - 1) + pixel(x, y + 1)
+                - 4.0 * pixel(x, y);
+            sum += laplacian;
+            sum_sq += laplacian * laplacian;
+        }
+    }
+    let count = ((width - 2) * (height - 2)) as f64;
+    let mean = sum / count;
+    sum_sq / count - mean * mean
+}
+
+pub fn validate(threshold: f64) -> Result<(), String> {
+    if !threshold.is_finite() || threshold <= 0.0 {
+        return Err(format!(
+            "The sharpness frames are removed below must be positive, got {}",
+            threshold
+        ));
+    }
+    Ok(())
+}
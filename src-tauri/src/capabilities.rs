@@ -0,0 +1,136 @@
+//! Which codecs, hardware accelerators and filters the ffmpeg in use offers,
+//! so the GUI can grey out options the binary cannot handle.
+
+use crate::ffmpeg;
+use crate::supervisor;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct FfmpegCapabilities {
+    pub video_encoders: Vec<String>,
+    pub audio_encoders: Vec<String>,
+    pub hwaccels: Vec<String>,
+    pub filters: Vec<String>,
+    /// Protocols ffmpeg can read from, like `https` or `smb`.
+    pub input_protocols: Vec<String>,
+}
+
+impl FfmpegCapabilities {
+    pub fn has_encoder(&self, name: &str) -> bool {
+        self.video_encoders
+            .iter()
+            .chain(&self.audio_encoders)
+            .any(|e| e == name)
+    }
+
+    pub fn has_hwaccel(&self, name: &str) -> bool {
+        self.hwaccels.iter().any(|h| h == name)
+    }
+
+    pub fn has_filter(&self, name: &str) -> bool {
+        self.filters.iter().any(|f| f == name)
+    }
+
+    pub fn has_input_protocol(&self, name: &str) -> bool {
+        self.input_protocols.iter().any(|p| p == name)
+    }
+}
+
+/// Capabilities keyed by the binary they were probed from, so switching
+/// binaries triggers a fresh probe.
+static CACHE: Lazy<Mutex<Option<(PathBuf, FfmpegCapabilities)>>> = Lazy::new(|| Mutex::new(None));
+
+fn run(flag: &str) -> Result<String, String> {
+    let output = supervisor::run(|| {
+        let mut command = ffmpeg::command()?;
+        command.arg(flag);
+        Ok(command)
+    })
+    .map_err(|e| format!("Failed to run ffmpeg {}: {}", flag, e))?;
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Encoder lines look like ` V....D libx264   libx264 H.264 ...` and follow
+/// a ` ------` separator.
+fn parse_encoders(output: &str, capabilities: &mut FfmpegCapabilities) {
+    let entries = output
+        .lines()
+        .skip_while(|line| !line.trim_start().starts_with("---"))
+        .skip(1);
+    for line in entries {
+        let mut fields = line.split_whitespace();
+        let (Some(flags), Some(name)) = (fields.next(), fields.next()) else {
+            continue;
+        };
+        match flags.chars().next() {
+            Some('V') => capabilities.video_encoders.push(name.to_string()),
+            Some('A') => capabilities.audio_encoders.push(name.to_string()),
+            _ => {}
+        }
+    }
+}
+
+/// One accelerator per line after the `Hardware acceleration methods:` header.
+fn parse_hwaccels(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .skip(1)
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Filter lines look like ` TSC scale   V->V   Scale the input video size`;
+/// the `A->A` style pad column tells them apart from the legend.
+fn parse_filters(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields.as_slice() {
+                [_, name, io, ..] if io.contains("->") => Some(name.to_string()),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// One protocol per line between the `Input:` and `Output:` headers.
+fn parse_input_protocols(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .map(str::trim)
+        .skip_while(|line| *line != "Input:")
+        .skip(1)
+        .take_while(|line| *line != "Output:")
+        .filter(|line| !line.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+fn probe() -> Result<FfmpegCapabilities, String> {
+    let mut capabilities = FfmpegCapabilities::default();
+    parse_encoders(&run("-encoders")?, &mut capabilities);
+    capabilities.hwaccels = parse_hwaccels(&run("-hwaccels")?);
+    capabilities.filters = parse_filters(&run("-filters")?);
+    capabilities.input_protocols = parse_input_protocols(&run("-protocols")?);
+    Ok(capabilities)
+}
+
+/// Probes the current ffmpeg once and returns the cached result afterwards.
+pub fn get_ffmpeg_capabilities() -> Result<FfmpegCapabilities, String> {
+    let path = ffmpeg::get_ffmpeg_info()?.path;
+    let mut cache = CACHE.lock().unwrap();
+    match cache.as_ref() {
+        Some((cached_path, capabilities)) if *cached_path == path => Ok(capabilities.clone()),
+        _ => {
+            let capabilities = probe()?;
+            *cache = Some((path, capabilities.clone()));
+            Ok(capabilities)
+        }
+    }
+}
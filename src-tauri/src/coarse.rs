@@ -0,0 +1,51 @@
+//! Coarse-to-fine analysis, for long recordings that are mostly live: only
+//! every `step`th frame is compared with the next one sampled at first, and
+//! frames are compared with their successors only where the samples turn up
+//! a dead span or leave the answer open.
+//!
+//! The frames between two samples found alike are all taken to be dead, and
+//! those between two samples found different to be live, unless a dead span
+//! is next to them, where its exact start or end is found frame by frame. A
+//! dead span shorter than `step` frames that lies between two samples found
+//! different goes unnoticed.
+
+/// Fewest frames between samples; every frame is compared at 1 anyway.
+pub const MIN_STEP: usize = 2;
+
+pub fn validate(step: usize) -> Result<(), String> {
+    if step < MIN_STEP {
+        return Err(format!(
+            "Frames sampled for a first analysis must be at least {} apart, got {}",
+            MIN_STEP, step
+        ));
+    }
+    Ok(())
+}
+
+/// The frames of `frames` sampled every `step` frames.
+pub fn samples(frames: usize, step: usize) -> Vec<usize> {
+    (0..frames).step_by(step.max(1)).collect()
+}
+
+/// The score of every frame against its successor that the samples settle,
+/// and `None` for the frames still to be compared. `gaps` holds, for each
+/// pair of consecutive samples, their score and whether the frames between
+/// them are dead, live or, as `None`, not clearly either.
+pub fn known_scores(frames: usize, step: usize, gaps: &[(f32, Option<bool>)]) -> Vec<Option<f32>> {
+    let mut known = vec![None; frames.saturating_sub(1)];
+    let dead = |gap: usize| gaps.get(gap).is_some_and(|&(_, dead)| dead == Some(true));
+    for (gap, &(score, dead_here)) in gaps.iter().enumerate() {
+        let settled = match dead_here {
+            Some(true) => true,
+            // the frames next to a dead span hold its start or end
+            Some(false) => !dead(gap + 1) && !gap.checked_sub(1).is_some_and(dead),
+            None => false,
+        };
+        if settled {
+            let start = gap * step;
+            let end = (start + step).min(known.len());
+            known[start..end].fill(Some(score));
+        }
+    }
+    known
+}
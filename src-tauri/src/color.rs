@@ -0,0 +1,244 @@
+//! The source's colour signalling: range, primaries, transfer
+//! characteristics and matrix, plus the mastering display and content light
+//! levels of HDR10. Extracted frames carry none of it, so it is read from
+//! the source and handed to the encoder; without it HDR output plays back
+//! washed out. The range and matrix also decide how frames are converted
+//! between YUV and RGB.
+
+use serde::{Deserialize, Serialize};
+
+/// A CIE 1931 chromaticity.
+pub type Chromaticity = (f64, f64);
+
+/// The display an HDR10 source was graded on.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MasteringDisplay {
+    pub red: Chromaticity,
+    pub green: Chromaticity,
+    pub blue: Chromaticity,
+    pub white_point: Chromaticity,
+    /// Luminance in cd/m².
+    pub min_luminance: f64,
+    pub max_luminance: f64,
+}
+
+/// Brightest pixel and brightest frame average of an HDR10 source, in
+/// cd/m².
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ContentLight {
+    pub max_cll: u32,
+    pub max_fall: u32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ColorInfo {
+    /// ffmpeg's names, e.g. `tv`, `bt2020`, `smpte2084` and `bt2020nc`;
+    /// `None` where the source leaves them unspecified.
+    pub range: Option<String>,
+    pub primaries: Option<String>,
+    pub transfer: Option<String>,
+    pub matrix: Option<String>,
+    pub mastering_display: Option<MasteringDisplay>,
+    pub content_light: Option<ContentLight>,
+}
+
+impl ColorInfo {
+    /// Whether the transfer is PQ (HDR10) or HLG.
+    pub fn is_hdr(&self) -> bool {
+        matches!(self.transfer.as_deref(), Some("smpte2084" | "arib-std-b67"))
+    }
+
+    /// ffmpeg output options tagging the encode of `encoder` with this
+    /// signalling. Only libx265 takes the HDR10 metadata itself.
+    pub fn encoder_args(&self, encoder: &str) -> Vec<String> {
+        let mut args = Vec::new();
+        for (option, value) in [
+            ("-color_range", &self.range),
+            ("-color_primaries", &self.primaries),
+            ("-color_trc", &self.transfer),
+            ("-colorspace", &self.matrix),
+        ] {
+            if let Some(value) = value {
+                args.extend([option.to_string(), value.clone()]);
+            }
+        }
+        if encoder == "libx265" {
+            let mut params = Vec::new();
+            if let Some(display) = &self.mastering_display {
+                // chromaticities in units of 0.00002, luminance of 0.0001
+                let point = |(x, y): Chromaticity| {
+                    format!("({},{})", (x * 50000.0).round(), (y * 50000.0).round())
+                };
+                params.push(format!(
+                    "master-display=G{}B{}R{}WP{}L({},{})",
+                    point(display.green),
+                    point(display.blue),
+                    point(display.red),
+                    point(display.white_point),
+                    (display.max_luminance * 10000.0).round(),
+                    (display.min_luminance * 10000.0).round()
+                ));
+            }
+            if let Some(light) = &self.content_light {
+                params.push(format!("max-cll={},{}", light.max_cll, light.max_fall));
+            }
+            if !params.is_empty() {
+                params.insert(0, "hdr10=1".to_string());
+                args.extend(["-x265-params".to_string(), params.join(":")]);
+            }
+        }
+        args
+    }
+
+    /// The matrix as the `scale` filter names it.
+    fn scale_matrix(&self) -> Option<&'static str> {
+        Some(match self.matrix.as_deref()? {
+            "bt709" => "bt709",
+            "bt2020nc" | "bt2020c" => "bt2020",
+            "smpte170m" | "bt470bg" => "bt601",
+            "smpte240m" => "smpte240m",
+            "fcc" => "fcc",
+            _ => return None,
+        })
+    }
+
+    /// A `scale` filter converting with this matrix and range from YUV when
+    /// `decode` is set, or to YUV otherwise. ffmpeg assumes BT.601 and
+    /// limited range where it is not told.
+    fn scale_filter(&self, decode: bool) -> Option<String> {
+        let side = if decode { "in" } else { "out" };
+        let mut options = Vec::new();
+        if let Some(matrix) = self.scale_matrix() {
+            options.push(format!("{}_color_matrix={}", side, matrix));
+        }
+        if let Some(range) = &self.range {
+            options.push(format!("{}_range={}", side, range));
+        }
+        (!options.is_empty()).then(|| format!("scale={}", options.join(":")))
+    }
+
+    /// A `scale` filter keeping the source's range in a conversion to
+    /// another YUV format, which ffmpeg would squeeze into limited range.
+    pub fn range_filter(&self) -> Option<String> {
+        let range = self.range.as_deref()?;
+        Some(format!("scale=in_range={0}:out_range={0}", range))
+    }
+
+    /// The filter turning the source's YUV frames into RGB images.
+    pub fn decode_filter(&self) -> Option<String> {
+        self.scale_filter(true)
+    }
+
+    /// The filter turning RGB images back into the source's YUV.
+    pub fn encode_filter(&self) -> Option<String> {
+        self.scale_filter(false)
+    }
+}
+
+/// The numbers following `label` up to the next closing parenthesis, as in
+/// `wp(0.3127, 0.3290)`.
+fn numbers_after(line: &str, label: &str) -> Vec<f64> {
+    let Some(start) = line.find(label) else {
+        return Vec::new();
+    };
+    let rest = &line[start + label.len()..];
+    rest[..rest.find(')').unwrap_or(rest.len())]
+        .split([',', ' '])
+        .filter_map(|number| number.parse().ok())
+        .collect()
+}
+
+/// The value following `key` up to the next space or comma, as in
+/// `MaxCLL=1000,`.
+fn value_after<'a>(line: &'a str, key: &str) -> Option<&'a str> {
+    let start = line.find(key)? + key.len();
+    line[start..].split([' ', ',']).next()
+}
+
+fn parse_mastering_display(line: &str) -> Option<MasteringDisplay> {
+    let point = |label: &str| match numbers_after(line, label)[..] {
+        [x, y] => Some((x, y)),
+        _ => None,
+    };
+    Some(MasteringDisplay {
+        red: point(" r(")?,
+        green: point(" g(")?,
+        blue: point(" b(")?,
+        white_point: point(" wp(")?,
+        min_luminance: value_after(line, "min_luminance=")?.parse().ok()?,
+        max_luminance: value_after(line, "max_luminance=")?.parse().ok()?,
+    })
+}
+
+fn parse_content_light(line: &str) -> Option<ContentLight> {
+    Some(ContentLight {
+        max_cll: value_after(line, "MaxCLL=")?.parse().ok()?,
+        max_fall: value_after(line, "MaxFALL=")?.parse().ok()?,
+    })
+}
+
+/// The colour signalling of the first frame in the output of ffmpeg's
+/// `showinfo` filter, or `None` when the source specifies none of it.
+pub fn reported(stderr: &str) -> Option<ColorInfo> {
+    let mut color = ColorInfo::default();
+    let specified = |value: Option<&str>| {
+        value
+            .filter(|value| *value != "unknown" && *value != "reserved")
+            .map(str::to_string)
+    };
+    let mut tagged = false;
+    for line in stderr.lines() {
+        if line.contains("color_primaries:") && !tagged {
+            tagged = true;
+            color.range = specified(value_after(line, "color_range:"));
+            color.primaries = specified(value_after(line, "color_primaries:"));
+            color.transfer = specified(value_after(line, "color_trc:"));
+            color.matrix = specified(value_after(line, "color_space:"));
+        } else if line.contains("Mastering display metadata") {
+            color.mastering_display = color.mastering_display.or(parse_mastering_display(line));
+        } else if line.contains("Content light level metadata") {
+            color.content_light = color.content_light.or(parse_content_light(line));
+        }
+    }
+    (color != ColorInfo::default()).then_some(color)
+}
+
+/// The signalling in the stream line ffmpeg reported for a video stream,
+/// from the parentheses after its pixel format, as in
+/// `yuv420p10le(tv, bt2020nc/bt2020/smpte2084, progressive)`: the range,
+/// then the matrix, primaries and transfer, or one name where all three
+/// agree. HDR10 metadata is not on the line. `None` where nothing is
+/// specified.
+pub fn reported_in_stream(line: &str) -> Option<ColorInfo> {
+    let properties = line
+        .split("Video: ")
+        .nth(1)?
+        .split_once(", ")?
+        .1
+        .split_once('(')?
+        .1
+        .split_once(')')?
+        .0;
+    let specified = |value: &str| (value != "unknown").then(|| value.to_string());
+    let mut color = ColorInfo::default();
+    for property in properties.split(", ") {
+        match property.split('/').collect::<Vec<_>>()[..] {
+            ["tv" | "pc"] => color.range = Some(property.to_string()),
+            [matrix, primaries, transfer] => {
+                color.matrix = specified(matrix);
+                color.primaries = specified(primaries);
+                color.transfer = specified(transfer);
+            }
+            ["progressive"] => {}
+            [field_order] if field_order.contains(" first") => {}
+            [all] => {
+                color.matrix = specified(all);
+                color.primaries = specified(all);
+                color.transfer = specified(all);
+            }
+            _ => {}
+        }
+    }
+    (color != ColorInfo::default()).then_some(color)
+}
@@ -0,0 +1,84 @@
+//! Comparators a library user brings: anything that scores a pair of frames
+//! can be registered under a name and then used wherever a
+//! [`Metric`] is, through [`Metric::Custom`], in options, sections and
+//! presets alike.
+//!
+//! ```no_run
+//! use dead_frames_lib::comparator::{self, FrameComparator};
+//! use dead_frames_lib::VideoFixer;
+//! use image::GrayImage;
+//!
+//! /// The share of pixels that did not change at all.
+//! struct Unchanged;
+//!
+//! impl FrameComparator for Unchanged {
+//!     fn score(&self, prev: &GrayImage, next: &GrayImage) -> f32 {
+//!         let same = prev.pixels().zip(next.pixels()).filter(|(a, b)| a == b).count();
+//!         same as f32 / prev.len() as f32
+//!     }
+//! }
+//!
+//! let metric = comparator::register("unchanged", Unchanged).unwrap();
+//! let summary = VideoFixer::new("recording.mp4").metric(metric).run()?;
+//! # Ok::<(), dead_frames_lib::error::ProcessError>(())
+//! ```
+
+use image::GrayImage;
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use crate::similarity::Metric;
+
+/// Scores how alike two equally sized luma images are: 1.0 when identical,
+/// falling towards 0.0 as they differ, so the detection thresholds mean the
+/// same as for the built-in metrics. Frames are scored in parallel.
+pub trait FrameComparator: Send + Sync {
+    fn score(&self, prev: &GrayImage, next: &GrayImage) -> f32;
+}
+
+impl<F> FrameComparator for F
+where
+    F: Fn(&GrayImage, &GrayImage) -> f32 + Send + Sync,
+{
+    fn score(&self, prev: &GrayImage, next: &GrayImage) -> f32 {
+        self(prev, next)
+    }
+}
+
+/// Registered comparators. Both names and comparators live as long as the
+/// process, so a [`Metric`] naming one stays `Copy`.
+static REGISTRY: Lazy<RwLock<HashMap<&'static str, &'static dyn FrameComparator>>> =
+    Lazy::new(Default::default);
+
+/// Registers `comparator` as `name`, replacing one registered before under
+/// the same name, and returns the metric that selects it. The names of the
+/// built-in metrics are taken.
+pub fn register(name: &str, comparator: impl FrameComparator + 'static) -> Result<Metric, String> {
+    if name.is_empty() || Metric::builtin(name).is_some() {
+        return Err(format!("\"{}\" cannot name a comparator", name));
+    }
+    let mut registry = REGISTRY.write().unwrap_or_else(|e| e.into_inner());
+    let name = match registry.get_key_value(name) {
+        Some((&name, _)) => name,
+        None => Box::leak(name.to_string().into_boxed_str()),
+    };
+    registry.insert(name, Box::leak(Box::new(comparator)));
+    Ok(Metric::Custom(name))
+}
+
+/// The comparator registered as `name`, with the name as registered.
+pub fn get(name: &str) -> Option<(&'static str, &'static dyn FrameComparator)> {
+    let registry = REGISTRY.read().unwrap_or_else(|e| e.into_inner());
+    registry
+        .get_key_value(name)
+        .map(|(&name, &comparator)| (name, comparator))
+}
+
+/// Names of the registered comparators, sorted.
+pub fn names() -> Vec<&'static str> {
+    let registry = REGISTRY.read().unwrap_or_else(|e| e.into_inner());
+    let mut names: Vec<_> = registry.keys().copied().collect();
+    names.sort_unstable();
+    names
+}
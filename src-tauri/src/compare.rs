@@ -0,0 +1,109 @@
+//! Before/after clips: a stretch of the original and the same stretch of
+//! the processed video side by side, for checking the result before the
+//! original is deleted.
+
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::ProcessError;
+use crate::ffmpeg;
+use crate::paths;
+use crate::supervisor;
+
+/// Height both sides are scaled to.
+const HEIGHT: u32 = 720;
+
+#[derive(Deserialize)]
+struct TimestampMap {
+    output_fps: f64,
+    frames: Vec<MappedFrame>,
+}
+
+#[derive(Deserialize)]
+struct MappedFrame {
+    time: f64,
+    source_time: f64,
+}
+
+/// Where `processed` shows the original's `start..start + duration`. The
+/// timestamp map written with `--timestamp-map` gives the exact span;
+/// without it the same times are used, which drift after every cut.
+fn processed_span(processed: &Path, start: f64, duration: f64) -> (f64, f64) {
+    let map = fs::read(processed.with_extension("timestamps.json"))
+        .ok()
+        .and_then(|json| serde_json::from_slice::<TimestampMap>(&json).ok());
+    let Some(map) = map else {
+        return (start, duration);
+    };
+    let mut inside = map
+        .frames
+        .iter()
+        .filter(|frame| frame.source_time >= start && frame.source_time < start + duration);
+    let frame = 1.0 / map.output_fps;
+    // half a frame early, so the seek cannot skip the first frame
+    let span = |first: &MappedFrame, last: &MappedFrame| {
+        (
+            (first.time - frame / 2.0).max(0.0),
+            last.time - first.time + frame,
+        )
+    };
+    match (inside.next(), inside.next_back()) {
+        (Some(first), Some(last)) => span(first, last),
+        (Some(first), None) => span(first, first),
+        _ => (start, duration),
+    }
+}
+
+/// The default name for the clip: `<processed stem>_compare.mp4` next to it.
+pub fn default_output(processed: &Path) -> PathBuf {
+    let mut name = processed.file_stem().unwrap_or_default().to_owned();
+    name.push("_compare.mp4");
+    processed.with_file_name(name)
+}
+
+/// Renders `duration` seconds from `start` of `original` (left) next to the
+/// matching part of `processed` (right) into `output`. The processed side is
+/// usually shorter and holds its last frame until the original catches up.
+pub fn render(
+    original: &Path,
+    processed: &Path,
+    start: f64,
+    duration: f64,
+    output: &Path,
+) -> Result<(), ProcessError> {
+    if duration <= 0.0 {
+        return Err(ProcessError::new("The clip must be longer than zero"));
+    }
+    let (processed_start, processed_duration) = processed_span(processed, start, duration);
+
+    let filter = format!(
+        "[0:v]scale=-2:{h},setsar=1,setpts=PTS-STARTPTS[a];\
+         [1:v]scale=-2:{h},setsar=1,setpts=PTS-STARTPTS[b];\
+         [a][b]hstack=inputs=2",
+        h = HEIGHT
+    );
+    let result = supervisor::run(|| {
+        let mut command = ffmpeg::command()?;
+        command
+            .args(["-ss", &start.to_string(), "-t", &duration.to_string(), "-i"])
+            .arg(paths::ffmpeg_arg(original))
+            .args([
+                "-ss",
+                &processed_start.to_string(),
+                "-t",
+                &processed_duration.to_string(),
+                "-i",
+            ])
+            .arg(paths::ffmpeg_arg(processed))
+            .args(["-filter_complex", &filter])
+            .args(["-c:v", "libx264", "-preset", "fast", "-pix_fmt", "yuv420p"])
+            // the stacked stream has no frame rate of its own
+            .args(["-fps_mode", "vfr", "-an", "-y"])
+            .arg(paths::ffmpeg_arg(output));
+        Ok(command)
+    });
+    result
+        .map(|_| ())
+        .map_err(|e| ProcessError::ffmpeg("Failed to render comparison", e))
+}
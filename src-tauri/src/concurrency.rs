@@ -0,0 +1,249 @@
+//! Sharing the machine between jobs that run at the same time, such as a
+//! queued job and one from the watch folder: the thread budget is split
+//! between running jobs, and encodes and ffmpeg processes are limited to a
+//! number of slots. Hardware encodes take a session on one of the
+//! configured GPUs instead, spread so no GPU exceeds its session limit.
+
+use crate::priority;
+use crate::settings;
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::thread;
+
+static ACTIVE_JOBS: AtomicUsize = AtomicUsize::new(0);
+
+/// A running job, counted for splitting the thread budget until dropped.
+pub struct JobLease(());
+
+/// Registers a job as running; see [`thread_count`].
+pub fn start_job() -> JobLease {
+    ACTIVE_JOBS.fetch_add(1, Ordering::SeqCst);
+    JobLease(())
+}
+
+impl Drop for JobLease {
+    fn drop(&mut self) {
+        ACTIVE_JOBS.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Threads all jobs together may use. Defaults to every available core.
+fn thread_budget() -> usize {
+    settings::current()
+        .threads
+        .filter(|&n| n > 0)
+        .unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()))
+}
+
+/// Number of worker threads used for frame comparison, and passed to ffmpeg
+/// as `-threads`: the job's share of the thread budget, taken when asked, so
+/// a job starting later gets fewer threads while others run.
+pub fn thread_count() -> usize {
+    let jobs = ACTIVE_JOBS.load(Ordering::SeqCst).max(1);
+    (thread_budget() / jobs).max(1)
+}
+
+/// Builds a rayon pool sized by [`thread_count`]. Comparison work runs inside
+/// it via `install` so the limit applies without touching the global pool.
+pub fn thread_pool() -> rayon::ThreadPool {
+    thread_pool_with(thread_count())
+}
+
+fn thread_pool_with(threads: usize) -> rayon::ThreadPool {
+    let mut builder = rayon::ThreadPoolBuilder::new().num_threads(threads);
+    if priority::enabled() {
+        builder = builder.start_handler(|_| priority::lower_current_thread());
+    }
+    builder.build().expect("Failed to build thread pool")
+}
+
+/// A number of slots shared by all jobs.
+struct Slots {
+    taken: Mutex<usize>,
+    freed: Condvar,
+}
+
+impl Slots {
+    fn new() -> Slots {
+        Slots {
+            taken: Mutex::new(0),
+            freed: Condvar::new(),
+        }
+    }
+
+    /// Blocks until fewer than `limit()` slots are taken, then takes one.
+    /// The limit is read again on every wake-up so settings changes apply.
+    fn acquire(&'static self, limit: fn() -> usize) -> Slot {
+        let mut taken = self.taken.lock().unwrap();
+        while *taken >= limit() {
+            taken = self.freed.wait(taken).unwrap();
+        }
+        *taken += 1;
+        Slot(self)
+    }
+}
+
+/// A taken slot, released on drop.
+pub struct Slot(&'static Slots);
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        *self.0.taken.lock().unwrap() -= 1;
+        self.0.freed.notify_all();
+    }
+}
+
+/// Bytes of memory not in use, or `None` where it cannot be read.
+pub fn available_memory() -> Option<u64> {
+    let mut system = sysinfo::System::new();
+    system.refresh_memory();
+    Some(system.available_memory()).filter(|&bytes| bytes > 0)
+}
+
+/// Threads comparisons and ffmpeg are limited to in low memory mode, each
+/// of which holds frames of its own.
+pub const LOW_MEMORY_THREADS: usize = 2;
+
+/// Threads encoders run on in deterministic mode, whatever the machine and
+/// the jobs alongside, as x264 and x265 give different output on different
+/// thread counts.
+pub const DETERMINISTIC_THREADS: usize = 4;
+
+/// Bounds of the automatic comparison batch size.
+const MIN_BATCH: usize = 4;
+const MAX_BATCH: usize = 256;
+
+/// How the frame pairs of a job are compared: on how many threads, in runs
+/// of how many pairs.
+pub struct ComparisonPlan {
+    pub pool: rayon::ThreadPool,
+    pub batch_size: usize,
+}
+
+/// Plans comparing `pairs` pairs of `width` x `height` frames.
+///
+/// Each thread holds a decoded frame and two luma buffers at a time, so the
+/// threads are capped to what half the available memory can hold. Batches
+/// are sized for about four runs per thread, which keeps every thread busy
+/// to the end, but not below a floor that grows with the resolution, since
+/// the first frame of every run is decoded twice.
+/// `comparison_batch_size` in the settings overrides the batch size, and
+/// `low_memory` caps the threads at [`LOW_MEMORY_THREADS`].
+pub fn comparison_plan(pairs: usize, width: u32, height: u32, low_memory: bool) -> ComparisonPlan {
+    let pixels = width as u64 * height as u64;
+    // RGBA while decoding, then two luma planes
+    let per_thread = pixels * 6;
+    let mut threads = thread_count();
+    if low_memory {
+        threads = threads.min(LOW_MEMORY_THREADS);
+    }
+    if let Some(available) = available_memory().filter(|_| per_thread > 0) {
+        threads = threads.min((available / 2 / per_thread).max(1) as usize);
+    }
+
+    let batch_size = match settings::current().comparison_batch_size {
+        Some(size) if size > 0 => size,
+        _ => {
+            let floor = match pixels {
+                0..=2_073_600 => MIN_BATCH,
+                2_073_601..=8_847_360 => MIN_BATCH * 2,
+                _ => MIN_BATCH * 4,
+            };
+            (pairs / (threads * 4)).clamp(floor, MAX_BATCH)
+        }
+    };
+    ComparisonPlan {
+        pool: thread_pool_with(threads),
+        batch_size,
+    }
+}
+
+fn max_processes() -> usize {
+    settings::current()
+        .max_ffmpeg_processes
+        .filter(|&n| n > 0)
+        .unwrap_or(usize::MAX)
+}
+
+/// Encodes that may run at once; one per four cores of the thread budget
+/// unless configured.
+fn max_encodes() -> usize {
+    settings::current()
+        .max_concurrent_encodes
+        .filter(|&n| n > 0)
+        .unwrap_or_else(|| (thread_budget() / 4).max(1))
+}
+
+static RUNNING_PROCESSES: Lazy<Slots> = Lazy::new(Slots::new);
+static RUNNING_ENCODES: Lazy<Slots> = Lazy::new(Slots::new);
+
+/// A slot for one ffmpeg child process, released on drop.
+pub type ProcessSlot = Slot;
+
+/// A slot for one job's encode, released on drop.
+pub type EncodeSlot = Slot;
+
+/// Blocks until fewer than the configured maximum of ffmpeg processes are
+/// running, then reserves a slot.
+pub fn acquire_process_slot() -> ProcessSlot {
+    RUNNING_PROCESSES.acquire(max_processes)
+}
+
+/// Blocks until fewer than the encode budget of jobs are encoding, then
+/// reserves a slot. Held for a job's whole encode stage, which may take more
+/// than one ffmpeg process.
+pub fn acquire_encode_slot() -> EncodeSlot {
+    RUNNING_ENCODES.acquire(max_encodes)
+}
+
+static GPU_SESSIONS: Lazy<(Mutex<HashMap<u32, usize>>, Condvar)> =
+    Lazy::new(|| (Mutex::new(HashMap::new()), Condvar::new()));
+
+/// A hardware encode session on one GPU, released on drop.
+pub struct GpuSession {
+    gpu: u32,
+}
+
+impl GpuSession {
+    /// Index of the GPU to encode on, as ffmpeg's NVENC `-gpu` takes it.
+    pub fn gpu(&self) -> u32 {
+        self.gpu
+    }
+}
+
+/// Blocks until one of the configured GPUs has a free encode session, then
+/// takes one on the least busy of them.
+pub fn acquire_gpu_session() -> GpuSession {
+    let (sessions, freed) = &*GPU_SESSIONS;
+    let mut sessions = sessions.lock().unwrap();
+    loop {
+        let settings = settings::current();
+        let limit = settings.nvenc_sessions_per_gpu.max(1);
+        let gpus = match settings.nvenc_gpus.as_slice() {
+            [] => vec![0],
+            gpus => gpus.to_vec(),
+        };
+        let free = gpus
+            .into_iter()
+            .map(|gpu| (gpu, sessions.get(&gpu).copied().unwrap_or(0)))
+            .filter(|&(_, taken)| taken < limit)
+            .min_by_key(|&(_, taken)| taken);
+        if let Some((gpu, _)) = free {
+            *sessions.entry(gpu).or_insert(0) += 1;
+            return GpuSession { gpu };
+        }
+        sessions = freed.wait(sessions).unwrap();
+    }
+}
+
+impl Drop for GpuSession {
+    fn drop(&mut self) {
+        let (sessions, freed) = &*GPU_SESSIONS;
+        if let Some(taken) = sessions.lock().unwrap().get_mut(&self.gpu) {
+            *taken -= 1;
+        }
+        freed.notify_all();
+    }
+}
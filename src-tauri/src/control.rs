@@ -0,0 +1,314 @@
+//! Progress reporting and cancellation for a running job.
+
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::error::ProcessError;
+
+/// A flag shared between a job and whoever may want to stop it. Clones
+/// refer to the same flag.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Asks the job to stop. It notices at the next frame or, while ffmpeg
+    /// runs, within a fraction of a second, and fails with a cancelled
+    /// [`ProcessError`].
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// How often a paused job looks whether it may go on.
+const PAUSE_POLL: Duration = Duration::from_millis(100);
+/// How far back [`Progress::fps`] looks.
+const RATE_WINDOW: Duration = Duration::from_secs(5);
+/// How often decisions are passed on in a batch.
+const DECISION_INTERVAL: Duration = Duration::from_millis(250);
+
+/// A flag asking a job to hold still until it is cleared. Clones refer to
+/// the same flag.
+#[derive(Debug, Clone, Default)]
+pub struct PauseToken(Arc<AtomicBool>);
+
+impl PauseToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Asks the job to stop where it is: the comparison waits at its next
+    /// frame and ffmpeg children are suspended.
+    pub fn pause(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn resume(&self) {
+        self.0.store(false, Ordering::Relaxed);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// The phases of a job, in order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Stage {
+    /// A remote input is downloaded into the job directory, in bytes.
+    Downloading,
+    /// ffmpeg decodes the input into intermediate frames.
+    Extracting,
+    /// Consecutive frames are compared.
+    Analyzing,
+    /// ffmpeg encodes the surviving frames.
+    Encoding,
+    /// The output is compared with the source frames it was made from.
+    Verifying,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Progress {
+    pub stage: Stage,
+    /// Units of work done in this stage: frame pairs while analysing.
+    pub done: usize,
+    /// Units of work in this stage: frames for the ffmpeg stages. 0 when it
+    /// is not known, as when ffmpeg cannot tell the length of its input.
+    pub total: usize,
+    /// Units done per second over the last few seconds.
+    pub fps: Option<f64>,
+    /// Seconds until the stage is done at the current rate.
+    pub eta_secs: Option<f64>,
+}
+
+/// Recent progress of the running stage, for its rate.
+#[derive(Debug, Default)]
+struct Throughput {
+    stage: Option<Stage>,
+    samples: VecDeque<(Instant, usize)>,
+}
+
+impl Throughput {
+    /// Records that `done` units of `stage` are done and returns the rate
+    /// over [`RATE_WINDOW`].
+    fn update(&mut self, stage: Stage, done: usize) -> Option<f64> {
+        if self.stage != Some(stage) {
+            self.stage = Some(stage);
+            self.samples.clear();
+        }
+        let now = Instant::now();
+        // analysis threads can report slightly out of order
+        let done = self
+            .samples
+            .back()
+            .map_or(done, |&(_, last)| done.max(last));
+        self.samples.push_back((now, done));
+        while self.samples.len() > 2
+            && self
+                .samples
+                .front()
+                .is_some_and(|&(at, _)| now - at > RATE_WINDOW)
+        {
+            self.samples.pop_front();
+        }
+        let (&(first_at, first), &(last_at, last)) = (self.samples.front()?, self.samples.back()?);
+        let secs = (last_at - first_at).as_secs_f64();
+        (secs > 0.0 && last > first).then(|| (last - first) as f64 / secs)
+    }
+}
+
+pub type ProgressCallback = Arc<dyn Fn(Progress) + Send + Sync>;
+
+/// Whether a frame goes, decided as soon as it has been compared with its
+/// successor.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct FrameDecision {
+    pub frame: usize,
+    pub score: f32,
+    pub removed: bool,
+}
+
+/// Called with batches of decisions, in the order they were made, which is
+/// not frame order when frames are compared in parallel.
+pub type DecisionCallback = Arc<dyn Fn(Vec<FrameDecision>) + Send + Sync>;
+
+/// Decisions waiting for the next batch.
+#[derive(Debug)]
+struct PendingDecisions {
+    decisions: Vec<FrameDecision>,
+    since: Instant,
+}
+
+impl Default for PendingDecisions {
+    fn default() -> Self {
+        PendingDecisions {
+            decisions: Vec::new(),
+            since: Instant::now(),
+        }
+    }
+}
+
+/// What a job is given to report progress through and to check for
+/// cancellation and pauses. The default reports nowhere and is never
+/// cancelled or paused.
+#[derive(Clone, Default)]
+pub struct JobControl {
+    pub progress: Option<ProgressCallback>,
+    pub cancel: CancellationToken,
+    pub pause: PauseToken,
+    pub decisions: Option<DecisionCallback>,
+    throughput: Arc<Mutex<Throughput>>,
+    pending: Arc<Mutex<PendingDecisions>>,
+}
+
+impl JobControl {
+    /// Reports that `done` of `total` units of `stage` are done, along with
+    /// the recent rate and the time left at that rate.
+    pub fn report(&self, stage: Stage, done: usize, total: usize) {
+        if let Some(progress) = &self.progress {
+            let fps = self.throughput.lock().unwrap().update(stage, done);
+            let eta_secs = fps
+                .filter(|_| total > 0)
+                .map(|fps| total.saturating_sub(done) as f64 / fps);
+            progress(Progress {
+                stage,
+                done,
+                total,
+                fps,
+                eta_secs,
+            });
+        }
+    }
+
+    /// A control reporting to the same place and cancelled along with this
+    /// one, which also times the stages it is told about on `clock`.
+    pub fn timed(&self, clock: Arc<StageClock>) -> JobControl {
+        let progress = self.progress.clone();
+        JobControl {
+            progress: Some(Arc::new(move |update: Progress| {
+                clock.enter(update.stage);
+                if let Some(progress) = &progress {
+                    progress(update);
+                }
+            })),
+            cancel: self.cancel.clone(),
+            pause: self.pause.clone(),
+            decisions: self.decisions.clone(),
+            throughput: self.throughput.clone(),
+            pending: self.pending.clone(),
+        }
+    }
+
+    /// Passes on a decision, batched so that no more than a few batches go
+    /// out per second.
+    pub fn decide(&self, decision: FrameDecision) {
+        let Some(callback) = &self.decisions else {
+            return;
+        };
+        let batch = {
+            let mut pending = self.pending.lock().unwrap();
+            pending.decisions.push(decision);
+            if pending.since.elapsed() < DECISION_INTERVAL {
+                return;
+            }
+            pending.since = Instant::now();
+            std::mem::take(&mut pending.decisions)
+        };
+        callback(batch);
+    }
+
+    /// Passes on the decisions still waiting for a batch.
+    pub fn flush_decisions(&self) {
+        let Some(callback) = &self.decisions else {
+            return;
+        };
+        let batch = std::mem::take(&mut self.pending.lock().unwrap().decisions);
+        if !batch.is_empty() {
+            callback(batch);
+        }
+    }
+
+    /// Blocks while the job is paused, returning early once it is cancelled.
+    pub fn wait_while_paused(&self) {
+        while self.pause.is_paused() && !self.cancel.is_cancelled() {
+            thread::sleep(PAUSE_POLL);
+        }
+    }
+
+    /// Waits out a pause, then fails with a cancelled error once
+    /// cancellation was requested.
+    pub fn check(&self) -> Result<(), ProcessError> {
+        self.wait_while_paused();
+        if self.cancel.is_cancelled() {
+            Err(ProcessError::cancelled())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Wall time a job spent in each stage, in seconds.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct StageTimes {
+    pub downloading: f64,
+    pub extracting: f64,
+    pub analyzing: f64,
+    pub encoding: f64,
+    pub verifying: f64,
+}
+
+impl StageTimes {
+    fn add(&mut self, stage: Stage, secs: f64) {
+        match stage {
+            Stage::Downloading => self.downloading += secs,
+            Stage::Extracting => self.extracting += secs,
+            Stage::Analyzing => self.analyzing += secs,
+            Stage::Encoding => self.encoding += secs,
+            Stage::Verifying => self.verifying += secs,
+        }
+    }
+}
+
+/// Times the stages of a job from its progress reports: a stage lasts from
+/// its first report until the first report of another.
+#[derive(Debug, Default)]
+pub struct StageClock {
+    state: Mutex<(Option<(Stage, Instant)>, StageTimes)>,
+}
+
+impl StageClock {
+    fn enter(&self, stage: Stage) {
+        let mut state = self.state.lock().unwrap();
+        let (current, times) = &mut *state;
+        match current {
+            Some((running, _)) if *running == stage => {}
+            _ => {
+                if let Some((running, since)) = current.replace((stage, Instant::now())) {
+                    times.add(running, since.elapsed().as_secs_f64());
+                }
+            }
+        }
+    }
+
+    /// Ends the running stage and returns the times so far.
+    pub fn finish(&self) -> StageTimes {
+        let mut state = self.state.lock().unwrap();
+        let (current, times) = &mut *state;
+        if let Some((running, since)) = current.take() {
+            times.add(running, since.elapsed().as_secs_f64());
+        }
+        *times
+    }
+}
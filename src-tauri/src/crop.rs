@@ -0,0 +1,97 @@
+//! Letterbox and pillarbox detection. Black bars are the same in every
+//! frame, so they make frames look more alike than their picture is; they
+//! are left out of the comparison and, optionally, cropped from the output.
+
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tracing::{info, warn};
+
+use crate::ffmpeg;
+use crate::paths;
+use crate::streams;
+use crate::supervisor;
+
+/// What is done about black bars around the picture.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CropMode {
+    #[default]
+    Off,
+    /// Leave the bars out of the comparison only.
+    Compare,
+    /// Leave them out of the comparison and crop them from the output.
+    /// Smart cuts and cut lists leave the source as it is.
+    Output,
+}
+
+/// The picture inside the bars, in pixels of the source frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CropRect {
+    pub width: u32,
+    pub height: u32,
+    pub x: u32,
+    pub y: u32,
+}
+
+impl CropRect {
+    /// The ffmpeg `crop` filter cutting the frames down to this.
+    pub fn filter(&self) -> String {
+        format!("crop={}:{}:{}:{}", self.width, self.height, self.x, self.y)
+    }
+}
+
+/// Detects the bars of video stream `stream` of `input_file` with ffmpeg's
+/// `cropdetect`, over its keyframes so the whole source is covered without
+/// decoding all of it. The picture found is the largest of any keyframe.
+/// Returns `None` when there are no bars or detection fails.
+pub fn detect(input_file: &Path, stream: usize) -> Option<CropRect> {
+    let output = supervisor::run(|| {
+        let mut command = ffmpeg::command()?;
+        command
+            .args(["-skip_frame", "nokey", "-i"])
+            .arg(paths::ffmpeg_arg(input_file))
+            .args(["-map", &streams::map(stream), "-an"])
+            .args(["-vf", "cropdetect=round=2:reset=0:skip=0"])
+            .args(["-f", "null", "-"]);
+        Ok(command)
+    });
+    let stderr = match output {
+        Ok(output) => output.stderr,
+        Err(e) => {
+            warn!("Failed to detect black bars: {}", e);
+            return None;
+        }
+    };
+    let (frame_width, frame_height) =
+        streams::reported_line(&stderr, stream).and_then(streams::frame_size)?;
+    let crop = stderr
+        .lines()
+        .rev()
+        .find_map(|line| line.split("crop=").nth(1))?;
+    let numbers: Vec<i64> = crop
+        .trim()
+        .split(':')
+        .filter_map(|number| number.parse().ok())
+        .collect();
+    let &[width, height, x, y] = &numbers[..] else {
+        return None;
+    };
+    // an all-black source reports a picture of nothing
+    if width <= 0 || height <= 0 || x < 0 || y < 0 {
+        return None;
+    }
+    let rect = CropRect {
+        width: width as u32,
+        height: height as u32,
+        x: x as u32,
+        y: y as u32,
+    };
+    if rect.width >= frame_width && rect.height >= frame_height {
+        return None;
+    }
+    info!(
+        "Found black bars around a {}x{} picture at {},{}",
+        rect.width, rect.height, rect.x, rect.y
+    );
+    Some(rect)
+}
@@ -0,0 +1,342 @@
+//! Cut lists: the time ranges of the source to keep, written for an editor
+//! to apply instead of re-encoding the video, or marker lists showing the
+//! dead sections on the editor's timeline. Also the timestamp map written
+//! next to processed videos.
+//!
+//! Times are frame numbers divided by the frame rate, so they are exact for
+//! constant frame rate sources only.
+
+use serde::{Deserialize, Serialize};
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CutListFormat {
+    /// CMX 3600 edit decision list, which most NLEs import.
+    Edl,
+    /// ffmpeg concat demuxer script with in and out points.
+    Concat,
+    /// One row per kept range, with frame numbers and times.
+    Csv,
+    /// Final Cut Pro XML project holding the source with a marker on each
+    /// dead section.
+    Fcpxml,
+    /// Tab-separated marker list in the layout Premiere Pro exports and
+    /// reads, one marker per dead section.
+    PremiereMarkers,
+}
+
+impl CutListFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            CutListFormat::Edl => "edl",
+            CutListFormat::Concat => "ffconcat",
+            CutListFormat::Csv => "csv",
+            CutListFormat::Fcpxml => "fcpxml",
+            CutListFormat::PremiereMarkers => "txt",
+        }
+    }
+}
+
+/// The runs of frames that are not removed, as frame index ranges.
+pub fn kept_ranges(removed: &[bool]) -> Vec<Range<usize>> {
+    runs(removed, false)
+}
+
+/// The runs of removed frames, as frame index ranges.
+pub fn removed_ranges(removed: &[bool]) -> Vec<Range<usize>> {
+    runs(removed, true)
+}
+
+/// Removes the runs of fewer than `gap` kept frames that lie between two
+/// runs of removed ones, and returns the frames removed this way.
+pub fn merge_removed(removed: &mut [bool], gap: usize) -> Vec<usize> {
+    let mut merged = Vec::new();
+    for range in kept_ranges(removed) {
+        if range.start > 0 && range.end < removed.len() && range.len() < gap {
+            merged.extend(range.clone());
+            removed[range].fill(true);
+        }
+    }
+    merged
+}
+
+fn runs(removed: &[bool], dead: bool) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut start = None;
+    for (index, &value) in removed.iter().enumerate() {
+        match (value == dead, start) {
+            (true, None) => start = Some(index),
+            (false, Some(first)) => {
+                ranges.push(first..index);
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(first) = start {
+        ranges.push(first..removed.len());
+    }
+    ranges
+}
+
+/// Writes the cut list for `source` to `output`. `frames` are the source's
+/// frame files when it is an image sequence, and empty for a video.
+pub fn write(
+    format: CutListFormat,
+    source: &Path,
+    frames: &[PathBuf],
+    removed: &[bool],
+    fps: f64,
+    output: &Path,
+) -> io::Result<()> {
+    let ranges = kept_ranges(removed);
+    // concat scripts resolve relative paths against their own directory
+    let source = fs::canonicalize(source).unwrap_or_else(|_| source.to_path_buf());
+    let source = source.as_path();
+    let text = match format {
+        CutListFormat::Edl => edl(source, &ranges, fps, output),
+        CutListFormat::Concat => concat(source, frames, &ranges, fps),
+        CutListFormat::Csv => csv(&ranges, fps),
+        CutListFormat::Fcpxml => fcpxml(source, removed, fps, output),
+        CutListFormat::PremiereMarkers => premiere_markers(removed, fps),
+    };
+    fs::write(output, text)
+}
+
+/// `frame` as an `HH:MM:SS:FF` non-drop-frame timecode.
+fn timecode(frame: usize, fps: f64) -> String {
+    let base = (fps.round() as usize).max(1);
+    let seconds = frame / base;
+    format!(
+        "{:02}:{:02}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+        frame % base
+    )
+}
+
+fn edl(source: &Path, ranges: &[Range<usize>], fps: f64, output: &Path) -> String {
+    let title = output.file_stem().unwrap_or_default().to_string_lossy();
+    let clip = source.file_name().unwrap_or_default().to_string_lossy();
+    let mut text = format!("TITLE: {}\nFCM: NON-DROP FRAME\n\n", title);
+    let mut record = 0;
+    for (number, range) in ranges.iter().enumerate() {
+        let record_end = record + range.len();
+        let _ = writeln!(
+            text,
+            "{:03}  AX       V     C        {} {} {} {}\n* FROM CLIP NAME: {}\n",
+            number + 1,
+            timecode(range.start, fps),
+            timecode(range.end, fps),
+            timecode(record, fps),
+            timecode(record_end, fps),
+            clip
+        );
+        record = record_end;
+    }
+    text
+}
+
+/// Quotes `path` for a concat script, which escapes quotes as `'\''`.
+fn quote(path: &Path) -> String {
+    format!("'{}'", path.to_string_lossy().replace('\'', r"'\''"))
+}
+
+fn concat(source: &Path, frames: &[PathBuf], ranges: &[Range<usize>], fps: f64) -> String {
+    let mut text = String::from("ffconcat version 1.0\n");
+    for range in ranges {
+        if frames.is_empty() {
+            let _ = writeln!(
+                text,
+                "file {}\ninpoint {:.6}\noutpoint {:.6}",
+                quote(source),
+                range.start as f64 / fps,
+                range.end as f64 / fps
+            );
+        } else {
+            // still images have no timeline, so each frame lasts one frame
+            for frame in &frames[range.clone()] {
+                let frame = fs::canonicalize(frame).unwrap_or_else(|_| frame.clone());
+                let _ = writeln!(text, "file {}\nduration {:.6}", quote(&frame), 1.0 / fps);
+            }
+        }
+    }
+    text
+}
+
+fn csv(ranges: &[Range<usize>], fps: f64) -> String {
+    let mut text = String::from("first_frame,last_frame,start_secs,end_secs\n");
+    for range in ranges {
+        let _ = writeln!(
+            text,
+            "{},{},{:.6},{:.6}",
+            range.start,
+            range.end - 1,
+            range.start as f64 / fps,
+            range.end as f64 / fps
+        );
+    }
+    text
+}
+
+/// The duration of one frame as a fraction of a second, recognising the
+/// NTSC rates such as 29.97 as multiples of 1000/1001.
+fn frame_duration(fps: f64) -> (u64, u64) {
+    let ntsc = fps * 1.001;
+    if (ntsc - ntsc.round()).abs() < 0.01 && (fps - fps.round()).abs() > 0.01 {
+        (1001, ntsc.round() as u64 * 1000)
+    } else {
+        (1, (fps.round() as u64).max(1))
+    }
+}
+
+/// `frames` frames as an FCPXML time such as `1001/30000s`.
+fn fcpxml_time(frames: usize, (numer, denom): (u64, u64)) -> String {
+    format!("{}/{}s", frames as u64 * numer, denom)
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn file_url(path: &Path) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+    let mut url = String::from(if path.starts_with('/') {
+        "file://"
+    } else {
+        "file:///"
+    });
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' | b':' => {
+                url.push(byte as char)
+            }
+            _ => {
+                let _ = write!(url, "%{:02X}", byte);
+            }
+        }
+    }
+    url
+}
+
+fn fcpxml(source: &Path, removed: &[bool], fps: f64, output: &Path) -> String {
+    let frame = frame_duration(fps);
+    let name = escape_xml(&source.file_stem().unwrap_or_default().to_string_lossy());
+    let project = escape_xml(&output.file_stem().unwrap_or_default().to_string_lossy());
+    let duration = fcpxml_time(removed.len(), frame);
+
+    let mut markers = String::new();
+    for range in removed_ranges(removed) {
+        let _ = writeln!(
+            markers,
+            "              <marker start=\"{}\" duration=\"{}\" value=\"Dead frames {}-{}\"/>",
+            fcpxml_time(range.start, frame),
+            fcpxml_time(range.len(), frame),
+            range.start,
+            range.end - 1
+        );
+    }
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE fcpxml>
+<fcpxml version="1.9">
+  <resources>
+    <format id="r1" frameDuration="{frame}"/>
+    <asset id="r2" name="{name}" start="0s" duration="{duration}" hasVideo="1" format="r1">
+      <media-rep kind="original-media" src="{src}"/>
+    </asset>
+  </resources>
+  <library>
+    <event name="Dead frames">
+      <project name="{project}">
+        <sequence format="r1" duration="{duration}" tcStart="0s">
+          <spine>
+            <asset-clip ref="r2" name="{name}" offset="0s" start="0s" duration="{duration}">
+{markers}            </asset-clip>
+          </spine>
+        </sequence>
+      </project>
+    </event>
+  </library>
+</fcpxml>
+"#,
+        frame = fcpxml_time(1, frame),
+        src = escape_xml(&file_url(source)),
+    )
+}
+
+fn premiere_markers(removed: &[bool], fps: f64) -> String {
+    let mut text = String::from("Marker Name\tDescription\tIn\tOut\tDuration\tMarker Type\n");
+    for range in removed_ranges(removed) {
+        let _ = writeln!(
+            text,
+            "Dead frames\tFrames {}-{}\t{}\t{}\t{}\tComment",
+            range.start,
+            range.end - 1,
+            timecode(range.start, fps),
+            timecode(range.end, fps),
+            timecode(range.len(), fps)
+        );
+    }
+    text
+}
+
+#[derive(Serialize)]
+struct TimestampMap<'a> {
+    source: &'a Path,
+    output: &'a Path,
+    source_fps: f64,
+    output_fps: f64,
+    frames: Vec<MappedFrame>,
+}
+
+/// One output frame and the source frame it shows.
+#[derive(Serialize)]
+struct MappedFrame {
+    frame: usize,
+    time: f64,
+    source_frame: usize,
+    source_time: f64,
+}
+
+/// Writes a JSON side-car to `path` mapping every frame of `output` back to
+/// the frame and time of `source` it came from, so subtitles and other
+/// timed data can be moved onto the shortened video.
+pub fn write_timestamp_map(
+    source: &Path,
+    output: &Path,
+    removed: &[bool],
+    source_fps: f64,
+    output_fps: f64,
+    path: &Path,
+) -> io::Result<()> {
+    let frames = kept_ranges(removed)
+        .into_iter()
+        .flatten()
+        .enumerate()
+        .map(|(frame, source_frame)| MappedFrame {
+            frame,
+            time: frame as f64 / output_fps,
+            source_frame,
+            source_time: source_frame as f64 / source_fps,
+        })
+        .collect();
+    let map = TimestampMap {
+        source,
+        output,
+        source_fps,
+        output_fps,
+        frames,
+    };
+    fs::write(path, serde_json::to_vec_pretty(&map)?)
+}
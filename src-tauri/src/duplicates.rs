@@ -0,0 +1,133 @@
+//! Frames the encoder repeated, for screen recordings. OBS and other
+//! capture tools write the last frame again when a capture tick brings no
+//! new one, or stretch its timestamp over the gap in variable frame rate
+//! recordings, which extraction at a constant rate turns back into copies.
+//!
+//! ffmpeg's `mpdecimate` finds the frames that differ from the one before
+//! by no more than encoding noise, and timestamps give away the gaps. Such
+//! repeats come from the capture falling behind for a moment, so only
+//! short runs of them are removed; a run lasting longer than
+//! [`MAX_REPEAT_SECS`] is a still screen that is meant to be there and is
+//! left alone.
+
+use std::collections::HashSet;
+use std::path::Path;
+use tracing::{info, warn};
+
+use crate::control::{JobControl, Stage};
+use crate::error::ProcessError;
+use crate::ffmpeg;
+use crate::paths;
+use crate::streams;
+use crate::supervisor;
+
+/// Longest run of repeated frames taken for the capture falling behind.
+pub const MAX_REPEAT_SECS: f64 = 0.25;
+
+/// A decoded frame as `showinfo` reports it.
+struct FrameInfo {
+    pts_time: f64,
+    duration_time: f64,
+}
+
+/// Parses the frames one `showinfo` instance, named `name`, logged.
+fn frames(stderr: &str, name: &str) -> Vec<FrameInfo> {
+    let prefix = format!("[showinfo@{} @", name);
+    stderr
+        .lines()
+        .filter(|line| line.starts_with(&prefix) && line.contains(" n:"))
+        .filter_map(|line| {
+            let field = |key: &str| {
+                line.split_whitespace()
+                    .find_map(|part| part.strip_prefix(key))
+            };
+            Some(FrameInfo {
+                pts_time: field("pts_time:")?.parse().ok()?,
+                duration_time: field("duration_time:")
+                    .and_then(|value| value.parse().ok())
+                    .unwrap_or(0.0),
+            })
+        })
+        .collect()
+}
+
+/// Whether each frame of video stream `stream` of `input_file`, extracted
+/// at `fps` frames a second, is a repeat to remove. `deinterlace` is
+/// the filter extraction deinterlaces with, which can change the number of
+/// frames.
+pub fn detect(
+    input_file: &Path,
+    stream: usize,
+    fps: f64,
+    deinterlace: Option<&str>,
+    control: &JobControl,
+) -> Result<Vec<bool>, ProcessError> {
+    let filter = deinterlace
+        .into_iter()
+        .chain(["showinfo@all", "mpdecimate", "showinfo@kept"])
+        .collect::<Vec<_>>()
+        .join(",");
+    let output = supervisor::run_reporting(
+        || {
+            let mut command = ffmpeg::command()?;
+            command
+                .arg("-i")
+                .arg(paths::ffmpeg_arg(input_file))
+                .args(["-map", &streams::map(stream), "-an"])
+                .args(["-vf", &filter])
+                .args(["-f", "null", "-"]);
+            Ok(command)
+        },
+        control,
+        Stage::Analyzing,
+        None,
+    )
+    .map_err(|e| ProcessError::ffmpeg("Failed to find repeated frames", e))?;
+
+    let decoded = frames(&output.stderr, "all");
+    let kept: HashSet<u64> = frames(&output.stderr, "kept")
+        .iter()
+        .map(|frame| frame.pts_time.to_bits())
+        .collect();
+    let (Some(first), Some(last)) = (decoded.first(), decoded.last()) else {
+        warn!("ffmpeg reported no frames, assuming none are repeated");
+        return Ok(Vec::new());
+    };
+    let start = first.pts_time;
+    let index = |secs: f64| ((secs - start) * fps).round() as usize;
+
+    // Every decoded frame stands for the extracted frames up to the next
+    // one; all but the first of those are copies filling a gap in time.
+    let mut repeats = Vec::with_capacity(decoded.len());
+    for frame in &decoded {
+        let at = index(frame.pts_time);
+        if at < repeats.len() {
+            // a timestamp at or behind the last one, which extraction drops
+            continue;
+        }
+        repeats.resize(at, true);
+        repeats.push(!kept.contains(&frame.pts_time.to_bits()));
+    }
+    let end = index(last.pts_time + last.duration_time);
+    if end > repeats.len() {
+        repeats.resize(end, true);
+    }
+
+    let max_run = ((MAX_REPEAT_SECS * fps).round() as usize).max(1);
+    let mut run_start = 0;
+    for frame in 0..=repeats.len() {
+        if repeats.get(frame) == Some(&true) {
+            continue;
+        }
+        if frame - run_start > max_run {
+            repeats[run_start..frame].fill(false);
+        }
+        run_start = frame + 1;
+    }
+    info!(
+        "{} of {} frames are encoder repeats",
+        repeats.iter().filter(|&&repeat| repeat).count(),
+        repeats.len()
+    );
+    Ok(repeats)
+}
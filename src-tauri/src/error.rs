@@ -0,0 +1,87 @@
+use crate::supervisor::RunError;
+use serde::Serialize;
+use std::fmt;
+
+/// How many trailing lines of ffmpeg's stderr are attached to an error.
+pub const STDERR_TAIL_LINES: usize = 20;
+
+/// A failed job, serialisable so the frontend can show what went wrong.
+#[derive(Debug, Clone, Serialize)]
+pub struct ProcessError {
+    pub message: String,
+    /// The last lines ffmpeg wrote to stderr, when ffmpeg was at fault. This
+    /// is where the actual reason shows up: a missing codec, a bad input
+    /// pattern, a permission error.
+    pub ffmpeg_stderr: Vec<String>,
+    /// The job that failed; its transcript is available through
+    /// [`crate::logging::LogStore::job_log`].
+    pub job_id: Option<String>,
+    /// The job was stopped through its cancellation token rather than failing.
+    pub cancelled: bool,
+}
+
+impl ProcessError {
+    pub fn new(message: impl Into<String>) -> Self {
+        ProcessError {
+            message: message.into(),
+            ffmpeg_stderr: Vec::new(),
+            job_id: None,
+            cancelled: false,
+        }
+    }
+
+    pub fn cancelled() -> Self {
+        ProcessError {
+            cancelled: true,
+            ..ProcessError::new("Cancelled")
+        }
+    }
+
+    pub fn ffmpeg(context: &str, error: RunError) -> Self {
+        if let RunError::Unavailable(error) = error {
+            return error;
+        }
+        let stderr = match &error {
+            RunError::Cancelled => return ProcessError::cancelled(),
+            RunError::Spawn(_) | RunError::Unavailable(_) => "",
+            RunError::Stalled { stderr, .. } | RunError::Failed { stderr, .. } => stderr,
+        };
+        ProcessError {
+            message: format!("{}: {}", context, error),
+            ffmpeg_stderr: stderr_tail(stderr, STDERR_TAIL_LINES),
+            job_id: None,
+            cancelled: false,
+        }
+    }
+
+    pub fn with_job_id(mut self, job_id: &str) -> Self {
+        self.job_id = Some(job_id.to_string());
+        self
+    }
+}
+
+/// The last `lines` non-empty lines of `stderr`. ffmpeg redraws its stats
+/// line with `\r`, so those count as line breaks too.
+pub fn stderr_tail(stderr: &str, lines: usize) -> Vec<String> {
+    let all: Vec<&str> = stderr
+        .split(['\n', '\r'])
+        .map(str::trim_end)
+        .filter(|line| !line.is_empty())
+        .collect();
+    all[all.len().saturating_sub(lines)..]
+        .iter()
+        .map(|line| line.to_string())
+        .collect()
+}
+
+impl fmt::Display for ProcessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        if let Some(last) = self.ffmpeg_stderr.last() {
+            write!(f, " ({})", last)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ProcessError {}
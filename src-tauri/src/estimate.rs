@@ -0,0 +1,74 @@
+//! Predicting how large a processed video will be before it is encoded.
+
+use serde::{Deserialize, Serialize};
+
+use crate::video_fixer::VideoCodec;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct SizeEstimate {
+    /// Predicted size of the output in bytes.
+    pub bytes: u64,
+    /// Measured from encoded data, a test encode of some kept frames or for
+    /// smart cuts the source itself, rather than guessed from the codec's
+    /// typical bitrate.
+    pub sampled: bool,
+}
+
+/// Share of a target size left for the container and for two-pass encodes
+/// overshooting their bitrate a little.
+const SIZE_MARGIN: f64 = 0.05;
+
+/// Bitrate of ffmpeg's AAC encoder, which retimed audio is encoded with.
+pub const AAC_KBPS: u32 = 128;
+
+/// Bits per pixel per frame each codec typically needs at the quality this
+/// app encodes with. Real footage varies by an order of magnitude either
+/// way, so guesses built on these are rough.
+fn bits_per_pixel(codec: VideoCodec) -> f64 {
+    match codec {
+        VideoCodec::H264 => 0.1,
+        VideoCodec::H265 => 0.05,
+        VideoCodec::Vp9 => 0.06,
+        VideoCodec::Av1 => 0.04,
+        VideoCodec::Ffv1 => 6.0,
+        VideoCodec::Gif => 1.5,
+        VideoCodec::WebP => 0.5,
+        VideoCodec::Apng => 8.0,
+        VideoCodec::ProRes => 5.0,
+        VideoCodec::DnxHr => 4.0,
+    }
+}
+
+/// Guesses the size of `frames` frames of `width` x `height` in `codec`.
+pub fn guess(codec: VideoCodec, width: u32, height: u32, frames: usize) -> SizeEstimate {
+    let bits = bits_per_pixel(codec) * width as f64 * height as f64 * frames as f64;
+    SizeEstimate {
+        bytes: (bits / 8.0) as u64,
+        sampled: false,
+    }
+}
+
+/// The size of `secs` seconds encoded at `kbps` kbit/s, which two-pass
+/// encodes land close to.
+pub fn at_bitrate(kbps: u32, secs: f64) -> SizeEstimate {
+    SizeEstimate {
+        bytes: (kbps as f64 * 1000.0 / 8.0 * secs) as u64,
+        sampled: false,
+    }
+}
+
+/// The video bitrate in kbit/s at which `secs` seconds of video, along with
+/// `audio_kbps` of audio, fit in `bytes`. `None` when they cannot.
+pub fn bitrate_for_size(bytes: u64, secs: f64, audio_kbps: u32) -> Option<u32> {
+    let total_kbps = bytes as f64 * 8.0 / 1000.0 * (1.0 - SIZE_MARGIN) / secs;
+    let kbps = (total_kbps - audio_kbps as f64).floor();
+    (kbps.is_finite() && kbps >= 1.0).then_some(kbps as u32)
+}
+
+/// Scales `bytes` measured for `measured` frames up to `frames` frames.
+pub fn scaled(bytes: u64, measured: usize, frames: usize) -> SizeEstimate {
+    SizeEstimate {
+        bytes: (bytes as f64 * frames as f64 / measured.max(1) as f64) as u64,
+        sampled: true,
+    }
+}
@@ -0,0 +1,113 @@
+//! Copying the removed frames of a job out for inspection, so QA users can
+//! audit exactly what was discarded.
+//!
+//! Each frame is named after its index in the source, counted from 0, and
+//! its time there: `frame_000059_1.967s.png`.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+use crate::control::{JobControl, Stage};
+use crate::cutlist;
+use crate::error::ProcessError;
+use crate::ffmpeg;
+use crate::paths;
+use crate::streams;
+use crate::supervisor;
+
+/// Prefix of the frames ffmpeg writes before they are renamed.
+const EXTRACTED_PREFIX: &str = ".extracted_";
+
+fn file_name(index: usize, fps: f64, extension: &str) -> String {
+    format!(
+        "frame_{:06}_{:.3}s.{}",
+        index,
+        index as f64 / fps,
+        extension
+    )
+}
+
+/// Copies the removed frames into `dir`. `frames` are the frame files of the
+/// job; when they are empty, as with y4m frames, the removed frames are
+/// extracted from video stream `stream` of `input` again as PNG. Returns
+/// how many were written.
+pub fn removed_frames(
+    input: &Path,
+    stream: usize,
+    frames: &[PathBuf],
+    removed: &[bool],
+    fps: f64,
+    dir: &Path,
+    control: &JobControl,
+) -> Result<usize, ProcessError> {
+    let indices: Vec<usize> = (0..removed.len()).filter(|&i| removed[i]).collect();
+    if indices.is_empty() {
+        return Ok(0);
+    }
+    fs::create_dir_all(dir)
+        .map_err(|e| ProcessError::new(format!("Failed to create {}: {}", dir.display(), e)))?;
+
+    if frames.is_empty() {
+        extract(input, stream, &indices, removed, fps, dir, control)?;
+    } else {
+        for &index in &indices {
+            let frame = &frames[index];
+            let extension = frame.extension().unwrap_or_default().to_string_lossy();
+            let target = dir.join(file_name(index, fps, &extension));
+            fs::copy(frame, &target).map_err(|e| {
+                ProcessError::new(format!("Failed to export {}: {}", frame.display(), e))
+            })?;
+        }
+    }
+    info!(
+        "Exported {} removed frames to {}",
+        indices.len(),
+        dir.display()
+    );
+    Ok(indices.len())
+}
+
+/// Decodes the frames at `indices` from `input` into `dir`.
+fn extract(
+    input: &Path,
+    stream: usize,
+    indices: &[usize],
+    removed: &[bool],
+    fps: f64,
+    dir: &Path,
+    control: &JobControl,
+) -> Result<(), ProcessError> {
+    let select: Vec<String> = cutlist::removed_ranges(removed)
+        .iter()
+        .map(|range| format!("between(n,{},{})", range.start, range.end - 1))
+        .collect();
+    let pattern = dir.join(format!("{}%06d.png", EXTRACTED_PREFIX));
+    supervisor::run_reporting(
+        || {
+            let mut command = ffmpeg::command()?;
+            command
+                .arg("-i")
+                .arg(paths::ffmpeg_arg(input))
+                .args(["-map", &streams::map(stream)])
+                .arg("-vf")
+                .arg(format!("select='{}'", select.join("+")))
+                .args(["-fps_mode", "passthrough", "-y"])
+                .arg(paths::ffmpeg_arg(&pattern));
+            Ok(command)
+        },
+        control,
+        Stage::Extracting,
+        Some(indices.len()),
+    )
+    .map_err(|e| ProcessError::ffmpeg("Failed to export removed frames", e))?;
+
+    // ffmpeg numbers its output from 1, in the order the frames were selected
+    for (position, &index) in indices.iter().enumerate() {
+        let extracted = dir.join(format!("{}{:06}.png", EXTRACTED_PREFIX, position + 1));
+        fs::rename(&extracted, dir.join(file_name(index, fps, "png"))).map_err(|e| {
+            ProcessError::new(format!("Failed to export {}: {}", extracted.display(), e))
+        })?;
+    }
+    Ok(())
+}
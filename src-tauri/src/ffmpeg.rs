@@ -0,0 +1,388 @@
+//! Finding the ffmpeg binary every job runs.
+//!
+//! Packaged apps ship ffmpeg as a Tauri sidecar: with
+//! `tauri build --config src-tauri/tauri.sidecar.conf.json`, the binary at
+//! `src-tauri/binaries/dead-frames-ffmpeg-<target triple>` is bundled and
+//! signed with the app and installed next to its executable. Builds without
+//! one fall back to the binary embedded by the default `embedded-ffmpeg`
+//! feature, extracted into the app data directory, or the one fetched by
+//! `download-ffmpeg`.
+
+#[cfg(bundled_ffmpeg)]
+use fs2::FileExt;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+#[cfg(bundled_ffmpeg)]
+use sha2::{Digest, Sha256};
+use std::env;
+#[cfg(bundled_ffmpeg)]
+use std::fs;
+#[cfg(bundled_ffmpeg)]
+use std::fs::File;
+#[cfg(embedded_ffmpeg)]
+use std::io::Cursor;
+#[cfg(bundled_ffmpeg)]
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use tracing::{info, warn};
+
+use crate::arch;
+use crate::error::ProcessError;
+use crate::priority;
+use crate::settings;
+
+/// Declares the ffmpeg build for the target: the name of its zstd payload in
+/// `resources/` (and among the downloads) and the SHA-256 of the decompressed
+/// binary, checked before the extracted file is first executed.
+macro_rules! ffmpeg_build {
+    ($asset:literal, $sha256:literal) => {
+        #[cfg(feature = "download-ffmpeg")]
+        pub(crate) const FFMPEG_ASSET: &str = $asset;
+        #[cfg(bundled_ffmpeg)]
+        const FFMPEG_SHA256: &str = $sha256;
+        #[cfg(embedded_ffmpeg)]
+        const FFMPEG_EXECUTABLE: &[u8] = include_bytes!(concat!("resources/", $asset));
+    };
+}
+
+#[cfg(all(target_os = "windows", target_arch = "x86_64"))]
+ffmpeg_build!(
+    "ffmpeg-windows-x86_64.zst",
+    "e84edc1e51c06d211cc0fc6edec0eccdeba6a796e9bb1936d411b4558895a2fa"
+);
+// Apple Silicon runs the Intel build under Rosetta 2
+#[cfg(all(
+    target_os = "macos",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+ffmpeg_build!(
+    "ffmpeg-macos-x86_64.zst",
+    "23fb76dd559e155e49b9808b86ab5117f297b76294a798e4ef6cbb12fd15a689"
+);
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+ffmpeg_build!(
+    "ffmpeg-linux-x86_64.zst",
+    "e7e7fb30477f717e6f55f9180a70386c62677ef8a4d4d1a5d948f4098aa3eb99"
+);
+#[cfg(all(
+    bundled_ffmpeg,
+    not(any(
+        all(target_os = "windows", target_arch = "x86_64"),
+        all(
+            target_os = "macos",
+            any(target_arch = "x86_64", target_arch = "aarch64")
+        ),
+        all(target_os = "linux", target_arch = "x86_64"),
+    ))
+))]
+compile_error!(
+    "there is no ffmpeg build for this target; add its payload to src/resources \
+     and an ffmpeg_build! entry for it in src/ffmpeg.rs, or build without the \
+     embedded-ffmpeg and download-ffmpeg features and ship a sidecar"
+);
+
+pub(crate) const FFMPEG_FILE_NAME: &str = if cfg!(target_os = "windows") {
+    "ffmpeg.exe"
+} else {
+    "ffmpeg"
+};
+
+/// Name of the sidecar binary next to the app's executable. Tauri installs
+/// it without the target triple it is bundled with; it is not called plain
+/// `ffmpeg` so Linux packages do not clash with the distribution's.
+const SIDECAR_FILE_NAME: &str = if cfg!(target_os = "windows") {
+    "dead-frames-ffmpeg.exe"
+} else {
+    "dead-frames-ffmpeg"
+};
+
+/// The locator every ffmpeg run of the process goes through.
+static LOCATOR: Lazy<Arc<FfmpegLocator>> = Lazy::new(Arc::default);
+
+/// The app data directory, set by the desktop app.
+#[cfg(embedded_ffmpeg)]
+static DATA_DIR: Lazy<Mutex<Option<PathBuf>>> = Lazy::new(|| Mutex::new(None));
+
+/// Extracts the embedded binary under `app_data_dir` from now on. Until
+/// then, as in `dfr-cli`, it goes into the temp directory.
+pub fn init(app_data_dir: &Path) {
+    #[cfg(embedded_ffmpeg)]
+    {
+        *DATA_DIR.lock().unwrap() = Some(app_data_dir.to_path_buf());
+    }
+    #[cfg(not(embedded_ffmpeg))]
+    let _ = app_data_dir;
+}
+
+/// App-specific directory the embedded binary is extracted into. It is keyed
+/// by app version and payload size so upgrades never reuse a stale binary.
+/// A temp directory anyone can write to trips Gatekeeper and some virus
+/// scanners, so the app data directory is preferred.
+#[cfg(embedded_ffmpeg)]
+fn extraction_dir() -> PathBuf {
+    let base = match DATA_DIR.lock().unwrap().clone() {
+        Some(dir) => dir.join("ffmpeg"),
+        None => env::temp_dir().join("dead-frames"),
+    };
+    base.join(format!(
+        "ffmpeg-{}-{}",
+        env!("CARGO_PKG_VERSION"),
+        FFMPEG_EXECUTABLE.len()
+    ))
+}
+
+#[cfg(bundled_ffmpeg)]
+fn sha256_file(path: &Path) -> std::io::Result<String> {
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
+
+#[cfg(bundled_ffmpeg)]
+fn is_intact(path: &Path) -> std::io::Result<bool> {
+    Ok(sha256_file(path)? == FFMPEG_SHA256)
+}
+
+/// Installs ffmpeg into `dir` from the zstd stream returned by `fetch`,
+/// unless an intact binary is already there.
+///
+/// The binary is decompressed to a process-specific file and atomically
+/// renamed into place, all while holding a lock file, so concurrent instances
+/// never execute a partially written binary. Both fresh installs and binaries
+/// left by earlier runs are checked against [`FFMPEG_SHA256`]; a mismatching
+/// binary from an earlier run is replaced.
+#[cfg(bundled_ffmpeg)]
+pub(crate) fn install(
+    dir: &Path,
+    fetch: impl FnOnce() -> std::io::Result<Box<dyn Read>>,
+) -> std::io::Result<PathBuf> {
+    use zstd::stream::read::Decoder;
+    fs::create_dir_all(dir)?;
+    let ffmpeg_path = dir.join(FFMPEG_FILE_NAME);
+
+    let lock = File::create(dir.join(".lock"))?;
+    lock.lock_exclusive()?;
+
+    if ffmpeg_path.is_file() && !is_intact(&ffmpeg_path)? {
+        warn!(
+            "Installed ffmpeg at {} failed verification, reinstalling",
+            ffmpeg_path.display()
+        );
+        fs::remove_file(&ffmpeg_path)?;
+    }
+
+    if !ffmpeg_path.is_file() {
+        let partial_path = dir.join(format!(
+            "{}.{}.partial",
+            FFMPEG_FILE_NAME,
+            std::process::id()
+        ));
+        let mut decoder = Decoder::new(fetch()?)?;
+        let mut out = File::create(&partial_path)?;
+        std::io::copy(&mut decoder, &mut out)?;
+        out.sync_all()?;
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mut perms = out.metadata()?.permissions();
+            perms.set_mode(0o755);
+            std::fs::set_permissions(&partial_path, perms)?;
+        }
+
+        drop(out);
+        if !is_intact(&partial_path)? {
+            fs::remove_file(&partial_path)?;
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "installed ffmpeg does not match the expected checksum",
+            ));
+        }
+        fs::rename(&partial_path, &ffmpeg_path)?;
+    }
+
+    lock.unlock()?;
+    Ok(ffmpeg_path)
+}
+
+/// Extracts the embedded ffmpeg unless another run already did.
+#[cfg(embedded_ffmpeg)]
+fn bundled_ffmpeg() -> Result<(PathBuf, FfmpegSource), String> {
+    install(&extraction_dir(), || {
+        Ok(Box::new(Cursor::new(FFMPEG_EXECUTABLE)))
+    })
+    .map(|path| (path, FfmpegSource::Embedded))
+    .map_err(|e| format!("Failed to extract ffmpeg: {}", e))
+}
+
+/// The ffmpeg fetched on first launch; see [`crate::ffmpeg_download`].
+#[cfg(feature = "download-ffmpeg")]
+fn bundled_ffmpeg() -> Result<(PathBuf, FfmpegSource), String> {
+    crate::ffmpeg_download::installed_path().map(|path| (path, FfmpegSource::Downloaded))
+}
+
+/// Builds with neither feature rely on the sidecar, or an ffmpeg in PATH.
+#[cfg(not(bundled_ffmpeg))]
+fn bundled_ffmpeg() -> Result<(PathBuf, FfmpegSource), String> {
+    find_in_path()
+        .map(|path| (path, FfmpegSource::System))
+        .ok_or_else(|| "This build has no ffmpeg of its own and none was found in PATH".to_string())
+}
+
+/// The sidecar bundled with the app, when there is one.
+fn sidecar_ffmpeg() -> Option<PathBuf> {
+    let path = env::current_exe().ok()?.parent()?.join(SIDECAR_FILE_NAME);
+    path.is_file().then_some(path)
+}
+
+/// Where the ffmpeg binary in use came from.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FfmpegSource {
+    Custom,
+    System,
+    /// Shipped next to the app's executable.
+    Sidecar,
+    Embedded,
+    Downloaded,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FfmpegInfo {
+    pub path: PathBuf,
+    pub version: String,
+    pub source: FfmpegSource,
+}
+
+/// Runs `ffmpeg -version` to check that `path` is a working ffmpeg binary
+/// for this machine and returns its version string.
+pub fn probe_version(path: &Path) -> Result<String, String> {
+    arch::check(path)?;
+    let output = Command::new(path)
+        .arg("-version")
+        .output()
+        .map_err(|e| format!("Failed to run {}: {}", path.display(), e))?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    match stdout
+        .lines()
+        .next()
+        .and_then(|l| l.strip_prefix("ffmpeg version "))
+    {
+        Some(rest) if output.status.success() => Ok(rest
+            .split_whitespace()
+            .next()
+            .unwrap_or("unknown")
+            .to_string()),
+        _ => Err(format!("{} is not an ffmpeg binary", path.display())),
+    }
+}
+
+fn find_in_path() -> Option<PathBuf> {
+    env::var_os("PATH").and_then(|paths| {
+        env::split_paths(&paths)
+            .map(|dir| dir.join(FFMPEG_FILE_NAME))
+            .find(|candidate| candidate.is_file())
+    })
+}
+
+fn probed(path: PathBuf, source: FfmpegSource) -> Result<FfmpegInfo, String> {
+    let version = probe_version(&path)?;
+    Ok(FfmpegInfo {
+        path,
+        version,
+        source,
+    })
+}
+
+/// Picks the binary to use: a user-specified path, then a system ffmpeg when
+/// preferred, then the sidecar, then the embedded (or downloaded) one.
+/// Binaries that fail the probe are skipped so a broken setting never leaves
+/// the app without ffmpeg.
+fn resolve_ffmpeg() -> Result<FfmpegInfo, String> {
+    let settings = settings::current();
+
+    if let Some(custom) = settings.ffmpeg_path {
+        match probed(custom, FfmpegSource::Custom) {
+            Ok(info) => return Ok(info),
+            Err(e) => warn!("Ignoring custom ffmpeg: {}", e),
+        }
+    }
+
+    if settings.prefer_system_ffmpeg {
+        match find_in_path().map(|path| probed(path, FfmpegSource::System)) {
+            Some(Ok(info)) => return Ok(info),
+            Some(Err(e)) => warn!("Ignoring system ffmpeg: {}", e),
+            None => info!("No system ffmpeg found in PATH"),
+        }
+    }
+
+    if let Some(path) = sidecar_ffmpeg() {
+        match probed(path, FfmpegSource::Sidecar) {
+            Ok(info) => return Ok(info),
+            Err(e) => warn!("Ignoring sidecar ffmpeg: {}", e),
+        }
+    }
+
+    let (path, source) = bundled_ffmpeg()?;
+    probed(path, source)
+}
+
+/// Finds the ffmpeg binary on first use and remembers it until the
+/// settings choosing it change.
+#[derive(Default)]
+pub struct FfmpegLocator {
+    cached: Mutex<Option<FfmpegInfo>>,
+}
+
+impl FfmpegLocator {
+    pub fn info(&self) -> Result<FfmpegInfo, String> {
+        let mut cached = self.cached.lock().unwrap();
+        if cached.is_none() {
+            *cached = Some(resolve_ffmpeg()?);
+        }
+        Ok(cached.clone().unwrap())
+    }
+
+    /// Forgets the resolved binary so the next use picks up changed settings.
+    pub fn reset(&self) {
+        *self.cached.lock().unwrap() = None;
+    }
+}
+
+/// The process's locator, shared with [`crate::state::AppState`].
+pub fn locator() -> Arc<FfmpegLocator> {
+    LOCATOR.clone()
+}
+
+pub fn get_ffmpeg_info() -> Result<FfmpegInfo, String> {
+    LOCATOR.info()
+}
+
+/// Forgets the resolved binary so the next use picks up changed settings.
+pub fn reset() {
+    LOCATOR.reset();
+}
+
+/// The ffmpeg binary, or an error when there is none yet, as before a
+/// first-run download has finished.
+pub fn get_ffmpeg_path() -> Result<PathBuf, ProcessError> {
+    get_ffmpeg_info()
+        .map(|info| info.path)
+        .map_err(|e| ProcessError::new(format!("Failed to locate ffmpeg: {}", e)))
+}
+
+/// An ffmpeg command with the process-wide settings applied. The banner is
+/// suppressed so stderr, and with it job logs and errors, starts with what
+/// ffmpeg actually did.
+pub fn command() -> Result<Command, ProcessError> {
+    let mut command = Command::new(get_ffmpeg_path()?);
+    command.arg("-hide_banner");
+    priority::configure_command(&mut command);
+    Ok(command)
+}
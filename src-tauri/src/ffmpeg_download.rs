@@ -0,0 +1,102 @@
+//! Fetches ffmpeg on first launch for builds with the `download-ffmpeg`
+//! feature, which leave the platform binaries out of the installer.
+//!
+//! The download is the same zstd payload that is otherwise embedded, so it
+//! goes through [`ffmpeg::install`] and the same checksum verification.
+
+use crate::ffmpeg;
+use crate::settings;
+use once_cell::sync::Lazy;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Base URL of the compressed binaries. Packagers can point this at a mirror
+/// by setting `DEAD_FRAMES_FFMPEG_URL` at build time.
+const BASE_URL: &str = match option_env!("DEAD_FRAMES_FFMPEG_URL") {
+    Some(url) => url,
+    None => "https://github.com/cernoh/dead-frame-remover-gui/releases/download/ffmpeg",
+};
+
+static INSTALL_DIR: Lazy<Mutex<Option<PathBuf>>> = Lazy::new(|| Mutex::new(None));
+
+/// Sets the directory (under the app data dir) ffmpeg is downloaded into.
+pub fn init(app_data_dir: &Path) {
+    *INSTALL_DIR.lock().unwrap() =
+        Some(app_data_dir.join("ffmpeg").join(env!("CARGO_PKG_VERSION")));
+}
+
+fn install_dir() -> Result<PathBuf, String> {
+    INSTALL_DIR
+        .lock()
+        .unwrap()
+        .clone()
+        .ok_or_else(|| "ffmpeg install directory is not initialised".to_string())
+}
+
+/// Path of the downloaded binary, if the download has completed.
+pub fn installed_path() -> Result<PathBuf, String> {
+    let path = install_dir()?.join(ffmpeg::FFMPEG_FILE_NAME);
+    if path.is_file() {
+        Ok(path)
+    } else {
+        Err("ffmpeg has not been downloaded yet".to_string())
+    }
+}
+
+/// Passes every read through to `progress` with the running byte count.
+struct ProgressReader<R, F> {
+    inner: R,
+    read: u64,
+    total: Option<u64>,
+    progress: F,
+}
+
+impl<R: Read, F: FnMut(u64, Option<u64>)> Read for ProgressReader<R, F> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.read += n as u64;
+        (self.progress)(self.read, self.total);
+        Ok(n)
+    }
+}
+
+fn agent() -> Result<ureq::Agent, String> {
+    let mut builder = ureq::AgentBuilder::new().try_proxy_from_env(true);
+    if let Some(proxy) = settings::current().download_proxy {
+        let proxy = ureq::Proxy::new(&proxy).map_err(|e| format!("Invalid proxy: {}", e))?;
+        builder = builder.proxy(proxy);
+    }
+    Ok(builder.build())
+}
+
+/// Downloads and verifies ffmpeg unless an intact copy is already installed.
+///
+/// `progress` receives the downloaded byte count and, when the server sends
+/// it, the total size. The proxy comes from the `download_proxy` setting or
+/// the usual `HTTPS_PROXY`/`ALL_PROXY` environment variables.
+pub fn download(progress: impl FnMut(u64, Option<u64>) + 'static) -> Result<PathBuf, String> {
+    let dir = install_dir()?;
+    let url = format!("{}/{}", BASE_URL, ffmpeg::FFMPEG_ASSET);
+    let agent = agent()?;
+
+    let path = ffmpeg::install(&dir, move || {
+        let response = agent
+            .get(&url)
+            .call()
+            .map_err(|e| std::io::Error::other(format!("Failed to download {}: {}", url, e)))?;
+        let total = response
+            .header("Content-Length")
+            .and_then(|len| len.parse().ok());
+        Ok(Box::new(ProgressReader {
+            inner: response.into_reader(),
+            read: 0,
+            total,
+            progress,
+        }))
+    })
+    .map_err(|e| e.to_string())?;
+
+    ffmpeg::reset();
+    Ok(path)
+}
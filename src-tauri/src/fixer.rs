@@ -0,0 +1,397 @@
+//! A builder for embedding the engine in other Rust programs.
+//!
+//! ```no_run
+//! use dead_frames_lib::similarity::Metric;
+//! use dead_frames_lib::VideoFixer;
+//!
+//! let summary = VideoFixer::new("recording.mp4")
+//!     .threshold(0.97)
+//!     .exit_threshold(0.93)
+//!     .metric(Metric::Ssim)
+//!     .on_progress(|p| eprintln!("{:?} {}/{}", p.stage, p.done, p.total))
+//!     .run()?;
+//! println!("removed {} frames", summary.frames_removed);
+//! # Ok::<(), dead_frames_lib::error::ProcessError>(())
+//! ```
+//!
+//! Options not set on the builder come from [`settings::current`], which
+//! holds the defaults unless [`settings::init`] loaded a settings file.
+//!
+//! [`settings::current`]: crate::settings::current
+//! [`settings::init`]: crate::settings::init
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::control::{CancellationToken, FrameDecision, JobControl, PauseToken, Progress};
+use crate::crop::CropMode;
+use crate::cutlist::CutListFormat;
+use crate::error::ProcessError;
+use crate::glitch::GlitchAction;
+use crate::mezzanine::{DnxhrProfile, ProResProfile};
+use crate::output::CollisionPolicy;
+use crate::representative::Representative;
+use crate::sections::Section;
+use crate::similarity::{ColorSpace, Metric};
+use crate::timelapse::Timelapse;
+use crate::video_fixer::{
+    self, Analysis, Deinterlace, FrameFormat, JobSummary, ProcessOptions, SequenceFormat,
+    VideoCodec,
+};
+
+/// One job on one input video, configured step by step and then run with
+/// [`run`](Self::run) or [`analyze`](Self::analyze). Both block until the
+/// job is done; run them on a worker thread to keep a UI responsive.
+#[derive(Clone)]
+pub struct VideoFixer {
+    input: PathBuf,
+    options: ProcessOptions,
+    control: JobControl,
+}
+
+impl VideoFixer {
+    pub fn new(input: impl Into<PathBuf>) -> Self {
+        VideoFixer {
+            input: input.into(),
+            options: ProcessOptions::default(),
+            control: JobControl::default(),
+        }
+    }
+
+    /// Replaces all options at once, e.g. with those of a preset.
+    pub fn options(mut self, options: ProcessOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Frames scoring above this against their successor are removed.
+    pub fn threshold(mut self, threshold: f32) -> Self {
+        self.options.threshold = threshold;
+        self
+    }
+
+    /// Once a frame is dead, frames stay dead while they score above this,
+    /// which is below the threshold.
+    pub fn exit_threshold(mut self, exit_threshold: f32) -> Self {
+        self.options.exit_threshold = Some(exit_threshold);
+        self
+    }
+
+    pub fn metric(mut self, metric: Metric) -> Self {
+        self.options.metric = metric;
+        self
+    }
+
+    /// Compares frames in `color_space` rather than by their luma alone.
+    pub fn color_space(mut self, color_space: ColorSpace) -> Self {
+        self.options.color_space = color_space;
+        self
+    }
+
+    /// Analyses a time range with its own threshold or metric. May be
+    /// called once per section; later sections win where they overlap.
+    pub fn section(mut self, section: Section) -> Self {
+        self.options.sections.push(section);
+        self
+    }
+
+    /// Compares each frame with this many of the last kept frames rather
+    /// than only its successor.
+    pub fn comparison_window(mut self, frames: usize) -> Self {
+        self.options.comparison_window = frames;
+        self
+    }
+
+    /// Compares only every `step`th frame at first, and frame by frame only
+    /// around the dead spans found; see [`coarse`](crate::coarse).
+    pub fn analysis_step(mut self, step: usize) -> Self {
+        self.options.analysis_step = Some(step);
+        self
+    }
+
+    /// Merges removed spans with fewer than `frames` kept frames between
+    /// them, removing those frames too.
+    pub fn merge_gap(mut self, frames: usize) -> Self {
+        self.options.merge_gap = frames;
+        self
+    }
+
+    /// Keeps this frame of each run of alike frames rather than the one
+    /// the comparison keeps.
+    pub fn representative(mut self, representative: Representative) -> Self {
+        self.options.representative = Some(representative);
+        self
+    }
+
+    /// Also removes frames less sharp than `threshold`; see
+    /// [`blur`](crate::blur).
+    pub fn blur_threshold(mut self, threshold: f64) -> Self {
+        self.options.blur_threshold = Some(threshold);
+        self
+    }
+
+    /// Removes or replaces single glitched frames; see
+    /// [`glitch`](crate::glitch).
+    pub fn glitches(mut self, action: GlitchAction) -> Self {
+        self.options.glitches = Some(action);
+        self
+    }
+
+    /// Lets the Rhai script at `path` keep or remove each frame after the
+    /// analysis; see [`script`](crate::script). Needs the `scripting`
+    /// feature.
+    pub fn script(mut self, path: impl Into<PathBuf>) -> Self {
+        self.options.script = Some(path.into());
+        self
+    }
+
+    /// Runs the detector plugin at `path` after the analysis; see
+    /// [`plugins`](crate::plugins). Needs the `plugins` feature.
+    pub fn plugin(mut self, path: impl Into<PathBuf>) -> Self {
+        self.options.plugins.push(path.into());
+        self
+    }
+
+    /// Builds a timelapse instead of removing only dead frames; see
+    /// [`timelapse`](crate::timelapse).
+    pub fn timelapse(mut self, timelapse: Timelapse) -> Self {
+        self.options.timelapse = Some(timelapse);
+        self
+    }
+
+    /// Removes only the frames the encoder repeated; see
+    /// [`duplicates`](crate::duplicates).
+    pub fn encoder_duplicates(mut self, encoder_duplicates: bool) -> Self {
+        self.options.encoder_duplicates = encoder_duplicates;
+        self
+    }
+
+    pub fn codec(mut self, codec: VideoCodec) -> Self {
+        self.options.codec = codec;
+        self
+    }
+
+    pub fn prores_profile(mut self, profile: ProResProfile) -> Self {
+        self.options.mezzanine.prores = profile;
+        self
+    }
+
+    pub fn dnxhr_profile(mut self, profile: DnxhrProfile) -> Self {
+        self.options.mezzanine.dnxhr = profile;
+        self
+    }
+
+    /// Encodes in two passes at this bitrate in kbit/s rather than at
+    /// constant quality. Needs H.264 or H.265.
+    pub fn target_bitrate(mut self, kbps: u32) -> Self {
+        self.options.target_bitrate = Some(kbps);
+        self
+    }
+
+    /// Encodes in two passes at the bitrate that keeps the output within
+    /// this many megabytes. Needs H.264 or H.265.
+    pub fn target_size_mb(mut self, mb: f64) -> Self {
+        self.options.target_size_mb = Some(mb);
+        self
+    }
+
+    pub fn frame_format(mut self, frame_format: FrameFormat) -> Self {
+        self.options.frame_format = frame_format;
+        self
+    }
+
+    /// Frame rate of the output instead of the source's.
+    pub fn framerate(mut self, fps: f64) -> Self {
+        self.options.framerate = Some(fps);
+        self
+    }
+
+    /// Plays the kept frames at this frame rate and keeps the source's
+    /// audio, its tempo changed to match; see [`retime`](crate::retime).
+    pub fn retime(mut self, fps: f64) -> Self {
+        self.options.retime = Some(fps);
+        self
+    }
+
+    /// Directory for the processed video instead of the input's.
+    pub fn output_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.options.output.dir = Some(dir.into());
+        self
+    }
+
+    /// Output name template; see [`crate::output::OutputOptions`].
+    pub fn template(mut self, template: impl Into<String>) -> Self {
+        self.options.output.template = template.into();
+        self
+    }
+
+    pub fn collision(mut self, collision: CollisionPolicy) -> Self {
+        self.options.output.collision = collision;
+        self
+    }
+
+    /// Write the kept frames as an image sequence instead of a video.
+    pub fn image_sequence(mut self, format: SequenceFormat) -> Self {
+        self.options.image_sequence = Some(format);
+        self
+    }
+
+    /// Stream-copy untouched GOPs and re-encode only around cut points.
+    pub fn smart_cut(mut self, smart_cut: bool) -> Self {
+        self.options.smart_cut = smart_cut;
+        self
+    }
+
+    /// Write a side-car JSON file mapping output frames to source times.
+    pub fn timestamp_map(mut self, timestamp_map: bool) -> Self {
+        self.options.timestamp_map = timestamp_map;
+        self
+    }
+
+    /// Compare the output with the source afterwards and report SSIM/VMAF.
+    pub fn verify_quality(mut self, verify_quality: bool) -> Self {
+        self.options.verify_quality = verify_quality;
+        self
+    }
+
+    /// Estimate the output size in [`analyze`](Self::analyze) with a short
+    /// test encode instead of the codec's typical bitrate.
+    pub fn test_encode(mut self, test_encode: bool) -> Self {
+        self.options.test_encode = test_encode;
+        self
+    }
+
+    /// Set removed frames aside so they can be restored later; see
+    /// [`crate::undo`].
+    pub fn keep_removed(mut self, keep_removed: bool) -> Self {
+        self.options.keep_removed = keep_removed;
+        self
+    }
+
+    /// Keep memory use bounded for very large sources; see
+    /// [`ProcessOptions::low_memory`].
+    pub fn low_memory(mut self, low_memory: bool) -> Self {
+        self.options.low_memory = low_memory;
+        self
+    }
+
+    /// Give the same output and removed frames on every run; see
+    /// [`ProcessOptions::deterministic`].
+    pub fn deterministic(mut self, deterministic: bool) -> Self {
+        self.options.deterministic = deterministic;
+        self
+    }
+
+    /// Take the cached analysis of an earlier job on the same file with the
+    /// same options, if there is one; see [`ProcessOptions::reuse_analysis`].
+    pub fn reuse_analysis(mut self, reuse_analysis: bool) -> Self {
+        self.options.reuse_analysis = reuse_analysis;
+        self
+    }
+
+    /// Encode at 8 bits even when the source has more.
+    pub fn force_8bit(mut self, force_8bit: bool) -> Self {
+        self.options.force_8bit = force_8bit;
+        self
+    }
+
+    /// Deinterlace frames as they are extracted; see [`Deinterlace`].
+    pub fn deinterlace(mut self, deinterlace: Deinterlace) -> Self {
+        self.options.deinterlace = deinterlace;
+        self
+    }
+
+    /// Leave black bars out of the comparison, or crop them from the
+    /// output as well; see [`CropMode`].
+    pub fn crop(mut self, crop: CropMode) -> Self {
+        self.options.crop = crop;
+        self
+    }
+
+    /// Process video stream `index`, counted among the source's video
+    /// streams; see [`crate::streams::list`].
+    pub fn video_stream(mut self, index: usize) -> Self {
+        self.options.video_stream_index = Some(index);
+        self
+    }
+
+    /// Download URL inputs before processing them instead of reading them
+    /// over the network.
+    pub fn cache_remote(mut self, cache_remote: bool) -> Self {
+        self.options.cache_remote = cache_remote;
+        self
+    }
+
+    /// Encode with NVENC where the codec and ffmpeg allow it.
+    pub fn hardware_encode(mut self, hardware_encode: bool) -> Self {
+        self.options.hardware_encode = hardware_encode;
+        self
+    }
+
+    /// Decode on the GPU while extracting frames, where ffmpeg can.
+    pub fn hardware_decode(mut self, hardware_decode: bool) -> Self {
+        self.options.hardware_decode = hardware_decode;
+        self
+    }
+
+    /// Copy every removed frame into `dir` for inspection.
+    pub fn export_removed(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.options.export_removed = Some(dir.into());
+        self
+    }
+
+    /// Write a cut list or marker list instead of encoding anything.
+    pub fn cut_list(mut self, format: CutListFormat) -> Self {
+        self.options.cut_list = Some(format);
+        self
+    }
+
+    /// Called from the job's threads as each stage starts and as frames are
+    /// compared. Keep it cheap; it runs once per frame pair.
+    pub fn on_progress(mut self, callback: impl Fn(Progress) + Send + Sync + 'static) -> Self {
+        self.control.progress = Some(Arc::new(callback));
+        self
+    }
+
+    /// Called with batches of frame decisions while frames are compared, a
+    /// few times a second, so a live timeline can be drawn.
+    pub fn on_decisions(
+        mut self,
+        callback: impl Fn(Vec<FrameDecision>) + Send + Sync + 'static,
+    ) -> Self {
+        self.control.decisions = Some(Arc::new(callback));
+        self
+    }
+
+    /// Stops the job when `token` is cancelled; it then fails with a
+    /// [`ProcessError`] whose `cancelled` flag is set.
+    pub fn cancellation(mut self, token: CancellationToken) -> Self {
+        self.control.cancel = token;
+        self
+    }
+
+    /// Holds the job while `token` is paused, suspending ffmpeg if it runs.
+    pub fn pausing(mut self, token: PauseToken) -> Self {
+        self.control.pause = token;
+        self
+    }
+
+    pub fn input(&self) -> &Path {
+        &self.input
+    }
+
+    /// The options the job will run with.
+    pub fn process_options(&self) -> &ProcessOptions {
+        &self.options
+    }
+
+    /// Removes dead frames and writes the processed video.
+    pub fn run(&self) -> Result<JobSummary, ProcessError> {
+        video_fixer::process(&self.input, &self.options, &self.control)
+    }
+
+    /// Scores every frame without writing a video.
+    pub fn analyze(&self) -> Result<Analysis, ProcessError> {
+        video_fixer::analyze(&self.input, &self.options, &self.control)
+    }
+}
@@ -0,0 +1,39 @@
+//! Glitches: single frames unlike both their neighbours while the
+//! neighbours are alike, such as the green or torn frames capture cards let
+//! through. They can be removed, or replaced by a blend of the frames
+//! either side of them, which keeps the timing.
+
+use serde::{Deserialize, Serialize};
+
+/// What is done with a glitch.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GlitchAction {
+    Remove,
+    /// Replace the frame with the mean of the frames before and after it.
+    Replace,
+}
+
+/// How many times as unlike its neighbours a glitch is as they are unlike
+/// each other, taking one minus the score for how unlike two frames are.
+pub const CONTRAST: f32 = 4.0;
+
+/// How unlike both its neighbours a glitch is at least, so that noise in a
+/// still shot is not taken for one.
+pub const MIN_DIFFERENCE: f32 = 0.1;
+
+/// Whether a frame scoring `before` against the frame before it and `after`
+/// against the one after it, whose neighbours score `across`, is a glitch.
+pub fn is_glitch(before: f32, after: f32, across: f32) -> bool {
+    let difference = 1.0 - before.max(after);
+    difference >= MIN_DIFFERENCE && difference > CONTRAST * (1.0 - across)
+}
+
+/// The frames that can be glitches by the score of every frame against its
+/// successor: those at least [`MIN_DIFFERENCE`] unlike both neighbours. The
+/// first and last frames have only one neighbour and never are.
+pub fn candidates(scores: &[f32]) -> Vec<usize> {
+    (1..scores.len())
+        .filter(|&frame| 1.0 - scores[frame - 1].max(scores[frame]) >= MIN_DIFFERENCE)
+        .collect()
+}
@@ -0,0 +1,224 @@
+//! wgpu compute implementation of the luma SSIM metric.
+//!
+//! Frames are uploaded as packed bytes, every invocation scores four pixels
+//! and each workgroup reduces its scores to a single partial sum, so only a
+//! few kilobytes have to be read back per frame pair.
+
+use image::GrayImage;
+use once_cell::sync::Lazy;
+use std::sync::Mutex;
+use wgpu::util::DeviceExt;
+
+const WORKGROUP_SIZE: u32 = 256;
+const PIXELS_PER_INVOCATION: u32 = 4;
+const MAX_WORKGROUPS: u32 = 65535;
+
+const SHADER: &str = r#"
+struct Params {
+    pixel_count: u32,
+    c1: f32,
+}
+
+@group(0) @binding(0) var<storage, read> frame1: array<u32>;
+@group(0) @binding(1) var<storage, read> frame2: array<u32>;
+@group(0) @binding(2) var<storage, read_write> partials: array<f32>;
+@group(0) @binding(3) var<uniform> params: Params;
+
+var<workgroup> scratch: array<f32, 256>;
+
+@compute @workgroup_size(256)
+fn main(
+    @builtin(global_invocation_id) gid: vec3<u32>,
+    @builtin(local_invocation_index) lid: u32,
+    @builtin(workgroup_id) wid: vec3<u32>,
+) {
+    let word = gid.x;
+    var sum = 0.0;
+    for (var i = 0u; i < 4u; i++) {
+        if (word * 4u + i < params.pixel_count) {
+            let p1 = f32((frame1[word] >> (8u * i)) & 0xffu);
+            let p2 = f32((frame2[word] >> (8u * i)) & 0xffu);
+            sum += (2.0 * p1 * p2 + params.c1) / (p1 * p1 + p2 * p2 + params.c1);
+        }
+    }
+    scratch[lid] = sum;
+    workgroupBarrier();
+
+    for (var stride = 128u; stride > 0u; stride >>= 1u) {
+        if (lid < stride) {
+            scratch[lid] += scratch[lid + stride];
+        }
+        workgroupBarrier();
+    }
+    if (lid == 0u) {
+        partials[wid.x] = scratch[0];
+    }
+}
+"#;
+
+pub struct GpuComparator {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    pipeline: wgpu::ComputePipeline,
+}
+
+/// Lazily initialised comparator; `None` when no suitable adapter exists.
+static GPU: Lazy<Option<Mutex<GpuComparator>>> = Lazy::new(|| GpuComparator::new().map(Mutex::new));
+
+/// Whether a GPU comparator could be created on this machine.
+pub fn is_available() -> bool {
+    GPU.is_some()
+}
+
+/// Scores a frame pair on the GPU, or returns `None` when no adapter exists.
+pub fn ssim_luma(
+    grey1: &GrayImage,
+    grey2: &GrayImage,
+) -> Option<Result<f32, Box<dyn std::error::Error>>> {
+    GPU.as_ref()
+        .map(|gpu| gpu.lock().unwrap().ssim_luma(grey1, grey2))
+}
+
+impl GpuComparator {
+    fn new() -> Option<Self> {
+        let instance = wgpu::Instance::default();
+        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            ..Default::default()
+        }))
+        .ok()?;
+        // software rasterizers are slower than the SIMD path
+        if adapter.get_info().device_type == wgpu::DeviceType::Cpu {
+            return None;
+        }
+        let (device, queue) =
+            pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor::default())).ok()?;
+
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("ssim"),
+            source: wgpu::ShaderSource::Wgsl(SHADER.into()),
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("ssim"),
+            layout: None,
+            module: &module,
+            entry_point: Some("main"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+
+        Some(GpuComparator {
+            device,
+            queue,
+            pipeline,
+        })
+    }
+
+    fn upload(&self, label: &str, image: &GrayImage) -> wgpu::Buffer {
+        let mut bytes = image.as_raw().clone();
+        bytes.resize(bytes.len().next_multiple_of(4), 0);
+        self.device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(label),
+                contents: &bytes,
+                usage: wgpu::BufferUsages::STORAGE,
+            })
+    }
+
+    pub fn ssim_luma(
+        &self,
+        grey1: &GrayImage,
+        grey2: &GrayImage,
+    ) -> Result<f32, Box<dyn std::error::Error>> {
+        if grey1.dimensions() != grey2.dimensions() {
+            return Err("images are different dimensions".into());
+        }
+        let (width, height) = grey1.dimensions();
+        let pixel_count = width * height;
+        if pixel_count == 0 {
+            return Err("images are empty".into());
+        }
+        let workgroups = pixel_count.div_ceil(PIXELS_PER_INVOCATION * WORKGROUP_SIZE);
+        if workgroups > MAX_WORKGROUPS {
+            return Err("frame is too large for a single dispatch".into());
+        }
+
+        let frame1 = self.upload("frame1", grey1);
+        let frame2 = self.upload("frame2", grey2);
+        let c1 = (0.01f32 * 255.0).powi(2);
+        let mut params = [0u8; 8];
+        params[..4].copy_from_slice(&pixel_count.to_le_bytes());
+        params[4..].copy_from_slice(&c1.to_le_bytes());
+        let params = self
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("params"),
+                contents: &params,
+                usage: wgpu::BufferUsages::UNIFORM,
+            });
+
+        let partials_size = workgroups as u64 * 4;
+        let partials = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("partials"),
+            size: partials_size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readback = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("readback"),
+            size: partials_size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("ssim"),
+            layout: &self.pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: frame1.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: frame2.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: partials.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: params.as_entire_binding(),
+                },
+            ],
+        });
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("ssim"),
+            });
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor::default());
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch_workgroups(workgroups, 1, 1);
+        }
+        encoder.copy_buffer_to_buffer(&partials, 0, &readback, 0, partials_size);
+        self.queue.submit(Some(encoder.finish()));
+
+        let slice = readback.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |_| {});
+        self.device.poll(wgpu::PollType::wait_indefinitely())?;
+
+        let ssim_sum: f64 = slice
+            .get_mapped_range()
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64)
+            .sum();
+        readback.unmap();
+
+        Ok((ssim_sum / pixel_count as f64) as f32)
+    }
+}
@@ -0,0 +1,195 @@
+//! Completed jobs, kept in a SQLite database in the app data dir so users
+//! can look back at earlier runs.
+
+use once_cell::sync::Lazy;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tracing::warn;
+
+use crate::video_fixer::{JobSummary, ProcessOptions};
+
+/// Entries returned when the caller does not ask for a specific number.
+pub const DEFAULT_LIMIT: usize = 200;
+
+/// The history the engine and app of this process record to.
+static STORE: Lazy<Arc<History>> = Lazy::new(Arc::default);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HistoryEntry {
+    pub id: i64,
+    pub input: String,
+    pub output: String,
+    /// The [`ProcessOptions`] the job ran with.
+    pub options: serde_json::Value,
+    pub frames_total: u64,
+    pub frames_removed: u64,
+    pub duration_secs: f64,
+    /// RFC 3339 timestamp of when the job finished.
+    pub finished_at: String,
+}
+
+fn open_db(path: &Path) -> rusqlite::Result<Connection> {
+    let connection = Connection::open(path)?;
+    connection.execute_batch(
+        "CREATE TABLE IF NOT EXISTS jobs (
+            id INTEGER PRIMARY KEY,
+            input TEXT NOT NULL,
+            output TEXT NOT NULL,
+            options TEXT NOT NULL,
+            frames_total INTEGER NOT NULL,
+            frames_removed INTEGER NOT NULL,
+            duration_secs REAL NOT NULL,
+            finished_at TEXT NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS jobs_finished_at ON jobs (finished_at);",
+    )?;
+    Ok(connection)
+}
+
+fn entry(row: &Row) -> rusqlite::Result<HistoryEntry> {
+    let options: String = row.get("options")?;
+    Ok(HistoryEntry {
+        id: row.get("id")?,
+        input: row.get("input")?,
+        output: row.get("output")?,
+        options: serde_json::from_str(&options).unwrap_or_default(),
+        frames_total: row.get("frames_total")?,
+        frames_removed: row.get("frames_removed")?,
+        duration_secs: row.get("duration_secs")?,
+        finished_at: row.get("finished_at")?,
+    })
+}
+
+/// Completed jobs, in the database [`History::open`] opens.
+#[derive(Default)]
+pub struct History {
+    db: Mutex<Option<Connection>>,
+}
+
+impl History {
+    /// Opens (or creates) the history database in `app_data_dir`. History is
+    /// best effort; without a database jobs simply are not recorded.
+    pub fn open(&self, app_data_dir: &Path) {
+        if let Err(e) = fs::create_dir_all(app_data_dir) {
+            warn!("Failed to create {}: {}", app_data_dir.display(), e);
+            return;
+        }
+        match open_db(&app_data_dir.join("history.sqlite3")) {
+            Ok(connection) => *self.db.lock().unwrap() = Some(connection),
+            Err(e) => warn!("Failed to open job history: {}", e),
+        }
+    }
+
+    /// Closes the database, for when the app exits. Jobs finishing afterwards
+    /// are not recorded.
+    pub fn close(&self) {
+        if let Some(connection) = self.db.lock().unwrap().take() {
+            if let Err((_, e)) = connection.close() {
+                warn!("Failed to close job history: {}", e);
+            }
+        }
+    }
+
+    fn with_db<T>(&self, f: impl FnOnce(&Connection) -> rusqlite::Result<T>) -> Result<T, String> {
+        let db = self.db.lock().unwrap();
+        let connection = db
+            .as_ref()
+            .ok_or_else(|| "Job history is not available".to_string())?;
+        f(connection).map_err(|e| format!("Job history error: {}", e))
+    }
+
+    /// Records a finished job. Skipped jobs are not recorded.
+    pub fn record(&self, input: &str, options: &ProcessOptions, summary: &JobSummary) {
+        if summary.skipped {
+            return;
+        }
+        let options = serde_json::to_string(options).unwrap_or_default();
+        let result = self.with_db(|db| {
+            db.execute(
+                "INSERT INTO jobs (input, output, options, frames_total, frames_removed,
+                    duration_secs, finished_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    input,
+                    summary.output,
+                    options,
+                    summary.frames_total as u64,
+                    summary.frames_removed as u64,
+                    summary.elapsed_secs,
+                    chrono::Local::now().to_rfc3339(),
+                ],
+            )
+        });
+        if let Err(e) = result {
+            warn!("Failed to record job: {}", e);
+        }
+    }
+
+    /// The most recent jobs, newest first.
+    pub fn recent(&self, limit: usize) -> Result<Vec<HistoryEntry>, String> {
+        self.with_db(|db| {
+            db.prepare("SELECT * FROM jobs ORDER BY finished_at DESC, id DESC LIMIT ?1")?
+                .query_map([limit as i64], entry)?
+                .collect()
+        })
+    }
+
+    /// Jobs whose input or output path contains `query`, newest first.
+    pub fn search(&self, query: &str, limit: usize) -> Result<Vec<HistoryEntry>, String> {
+        let pattern = format!(
+            "%{}%",
+            query
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_")
+        );
+        self.with_db(|db| {
+            db.prepare(
+                "SELECT * FROM jobs
+                 WHERE input LIKE ?1 ESCAPE '\\' OR output LIKE ?1 ESCAPE '\\'
+                 ORDER BY finished_at DESC, id DESC LIMIT ?2",
+            )?
+            .query_map(params![pattern, limit as i64], entry)?
+            .collect()
+        })
+    }
+
+    /// The latest job on `input`, if one was recorded.
+    pub fn latest(&self, input: &str) -> Result<Option<HistoryEntry>, String> {
+        self.with_db(|db| {
+            db.query_row(
+                "SELECT * FROM jobs WHERE input = ?1 ORDER BY finished_at DESC, id DESC LIMIT 1",
+                [input],
+                entry,
+            )
+            .optional()
+        })
+    }
+
+    pub fn clear(&self) -> Result<(), String> {
+        self.with_db(|db| db.execute("DELETE FROM jobs", []).map(|_| ()))
+    }
+}
+
+/// The process's history, shared with [`crate::state::AppState`].
+pub fn store() -> Arc<History> {
+    STORE.clone()
+}
+
+/// Opens (or creates) the history database in `app_data_dir`.
+pub fn init(app_data_dir: &Path) {
+    STORE.open(app_data_dir);
+}
+
+/// Records a finished job. Skipped jobs are not recorded.
+pub fn record(input: &str, options: &ProcessOptions, summary: &JobSummary) {
+    STORE.record(input, options, summary);
+}
+
+/// The latest job on `input`, if one was recorded.
+pub fn latest(input: &str) -> Result<Option<HistoryEntry>, String> {
+    STORE.latest(input)
+}
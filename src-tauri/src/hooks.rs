@@ -0,0 +1,157 @@
+//! Shell commands run around each job, to tie the app into a pipeline of
+//! the user's: tell a render farm a file is taken, update a database,
+//! move the output on.
+//!
+//! Each hook runs through the shell with the job in its environment:
+//! `DFR_EVENT` (`before-job`, `after-job` or `failure`), `DFR_JOB` and
+//! `DFR_INPUT`, then `DFR_OUTPUT`, `DFR_FRAMES_TOTAL` and
+//! `DFR_FRAMES_REMOVED` once a job finishes, or `DFR_ERROR` once it
+//! fails. `before_job` holds the job until it exits; the others run
+//! alongside the queue. Hooks still running after [`TIMEOUT`] are killed,
+//! along with anything they started.
+
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::process::{Child, Command};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+use crate::error::ProcessError;
+use crate::video_fixer::JobSummary;
+
+/// How long a hook may run before it is killed; a job waiting for
+/// `before_job` then goes ahead anyway.
+pub const TIMEOUT: Duration = Duration::from_secs(300);
+
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The persisted hook commands; a hook left unset does nothing.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Hooks {
+    /// Run as each job starts, which waits for it.
+    pub before_job: Option<String>,
+    /// Run after each job that finished. Settings from before version 3
+    /// had this as `post_actions.command`.
+    pub after_job: Option<String>,
+    /// Run after each job that failed.
+    pub on_failure: Option<String>,
+}
+
+/// Runs `before_job` for the job `job` on `input` and waits for it.
+pub fn before_job(hooks: &Hooks, job: u64, input: &Path) {
+    let Some(command) = &hooks.before_job else {
+        return;
+    };
+    let mut shell = shell(command, "before-job", job, input);
+    match shell.spawn() {
+        Ok(child) => wait(child, command),
+        Err(e) => warn!("Failed to run hook {}: {}", command, e),
+    }
+}
+
+/// Runs `after_job` for the job `job` that turned `input` into the output
+/// of `summary`, in the background.
+pub fn after_job(hooks: &Hooks, job: u64, input: &Path, summary: &JobSummary) {
+    let Some(command) = &hooks.after_job else {
+        return;
+    };
+    let mut shell = shell(command, "after-job", job, input);
+    shell
+        .env("DFR_OUTPUT", &summary.output)
+        .env("DFR_FRAMES_TOTAL", summary.frames_total.to_string())
+        .env("DFR_FRAMES_REMOVED", summary.frames_removed.to_string());
+    spawn(shell, command);
+}
+
+/// Runs `on_failure` for the job `job` on `input` that failed with
+/// `error`, in the background.
+pub fn job_failed(hooks: &Hooks, job: u64, input: &Path, error: &ProcessError) {
+    let Some(command) = &hooks.on_failure else {
+        return;
+    };
+    let mut shell = shell(command, "failure", job, input);
+    shell.env("DFR_ERROR", &error.message);
+    spawn(shell, command);
+}
+
+/// `command` run through the platform's shell. On Unix the shell leads a
+/// process group of its own, so a hook that timed out can be killed with
+/// whatever it started.
+fn shell(command: &str, event: &str, job: u64, input: &Path) -> Command {
+    let mut shell = if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.args(["/C", command]);
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.args(["-c", command]);
+        shell
+    };
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut shell, 0);
+    shell
+        .env("DFR_EVENT", event)
+        .env("DFR_JOB", job.to_string())
+        .env("DFR_INPUT", input);
+    shell
+}
+
+fn spawn(mut shell: Command, command: &str) {
+    match shell.spawn() {
+        Ok(child) => {
+            let command = command.to_string();
+            thread::spawn(move || wait(child, &command));
+        }
+        Err(e) => warn!("Failed to run hook {}: {}", command, e),
+    }
+}
+
+/// Waits for the hook `command` run as `child`, killing it should it take
+/// longer than [`TIMEOUT`].
+fn wait(mut child: Child, command: &str) {
+    let started = Instant::now();
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) => {
+                if !status.success() {
+                    warn!("Hook {} exited with {}", command, status);
+                }
+                return;
+            }
+            Ok(None) if started.elapsed() > TIMEOUT => {
+                warn!("Hook {} is taking too long, killing it", command);
+                kill_tree(&mut child);
+                return;
+            }
+            Ok(None) => thread::sleep(POLL_INTERVAL),
+            Err(e) => {
+                warn!("Failed to wait for hook {}: {}", command, e);
+                return;
+            }
+        }
+    }
+}
+
+/// Kills the shell `child` and the processes it started, which would
+/// otherwise be left running once the shell is gone.
+fn kill_tree(child: &mut Child) {
+    #[cfg(unix)]
+    // SAFETY: a plain syscall; the shell leads its own group, see `shell`
+    unsafe {
+        libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+    }
+    #[cfg(windows)]
+    {
+        let status = Command::new("taskkill")
+            .args(["/T", "/F", "/PID"])
+            .arg(child.id().to_string())
+            .output();
+        if let Err(e) = status {
+            warn!("Failed to run taskkill: {}", e);
+        }
+    }
+    let _ = child.kill();
+    let _ = child.wait();
+}
@@ -0,0 +1,153 @@
+//! Turning dropped or opened paths into inputs: directories are searched
+//! for video files and each candidate is checked before it is queued.
+
+use rayon::prelude::*;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::animation;
+use crate::concurrency;
+use crate::ffmpeg;
+use crate::paths;
+use crate::remote;
+use crate::sequence;
+use crate::supervisor::{self, RunError};
+
+/// Extensions of the files treated as videos, compared case-insensitively.
+pub const VIDEO_EXTENSIONS: &[&str] = &[
+    "mp4", "m4v", "mov", "mkv", "webm", "avi", "wmv", "flv", "mpg", "mpeg", "ts", "gif", "apng",
+];
+
+/// Whether `path` is a video or an animation. WebP files only count when
+/// animated, so folders of WebP frames are not mistaken for videos.
+pub fn is_video(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| {
+        VIDEO_EXTENSIONS
+            .iter()
+            .any(|video| ext.eq_ignore_ascii_case(video))
+    }) || animation::is_animated_webp(path)
+}
+
+/// The outcome of checking one file.
+#[derive(Debug, Clone, Serialize)]
+pub struct FileCheck {
+    pub path: PathBuf,
+    /// Why the file cannot be processed; `None` when it can.
+    pub error: Option<String>,
+}
+
+fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+}
+
+/// Video files under `dir`, without following symlinks or descending into
+/// hidden directories.
+fn find_videos(dir: &Path, found: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        if is_hidden(&path) {
+            continue;
+        }
+        if file_type.is_dir() {
+            find_videos(&path, found);
+        } else if file_type.is_file() && is_video(&path) {
+            found.push(path);
+        }
+    }
+}
+
+/// Checks that ffmpeg can open `path` and finds a video stream in it.
+fn probe(path: &Path) -> Result<(), String> {
+    if let Some(url) = remote::url(path) {
+        remote::check(url).map_err(|e| e.message)?;
+    }
+    let result = supervisor::run(|| {
+        let mut command = ffmpeg::command()?;
+        command.arg("-i").arg(paths::ffmpeg_arg(path)).args([
+            "-map",
+            "0:v:0",
+            "-frames:v",
+            "0",
+            "-f",
+            "null",
+            "-",
+        ]);
+        Ok(command)
+    });
+    match result {
+        Ok(_) => Ok(()),
+        Err(RunError::Failed { stderr, .. }) if stderr.contains("matches no streams") => {
+            Err("No video stream".to_string())
+        }
+        Err(RunError::Failed { stderr, .. }) => Err(stderr
+            .lines()
+            .rev()
+            .find(|line| !line.trim().is_empty())
+            .map_or_else(
+                || "ffmpeg cannot read the file".to_string(),
+                |line| line.trim().to_string(),
+            )),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Expands `paths` into the video files they name or contain and checks
+/// each one. Files inside directories are only considered when they have a
+/// video extension; files named directly are always reported. A directory
+/// without videos that holds an image sequence is an input of its own, and
+/// so is an HTTP(S) or SMB URL.
+pub fn check_paths(paths: &[PathBuf]) -> Vec<FileCheck> {
+    let mut candidates = Vec::new();
+    let mut sequences = Vec::new();
+    let mut rejected = Vec::new();
+    for path in paths {
+        if remote::url(path).is_some() {
+            candidates.push(path.clone());
+        } else if path.is_dir() {
+            let mut found = Vec::new();
+            find_videos(path, &mut found);
+            if found.is_empty() && sequence::detect(path).is_ok() {
+                sequences.push(path.clone());
+            }
+            found.sort();
+            candidates.extend(found);
+        } else if !path.is_file() {
+            rejected.push((path.clone(), "File not found"));
+        } else if !is_video(path) {
+            rejected.push((path.clone(), "Not a supported video file"));
+        } else {
+            candidates.push(path.clone());
+        }
+    }
+    let mut seen = HashSet::new();
+    candidates.retain(|path| seen.insert(path.clone()));
+
+    let mut checks: Vec<FileCheck> = concurrency::thread_pool().install(|| {
+        candidates
+            .par_iter()
+            .map(|path| FileCheck {
+                path: path.clone(),
+                error: probe(path).err(),
+            })
+            .collect()
+    });
+    checks.extend(
+        sequences
+            .into_iter()
+            .map(|path| FileCheck { path, error: None }),
+    );
+    checks.extend(rejected.into_iter().map(|(path, error)| FileCheck {
+        path,
+        error: Some(error.to_string()),
+    }));
+    checks
+}
@@ -0,0 +1,60 @@
+//! A learned duplicate classifier, for content where SSIM thresholds are
+//! unreliable, such as film grain, heavy compression noise or slow fades.
+//! Built with the `onnx` feature, which runs the model through an ONNX
+//! Runtime library loaded at run time. The model is not bundled; it is read
+//! from the path in the `learned_model` setting.
+//!
+//! The model takes a pair of frames as a float tensor of shape
+//! `[1, 2, SIZE, SIZE]`: the luma of each, scaled to [`SIZE`] pixels square,
+//! from 0.0 for black to 1.0 for white. It outputs the probability that the
+//! second frame duplicates the first as a single float, which is used as the
+//! score, so thresholds work as they do for the other metrics.
+
+use image::{imageops, GrayImage};
+use once_cell::sync::Lazy;
+use ort::session::Session;
+use ort::value::Tensor;
+use std::sync::Mutex;
+
+use crate::settings;
+
+/// Side of the square frames are scaled to for the model.
+pub const SIZE: u32 = 64;
+
+/// The model, loaded on first use. ONNX Runtime spreads a single run over
+/// the cores, so pairs go through it one at a time.
+static SESSION: Lazy<Result<Mutex<Session>, String>> = Lazy::new(load);
+
+fn load() -> Result<Mutex<Session>, String> {
+    let path = settings::current()
+        .learned_model
+        .ok_or("The learned metric needs an ONNX model; set learned_model in the settings")?;
+    let session = Session::builder()
+        .and_then(|builder| builder.commit_from_file(&path))
+        .map_err(|e| format!("Failed to load {}: {}", path.display(), e))?;
+    Ok(Mutex::new(session))
+}
+
+/// Loads the model, so a missing or broken one fails the job up front
+/// rather than scoring every pair 0.0.
+pub fn ready() -> Result<(), String> {
+    SESSION.as_ref().map(|_| ()).map_err(String::clone)
+}
+
+/// The probability the model gives that `grey2` duplicates `grey1`.
+pub fn score(grey1: &GrayImage, grey2: &GrayImage) -> Result<f32, Box<dyn std::error::Error>> {
+    let session = SESSION.as_ref().map_err(String::clone)?;
+    let mut pair = Vec::with_capacity(2 * (SIZE * SIZE) as usize);
+    for grey in [grey1, grey2] {
+        let scaled = imageops::resize(grey, SIZE, SIZE, imageops::FilterType::Triangle);
+        pair.extend(scaled.as_raw().iter().map(|&pixel| pixel as f32 / 255.0));
+    }
+    let input = Tensor::from_array(([1usize, 2, SIZE as usize, SIZE as usize], pair))?;
+    let mut session = session.lock().unwrap();
+    let outputs = session.run(ort::inputs![input])?;
+    let (_, probability) = outputs[0].try_extract_tensor::<f32>()?;
+    probability
+        .first()
+        .map(|probability| probability.clamp(0.0, 1.0))
+        .ok_or_else(|| "the model output nothing".into())
+}
@@ -1,26 +1,81 @@
-// Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
+//! The dead frame removal engine, shared by the desktop app and `dfr-cli`.
+//! Everything that needs Tauri sits behind the default `gui` feature.
+//!
+//! Other programs embed the engine through [`VideoFixer`].
 
+pub mod analysis_cache;
+pub mod animation;
+#[cfg(feature = "gui")]
+mod app;
+pub mod arch;
+pub mod benchmark;
+pub mod blur;
+pub mod capabilities;
+pub mod coarse;
+pub mod color;
+pub mod comparator;
+pub mod compare;
+pub mod concurrency;
+pub mod control;
+pub mod crop;
+pub mod cutlist;
+pub mod duplicates;
+pub mod error;
+pub mod estimate;
+pub mod export;
+pub mod ffmpeg;
+#[cfg(feature = "download-ffmpeg")]
+pub mod ffmpeg_download;
+pub mod fixer;
+pub mod glitch;
+#[cfg(feature = "gpu")]
+pub mod gpu;
+pub mod history;
+pub mod hooks;
+pub mod ingest;
+#[cfg(feature = "onnx")]
+pub mod learned;
+pub mod logging;
+pub mod mezzanine;
+#[cfg(feature = "gui")]
+pub mod notify;
+pub mod output;
+pub mod paths;
+pub mod pixel_format;
+#[cfg(feature = "plugins")]
+pub mod plugins;
+pub mod postaction;
+pub mod power;
+pub mod presets;
+pub mod priority;
+pub mod processed;
+pub mod quality;
+pub mod queue;
+pub mod remote;
+pub mod representative;
+pub mod retime;
+pub mod sandbox;
+#[cfg(feature = "scripting")]
+pub mod script;
+pub mod sections;
+pub mod sequence;
+pub mod serve;
+pub mod settings;
+pub mod similarity;
+pub mod smartcut;
+pub mod state;
+pub mod streams;
+pub mod supervisor;
+pub mod synthetic;
+pub mod timelapse;
+pub mod timeline;
+pub mod undo;
 pub mod video_fixer;
+pub mod watch;
+pub mod webhook;
+pub mod workspace;
+pub mod y4m;
 
-use video_fixer::process_video;
-
-#[tauri::command]
-#[tokio::main]
-async fn greet(name: &str) -> String {
-    format!("Hello, {}! You've been greeted from Rust!", name)
-}
-
-#[cfg_attr(mobile, tauri::mobile_entry_point)]
-pub fn run() {
-    tauri::Builder::default()
-        .plugin(tauri_plugin_opener::init())
-        .invoke_handler(tauri::generate_handler![greet])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
-}
-
-#[tokio::main]
-async fn video() {
-    let input_file = "path/to/your/video.mp4";
-    process_video(input_file).await;
-}
+#[cfg(feature = "gui")]
+pub use app::run;
+pub use fixer::VideoFixer;
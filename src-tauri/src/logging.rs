@@ -0,0 +1,290 @@
+//! Log output: stderr, daily rotated files in the app log dir, an in-memory
+//! buffer of recent records the frontend can display, and a transcript per
+//! job. A transcript is written into the job's directory and moved to the
+//! log dir once the job is done.
+
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{filter::LevelFilter, fmt, Layer};
+
+use crate::workspace;
+
+/// Records kept in memory for [`LogStore::recent`].
+const MEMORY_CAPACITY: usize = 2000;
+/// Daily log files kept on disk.
+const MAX_LOG_FILES: usize = 7;
+/// Name of the span a job runs in. Everything logged inside it, at debug
+/// level and up, is also written to that job's transcript.
+pub const JOB_SPAN: &str = "job";
+
+/// The logs of this process.
+static STORE: Lazy<Arc<LogStore>> = Lazy::new(Arc::default);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LogRecord {
+    /// RFC 3339 timestamp.
+    pub timestamp: String,
+    pub level: String,
+    pub target: String,
+    pub message: String,
+    #[serde(skip)]
+    severity: Level,
+}
+
+/// What the app reads back of the logs: recent records and job transcripts.
+#[derive(Default)]
+pub struct LogStore {
+    recent: Mutex<VecDeque<LogRecord>>,
+    /// Directory holding one transcript per job, set by [`init`].
+    job_dir: Mutex<Option<PathBuf>>,
+    job_counter: AtomicU64,
+}
+
+/// Collects an event's message followed by its other fields as `key=value`,
+/// and the `job_id` and `job_dir` of a job span.
+#[derive(Default)]
+struct MessageVisitor {
+    message: String,
+    fields: String,
+    job_id: Option<String>,
+    job_dir: Option<String>,
+}
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        match field.name() {
+            "message" => {
+                let _ = write!(self.message, "{:?}", value);
+            }
+            "job_id" => self.job_id = Some(format!("{:?}", value)),
+            "job_dir" => self.job_dir = Some(format!("{:?}", value)),
+            name => {
+                let _ = write!(self.fields, " {}={:?}", name, value);
+            }
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "message" => self.message.push_str(value),
+            "job_id" => self.job_id = Some(value.to_string()),
+            "job_dir" => self.job_dir = Some(value.to_string()),
+            name => {
+                let _ = write!(self.fields, " {}={}", name, value);
+            }
+        }
+    }
+}
+
+struct MemoryLayer;
+
+impl<S: Subscriber> Layer<S> for MemoryLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut visitor = MessageVisitor::default();
+        event.record(&mut visitor);
+        let metadata = event.metadata();
+        let record = LogRecord {
+            timestamp: chrono::Local::now().to_rfc3339(),
+            level: metadata.level().to_string(),
+            target: metadata.target().to_string(),
+            message: visitor.message + visitor.fields.as_str(),
+            severity: *metadata.level(),
+        };
+
+        let mut recent = STORE.recent.lock().unwrap();
+        if recent.len() == MEMORY_CAPACITY {
+            recent.pop_front();
+        }
+        recent.push_back(record);
+    }
+}
+
+/// The open transcript of a job, stored in its span's extensions.
+struct JobLog(Mutex<File>);
+
+/// Writes events inside a [`JOB_SPAN`] to the `job.log` of its `job_dir`,
+/// or to `<dir>/<job_id>.log` for spans without one.
+struct JobLogLayer {
+    dir: PathBuf,
+}
+
+impl<S> Layer<S> for JobLogLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        if attrs.metadata().name() != JOB_SPAN {
+            return;
+        }
+        let mut visitor = MessageVisitor::default();
+        attrs.record(&mut visitor);
+        let Some(job_id) = visitor.job_id.filter(|id| is_valid_job_id(id)) else {
+            return;
+        };
+        let log = match visitor.job_dir {
+            Some(job_dir) => File::create(Path::new(&job_dir).join(workspace::JOB_LOG)),
+            None => fs::create_dir_all(&self.dir)
+                .and_then(|_| File::create(self.dir.join(format!("{}.log", job_id)))),
+        };
+        match (log, ctx.span(id)) {
+            (Ok(file), Some(span)) => span.extensions_mut().insert(JobLog(Mutex::new(file))),
+            (Err(e), _) => eprintln!("Failed to create log for job {}: {}", job_id, e),
+            _ => {}
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let Some(scope) = ctx.event_scope(event) else {
+            return;
+        };
+        for span in scope {
+            if let Some(JobLog(file)) = span.extensions().get::<JobLog>() {
+                let mut visitor = MessageVisitor::default();
+                event.record(&mut visitor);
+                let _ = writeln!(
+                    file.lock().unwrap(),
+                    "{} {:>5} {}{}",
+                    chrono::Local::now().format("%H:%M:%S%.3f"),
+                    event.metadata().level(),
+                    visitor.message,
+                    visitor.fields
+                );
+                return;
+            }
+        }
+    }
+}
+
+/// Job IDs double as file names, so only a safe alphabet is accepted.
+pub(crate) fn is_valid_job_id(id: &str) -> bool {
+    !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+impl LogStore {
+    /// A new ID for a job: the start time plus the process and a counter, for
+    /// jobs started within the same second by this or another instance.
+    pub fn new_job_id(&self) -> String {
+        format!(
+            "{}-{}-{}",
+            chrono::Local::now().format("%Y%m%d-%H%M%S"),
+            std::process::id(),
+            self.job_counter.fetch_add(1, Ordering::Relaxed)
+        )
+    }
+
+    /// The file `<job_id>.<extension>` among the job transcripts, where other
+    /// per-job records are kept too.
+    pub fn job_file(&self, job_id: &str, extension: &str) -> Result<PathBuf, String> {
+        if !is_valid_job_id(job_id) {
+            return Err(format!("Invalid job ID: {}", job_id));
+        }
+        let dir = self
+            .job_dir
+            .lock()
+            .unwrap()
+            .clone()
+            .ok_or_else(|| "Job logs are not available".to_string())?;
+        Ok(dir.join(format!("{}.{}", job_id, extension)))
+    }
+
+    /// The transcript of a job: every ffmpeg invocation, its output and each
+    /// frame decision. A running job's is read from its directory.
+    pub fn job_log(&self, job_id: &str) -> Result<String, String> {
+        let archived = self.job_file(job_id, "log")?;
+        let path = if archived.is_file() {
+            archived
+        } else {
+            workspace::job_dir_path(job_id)?.join(workspace::JOB_LOG)
+        };
+        fs::read_to_string(path).map_err(|e| format!("No log for job {}: {}", job_id, e))
+    }
+
+    /// Up to `limit` of the most recent records at `level` or more severe,
+    /// oldest first.
+    pub fn recent(&self, level: Level, limit: usize) -> Vec<LogRecord> {
+        let recent = self.recent.lock().unwrap();
+        let mut records: Vec<LogRecord> = recent
+            .iter()
+            .rev()
+            .filter(|record| record.severity <= level)
+            .take(limit)
+            .cloned()
+            .collect();
+        records.reverse();
+        records
+    }
+}
+
+/// The process's logs, shared with [`crate::state::AppState`].
+pub fn store() -> Arc<LogStore> {
+    STORE.clone()
+}
+
+/// A new ID for a job.
+pub fn new_job_id() -> String {
+    STORE.new_job_id()
+}
+
+/// The file `<job_id>.<extension>` among the job transcripts.
+pub fn job_file(job_id: &str, extension: &str) -> Result<PathBuf, String> {
+    STORE.job_file(job_id, extension)
+}
+
+/// Moves the transcript `log` of the finished job `job_id` out of its
+/// directory into the log dir, before the directory goes.
+pub(crate) fn archive_job_log(job_id: &str, log: &Path) {
+    if !log.is_file() {
+        return;
+    }
+    let Ok(archived) = job_file(job_id, "log") else {
+        return;
+    };
+    let result = archived
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::rename(log, &archived).or_else(|_| fs::copy(log, &archived).map(|_| ())));
+    if let Err(e) = result {
+        eprintln!("Failed to keep the log of job {}: {}", job_id, e);
+    }
+}
+
+/// Installs the global subscriber. Without a usable `log_dir` logging still
+/// goes to stderr and memory.
+pub fn init(log_dir: &Path) {
+    let file_layer = tracing_appender::rolling::Builder::new()
+        .rotation(tracing_appender::rolling::Rotation::DAILY)
+        .filename_prefix("dead-frames")
+        .filename_suffix("log")
+        .max_log_files(MAX_LOG_FILES)
+        .build(log_dir)
+        .map_err(|e| eprintln!("Failed to open log dir {}: {}", log_dir.display(), e))
+        .ok()
+        .map(|appender| fmt::layer().with_ansi(false).with_writer(appender));
+
+    let job_dir = log_dir.join("jobs");
+    *STORE.job_dir.lock().unwrap() = Some(job_dir.clone());
+
+    let _ = tracing_subscriber::registry()
+        .with(
+            fmt::layer()
+                .with_writer(std::io::stderr)
+                .with_filter(LevelFilter::INFO),
+        )
+        .with(file_layer.with_filter(LevelFilter::INFO))
+        .with(MemoryLayer.with_filter(LevelFilter::INFO))
+        .with(JobLogLayer { dir: job_dir }.with_filter(LevelFilter::DEBUG))
+        .try_init();
+}
@@ -0,0 +1,128 @@
+//! Intermediate codecs for taking the output into an editor: ProRes and
+//! DNxHR decode a frame at a time without reference frames, so they scrub
+//! smoothly, at many times the size of H.264. Each comes in profiles that
+//! trade size against quality.
+
+use serde::{Deserialize, Serialize};
+
+use crate::pixel_format::{Chroma, PixelFormat};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProResProfile {
+    Proxy,
+    Lt,
+    Standard,
+    Hq,
+    /// 4:4:4 with an alpha channel.
+    #[default]
+    #[serde(rename = "4444")]
+    P4444,
+    #[serde(rename = "4444xq")]
+    P4444Xq,
+}
+
+impl ProResProfile {
+    /// The profile as `prores_ks` names it.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ProResProfile::Proxy => "proxy",
+            ProResProfile::Lt => "lt",
+            ProResProfile::Standard => "standard",
+            ProResProfile::Hq => "hq",
+            ProResProfile::P4444 => "4444",
+            ProResProfile::P4444Xq => "4444xq",
+        }
+    }
+
+    /// The profile as ffmpeg reports it for a stream.
+    pub fn reported_name(&self) -> &'static str {
+        match self {
+            ProResProfile::Proxy => "Proxy",
+            ProResProfile::Lt => "LT",
+            ProResProfile::Standard => "Standard",
+            ProResProfile::Hq => "HQ",
+            ProResProfile::P4444 => "4444",
+            ProResProfile::P4444Xq => "XQ",
+        }
+    }
+
+    /// The 4444 profiles are 4:4:4 and keep transparency; the others are
+    /// 4:2:2 without. All are 10-bit.
+    pub fn is_444(&self) -> bool {
+        matches!(self, ProResProfile::P4444 | ProResProfile::P4444Xq)
+    }
+
+    /// Pixel format the encoder is fed for a source with or without
+    /// `alpha`.
+    pub fn pixel_format(&self, alpha: bool) -> PixelFormat {
+        PixelFormat {
+            bit_depth: 10,
+            chroma: match self.is_444() {
+                true => Chroma::Yuv444,
+                false => Chroma::Yuv422,
+            },
+            alpha: alpha && self.is_444(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DnxhrProfile {
+    Lb,
+    Sq,
+    #[default]
+    Hq,
+    /// 10-bit, for HDR and grading.
+    Hqx,
+    /// 10-bit 4:4:4.
+    #[serde(rename = "444")]
+    P444,
+}
+
+impl DnxhrProfile {
+    /// The profile as the `dnxhd` encoder names it.
+    pub fn name(&self) -> &'static str {
+        match self {
+            DnxhrProfile::Lb => "dnxhr_lb",
+            DnxhrProfile::Sq => "dnxhr_sq",
+            DnxhrProfile::Hq => "dnxhr_hq",
+            DnxhrProfile::Hqx => "dnxhr_hqx",
+            DnxhrProfile::P444 => "dnxhr_444",
+        }
+    }
+
+    /// The profile as ffmpeg reports it for a stream.
+    pub fn reported_name(&self) -> &'static str {
+        match self {
+            DnxhrProfile::Lb => "DNXHR LB",
+            DnxhrProfile::Sq => "DNXHR SQ",
+            DnxhrProfile::Hq => "DNXHR HQ",
+            DnxhrProfile::Hqx => "DNXHR HQX",
+            DnxhrProfile::P444 => "DNXHR 444",
+        }
+    }
+
+    /// Pixel format the encoder is fed. DNxHR has no alpha channel.
+    pub fn pixel_format(&self) -> PixelFormat {
+        let (bit_depth, chroma) = match self {
+            DnxhrProfile::Lb | DnxhrProfile::Sq | DnxhrProfile::Hq => (8, Chroma::Yuv422),
+            DnxhrProfile::Hqx => (10, Chroma::Yuv422),
+            DnxhrProfile::P444 => (10, Chroma::Yuv444),
+        };
+        PixelFormat {
+            bit_depth,
+            chroma,
+            alpha: false,
+        }
+    }
+}
+
+/// The profiles ProRes and DNxHR are encoded with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Profiles {
+    pub prores: ProResProfile,
+    pub dnxhr: DnxhrProfile,
+}
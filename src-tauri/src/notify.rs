@@ -0,0 +1,44 @@
+//! Desktop notifications for finished and failed jobs.
+
+use crate::error::ProcessError;
+use crate::settings;
+use crate::video_fixer::JobSummary;
+use std::path::Path;
+use tauri::AppHandle;
+use tauri_plugin_notification::NotificationExt;
+use tracing::warn;
+
+fn show(app: &AppHandle, title: &str, body: &str) {
+    if settings::current().mute_notifications {
+        return;
+    }
+    if let Err(e) = app.notification().builder().title(title).body(body).show() {
+        warn!("Failed to show notification: {}", e);
+    }
+}
+
+fn file_name(path: &str) -> String {
+    Path::new(path)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.to_string())
+}
+
+pub fn job_finished(app: &AppHandle, input: &str, summary: &JobSummary) {
+    if summary.skipped {
+        return;
+    }
+    let body = format!(
+        "Removed {} of {} frames in {:.1}s",
+        summary.frames_removed, summary.frames_total, summary.elapsed_secs
+    );
+    show(app, &format!("Finished {}", file_name(input)), &body);
+}
+
+pub fn job_failed(app: &AppHandle, input: &str, error: &ProcessError) {
+    show(
+        app,
+        &format!("Failed to process {}", file_name(input)),
+        &error.to_string(),
+    );
+}
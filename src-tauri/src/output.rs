@@ -0,0 +1,185 @@
+//! Where processed videos are written and what they are called.
+
+use serde::{Deserialize, Serialize};
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+
+use crate::error::ProcessError;
+
+pub const DEFAULT_TEMPLATE: &str = "{stem}_processed.{ext}";
+
+/// What to do when the output file already exists.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CollisionPolicy {
+    Overwrite,
+    /// Append ` (1)`, ` (2)`, ... to the stem until the name is free.
+    #[default]
+    AutoIncrement,
+    /// Leave the existing file alone and do not process the input.
+    Skip,
+    Error,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OutputOptions {
+    /// Directory the output is written to; defaults to the input's directory.
+    pub dir: Option<PathBuf>,
+    /// File name template. `{stem}` is the input name without extension,
+    /// `{date}` today's date as `YYYY-MM-DD`, `{preset}` the preset name and
+    /// `{ext}` the output container's extension.
+    pub template: String,
+    pub collision: CollisionPolicy,
+}
+
+impl Default for OutputOptions {
+    fn default() -> Self {
+        OutputOptions {
+            dir: None,
+            template: DEFAULT_TEMPLATE.to_string(),
+            collision: CollisionPolicy::default(),
+        }
+    }
+}
+
+/// Where a job's output goes, after the collision policy has been applied.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Destination {
+    Write(PathBuf),
+    /// The output exists and the policy says to leave it alone.
+    Skip(PathBuf),
+}
+
+/// Fills in `template`. The stem goes in as it is, so names that are not
+/// valid Unicode survive.
+fn render(template: &str, stem: &OsStr, preset: &str, ext: &str) -> Result<OsString, ProcessError> {
+    let rendered = template
+        .replace(
+            "{date}",
+            &chrono::Local::now().format("%Y-%m-%d").to_string(),
+        )
+        .replace("{preset}", preset)
+        .replace("{ext}", ext);
+    if rendered.contains(['/', '\\']) {
+        return Err(ProcessError::new(format!(
+            "Output template produced an invalid file name: {:?}",
+            rendered
+        )));
+    }
+    let mut name = OsString::new();
+    for (index, part) in rendered.split("{stem}").enumerate() {
+        if index > 0 {
+            name.push(stem);
+        }
+        name.push(part);
+    }
+    if name.is_empty() {
+        return Err(ProcessError::new(
+            "Output template produced an empty file name",
+        ));
+    }
+    Ok(name)
+}
+
+/// `path` with ` (n)` appended to its stem for the first free `n`.
+pub(crate) fn next_free(path: &Path) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default();
+    (1..)
+        .map(|n| {
+            let mut name = stem.to_owned();
+            name.push(format!(" ({})", n));
+            if let Some(ext) = path.extension() {
+                name.push(".");
+                name.push(ext);
+            }
+            path.with_file_name(name)
+        })
+        .find(|candidate| !candidate.exists())
+        .unwrap()
+}
+
+/// The output path for `input` before the collision policy is applied.
+pub(crate) fn target(
+    input: &Path,
+    options: &OutputOptions,
+    preset: Option<&str>,
+    ext: &str,
+) -> Result<PathBuf, ProcessError> {
+    let stem = input
+        .file_stem()
+        .ok_or_else(|| ProcessError::new(format!("{} has no file name", input.display())))?;
+    let name = render(&options.template, stem, preset.unwrap_or("custom"), ext)?;
+
+    let dir = match &options.dir {
+        Some(dir) => dir.clone(),
+        None => input.parent().map(Path::to_path_buf).unwrap_or_default(),
+    };
+    let path = dir.join(name);
+    if path == input {
+        return Err(ProcessError::new(format!(
+            "Output would overwrite the input {}",
+            input.display()
+        )));
+    }
+    Ok(path)
+}
+
+pub(crate) fn resolve(
+    path: PathBuf,
+    collision: CollisionPolicy,
+) -> Result<Destination, ProcessError> {
+    if !path.exists() {
+        return Ok(Destination::Write(path));
+    }
+
+    match collision {
+        CollisionPolicy::Overwrite => Ok(Destination::Write(path)),
+        CollisionPolicy::AutoIncrement => Ok(Destination::Write(next_free(&path))),
+        CollisionPolicy::Skip => Ok(Destination::Skip(path)),
+        CollisionPolicy::Error => Err(ProcessError::new(format!(
+            "{} already exists",
+            path.display()
+        ))),
+    }
+}
+
+/// Resolves the output path for `input` with container extension `ext`.
+pub fn destination(
+    input: &Path,
+    options: &OutputOptions,
+    preset: Option<&str>,
+    ext: &str,
+) -> Result<Destination, ProcessError> {
+    resolve(target(input, options, preset, ext)?, options.collision)
+}
+
+/// Resolves the directory an image sequence with extension `ext` is written
+/// to: the output path without its extension, so the default template gives
+/// `clip_processed/` for `clip.mp4`.
+pub fn sequence_destination(
+    input: &Path,
+    options: &OutputOptions,
+    preset: Option<&str>,
+    ext: &str,
+) -> Result<Destination, ProcessError> {
+    resolve(
+        sequence_target(input, options, preset, ext)?,
+        options.collision,
+    )
+}
+
+/// The directory of [`sequence_destination`] before the collision policy
+/// is applied.
+pub(crate) fn sequence_target(
+    input: &Path,
+    options: &OutputOptions,
+    preset: Option<&str>,
+    ext: &str,
+) -> Result<PathBuf, ProcessError> {
+    let mut path = target(input, options, preset, ext)?;
+    if path.extension().is_some_and(|e| e == ext) {
+        path.set_extension("");
+    }
+    Ok(path)
+}
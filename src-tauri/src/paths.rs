@@ -0,0 +1,98 @@
+//! Paths handed to ffmpeg. Sources sit deep in folder trees and have names
+//! in any script, or on Unix no valid Unicode at all, so they stay `Path`s
+//! all the way to the command line rather than going through strings. On
+//! Windows, long paths get the `\\?\` prefix that lifts the old `MAX_PATH`
+//! limit.
+
+use std::ffi::OsString;
+use std::path::Path;
+
+/// Length from which a Windows path needs the extended-length prefix:
+/// `MAX_PATH` less the room Windows keeps for a file name in a directory.
+#[cfg(windows)]
+const LONG_PATH: usize = 248;
+
+/// `path` as an ffmpeg argument.
+pub fn ffmpeg_arg(path: impl AsRef<Path>) -> OsString {
+    let path = path.as_ref();
+    #[cfg(windows)]
+    if let Some(extended) = extended_length(path) {
+        return extended;
+    }
+    path.as_os_str().to_owned()
+}
+
+/// The extended-length form of `path` when it is too long for the classic
+/// Windows API: `\\?\C:\...` for drive paths, `\\?\UNC\server\share\...`
+/// for network shares. `None` for short paths, URLs, and paths that already
+/// have a prefix of their own.
+#[cfg(windows)]
+fn extended_length(path: &Path) -> Option<OsString> {
+    use std::os::windows::ffi::{OsStrExt, OsStringExt};
+    use std::path::{Component, Prefix};
+
+    if path.to_str().is_some_and(crate::remote::is_url) {
+        return None;
+    }
+    // the prefix also turns off normalisation, so `..` and `/` must be
+    // resolved first
+    let absolute = std::path::absolute(path).ok()?;
+    let wide: Vec<u16> = absolute.as_os_str().encode_wide().collect();
+    if wide.len() < LONG_PATH {
+        return None;
+    }
+    let Some(Component::Prefix(prefix)) = absolute.components().next() else {
+        return None;
+    };
+    let (extended, rest) = match prefix.kind() {
+        Prefix::Disk(_) => (r"\\?\", &wide[..]),
+        Prefix::UNC(..) => (r"\\?\UNC\", &wide[2..]),
+        _ => return None,
+    };
+    let mut extended: Vec<u16> = extended.encode_utf16().collect();
+    extended.extend_from_slice(rest);
+    Some(OsString::from_wide(&extended))
+}
+
+#[cfg(all(test, windows))]
+mod tests {
+    use super::*;
+
+    /// A path under `root` of at least `units` UTF-16 units, in folders
+    /// named `folder`.
+    fn long_path(root: &str, folder: &str, units: usize) -> String {
+        let mut path = root.to_string();
+        while path.encode_utf16().count() < units {
+            path.push_str(folder);
+            path.push('\\');
+        }
+        path + "clip.mp4"
+    }
+
+    #[test]
+    fn long_drive_path_is_extended() {
+        let path = long_path(r"C:\", "folder", 260);
+        assert_eq!(
+            extended_length(Path::new(&path)),
+            Some(OsString::from(format!(r"\\?\{}", path)))
+        );
+    }
+
+    #[test]
+    fn long_unc_path_is_extended() {
+        let path = long_path(r"\\server\share\", "folder", 260);
+        assert_eq!(
+            extended_length(Path::new(&path)),
+            Some(OsString::from(format!(r"\\?\UNC\{}", &path[2..])))
+        );
+    }
+
+    #[test]
+    fn short_path_is_left_alone() {
+        assert_eq!(extended_length(Path::new(r"C:\videos\clip.mp4")), None);
+        // longer than the limit in UTF-8, not in UTF-16
+        let path = long_path(r"C:\", "视频", 200);
+        assert!(path.len() > LONG_PATH);
+        assert_eq!(extended_length(Path::new(&path)), None);
+    }
+}
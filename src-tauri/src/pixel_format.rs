@@ -0,0 +1,173 @@
+//! The source's bit depth, chroma subsampling and alpha channel, carried
+//! through the intermediate frames and the encode so 10-bit, 4:4:4 and
+//! transparent sources do not end up as opaque 8-bit 4:2:0.
+
+use serde::{Deserialize, Serialize};
+
+use crate::streams;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Chroma {
+    #[serde(rename = "420")]
+    Yuv420,
+    #[serde(rename = "422")]
+    Yuv422,
+    #[serde(rename = "444")]
+    Yuv444,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PixelFormat {
+    /// Bits per sample: 8, 10, 12 or 16.
+    pub bit_depth: u32,
+    /// Subsampling of the colour planes; RGB formats count as 4:4:4.
+    pub chroma: Chroma,
+    /// Whether there is an alpha channel.
+    #[serde(default)]
+    pub alpha: bool,
+}
+
+impl PixelFormat {
+    pub const YUV420P: PixelFormat = PixelFormat {
+        bit_depth: 8,
+        chroma: Chroma::Yuv420,
+        alpha: false,
+    };
+
+    /// Parses an ffmpeg pixel format name such as `yuv420p10le`, `p010le`,
+    /// `nv12`, `gbrp12le` or `rgba`. Returns `None` for formats without a fixed
+    /// layout, like palettes and hardware surfaces.
+    pub fn parse(name: &str) -> Option<PixelFormat> {
+        let name = name.trim().to_ascii_lowercase();
+        let bare = name
+            .strip_suffix("le")
+            .or_else(|| name.strip_suffix("be"))
+            .unwrap_or(&name);
+
+        // the packed formats hardware decoders hand out: p010, p216, p412
+        if let Some(digits) = bare.strip_prefix('p') {
+            if digits.len() == 3 && digits.chars().all(|c| c.is_ascii_digit()) {
+                let chroma = match &digits[..1] {
+                    "0" => Chroma::Yuv420,
+                    "2" => Chroma::Yuv422,
+                    "4" => Chroma::Yuv444,
+                    _ => return None,
+                };
+                return Some(PixelFormat::new(digits[1..].parse().ok()?, chroma, false));
+            }
+        }
+        if let Some(layout) = bare.strip_prefix("nv") {
+            let (chroma, bit_depth) = match layout {
+                "12" | "21" => (Chroma::Yuv420, 8),
+                "16" => (Chroma::Yuv422, 8),
+                "20" => (Chroma::Yuv422, 10),
+                "24" | "42" => (Chroma::Yuv444, 8),
+                _ => return None,
+            };
+            return Some(PixelFormat::new(bit_depth, chroma, false));
+        }
+        if ["rgb48", "bgr48", "rgba64", "bgra64"].contains(&bare) {
+            return Some(PixelFormat::new(16, Chroma::Yuv444, bare.ends_with("64")));
+        }
+
+        // planar formats end in their depth, as in yuv422p10 or gbrap12
+        let layout = bare.trim_end_matches(|c: char| c.is_ascii_digit());
+        let bit_depth = bare[layout.len()..].parse().unwrap_or(8);
+        if ["rgb", "bgr", "argb", "abgr"]
+            .iter()
+            .any(|prefix| layout.starts_with(prefix))
+        {
+            // packed, where the number is the size of the whole pixel
+            return Some(PixelFormat::new(8, Chroma::Yuv444, layout.contains('a')));
+        }
+        let chroma = if layout.starts_with("yuv") {
+            if layout.contains("444") {
+                Chroma::Yuv444
+            } else if layout.contains("422") {
+                Chroma::Yuv422
+            } else {
+                Chroma::Yuv420
+            }
+        } else if layout.starts_with("gray") || layout.starts_with("ya") {
+            Chroma::Yuv420
+        } else if layout.starts_with("gbr") {
+            Chroma::Yuv444
+        } else {
+            return None;
+        };
+        let alpha = ["yuva", "gbrap", "ya"]
+            .iter()
+            .any(|prefix| layout.starts_with(prefix));
+        Some(PixelFormat::new(bit_depth, chroma, alpha))
+    }
+
+    fn new(bit_depth: u32, chroma: Chroma, alpha: bool) -> PixelFormat {
+        // 9-bit and 14-bit formats exist but few encoders take them
+        let bit_depth = match bit_depth {
+            0..=8 => 8,
+            9..=10 => 10,
+            11..=12 => 12,
+            _ => 16,
+        };
+        PixelFormat {
+            bit_depth,
+            chroma,
+            alpha,
+        }
+    }
+
+    pub fn is_high_depth(&self) -> bool {
+        self.bit_depth > 8
+    }
+
+    /// The same subsampling at 8 bits.
+    pub fn eight_bit(self) -> PixelFormat {
+        PixelFormat {
+            bit_depth: 8,
+            ..self
+        }
+    }
+
+    /// The same subsampling at no more than `max_depth` bits.
+    pub fn capped(self, max_depth: u32) -> PixelFormat {
+        PixelFormat {
+            bit_depth: self.bit_depth.min(max_depth),
+            ..self
+        }
+    }
+
+    /// The same without its alpha channel.
+    pub fn opaque(self) -> PixelFormat {
+        PixelFormat {
+            alpha: false,
+            ..self
+        }
+    }
+
+    /// The planar YUV format ffmpeg knows this as, e.g. `yuv444p10le` or
+    /// `yuva420p`.
+    pub fn yuv_name(&self) -> String {
+        let chroma = match self.chroma {
+            Chroma::Yuv420 => "420",
+            Chroma::Yuv422 => "422",
+            Chroma::Yuv444 => "444",
+        };
+        let alpha = if self.alpha { "a" } else { "" };
+        if self.is_high_depth() {
+            format!("yuv{}{}p{}le", alpha, chroma, self.bit_depth)
+        } else {
+            format!("yuv{}{}p", alpha, chroma)
+        }
+    }
+}
+
+/// The pixel format of video stream `stream` ffmpeg reported in `stderr`.
+pub fn reported(stderr: &str, stream: usize) -> Option<PixelFormat> {
+    let stream = streams::reported_line(stderr, stream)?
+        .split("Video: ")
+        .nth(1)?;
+    // the codec comes first, then the pixel format with its colour
+    // properties in parentheses
+    let name = stream.split(", ").nth(1)?.split('(').next()?;
+    PixelFormat::parse(name)
+}
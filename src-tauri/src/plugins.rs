@@ -0,0 +1,265 @@
+//! Detector plugins: WebAssembly modules in the plugins directory that see
+//! every frame after the analysis and may keep or remove it, like a
+//! [`script`](crate::script) with the frames themselves to look at.
+//!
+//! A plugin is a core module, named after its file, that imports nothing
+//! and exports
+//!
+//! - `memory`,
+//! - `buffer(len: i32) -> i32`, the address of `len` bytes the frames are
+//!   written to; called again whenever the frame size changes,
+//! - `decide(index: i32, width: i32, height: i32, score: f32, removed: i32)
+//!   -> i32`, called for every frame in order with the buffer holding the
+//!   luma of the frame and then of its successor, row by row, or of the
+//!   frame twice for the last one. `score` is the frame's score as in
+//!   [`Analysis::scores`], or -1 if it has none, and `removed` is 1 when
+//!   the frame is removed so far. It returns 1 to remove the frame, 0 to
+//!   keep it and anything else to leave it as it is.
+//!
+//! Plugins run sandboxed: without imports they cannot reach files, the
+//! network or the clock, their memory is capped at [`MAX_MEMORY`] and
+//! each call at [`FUEL`] instructions. Enabled plugins run in name order,
+//! each seeing what the ones before decided.
+//!
+//! [`Analysis::scores`]: crate::video_fixer::Analysis::scores
+
+use image::GrayImage;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use wasmtime::{
+    Config, Engine, Instance, Memory, Module, Store, StoreLimits, StoreLimitsBuilder, TypedFunc,
+};
+
+use crate::settings;
+
+/// Memory a plugin may grow to, in bytes.
+pub const MAX_MEMORY: usize = 1 << 30;
+
+/// Instructions, roughly, a single call may run, so a plugin stuck in a
+/// loop fails the job rather than hanging it.
+pub const FUEL: u64 = 1 << 32;
+
+const EXTENSION: &str = "wasm";
+
+/// The plugins directory of this process.
+static STORE: Lazy<Arc<PluginDir>> = Lazy::new(Arc::default);
+
+/// A plugin found in the plugins directory.
+#[derive(Debug, Clone, Serialize)]
+pub struct PluginInfo {
+    pub name: String,
+    pub path: PathBuf,
+    pub enabled: bool,
+}
+
+/// The plugins directory, once [`PluginDir::open`] sets it.
+#[derive(Default)]
+pub struct PluginDir {
+    dir: Mutex<Option<PathBuf>>,
+}
+
+impl PluginDir {
+    /// Sets the plugins directory, under the app data dir, and creates it.
+    pub fn open(&self, app_data_dir: &Path) {
+        let dir = app_data_dir.join("plugins");
+        if let Err(e) = fs::create_dir_all(&dir) {
+            tracing::warn!("Failed to create {}: {}", dir.display(), e);
+        }
+        *self.dir.lock().unwrap() = Some(dir);
+    }
+
+    fn dir(&self) -> Result<PathBuf, String> {
+        self.dir
+            .lock()
+            .unwrap()
+            .clone()
+            .ok_or_else(|| "Plugins directory is not initialised".to_string())
+    }
+
+    /// The plugins in the plugins directory, by name.
+    pub fn list(&self) -> Result<Vec<PluginInfo>, String> {
+        let dir = self.dir()?;
+        let enabled = settings::current().enabled_plugins;
+        let entries =
+            fs::read_dir(&dir).map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?;
+        let mut plugins: Vec<PluginInfo> = entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == EXTENSION))
+            .filter_map(|path| {
+                let name = path.file_stem()?.to_str()?.to_string();
+                Some(PluginInfo {
+                    enabled: enabled.contains(&name),
+                    name,
+                    path,
+                })
+            })
+            .collect();
+        plugins.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(plugins)
+    }
+
+    /// Enables or disables the plugin called `name` for every job.
+    pub fn enable(&self, name: &str, enabled: bool) -> Result<(), String> {
+        if enabled && !self.list()?.iter().any(|plugin| plugin.name == name) {
+            return Err(format!("No plugin called \"{}\"", name));
+        }
+        settings::update(|settings| {
+            settings.enabled_plugins.retain(|plugin| plugin != name);
+            if enabled {
+                settings.enabled_plugins.push(name.to_string());
+                settings.enabled_plugins.sort();
+            }
+        })
+        .map_err(|e| format!("Failed to save settings: {}", e))
+    }
+
+    /// The files of the enabled plugins, in name order; none before
+    /// [`PluginDir::open`].
+    pub fn enabled(&self) -> Vec<PathBuf> {
+        let Ok(dir) = self.dir() else {
+            return Vec::new();
+        };
+        settings::current()
+            .enabled_plugins
+            .iter()
+            .map(|name| dir.join(name).with_extension(EXTENSION))
+            .collect()
+    }
+}
+
+/// The process's plugins directory, shared with [`crate::state::AppState`].
+pub fn store() -> Arc<PluginDir> {
+    STORE.clone()
+}
+
+/// Sets the plugins directory, under the app data dir, and creates it.
+pub fn init(app_data_dir: &Path) {
+    STORE.open(app_data_dir);
+}
+
+/// The files of the enabled plugins, in name order.
+pub fn enabled() -> Vec<PathBuf> {
+    STORE.enabled()
+}
+
+/// A loaded plugin.
+pub struct Plugin {
+    name: String,
+    store: Store<StoreLimits>,
+    memory: Memory,
+    buffer: TypedFunc<i32, i32>,
+    decide: TypedFunc<(i32, i32, i32, f32, i32), i32>,
+    /// Address and length of the frame buffer.
+    frames: Option<(usize, usize)>,
+}
+
+impl Plugin {
+    /// Compiles and instantiates the plugin at `path`.
+    pub fn load(path: &Path) -> Result<Plugin, String> {
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let failed = |e: wasmtime::Error| format!("Failed to load plugin {}: {}", name, e);
+        let mut config = Config::new();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config).map_err(failed)?;
+        let module = Module::from_file(&engine, path).map_err(failed)?;
+        let mut store = Store::new(
+            &engine,
+            StoreLimitsBuilder::new().memory_size(MAX_MEMORY).build(),
+        );
+        store.limiter(|limits| limits);
+        let instance = Instance::new(&mut store, &module, &[]).map_err(failed)?;
+        let memory = instance
+            .get_memory(&mut store, "memory")
+            .ok_or_else(|| format!("Plugin {} exports no memory", name))?;
+        let buffer = instance
+            .get_typed_func(&mut store, "buffer")
+            .map_err(failed)?;
+        let decide = instance
+            .get_typed_func(&mut store, "decide")
+            .map_err(failed)?;
+        Ok(Plugin {
+            name,
+            store,
+            memory,
+            buffer,
+            decide,
+            frames: None,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The plugin's decision on frame `index`, given its luma and its
+    /// successor's: whether it is removed, or `None` to leave it as it is.
+    pub fn decide(
+        &mut self,
+        index: usize,
+        frame: &GrayImage,
+        next: &GrayImage,
+        score: Option<f32>,
+        removed: bool,
+    ) -> Result<Option<bool>, String> {
+        let failed = |e: wasmtime::Error| {
+            format!(
+                "Plugin {} failed on frame {}: {}",
+                self.name,
+                index,
+                e.root_cause()
+            )
+        };
+        let len = frame.len() + next.len();
+        let address = match self.frames {
+            Some((address, frames_len)) if frames_len == len => address,
+            _ => {
+                self.store.set_fuel(FUEL).map_err(failed)?;
+                let address = self
+                    .buffer
+                    .call(&mut self.store, len as i32)
+                    .map_err(failed)? as u32 as usize;
+                self.frames = Some((address, len));
+                address
+            }
+        };
+        let written = self
+            .memory
+            .write(&mut self.store, address, frame.as_raw())
+            .and_then(|()| {
+                self.memory
+                    .write(&mut self.store, address + frame.len(), next.as_raw())
+            });
+        if written.is_err() {
+            return Err(format!(
+                "Plugin {} gave a frame buffer outside its memory",
+                self.name
+            ));
+        }
+        self.store.set_fuel(FUEL).map_err(failed)?;
+        let decision = self
+            .decide
+            .call(
+                &mut self.store,
+                (
+                    index as i32,
+                    frame.width() as i32,
+                    frame.height() as i32,
+                    score.unwrap_or(-1.0),
+                    removed as i32,
+                ),
+            )
+            .map_err(failed)?;
+        Ok(match decision {
+            1 => Some(true),
+            0 => Some(false),
+            _ => None,
+        })
+    }
+}
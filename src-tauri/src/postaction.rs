@@ -0,0 +1,151 @@
+//! What the app does after a job finishes and after the queue drains: tidy
+//! away the original, copy the output elsewhere, or shut the computer down.
+//! Commands run after a job are [`crate::hooks`].
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tracing::{info, warn};
+
+use crate::output;
+use crate::video_fixer::JobSummary;
+
+/// What happens to an original once it has been processed.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "kebab-case")]
+pub enum OriginalAction {
+    #[default]
+    Keep,
+    /// Move it into `dir`, numbering it should the name be taken.
+    Move {
+        dir: PathBuf,
+    },
+    /// Move it to the system's recycle bin or trash.
+    Recycle,
+    Delete,
+}
+
+/// The persisted post-processing configuration.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PostActions {
+    pub original: OriginalAction,
+    /// Copy each output into this directory as well.
+    pub copy_output_to: Option<PathBuf>,
+    /// Shut the computer down once the queue has no more jobs.
+    pub shutdown_when_done: bool,
+}
+
+/// Runs the per-job actions for a job that turned `input` into the output
+/// of `summary`. Failures are logged, as the job itself succeeded.
+pub fn after_job(actions: &PostActions, input: &Path, summary: &JobSummary) {
+    if summary.skipped {
+        return;
+    }
+    let output = Path::new(&summary.output);
+    if let Some(dir) = &actions.copy_output_to {
+        if let Err(e) = copy_output(output, dir) {
+            warn!(
+                "Failed to copy {} to {}: {}",
+                output.display(),
+                dir.display(),
+                e
+            );
+        }
+    }
+    if actions.original != OriginalAction::Keep {
+        if let Err(e) = handle_original(&actions.original, input, output) {
+            warn!("Failed to clean up {}: {}", input.display(), e);
+        }
+    }
+}
+
+/// Runs the actions for when the queue has drained.
+pub fn after_queue(actions: &PostActions) {
+    if actions.shutdown_when_done {
+        info!("Queue finished, shutting down");
+        if let Err(e) = shut_down() {
+            warn!("Failed to shut down: {}", e);
+        }
+    }
+}
+
+fn copy_output(output: &Path, dir: &Path) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let mut target = dir.join(output.file_name().unwrap_or_default());
+    if target.exists() {
+        target = output::next_free(&target);
+    }
+    if output.is_dir() {
+        // an image sequence
+        fs::create_dir(&target)?;
+        for entry in fs::read_dir(output)? {
+            let entry = entry?;
+            if entry.file_type()?.is_file() {
+                fs::copy(entry.path(), target.join(entry.file_name()))?;
+            }
+        }
+    } else {
+        fs::copy(output, &target)?;
+    }
+    info!("Copied {} to {}", output.display(), target.display());
+    Ok(())
+}
+
+fn handle_original(action: &OriginalAction, input: &Path, output: &Path) -> io::Result<()> {
+    // image sequence inputs are folders of the user's, and an output written
+    // over its input is all that is left of it
+    if !input.is_file() || same_file(input, output) {
+        return Ok(());
+    }
+    match action {
+        OriginalAction::Keep => return Ok(()),
+        OriginalAction::Move { dir } => {
+            fs::create_dir_all(dir)?;
+            let mut target = dir.join(input.file_name().unwrap_or_default());
+            if target.exists() {
+                target = output::next_free(&target);
+            }
+            // a rename cannot cross file systems
+            if fs::rename(input, &target).is_err() {
+                fs::copy(input, &target)?;
+                fs::remove_file(input)?;
+            }
+        }
+        OriginalAction::Recycle => trash::delete(input).map_err(io::Error::other)?,
+        OriginalAction::Delete => fs::remove_file(input)?,
+    }
+    info!("Cleaned up original {}", input.display());
+    Ok(())
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+fn shut_down() -> io::Result<()> {
+    // a minute's grace, so the user can still cancel it
+    let mut command = if cfg!(windows) {
+        let mut command = Command::new("shutdown");
+        command.args(["/s", "/t", "60"]);
+        command
+    } else if cfg!(target_os = "macos") {
+        let mut command = Command::new("osascript");
+        command.args(["-e", "tell application \"System Events\" to shut down"]);
+        command
+    } else {
+        let mut command = Command::new("shutdown");
+        command.args(["-h", "+1"]);
+        command
+    };
+    let status = command.status()?;
+    if !status.success() {
+        return Err(io::Error::other(format!("shutdown exited with {}", status)));
+    }
+    Ok(())
+}
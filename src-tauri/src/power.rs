@@ -0,0 +1,145 @@
+//! Keeps the machine awake while jobs are running.
+//!
+//! The inhibitor is reference counted: the first active job acquires it and
+//! it is released when the last [`SleepGuard`] is dropped.
+
+use once_cell::sync::Lazy;
+use std::sync::Mutex;
+
+enum Inhibitor {
+    /// `systemd-inhibit` on Linux or `caffeinate` on macOS, both of which hold
+    /// their lock for as long as the child lives and end with this process.
+    #[cfg(unix)]
+    Child(std::process::Child),
+    /// SetThreadExecutionState is per-thread, so a dedicated thread holds it
+    /// until the sender is dropped.
+    #[cfg(windows)]
+    Thread(std::sync::mpsc::Sender<()>),
+}
+
+struct InhibitState {
+    active_jobs: usize,
+    inhibitor: Option<Inhibitor>,
+}
+
+static STATE: Lazy<Mutex<InhibitState>> = Lazy::new(|| {
+    Mutex::new(InhibitState {
+        active_jobs: 0,
+        inhibitor: None,
+    })
+});
+
+/// Prevents system sleep until dropped.
+pub struct SleepGuard(());
+
+pub fn inhibit_sleep() -> SleepGuard {
+    let mut state = STATE.lock().unwrap();
+    state.active_jobs += 1;
+    if state.active_jobs == 1 {
+        state.inhibitor = acquire();
+    }
+    SleepGuard(())
+}
+
+impl Drop for SleepGuard {
+    fn drop(&mut self) {
+        let mut state = STATE.lock().unwrap();
+        state.active_jobs -= 1;
+        if state.active_jobs == 0 {
+            if let Some(inhibitor) = state.inhibitor.take() {
+                release(inhibitor);
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn acquire() -> Option<Inhibitor> {
+    use std::os::unix::process::CommandExt;
+
+    let parent = std::process::id();
+    let mut command = std::process::Command::new("systemd-inhibit");
+    // the lock is held by a tail that exits by itself should this process
+    // die, in a process group of its own that release kills as a whole
+    command
+        .args([
+            "--what=sleep:idle",
+            "--who=dead-frames",
+            "--why=Processing video",
+            "--mode=block",
+            "tail",
+            &format!("--pid={}", parent),
+            "-f",
+            "/dev/null",
+        ])
+        .process_group(0);
+    // SAFETY: only async-signal-safe calls that touch no parent state
+    unsafe {
+        command.pre_exec(move || {
+            libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGKILL as libc::c_ulong);
+            // the parent may have died before the signal was set up
+            if libc::getppid() as u32 != parent {
+                return Err(std::io::Error::other("the app exited"));
+            }
+            Ok(())
+        });
+    }
+    command
+        .spawn()
+        .map(Inhibitor::Child)
+        .map_err(|e| tracing::warn!("Failed to inhibit sleep: {}", e))
+        .ok()
+}
+
+#[cfg(target_os = "macos")]
+fn acquire() -> Option<Inhibitor> {
+    // caffeinate holds a PreventUserIdleSystemSleep power assertion and exits
+    // by itself should this process die
+    std::process::Command::new("caffeinate")
+        .args(["-i", "-w", &std::process::id().to_string()])
+        .spawn()
+        .map(Inhibitor::Child)
+        .map_err(|e| tracing::warn!("Failed to inhibit sleep: {}", e))
+        .ok()
+}
+
+#[cfg(windows)]
+fn acquire() -> Option<Inhibitor> {
+    use windows_sys::Win32::System::Power::{
+        SetThreadExecutionState, ES_CONTINUOUS, ES_SYSTEM_REQUIRED,
+    };
+
+    let (sender, receiver) = std::sync::mpsc::channel::<()>();
+    std::thread::spawn(move || {
+        // SAFETY: only changes the execution state of this thread
+        unsafe { SetThreadExecutionState(ES_CONTINUOUS | ES_SYSTEM_REQUIRED) };
+        // returns once the sender is dropped
+        let _ = receiver.recv();
+        unsafe { SetThreadExecutionState(ES_CONTINUOUS) };
+    });
+    Some(Inhibitor::Thread(sender))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+fn acquire() -> Option<Inhibitor> {
+    None
+}
+
+fn release(inhibitor: Inhibitor) {
+    match inhibitor {
+        #[cfg(unix)]
+        Inhibitor::Child(mut child) => {
+            // systemd-inhibit and the command holding the lock, which
+            // killing systemd-inhibit alone would leave running
+            #[cfg(target_os = "linux")]
+            // SAFETY: signals only the process group the child leads
+            unsafe {
+                libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+            }
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+        #[cfg(windows)]
+        Inhibitor::Thread(sender) => drop(sender),
+    }
+}
@@ -0,0 +1,292 @@
+//! Named bundles of processing options. A few presets are built in; the
+//! ones users create are stored next to the settings.
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tracing::warn;
+
+use crate::mezzanine::{DnxhrProfile, ProResProfile, Profiles};
+use crate::similarity::Metric;
+use crate::timelapse::Timelapse;
+use crate::video_fixer::{ProcessOptions, VideoCodec};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Preset {
+    pub name: String,
+    pub threshold: f32,
+    pub metric: Metric,
+    pub codec: VideoCodec,
+    /// Profiles of ProRes and DNxHR outputs.
+    #[serde(default)]
+    pub mezzanine: Profiles,
+    /// Frame rate of the output; the source's when unset.
+    #[serde(default)]
+    pub framerate: Option<f64>,
+    /// Build a timelapse, in place of the threshold, metric and frame rate.
+    #[serde(default)]
+    pub timelapse: Option<Timelapse>,
+    /// Remove only the frames the encoder repeated.
+    #[serde(default)]
+    pub encoder_duplicates: bool,
+    /// Built-in presets cannot be renamed, deleted or overwritten.
+    #[serde(default, skip_deserializing)]
+    pub builtin: bool,
+}
+
+impl Preset {
+    /// The user's default options with this preset applied on top.
+    pub fn options(&self) -> ProcessOptions {
+        ProcessOptions {
+            threshold: self.threshold,
+            metric: self.metric,
+            codec: self.codec,
+            mezzanine: self.mezzanine,
+            framerate: self.framerate,
+            timelapse: self.timelapse,
+            encoder_duplicates: self.encoder_duplicates,
+            preset: Some(self.name.clone()),
+            ..ProcessOptions::default()
+        }
+    }
+}
+
+fn builtin_presets() -> Vec<Preset> {
+    vec![
+        // screen recorders repeat frames when the capture falls behind, so
+        // only those short runs are dropped and still screens stay
+        Preset {
+            name: "Screen recording".into(),
+            threshold: 0.995,
+            metric: Metric::MeanAbsDiff,
+            codec: VideoCodec::H264,
+            mezzanine: Profiles::default(),
+            framerate: None,
+            timelapse: None,
+            encoder_duplicates: true,
+            builtin: true,
+        },
+        Preset {
+            name: "Timelapse".into(),
+            threshold: 0.9,
+            metric: Metric::Ssim,
+            codec: VideoCodec::H265,
+            mezzanine: Profiles::default(),
+            framerate: Some(30.0),
+            timelapse: Some(Timelapse::default()),
+            encoder_duplicates: false,
+            builtin: true,
+        },
+        Preset {
+            name: "Archival lossless".into(),
+            threshold: 0.999,
+            metric: Metric::Ssim,
+            codec: VideoCodec::Ffv1,
+            mezzanine: Profiles::default(),
+            framerate: None,
+            timelapse: None,
+            encoder_duplicates: false,
+            builtin: true,
+        },
+        // intermediates that editors scrub through smoothly
+        Preset {
+            name: "ProRes for editing".into(),
+            threshold: 0.999,
+            metric: Metric::Ssim,
+            codec: VideoCodec::ProRes,
+            mezzanine: Profiles {
+                prores: ProResProfile::Hq,
+                ..Profiles::default()
+            },
+            framerate: None,
+            timelapse: None,
+            encoder_duplicates: false,
+            builtin: true,
+        },
+        Preset {
+            name: "DNxHR for editing".into(),
+            threshold: 0.999,
+            metric: Metric::Ssim,
+            codec: VideoCodec::DnxHr,
+            mezzanine: Profiles {
+                dnxhr: DnxhrProfile::Hq,
+                ..Profiles::default()
+            },
+            framerate: None,
+            timelapse: None,
+            encoder_duplicates: false,
+            builtin: true,
+        },
+    ]
+}
+
+/// The presets the engine and app of this process use.
+static STORE: Lazy<Arc<PresetStore>> = Lazy::new(Arc::default);
+
+fn read_presets(path: &Path) -> Result<Vec<Preset>, String> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    serde_json::from_str(&contents)
+        .map_err(|e| format!("Invalid presets {}: {}", path.display(), e))
+}
+
+fn write_presets(path: &Path, presets: &[Preset]) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    let contents = serde_json::to_string_pretty(presets).map_err(|e| e.to_string())?;
+    fs::write(path, contents).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+fn check_name(name: &str, presets: &[Preset]) -> Result<(), String> {
+    if name.trim().is_empty() {
+        return Err("Preset name must not be empty".to_string());
+    }
+    if builtin_presets()
+        .iter()
+        .chain(presets)
+        .any(|p| p.name == name)
+    {
+        return Err(format!("A preset named \"{}\" already exists", name));
+    }
+    Ok(())
+}
+
+fn user_preset_index(name: &str, presets: &[Preset]) -> Result<usize, String> {
+    if builtin_presets().iter().any(|p| p.name == name) {
+        return Err(format!("\"{}\" is a built-in preset", name));
+    }
+    presets
+        .iter()
+        .position(|p| p.name == name)
+        .ok_or_else(|| format!("No preset named \"{}\"", name))
+}
+
+/// The user's presets, saved to a file once [`PresetStore::load`] gives one.
+#[derive(Default)]
+pub struct PresetStore {
+    presets: Mutex<Vec<Preset>>,
+    path: Mutex<Option<PathBuf>>,
+}
+
+impl PresetStore {
+    /// Loads the user presets from `path` and remembers it for later saves.
+    pub fn load(&self, path: PathBuf) {
+        let presets = if path.exists() {
+            read_presets(&path).unwrap_or_else(|e| {
+                warn!("Ignoring presets: {}", e);
+                Vec::new()
+            })
+        } else {
+            Vec::new()
+        };
+        *self.presets.lock().unwrap() = presets;
+        *self.path.lock().unwrap() = Some(path);
+    }
+
+    /// Applies `change` to the user presets and saves them if it succeeds.
+    fn modify<T>(
+        &self,
+        change: impl FnOnce(&mut Vec<Preset>) -> Result<T, String>,
+    ) -> Result<T, String> {
+        let mut presets = self.presets.lock().unwrap();
+        let mut updated = presets.clone();
+        let result = change(&mut updated)?;
+        if let Some(path) = self.path.lock().unwrap().as_ref() {
+            write_presets(path, &updated)?;
+        }
+        *presets = updated;
+        Ok(result)
+    }
+
+    /// Built-in presets followed by the user's.
+    pub fn list(&self) -> Vec<Preset> {
+        let mut presets = builtin_presets();
+        presets.extend(self.presets.lock().unwrap().iter().cloned());
+        presets
+    }
+
+    pub fn get(&self, name: &str) -> Option<Preset> {
+        self.list().into_iter().find(|p| p.name == name)
+    }
+
+    pub fn create(&self, mut preset: Preset) -> Result<(), String> {
+        preset.builtin = false;
+        self.modify(|presets| {
+            check_name(&preset.name, presets)?;
+            presets.push(preset);
+            Ok(())
+        })
+    }
+
+    pub fn rename(&self, name: &str, new_name: &str) -> Result<(), String> {
+        self.modify(|presets| {
+            let index = user_preset_index(name, presets)?;
+            check_name(new_name, presets)?;
+            presets[index].name = new_name.to_string();
+            Ok(())
+        })
+    }
+
+    pub fn delete(&self, name: &str) -> Result<(), String> {
+        self.modify(|presets| {
+            let index = user_preset_index(name, presets)?;
+            presets.remove(index);
+            Ok(())
+        })
+    }
+
+    /// Writes the named presets, or all user presets when `names` is empty, to
+    /// `path` as JSON.
+    pub fn export(&self, path: &Path, names: &[String]) -> Result<(), String> {
+        let selected: Vec<Preset> = if names.is_empty() {
+            self.presets.lock().unwrap().clone()
+        } else {
+            names
+                .iter()
+                .map(|name| {
+                    self.get(name)
+                        .ok_or_else(|| format!("No preset named \"{}\"", name))
+                })
+                .collect::<Result<_, _>>()?
+        };
+        write_presets(path, &selected)
+    }
+
+    /// Adds the presets from the JSON file at `path`. Imports replace user
+    /// presets of the same name; presets named like a built-in one are skipped.
+    /// Returns how many presets were imported.
+    pub fn import(&self, path: &Path) -> Result<usize, String> {
+        let builtin = builtin_presets();
+        let imported: Vec<Preset> = read_presets(path)?
+            .into_iter()
+            .filter(|preset| !builtin.iter().any(|p| p.name == preset.name))
+            .collect();
+        if imported.iter().any(|preset| preset.name.trim().is_empty()) {
+            return Err("Preset name must not be empty".to_string());
+        }
+        self.modify(|presets| {
+            presets.retain(|p| !imported.iter().any(|i| i.name == p.name));
+            presets.extend(imported.iter().cloned());
+            Ok(imported.len())
+        })
+    }
+}
+
+/// The process's presets, shared with [`crate::state::AppState`].
+pub fn store() -> Arc<PresetStore> {
+    STORE.clone()
+}
+
+/// Loads the user presets from `path` and remembers it for later saves.
+pub fn init(path: PathBuf) {
+    STORE.load(path);
+}
+
+/// The built-in or user preset called `name`.
+pub fn get(name: &str) -> Option<Preset> {
+    STORE.get(name)
+}
@@ -0,0 +1,64 @@
+//! Background mode: runs ffmpeg children and comparison threads at reduced OS
+//! priority so processing doesn't make the desktop unusable.
+
+use crate::settings;
+use std::process::Command;
+
+/// Niceness applied on Unix; high enough to yield to interactive work.
+#[cfg(unix)]
+const NICENESS: i32 = 10;
+
+pub fn enabled() -> bool {
+    settings::current().low_priority
+}
+
+/// Makes `command` start at reduced priority when background mode is on.
+pub fn configure_command(command: &mut Command) {
+    if !enabled() {
+        return;
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        // SAFETY: setpriority is async-signal-safe and touches no parent state
+        unsafe {
+            command.pre_exec(|| {
+                libc::setpriority(libc::PRIO_PROCESS, 0, NICENESS);
+                Ok(())
+            });
+        }
+    }
+
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        use windows_sys::Win32::System::Threading::BELOW_NORMAL_PRIORITY_CLASS;
+        command.creation_flags(BELOW_NORMAL_PRIORITY_CLASS);
+    }
+}
+
+/// Lowers the priority of the calling thread. Used as the start handler of
+/// the comparison thread pool.
+pub fn lower_current_thread() {
+    #[cfg(target_os = "linux")]
+    // SAFETY: on Linux PRIO_PROCESS with id 0 only affects the calling thread
+    unsafe {
+        libc::setpriority(libc::PRIO_PROCESS, 0, NICENESS);
+    }
+
+    #[cfg(target_os = "macos")]
+    // SAFETY: only changes the QoS class of the calling thread
+    unsafe {
+        libc::pthread_set_qos_class_self_np(libc::qos_class_t::QOS_CLASS_UTILITY, 0);
+    }
+
+    #[cfg(windows)]
+    // SAFETY: GetCurrentThread returns a pseudo handle that needs no cleanup
+    unsafe {
+        use windows_sys::Win32::System::Threading::{
+            GetCurrentThread, SetThreadPriority, THREAD_PRIORITY_BELOW_NORMAL,
+        };
+        SetThreadPriority(GetCurrentThread(), THREAD_PRIORITY_BELOW_NORMAL);
+    }
+}
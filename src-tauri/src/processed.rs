@@ -0,0 +1,78 @@
+//! Files a batch or the watch folder processed before: ones whose output
+//! is already where the options would write it, or that the job history
+//! records finishing after the file last changed. Depending on the
+//! [`ProcessedPolicy`] they are skipped rather than encoded again.
+//!
+//! The history is only there in the desktop app; `dfr-cli` goes by the
+//! output alone.
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+use crate::history;
+use crate::video_fixer::{self, ProcessOptions};
+
+/// What a batch or the watch folder does with a file processed before.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ProcessedPolicy {
+    /// Process it again, with the output's collision policy deciding what
+    /// becomes of the earlier output.
+    #[default]
+    Reprocess,
+    /// Leave it be.
+    Skip,
+}
+
+/// Why a file counts as processed.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum Processed {
+    /// The output the options would write exists.
+    Output { output: PathBuf },
+    /// The history records a job on the file since it last changed.
+    History {
+        output: PathBuf,
+        finished_at: String,
+    },
+}
+
+impl Processed {
+    /// The output of the earlier run.
+    pub fn output(&self) -> &Path {
+        match self {
+            Processed::Output { output } | Processed::History { output, .. } => output,
+        }
+    }
+}
+
+/// How `input` was processed before with `options`, if it was.
+pub fn check(input: &Path, options: &ProcessOptions) -> Option<Processed> {
+    let output = video_fixer::output_path(input, options).ok()?;
+    if output.exists() {
+        return Some(Processed::Output { output });
+    }
+    let entry = history::latest(&input.to_string_lossy()).ok()??;
+    let finished_at = chrono::DateTime::parse_from_rfc3339(&entry.finished_at).ok()?;
+    let modified = fs::metadata(input).and_then(|m| m.modified()).ok()?;
+    (finished_at >= chrono::DateTime::<chrono::Utc>::from(modified)).then(|| Processed::History {
+        output: PathBuf::from(entry.output),
+        finished_at: entry.finished_at,
+    })
+}
+
+/// Whether `input` is to be skipped under `policy`, logging why it is.
+pub fn skip(input: &Path, options: &ProcessOptions, policy: ProcessedPolicy) -> Option<Processed> {
+    if policy == ProcessedPolicy::Reprocess {
+        return None;
+    }
+    let processed = check(input, options)?;
+    info!(
+        "Skipping {}, already processed into {}",
+        input.display(),
+        processed.output().display()
+    );
+    Some(processed)
+}
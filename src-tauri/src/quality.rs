@@ -0,0 +1,123 @@
+//! Checking an encoded output against the source frames it was made from,
+//! so archival users can confirm the re-encode lost nothing that matters.
+
+use serde::Serialize;
+use std::path::Path;
+use tracing::info;
+
+use crate::capabilities;
+use crate::control::{JobControl, Stage};
+use crate::crop::CropRect;
+use crate::cutlist;
+use crate::error::ProcessError;
+use crate::ffmpeg;
+use crate::paths;
+use crate::supervisor;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct QualityReport {
+    /// Mean SSIM over all planes, 1.0 for identical frames.
+    pub ssim: f64,
+    /// Mean VMAF from 0 to 100; only when ffmpeg was built with libvmaf.
+    pub vmaf: Option<f64>,
+}
+
+/// A `select` expression keeping the frames not marked in `removed`.
+fn kept_frames_expr(removed: &[bool]) -> String {
+    let ranges: Vec<String> = cutlist::kept_ranges(removed)
+        .iter()
+        .map(|range| format!("between(n,{},{})", range.start, range.end - 1))
+        .collect();
+    ranges.join("+")
+}
+
+fn parse_ssim(stderr: &str) -> Option<f64> {
+    let line = stderr.lines().rev().find(|line| line.contains("SSIM "))?;
+    line.split("All:")
+        .nth(1)?
+        .split_whitespace()
+        .next()?
+        .parse()
+        .ok()
+}
+
+fn parse_vmaf(stderr: &str) -> Option<f64> {
+    let line = stderr
+        .lines()
+        .rev()
+        .find(|line| line.contains("VMAF score"))?;
+    line.rsplit(':').next()?.trim().parse().ok()
+}
+
+/// Compares every frame of `output` with the frame of video stream `stream`
+/// of `source` it was kept from.
+pub fn verify(
+    source: &Path,
+    output: &Path,
+    stream: usize,
+    removed: &[bool],
+    crop: Option<CropRect>,
+    control: &JobControl,
+) -> Result<QualityReport, ProcessError> {
+    let kept = removed.iter().filter(|&&dead| !dead).count();
+    control.report(Stage::Verifying, 0, kept);
+    let vmaf = capabilities::get_ffmpeg_capabilities()
+        .map(|capabilities| capabilities.has_filter("libvmaf"))
+        .unwrap_or(false);
+
+    // frame n of either side gets timestamp n seconds, so frames pair up by
+    // position even when the output's frame rate differs from the source's
+    let prepare = "settb=1,setpts=N,format=yuv420p";
+    // the source frames are cut down to the output's picture
+    let keep = match crop {
+        Some(crop) => format!("select='{}',{}", kept_frames_expr(removed), crop.filter()),
+        None => format!("select='{}'", kept_frames_expr(removed)),
+    };
+    let filter = if vmaf {
+        format!(
+            "[0:v]{p},split[d1][d2];[1:v:{stream}]{keep},{p},split[r1][r2];\
+             [d1][r1]ssim;[d2][r2]libvmaf",
+            p = prepare,
+            keep = keep,
+            stream = stream,
+        )
+    } else {
+        format!(
+            "[0:v]{p}[d];[1:v:{stream}]{keep},{p}[r];[d][r]ssim",
+            p = prepare,
+            keep = keep,
+            stream = stream,
+        )
+    };
+    let result = supervisor::run_reporting(
+        || {
+            let mut command = ffmpeg::command()?;
+            command
+                .arg("-i")
+                .arg(paths::ffmpeg_arg(output))
+                .arg("-i")
+                .arg(paths::ffmpeg_arg(source))
+                .args(["-filter_complex", &filter, "-f", "null", "-"]);
+            Ok(command)
+        },
+        control,
+        Stage::Verifying,
+        Some(kept),
+    )
+    .map_err(|e| ProcessError::ffmpeg("Failed to verify quality", e))?;
+
+    let ssim =
+        parse_ssim(&result.stderr).ok_or_else(|| ProcessError::new("ffmpeg reported no SSIM"))?;
+    let vmaf = if vmaf {
+        parse_vmaf(&result.stderr)
+    } else {
+        None
+    };
+    info!(
+        "Output quality: SSIM {:.4}{}",
+        ssim,
+        vmaf.map(|vmaf| format!(", VMAF {:.2}", vmaf))
+            .unwrap_or_default()
+    );
+    Ok(QualityReport { ssim, vmaf })
+}
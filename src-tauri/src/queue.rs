@@ -0,0 +1,480 @@
+//! The job queue behind the desktop app and `--serve`: jobs run one at a
+//! time in the order queued, on a worker thread of their own.
+//!
+//! A queue made with [`JobQueue::persistent`] keeps its unfinished jobs in a
+//! file, so a batch outlives quitting the app or a crash.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+use crate::analysis_cache::{self, CachedPlan};
+use crate::control::{CancellationToken, FrameDecision, PauseToken, Progress};
+use crate::error::ProcessError;
+use crate::fixer::VideoFixer;
+use crate::video_fixer::{JobSummary, ProcessOptions};
+
+/// How often [`JobQueue::shut_down`] checks on the worker.
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobState {
+    /// Added but waiting for [`JobQueue::release`] before it is queued.
+    Held,
+    Queued,
+    Running,
+    /// Running, but held by [`JobQueue::pause`] until resumed.
+    Paused,
+    Finished,
+    Failed,
+    Cancelled,
+}
+
+impl JobState {
+    /// Whether the job has stopped for good.
+    pub fn is_done(&self) -> bool {
+        matches!(
+            self,
+            JobState::Finished | JobState::Failed | JobState::Cancelled
+        )
+    }
+
+    /// Whether the job is queued or under way.
+    pub fn is_active(&self) -> bool {
+        matches!(
+            self,
+            JobState::Queued | JobState::Running | JobState::Paused
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Job {
+    pub id: u64,
+    pub input: PathBuf,
+    pub options: ProcessOptions,
+    pub state: JobState,
+    /// The latest progress while running.
+    pub progress: Option<Progress>,
+    pub summary: Option<JobSummary>,
+    pub error: Option<ProcessError>,
+    /// What an earlier job's analysis of the same file with the same
+    /// options removed, which this one reuses.
+    pub cached_plan: Option<CachedPlan>,
+    #[serde(skip)]
+    cancel: CancellationToken,
+    #[serde(skip)]
+    pause: PauseToken,
+}
+
+/// A change in a job's state, named for how `--serve` reports it.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum QueueEvent {
+    JobAdded {
+        job: u64,
+    },
+    JobStarted {
+        job: u64,
+    },
+    Progress {
+        job: u64,
+        progress: Progress,
+    },
+    /// Frames compared since the last such event and whether they go.
+    Decisions {
+        job: u64,
+        decisions: Vec<FrameDecision>,
+    },
+    JobFinished {
+        job: u64,
+        summary: JobSummary,
+    },
+    JobFailed {
+        job: u64,
+        error: ProcessError,
+    },
+    JobCancelled {
+        job: u64,
+    },
+    JobPaused {
+        job: u64,
+    },
+    JobResumed {
+        job: u64,
+    },
+    /// The last queued job is done and no other is waiting.
+    QueueDrained,
+}
+
+/// An unfinished job as it is stored by a persistent queue.
+#[derive(Serialize, Deserialize)]
+struct SavedJob {
+    input: PathBuf,
+    options: ProcessOptions,
+}
+
+#[derive(Default)]
+struct State {
+    jobs: BTreeMap<u64, Job>,
+    pending: VecDeque<u64>,
+    next_id: u64,
+    /// No more jobs are coming; the worker exits once `pending` is empty.
+    closed: bool,
+    /// The app is exiting; jobs cancelled now are left unfinished.
+    shutting_down: bool,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    queued: Condvar,
+    on_event: Box<dyn Fn(&QueueEvent) + Send + Sync>,
+    /// Where unfinished jobs are kept, for persistent queues.
+    store: Option<PathBuf>,
+}
+
+pub struct JobQueue {
+    shared: Arc<Shared>,
+    worker: Mutex<Option<thread::JoinHandle<()>>>,
+}
+
+impl JobQueue {
+    /// Starts an empty queue. `on_event` is called from the worker thread,
+    /// or the caller's for jobs added or cancelled before they ran, and never
+    /// while the queue is locked.
+    pub fn new(on_event: impl Fn(&QueueEvent) + Send + Sync + 'static) -> JobQueue {
+        Self::start(None, on_event)
+    }
+
+    /// Like [`Self::new`], but writes the unfinished jobs to `path` whenever
+    /// they change. Call [`Self::restore`] before queueing anything to pick
+    /// up the jobs a previous run left there.
+    pub fn persistent(
+        path: PathBuf,
+        on_event: impl Fn(&QueueEvent) + Send + Sync + 'static,
+    ) -> JobQueue {
+        Self::start(Some(path), on_event)
+    }
+
+    fn start(
+        store: Option<PathBuf>,
+        on_event: impl Fn(&QueueEvent) + Send + Sync + 'static,
+    ) -> JobQueue {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State::default()),
+            queued: Condvar::new(),
+            on_event: Box::new(on_event),
+            store,
+        });
+        let worker = {
+            let shared = shared.clone();
+            thread::spawn(move || shared.work())
+        };
+        JobQueue {
+            shared,
+            worker: Mutex::new(Some(worker)),
+        }
+    }
+
+    /// Adds a job and returns its ID. A held job waits for [`Self::release`].
+    pub fn enqueue(&self, input: PathBuf, options: ProcessOptions, hold: bool) -> u64 {
+        let cached_plan = analysis_cache::peek(&input, &options);
+        let mut state = self.shared.state.lock().unwrap();
+        let id = state.next_id;
+        state.next_id += 1;
+        state.jobs.insert(
+            id,
+            Job {
+                id,
+                input,
+                options,
+                state: if hold {
+                    JobState::Held
+                } else {
+                    JobState::Queued
+                },
+                progress: None,
+                summary: None,
+                error: None,
+                cached_plan,
+                cancel: CancellationToken::new(),
+                pause: PauseToken::new(),
+            },
+        );
+        if !hold {
+            state.pending.push_back(id);
+            self.shared.queued.notify_one();
+        }
+        self.shared.save(&state);
+        drop(state);
+        (self.shared.on_event)(&QueueEvent::JobAdded { job: id });
+        id
+    }
+
+    /// Takes a job ID for a job run outside the queue, such as a direct
+    /// `process_video` call, so hooks and webhooks never see two jobs with
+    /// the same one.
+    pub fn reserve_id(&self) -> u64 {
+        let mut state = self.shared.state.lock().unwrap();
+        let id = state.next_id;
+        state.next_id += 1;
+        id
+    }
+
+    /// Adds the jobs a previous run of a persistent queue did not finish,
+    /// held so the user can choose to resume them, and returns their IDs.
+    pub fn restore(&self) -> Vec<u64> {
+        let Some(path) = &self.shared.store else {
+            return Vec::new();
+        };
+        let saved = match fs::read(path) {
+            Ok(json) => serde_json::from_slice::<Vec<SavedJob>>(&json).unwrap_or_else(|e| {
+                warn!("Ignoring malformed queue {}: {}", path.display(), e);
+                Vec::new()
+            }),
+            Err(_) => Vec::new(),
+        };
+        saved
+            .into_iter()
+            .map(|job| self.enqueue(job.input, job.options, true))
+            .collect()
+    }
+
+    /// Queues a held job.
+    pub fn release(&self, id: u64) -> Result<(), String> {
+        let mut state = self.shared.state.lock().unwrap();
+        let job = state.jobs.get_mut(&id).ok_or_else(|| unknown_job(id))?;
+        if job.state == JobState::Held {
+            job.state = JobState::Queued;
+            state.pending.push_back(id);
+            self.shared.queued.notify_one();
+        }
+        Ok(())
+    }
+
+    /// Cancels a job that has not finished. Running jobs stop at their next
+    /// cancellation check and are reported by the worker.
+    pub fn cancel(&self, id: u64) -> Result<(), String> {
+        let mut state = self.shared.state.lock().unwrap();
+        let job = state.jobs.get_mut(&id).ok_or_else(|| unknown_job(id))?;
+        match job.state {
+            JobState::Held | JobState::Queued => {
+                job.state = JobState::Cancelled;
+                state.pending.retain(|&pending| pending != id);
+                self.shared.save(&state);
+                drop(state);
+                (self.shared.on_event)(&QueueEvent::JobCancelled { job: id });
+            }
+            JobState::Running | JobState::Paused => job.cancel.cancel(),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Holds a running job where it is until [`Self::resume`]; its ffmpeg
+    /// children are suspended and the comparison waits.
+    pub fn pause(&self, id: u64) -> Result<(), String> {
+        let mut state = self.shared.state.lock().unwrap();
+        let job = state.jobs.get_mut(&id).ok_or_else(|| unknown_job(id))?;
+        if job.state == JobState::Running {
+            job.state = JobState::Paused;
+            job.pause.pause();
+            drop(state);
+            (self.shared.on_event)(&QueueEvent::JobPaused { job: id });
+        }
+        Ok(())
+    }
+
+    /// Lets a paused job go on.
+    pub fn resume(&self, id: u64) -> Result<(), String> {
+        let mut state = self.shared.state.lock().unwrap();
+        let job = state.jobs.get_mut(&id).ok_or_else(|| unknown_job(id))?;
+        if job.state == JobState::Paused {
+            job.state = JobState::Running;
+            job.pause.resume();
+            drop(state);
+            (self.shared.on_event)(&QueueEvent::JobResumed { job: id });
+        }
+        Ok(())
+    }
+
+    pub fn job(&self, id: u64) -> Option<Job> {
+        self.shared.state.lock().unwrap().jobs.get(&id).cloned()
+    }
+
+    /// Every job still known, in the order added.
+    pub fn jobs(&self) -> Vec<Job> {
+        self.shared
+            .state
+            .lock()
+            .unwrap()
+            .jobs
+            .values()
+            .cloned()
+            .collect()
+    }
+
+    /// Stops the queue for the app's exit: nothing more is started and the
+    /// running job is cancelled, but unlike [`Self::cancel`] the jobs stay
+    /// unfinished, so a persistent queue restores them on the next launch.
+    /// Waits up to `timeout` for the worker and returns whether it stopped.
+    pub fn shut_down(&self, timeout: Duration) -> bool {
+        {
+            let mut state = self.shared.state.lock().unwrap();
+            state.shutting_down = true;
+            state.closed = true;
+            state.pending.clear();
+            for job in state.jobs.values() {
+                if matches!(job.state, JobState::Running | JobState::Paused) {
+                    job.pause.resume();
+                    job.cancel.cancel();
+                }
+            }
+        }
+        self.shared.queued.notify_one();
+        let Some(worker) = self.worker.lock().unwrap().take() else {
+            return true;
+        };
+        let deadline = Instant::now() + timeout;
+        while !worker.is_finished() {
+            if Instant::now() >= deadline {
+                return false;
+            }
+            thread::sleep(SHUTDOWN_POLL_INTERVAL);
+        }
+        let _ = worker.join();
+        true
+    }
+
+    /// Waits for every queued job to run. Nothing can be queued afterwards.
+    pub fn finish(&self) {
+        self.shared.state.lock().unwrap().closed = true;
+        self.shared.queued.notify_one();
+        if let Some(worker) = self.worker.lock().unwrap().take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn unknown_job(id: u64) -> String {
+    format!("No job {}", id)
+}
+
+impl Shared {
+    fn work(self: Arc<Self>) {
+        while let Some((id, fixer)) = self.next_job() {
+            (self.on_event)(&QueueEvent::JobStarted { job: id });
+            let shared = self.clone();
+            let last_percent = Mutex::new(None);
+            let fixer = fixer.on_progress(move |progress| {
+                // stage starts have no total; analysis is reported every percent
+                let percent = (progress.done * 100).checked_div(progress.total);
+                if percent.is_none()
+                    || last_percent.lock().unwrap().replace(percent) != Some(percent)
+                {
+                    shared.progress(id, progress);
+                }
+            });
+            let shared = self.clone();
+            let fixer = fixer.on_decisions(move |decisions| {
+                (shared.on_event)(&QueueEvent::Decisions { job: id, decisions })
+            });
+            let result = fixer.run();
+
+            let mut state = self.state.lock().unwrap();
+            if state.shutting_down && result.as_ref().is_err_and(|e| e.cancelled) {
+                // stays in the store to be restored
+                return;
+            }
+            let Some(job) = state.jobs.get_mut(&id) else {
+                continue;
+            };
+            let event = match result {
+                Ok(summary) => {
+                    job.state = JobState::Finished;
+                    job.summary = Some(summary.clone());
+                    QueueEvent::JobFinished { job: id, summary }
+                }
+                Err(e) if e.cancelled => {
+                    job.state = JobState::Cancelled;
+                    job.error = Some(e);
+                    QueueEvent::JobCancelled { job: id }
+                }
+                Err(e) => {
+                    job.state = JobState::Failed;
+                    job.error = Some(e.clone());
+                    QueueEvent::JobFailed { job: id, error: e }
+                }
+            };
+            let drained = state.pending.is_empty();
+            self.save(&state);
+            drop(state);
+            (self.on_event)(&event);
+            if drained {
+                (self.on_event)(&QueueEvent::QueueDrained);
+            }
+        }
+    }
+
+    fn next_job(&self) -> Option<(u64, VideoFixer)> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(id) = state.pending.pop_front() {
+                let job = state.jobs.get_mut(&id)?;
+                job.state = JobState::Running;
+                let fixer = VideoFixer::new(&job.input)
+                    .options(job.options.clone())
+                    .cancellation(job.cancel.clone())
+                    .pausing(job.pause.clone());
+                return Some((id, fixer));
+            }
+            if state.closed {
+                return None;
+            }
+            state = self.queued.wait(state).unwrap();
+        }
+    }
+
+    /// Writes the unfinished jobs to the store, if there is one.
+    fn save(&self, state: &State) {
+        let Some(path) = &self.store else {
+            return;
+        };
+        let saved: Vec<SavedJob> = state
+            .jobs
+            .values()
+            .filter(|job| !job.state.is_done())
+            .map(|job| SavedJob {
+                input: job.input.clone(),
+                options: job.options.clone(),
+            })
+            .collect();
+        if let Err(e) = write_atomically(path, &saved) {
+            warn!("Failed to save the queue to {}: {}", path.display(), e);
+        }
+    }
+
+    fn progress(&self, id: u64, progress: Progress) {
+        if let Some(job) = self.state.lock().unwrap().jobs.get_mut(&id) {
+            job.progress = Some(progress);
+        }
+        (self.on_event)(&QueueEvent::Progress { job: id, progress });
+    }
+}
+
+/// Writes `jobs` next to `path` first, so a crash mid-write leaves the last
+/// complete queue behind.
+fn write_atomically(path: &Path, jobs: &[SavedJob]) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let temp = path.with_extension("json.tmp");
+    fs::write(&temp, serde_json::to_vec_pretty(jobs)?)?;
+    fs::rename(&temp, path)
+}
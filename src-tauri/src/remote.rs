@@ -0,0 +1,210 @@
+//! Inputs named by an HTTP(S) or SMB URL. ffmpeg reads them itself, so by
+//! default frames are extracted straight off the network. Every pass over
+//! the source reads it again, though, so a remote file can be downloaded
+//! into the job directory first instead.
+
+use percent_encoding::percent_decode_str;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::info;
+use url::Url;
+
+use crate::capabilities;
+use crate::control::{JobControl, Stage};
+use crate::error::ProcessError;
+use crate::ffmpeg;
+use crate::paths;
+use crate::supervisor;
+use crate::workspace::{JobDir, DOWNLOAD_DIR};
+
+/// Schemes passed on to ffmpeg.
+const SCHEMES: &[&str] = &["http", "https", "smb"];
+
+/// How long the pre-check waits for the server.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Bytes read from the network at a time.
+const CHUNK_SIZE: usize = 1 << 20;
+
+/// Whether `input` is a URL rather than a path.
+pub fn is_url(input: &str) -> bool {
+    parse(input).is_some()
+}
+
+/// `input` as a URL string, when it is one.
+pub fn url(input: &Path) -> Option<&str> {
+    input.to_str().filter(|input| is_url(input))
+}
+
+fn parse(input: &str) -> Option<Url> {
+    Url::parse(input)
+        .ok()
+        .filter(|url| SCHEMES.contains(&url.scheme()) && url.has_host())
+}
+
+/// The file name of a URL input, for naming its outputs; `input` itself
+/// when it is a path. Outputs of URLs go to the output directory of the
+/// settings, or the working directory.
+pub fn naming_path(input: &Path) -> PathBuf {
+    let Some(url) = url(input).and_then(parse) else {
+        return input.to_path_buf();
+    };
+    let name = url
+        .path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .filter(|name| !name.is_empty())
+        .map(|name| {
+            percent_decode_str(name)
+                .decode_utf8_lossy()
+                .replace(['/', '\\'], "_")
+        })
+        .unwrap_or_else(|| url.host_str().unwrap_or("remote").to_string());
+    PathBuf::from(name)
+}
+
+/// Checks that ffmpeg can read `url` and its server answers, so a job fails
+/// up front rather than part way through extraction.
+pub fn check(url: &str) -> Result<(), ProcessError> {
+    let parsed = parse(url).ok_or_else(|| ProcessError::new(format!("{} is not a URL", url)))?;
+    let scheme = parsed.scheme();
+    // an unknown set of protocols is left for ffmpeg to complain about
+    if let Ok(capabilities) = capabilities::get_ffmpeg_capabilities() {
+        if !capabilities.has_input_protocol(scheme) {
+            return Err(ProcessError::new(format!(
+                "This ffmpeg cannot read {}:// URLs",
+                scheme
+            )));
+        }
+    }
+    match scheme {
+        "http" | "https" => {
+            let agent = ureq::AgentBuilder::new().timeout(CHECK_TIMEOUT).build();
+            match agent.head(url).call() {
+                Ok(_) => Ok(()),
+                // some servers only refuse the method
+                Err(ureq::Error::Status(405 | 501, _)) => Ok(()),
+                Err(ureq::Error::Status(status, _)) => Err(ProcessError::new(format!(
+                    "{} answered with HTTP {}",
+                    url, status
+                ))),
+                Err(e) => Err(ProcessError::new(format!("Cannot reach {}: {}", url, e))),
+            }
+        }
+        _ => {
+            let host = parsed.host_str().unwrap_or_default();
+            let port = parsed.port().unwrap_or(445);
+            let reachable = (host, port)
+                .to_socket_addrs()
+                .map_err(|e| ProcessError::new(format!("Cannot resolve {}: {}", host, e)))?
+                .any(|address| TcpStream::connect_timeout(&address, CHECK_TIMEOUT).is_ok());
+            if reachable {
+                Ok(())
+            } else {
+                Err(ProcessError::new(format!(
+                    "Cannot reach {} on port {}",
+                    host, port
+                )))
+            }
+        }
+    }
+}
+
+/// The input a job reads: a local path, a URL read over the network, or
+/// the local copy of one, which goes with the job directory.
+pub struct Input {
+    path: PathBuf,
+}
+
+impl Input {
+    /// Where the job reads the source from.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// Checks a URL `input` and, with `cache`, downloads it into `job`. Paths
+/// are passed through untouched.
+pub fn open(
+    input: &Path,
+    cache: bool,
+    job: &JobDir,
+    control: &JobControl,
+) -> Result<Input, ProcessError> {
+    let Some(url) = url(input) else {
+        return Ok(Input {
+            path: input.to_path_buf(),
+        });
+    };
+    check(url)?;
+    if !cache {
+        info!("Reading {} over the network", url);
+        return Ok(Input {
+            path: input.to_path_buf(),
+        });
+    }
+    let dir = job.path().join(DOWNLOAD_DIR);
+    fs::create_dir_all(&dir)
+        .map_err(|e| ProcessError::new(format!("Failed to create {}: {}", dir.display(), e)))?;
+    let path = dir.join(naming_path(input));
+    download(url, &path, control)?;
+    Ok(Input { path })
+}
+
+/// Downloads `url` to `path`, reporting the bytes received.
+fn download(url: &str, path: &Path, control: &JobControl) -> Result<(), ProcessError> {
+    info!("Downloading {} to {}", url, path.display());
+    control.report(Stage::Downloading, 0, 0);
+    let failed =
+        |e: &dyn std::fmt::Display| ProcessError::new(format!("Failed to download {}: {}", url, e));
+    if !url.starts_with("http") {
+        // only ffmpeg speaks SMB; the streams are copied as they are
+        let result = supervisor::run_reporting(
+            || {
+                let mut command = ffmpeg::command()?;
+                command
+                    .args(["-i", url, "-map", "0", "-c", "copy", "-y"])
+                    .arg(paths::ffmpeg_arg(path));
+                Ok(command)
+            },
+            control,
+            Stage::Downloading,
+            None,
+        );
+        return result
+            .map(|_| ())
+            .map_err(|e| ProcessError::ffmpeg("Failed to download the input", e));
+    }
+
+    let response = ureq::get(url).call().map_err(|e| failed(&e))?;
+    let total: usize = response
+        .header("Content-Length")
+        .and_then(|length| length.parse().ok())
+        .unwrap_or(0);
+    let mut reader = response.into_reader();
+    let mut file = File::create(path).map_err(|e| failed(&e))?;
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+    let mut done = 0;
+    loop {
+        control.check()?;
+        let read = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(failed(&e)),
+        };
+        file.write_all(&buffer[..read]).map_err(|e| failed(&e))?;
+        // reads come back in whatever pieces the network delivers, so
+        // progress is reported per chunk's worth
+        if (done + read) / CHUNK_SIZE != done / CHUNK_SIZE {
+            control.report(Stage::Downloading, done + read, total);
+        }
+        done += read;
+    }
+    file.flush().map_err(|e| failed(&e))?;
+    control.report(Stage::Downloading, done, total);
+    info!("Downloaded {} bytes", done);
+    Ok(())
+}
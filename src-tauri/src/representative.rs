@@ -0,0 +1,50 @@
+//! Which frame stands for a run of alike frames. The comparison keeps the
+//! last frame of a run, the one the frames before it matched, or the first
+//! under a comparison window or when removing encoder repeats, where the
+//! frames after it match an earlier one. Any frame of the run can be kept
+//! instead; for motion-blurred sources the sharpest, by
+//! [`blur::sharpness`](crate::blur::sharpness).
+
+use serde::{Deserialize, Serialize};
+use std::ops::RangeInclusive;
+
+use crate::cutlist;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Representative {
+    First,
+    Last,
+    Sharpest,
+}
+
+impl Representative {
+    /// The frame of `run` to keep, given the `sharpness` of each of its
+    /// frames. The earliest of equally sharp frames is kept.
+    pub fn pick(&self, run: &RangeInclusive<usize>, sharpness: impl Fn(usize) -> f64) -> usize {
+        match self {
+            Representative::First => *run.start(),
+            Representative::Last => *run.end(),
+            Representative::Sharpest => run.clone().fold(*run.start(), |best, frame| {
+                if sharpness(frame) > sharpness(best) {
+                    frame
+                } else {
+                    best
+                }
+            }),
+        }
+    }
+}
+
+/// The runs of alike frames in `removed`: each removed span with the kept
+/// frame standing for it, which follows the span, or precedes it when
+/// `kept_first`.
+pub fn runs(removed: &[bool], kept_first: bool) -> Vec<RangeInclusive<usize>> {
+    cutlist::removed_ranges(removed)
+        .into_iter()
+        .filter_map(|span| match kept_first {
+            true => Some(span.start.checked_sub(1)?..=span.end - 1),
+            false => (span.end < removed.len()).then_some(span.start..=span.end),
+        })
+        .collect()
+}
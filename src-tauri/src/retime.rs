@@ -0,0 +1,57 @@
+//! Retiming: the kept frames play at a constant frame rate of the user's
+//! choosing, so the output's speed does not depend on how many frames were
+//! removed, and the source's audio comes along with its tempo changed by
+//! the factor the video was sped up or slowed down by. The audio plays at
+//! one even tempo throughout; it does not skip where frames were removed,
+//! so in between it can run ahead of or behind the picture, and the two
+//! meet again at the end.
+
+use std::path::PathBuf;
+
+/// `atempo` only goes from half to double speed in older ffmpeg builds.
+const MAX_STAGE: f64 = 2.0;
+
+/// The source's audio, to be played `tempo` times as fast.
+#[derive(Debug, Clone)]
+pub struct Audio {
+    pub input: PathBuf,
+    pub tempo: f64,
+}
+
+impl Audio {
+    /// The `atempo` chain that changes the tempo by `self.tempo`.
+    pub fn filter(&self) -> String {
+        let mut stages = Vec::new();
+        let mut rest = self.tempo;
+        while rest > MAX_STAGE {
+            stages.push(MAX_STAGE);
+            rest /= MAX_STAGE;
+        }
+        while rest < 1.0 / MAX_STAGE {
+            stages.push(1.0 / MAX_STAGE);
+            rest *= MAX_STAGE;
+        }
+        stages.push(rest);
+        stages
+            .iter()
+            .map(|tempo| format!("atempo={}", tempo))
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+/// How much faster than the source a video of `after_secs` that was
+/// `before_secs` long plays. `None` when nothing is left to play.
+pub fn tempo(before_secs: f64, after_secs: f64) -> Option<f64> {
+    (after_secs > 0.0 && before_secs > 0.0).then(|| before_secs / after_secs)
+}
+
+pub fn validate(fps: f64) -> Result<(), String> {
+    if !fps.is_finite() || fps <= 0.0 {
+        return Err(format!(
+            "The frame rate to retime to must be positive, got {}",
+            fps
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,229 @@
+//! Restrictions on the ffmpeg children. ffmpeg parses whatever file it is
+//! handed, so it runs with as little as it needs: an optional memory cap,
+//! and on Windows a CPU cap, no network access on Linux unless the job
+//! reads a URL, and never outliving the app. On Linux the kernel kills a
+//! child whose parent dies; on Windows it sits in a job object that is
+//! closed, taking the child with it, when the app goes away.
+
+use std::io;
+use std::ops::{Deref, DerefMut};
+use std::process::{Child, Command};
+use tracing::debug;
+
+use crate::remote;
+use crate::settings;
+
+/// What a child is held to, from the settings.
+struct Limits {
+    memory_bytes: Option<u64>,
+    #[cfg_attr(not(windows), allow(dead_code))]
+    cpu_percent: Option<u32>,
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    isolate_network: bool,
+}
+
+impl Limits {
+    fn for_command(command: &Command) -> Limits {
+        let settings = settings::current();
+        // URL inputs are read by ffmpeg itself
+        let reads_url = command
+            .get_args()
+            .any(|arg| arg.to_str().is_some_and(remote::is_url));
+        Limits {
+            memory_bytes: settings.ffmpeg_memory_limit_mb.map(|mb| mb << 20),
+            cpu_percent: settings.ffmpeg_cpu_limit_percent.map(|p| p.clamp(1, 100)),
+            isolate_network: settings.isolate_ffmpeg_network && !reads_url,
+        }
+    }
+}
+
+/// A child started by [`spawn`]. It is killed when dropped while still
+/// running, so an early return or a panic leaves no ffmpeg behind.
+pub struct SandboxedChild {
+    child: Child,
+    #[cfg(windows)]
+    _job: Option<JobObject>,
+}
+
+impl Deref for SandboxedChild {
+    type Target = Child;
+
+    fn deref(&self) -> &Child {
+        &self.child
+    }
+}
+
+impl DerefMut for SandboxedChild {
+    fn deref_mut(&mut self) -> &mut Child {
+        &mut self.child
+    }
+}
+
+impl Drop for SandboxedChild {
+    fn drop(&mut self) {
+        if let Ok(None) = self.child.try_wait() {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+    }
+}
+
+/// Starts `command` under the limits of the settings.
+pub fn spawn(command: &mut Command) -> io::Result<SandboxedChild> {
+    let limits = Limits::for_command(command);
+    #[cfg(unix)]
+    restrict(command, &limits);
+    let child = command.spawn()?;
+    #[cfg(windows)]
+    let job = match JobObject::new(&limits).and_then(|job| job.assign(&child).map(|_| job)) {
+        Ok(job) => Some(job),
+        Err(e) => {
+            debug!("Running ffmpeg outside a job object: {}", e);
+            None
+        }
+    };
+    Ok(SandboxedChild {
+        child,
+        #[cfg(windows)]
+        _job: job,
+    })
+}
+
+/// Applies `limits` in the child between fork and exec. Restrictions the
+/// system does not allow, such as user namespaces where they are turned
+/// off, are skipped rather than failing the run.
+#[cfg(unix)]
+fn restrict(command: &mut Command, limits: &Limits) {
+    use std::os::unix::process::CommandExt;
+
+    let memory_bytes = limits.memory_bytes;
+    #[cfg(target_os = "linux")]
+    let isolate_network = limits.isolate_network;
+    #[cfg(target_os = "linux")]
+    let parent = std::process::id();
+    // SAFETY: only async-signal-safe calls that touch no parent state
+    unsafe {
+        command.pre_exec(move || {
+            let no_core = libc::rlimit {
+                rlim_cur: 0,
+                rlim_max: 0,
+            };
+            libc::setrlimit(libc::RLIMIT_CORE, &no_core);
+            if let Some(bytes) = memory_bytes {
+                let limit = libc::rlimit {
+                    rlim_cur: bytes as libc::rlim_t,
+                    rlim_max: bytes as libc::rlim_t,
+                };
+                libc::setrlimit(libc::RLIMIT_AS, &limit);
+            }
+            #[cfg(target_os = "linux")]
+            {
+                libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGKILL as libc::c_ulong);
+                // the parent may have died before the signal was set up
+                if libc::getppid() as u32 != parent {
+                    return Err(io::Error::other("the app exited"));
+                }
+                libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1 as libc::c_ulong, 0, 0, 0);
+                if isolate_network {
+                    // a network namespace of its own, with nothing but a
+                    // loopback that is down; unprivileged processes need a
+                    // user namespace to get one
+                    libc::unshare(libc::CLONE_NEWUSER | libc::CLONE_NEWNET);
+                }
+            }
+            Ok(())
+        });
+    }
+    debug!(
+        "Sandboxing ffmpeg: memory limit {:?}, network isolated: {}",
+        memory_bytes,
+        cfg!(target_os = "linux") && limits.isolate_network
+    );
+}
+
+/// A job object that kills its processes once its last handle is closed,
+/// which is also what happens when the app dies.
+#[cfg(windows)]
+struct JobObject(windows_sys::Win32::Foundation::HANDLE);
+
+#[cfg(windows)]
+// SAFETY: a job handle may be used and closed from any thread
+unsafe impl Send for JobObject {}
+
+#[cfg(windows)]
+impl JobObject {
+    fn new(limits: &Limits) -> io::Result<JobObject> {
+        use windows_sys::Win32::System::JobObjects::{
+            CreateJobObjectW, JobObjectCpuRateControlInformation,
+            JobObjectExtendedLimitInformation, SetInformationJobObject,
+            JOBOBJECT_CPU_RATE_CONTROL_INFORMATION, JOBOBJECT_EXTENDED_LIMIT_INFORMATION,
+            JOB_OBJECT_CPU_RATE_CONTROL_ENABLE, JOB_OBJECT_CPU_RATE_CONTROL_HARD_CAP,
+            JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE, JOB_OBJECT_LIMIT_PROCESS_MEMORY,
+        };
+
+        // SAFETY: the handle is checked and owned by the returned value, and
+        // the structures passed are plain data of the sizes given
+        unsafe {
+            let handle = CreateJobObjectW(std::ptr::null(), std::ptr::null());
+            if handle.is_null() {
+                return Err(io::Error::last_os_error());
+            }
+            let job = JobObject(handle);
+
+            let mut info: JOBOBJECT_EXTENDED_LIMIT_INFORMATION = std::mem::zeroed();
+            info.BasicLimitInformation.LimitFlags = JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE;
+            if let Some(bytes) = limits.memory_bytes {
+                info.BasicLimitInformation.LimitFlags |= JOB_OBJECT_LIMIT_PROCESS_MEMORY;
+                info.ProcessMemoryLimit = bytes as usize;
+            }
+            if SetInformationJobObject(
+                job.0,
+                JobObjectExtendedLimitInformation,
+                &info as *const _ as *const _,
+                std::mem::size_of_val(&info) as u32,
+            ) == 0
+            {
+                return Err(io::Error::last_os_error());
+            }
+
+            if let Some(percent) = limits.cpu_percent {
+                let mut rate: JOBOBJECT_CPU_RATE_CONTROL_INFORMATION = std::mem::zeroed();
+                rate.ControlFlags =
+                    JOB_OBJECT_CPU_RATE_CONTROL_ENABLE | JOB_OBJECT_CPU_RATE_CONTROL_HARD_CAP;
+                // in hundredths of a percent of all processors
+                rate.Anonymous.CpuRate = percent * 100;
+                if SetInformationJobObject(
+                    job.0,
+                    JobObjectCpuRateControlInformation,
+                    &rate as *const _ as *const _,
+                    std::mem::size_of_val(&rate) as u32,
+                ) == 0
+                {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            Ok(job)
+        }
+    }
+
+    fn assign(&self, child: &Child) -> io::Result<()> {
+        use std::os::windows::io::AsRawHandle;
+        use windows_sys::Win32::System::JobObjects::AssignProcessToJobObject;
+
+        // SAFETY: both handles are open for the duration of the call
+        if unsafe { AssignProcessToJobObject(self.0, child.as_raw_handle() as _) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(windows)]
+impl Drop for JobObject {
+    fn drop(&mut self) {
+        // SAFETY: the handle is owned and closed once
+        unsafe {
+            windows_sys::Win32::Foundation::CloseHandle(self.0);
+        }
+    }
+}
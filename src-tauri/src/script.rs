@@ -0,0 +1,113 @@
+//! The last word on which frames are removed, for heuristics the options
+//! do not cover: a [Rhai](https://rhai.rs) script defining `decide(frame)`
+//! is called for every frame in order, after the analysis and everything
+//! done with its result, with a map of
+//!
+//! - `index`, the frame's index from 0,
+//! - `time`, its time in seconds, or `()` when the frame rate is unknown,
+//! - `score`, its score as in [`Analysis::scores`], or `()` if it has none,
+//! - `removed`, whether the analysis removes it,
+//!
+//! and returns `true` to remove the frame, `false` to keep it or `()` to
+//! leave it as it is. `this` is a map kept from one call to the next, so a
+//! script can remember the frames before. This one keeps a frame a second
+//! of long frozen spans at 30 fps:
+//!
+//! ```rhai
+//! fn decide(frame) {
+//!     if !frame.removed {
+//!         this.run = 0;
+//!         return;
+//!     }
+//!     this.run = (this.run ?? 0) + 1;
+//!     if this.run % 30 == 0 {
+//!         false
+//!     }
+//! }
+//! ```
+//!
+//! Scripts cannot touch files or the network, and each call is cut off
+//! after [`MAX_OPERATIONS`].
+//!
+//! [`Analysis::scores`]: crate::video_fixer::Analysis::scores
+
+use rhai::{CallFnOptions, Dynamic, Engine, Map, Scope, AST};
+use std::path::Path;
+
+/// Operations a single call may take, so a script stuck in a loop fails
+/// the job rather than hanging it.
+pub const MAX_OPERATIONS: u64 = 1_000_000;
+
+/// What a script is told about a frame.
+pub struct Frame {
+    pub index: usize,
+    pub time: Option<f64>,
+    pub score: Option<f32>,
+    pub removed: bool,
+}
+
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+    /// `this` of every call.
+    state: Dynamic,
+}
+
+impl Script {
+    /// Compiles the script at `path` and checks it defines `decide`.
+    pub fn load(path: &Path) -> Result<Script, String> {
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        let ast = engine
+            .compile_file(path.to_path_buf())
+            .map_err(|e| format!("Failed to load script {}: {}", path.display(), e))?;
+        if !ast
+            .iter_functions()
+            .any(|function| function.name == "decide" && function.params.len() == 1)
+        {
+            return Err(format!(
+                "Script {} defines no decide(frame) function",
+                path.display()
+            ));
+        }
+        Ok(Script {
+            engine,
+            ast,
+            state: Dynamic::from_map(Map::new()),
+        })
+    }
+
+    /// The script's decision on `frame`: whether it is removed, or `None`
+    /// to leave it as the analysis has it.
+    pub fn decide(&mut self, frame: &Frame) -> Result<Option<bool>, String> {
+        let mut map = Map::new();
+        map.insert("index".into(), (frame.index as i64).into());
+        map.insert(
+            "time".into(),
+            frame.time.map_or(Dynamic::UNIT, Dynamic::from_float),
+        );
+        map.insert(
+            "score".into(),
+            frame
+                .score
+                .map_or(Dynamic::UNIT, |score| Dynamic::from_float(score as f64)),
+        );
+        map.insert("removed".into(), frame.removed.into());
+
+        let mut options = CallFnOptions::new().bind_this_ptr(&mut self.state);
+        options.eval_ast = false;
+        let decision: Dynamic = self
+            .engine
+            .call_fn_with_options(options, &mut Scope::new(), &self.ast, "decide", (map,))
+            .map_err(|e| format!("Script failed on frame {}: {}", frame.index, e))?;
+        if decision.is_unit() {
+            return Ok(None);
+        }
+        decision.as_bool().map(Some).map_err(|type_name| {
+            format!(
+                "Script returned {} for frame {}; decide returns true, false or ()",
+                type_name, frame.index
+            )
+        })
+    }
+}
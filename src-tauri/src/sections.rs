@@ -0,0 +1,65 @@
+//! Stretches of a source analysed with a threshold or metric of their own,
+//! such as a title card where anything close to the last frame is dead,
+//! within gameplay where only exact repeats are.
+
+use serde::{Deserialize, Serialize};
+
+use crate::similarity::Metric;
+
+/// A time range of the source and what it overrides there. Where sections
+/// overlap, the later one in the list wins.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Section {
+    pub start_secs: f64,
+    /// End of the section, exclusive.
+    pub end_secs: f64,
+    #[serde(default)]
+    pub threshold: Option<f32>,
+    #[serde(default)]
+    pub metric: Option<Metric>,
+}
+
+/// Checks sections the frontend could have sent out of range.
+pub fn validate(sections: &[Section]) -> Result<(), String> {
+    for section in sections {
+        if !(section.start_secs >= 0.0 && section.start_secs < section.end_secs) {
+            return Err(format!(
+                "Section {}s to {}s is empty or out of order",
+                section.start_secs, section.end_secs
+            ));
+        }
+        if let Some(threshold) = section.threshold {
+            if !(0.0..=1.0).contains(&threshold) {
+                return Err(format!(
+                    "Threshold must be between 0 and 1, got {}",
+                    threshold
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Sections looked up by frame index at the source's frame rate.
+pub struct SectionMap<'a> {
+    sections: &'a [Section],
+    fps: f64,
+}
+
+impl<'a> SectionMap<'a> {
+    pub fn new(sections: &'a [Section], fps: f64) -> SectionMap<'a> {
+        SectionMap { sections, fps }
+    }
+
+    /// The section frame `frame` falls in, if any.
+    pub fn at(&self, frame: usize) -> Option<&'a Section> {
+        if self.sections.is_empty() {
+            return None;
+        }
+        let secs = frame as f64 / self.fps;
+        self.sections
+            .iter()
+            .rev()
+            .find(|section| section.start_secs <= secs && secs < section.end_secs)
+    }
+}
@@ -0,0 +1,121 @@
+//! Numbered image sequences used directly as input, such as an exported
+//! `shot_0001.png`, `shot_0002.png`, ... folder.
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Extensions of the images a sequence can consist of.
+pub const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "webp", "bmp", "tif", "tiff"];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImageSequence {
+    /// The naming pattern with the frame number as `#`s, e.g. `shot_####.png`.
+    pub pattern: String,
+    /// Extension of every frame, lowercase.
+    pub extension: String,
+    pub first: u64,
+    pub last: u64,
+    /// Frames in numeric order. Gaps in the numbering are allowed.
+    #[serde(skip)]
+    pub frames: Vec<PathBuf>,
+}
+
+impl ImageSequence {
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+}
+
+/// Digits the frames the app writes are numbered with when their count is
+/// not known up front; enough for over a month of 30 fps video.
+pub const FRAME_DIGITS: usize = 8;
+
+/// Digits needed to number `count` frames from 1, at least the four of
+/// `frame_0001`.
+pub fn digits_for(count: usize) -> usize {
+    count.to_string().len().max(4)
+}
+
+/// Name of the frame numbered `number` in a sequence the app writes, e.g.
+/// `frame_0001.png`.
+pub fn frame_name(number: usize, digits: usize, extension: &str) -> String {
+    format!("frame_{:0width$}.{}", number, extension, width = digits)
+}
+
+/// The ffmpeg pattern matching the [`frame_name`]s of `digits` digits.
+pub fn frame_pattern(digits: usize, extension: &str) -> String {
+    format!("frame_%0{}d.{}", digits, extension)
+}
+
+/// A frame's number, the width it is written with and its path.
+type NumberedFrame = (u64, usize, PathBuf);
+
+/// Splits a file name into the text around its last run of digits, the
+/// number itself and its width, e.g. `shot_0012.png` into
+/// `("shot_", ".png", 12, 4)`.
+fn split_number(name: &str) -> Option<(&str, &str, u64, usize)> {
+    let end = name.rfind(|c: char| c.is_ascii_digit())? + 1;
+    let start = name[..end]
+        .rfind(|c: char| !c.is_ascii_digit())
+        .map_or(0, |i| i + 1);
+    let number = name[start..end].parse().ok()?;
+    Some((&name[..start], &name[end..], number, end - start))
+}
+
+/// Finds the image sequence in `dir`. When several are present, the one
+/// with the most frames is used, and of equally long ones the first by
+/// name.
+pub fn detect(dir: &Path) -> Result<ImageSequence, String> {
+    let entries =
+        fs::read_dir(dir).map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?;
+
+    // frames grouped by the name around their number and the extension
+    let mut groups: HashMap<(String, String), Vec<NumberedFrame>> = HashMap::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let is_image = path.extension().is_some_and(|ext| {
+            IMAGE_EXTENSIONS
+                .iter()
+                .any(|image| ext.eq_ignore_ascii_case(image))
+        });
+        if !is_image {
+            continue;
+        }
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if let Some((prefix, suffix, number, width)) = split_number(&name) {
+            groups
+                .entry((prefix.to_string(), suffix.to_string()))
+                .or_default()
+                .push((number, width, path));
+        }
+    }
+
+    let ((prefix, suffix), mut frames) = groups
+        .into_iter()
+        .max_by(|(a_name, a), (b_name, b)| a.len().cmp(&b.len()).then(b_name.cmp(a_name)))
+        .ok_or_else(|| format!("No numbered images in {}", dir.display()))?;
+    if frames.len() < 2 {
+        return Err(format!("Only one numbered image in {}", dir.display()));
+    }
+    frames.sort_by_key(|(number, _, _)| *number);
+
+    let width = frames.iter().map(|(_, width, _)| *width).min().unwrap_or(1);
+    let extension = frames[0]
+        .2
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    Ok(ImageSequence {
+        pattern: format!("{}{}{}", prefix, "#".repeat(width), suffix),
+        extension,
+        first: frames[0].0,
+        last: frames[frames.len() - 1].0,
+        frames: frames.into_iter().map(|(_, _, path)| path).collect(),
+    })
+}
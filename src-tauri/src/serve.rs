@@ -0,0 +1,192 @@
+//! Automation over stdin and stdout: newline-delimited JSON-RPC 2.0 requests
+//! come in, responses and job events go out, one JSON object per line.
+//!
+//! Methods:
+//!
+//! - `enqueue {input, options?, preset?}` queues a job and returns
+//!   `{job}`. Full `options` win over a `preset`; with neither the settings
+//!   apply.
+//! - `status {job?}` returns one job's status, or all of them.
+//! - `cancel {job}` cancels a queued or running job.
+//! - `pause {job}` holds a running job, suspending its ffmpeg children, and
+//!   `resume {job}` lets it go on.
+//!
+//! Jobs run one at a time in the order queued. Events are notifications
+//! (no `id`) named `job-added`, `job-started`, `progress`, `decisions`,
+//! `job-finished`, `job-failed`, `job-cancelled`, `job-paused` and
+//! `job-resumed`, each with the job number in `params.job`, and
+//! `queue-drained` once no job is left waiting. At the end of input the
+//! queue is worked off before returning.
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::io::{BufRead, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tracing::warn;
+
+use crate::presets;
+use crate::queue::JobQueue;
+use crate::video_fixer::ProcessOptions;
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+/// A request referring to a job that does not exist.
+const UNKNOWN_JOB: i64 = -32000;
+
+type Output = Arc<Mutex<Box<dyn Write + Send>>>;
+
+struct Server {
+    queue: JobQueue,
+    output: Output,
+}
+
+#[derive(Deserialize)]
+struct Request {
+    #[serde(default)]
+    id: Value,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Deserialize)]
+struct EnqueueParams {
+    input: PathBuf,
+    options: Option<ProcessOptions>,
+    preset: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct JobParams {
+    job: Option<u64>,
+}
+
+type RpcError = (i64, String);
+
+fn send(output: &Output, message: Value) {
+    let mut output = output.lock().unwrap();
+    let _ = writeln!(output, "{}", message).and_then(|_| output.flush());
+}
+
+impl Server {
+    fn handle(&self, line: &str) {
+        let request: Request = match serde_json::from_str::<Value>(line) {
+            Err(e) => return self.reply(Value::Null, Err((PARSE_ERROR, e.to_string()))),
+            Ok(value) => match serde_json::from_value(value) {
+                Ok(request) => request,
+                Err(e) => return self.reply(Value::Null, Err((INVALID_REQUEST, e.to_string()))),
+            },
+        };
+        let result = match request.method.as_str() {
+            "enqueue" => params(request.params).and_then(|p| self.enqueue(p)),
+            "status" => params(request.params).and_then(|p| self.status(p)),
+            "cancel" => params(request.params).and_then(|p| self.cancel(p)),
+            "pause" => params(request.params).and_then(|p| self.pause(p)),
+            "resume" => params(request.params).and_then(|p| self.resume(p)),
+            other => Err((METHOD_NOT_FOUND, format!("Unknown method: {}", other))),
+        };
+        self.reply(request.id, result);
+    }
+
+    fn reply(&self, id: Value, result: Result<Value, RpcError>) {
+        send(
+            &self.output,
+            match result {
+                Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                Err((code, message)) => json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "error": { "code": code, "message": message },
+                }),
+            },
+        );
+    }
+
+    fn enqueue(&self, params: EnqueueParams) -> Result<Value, RpcError> {
+        let options = match (params.options, params.preset) {
+            (Some(options), _) => options,
+            (None, Some(name)) => presets::get(&name)
+                .ok_or_else(|| (INVALID_PARAMS, format!("No preset named \"{}\"", name)))?
+                .options(),
+            (None, None) => ProcessOptions::default(),
+        };
+        let job = self.queue.enqueue(params.input, options, false);
+        Ok(json!({ "job": job }))
+    }
+
+    fn status(&self, params: JobParams) -> Result<Value, RpcError> {
+        match params.job {
+            Some(job) => self
+                .queue
+                .job(job)
+                .map(|job| json!(job))
+                .ok_or_else(|| (UNKNOWN_JOB, format!("No job {}", job))),
+            None => Ok(json!(self.queue.jobs())),
+        }
+    }
+
+    fn cancel(&self, params: JobParams) -> Result<Value, RpcError> {
+        let job = params
+            .job
+            .ok_or_else(|| (INVALID_PARAMS, "Missing job".to_string()))?;
+        self.queue.cancel(job).map_err(|e| (UNKNOWN_JOB, e))?;
+        Ok(json!({ "job": job }))
+    }
+
+    fn pause(&self, params: JobParams) -> Result<Value, RpcError> {
+        let job = params
+            .job
+            .ok_or_else(|| (INVALID_PARAMS, "Missing job".to_string()))?;
+        self.queue.pause(job).map_err(|e| (UNKNOWN_JOB, e))?;
+        Ok(json!({ "job": job }))
+    }
+
+    fn resume(&self, params: JobParams) -> Result<Value, RpcError> {
+        let job = params
+            .job
+            .ok_or_else(|| (INVALID_PARAMS, "Missing job".to_string()))?;
+        self.queue.resume(job).map_err(|e| (UNKNOWN_JOB, e))?;
+        Ok(json!({ "job": job }))
+    }
+}
+
+fn params<T: for<'de> Deserialize<'de>>(params: Value) -> Result<T, RpcError> {
+    // a missing params member means no parameters
+    let params = if params.is_null() { json!({}) } else { params };
+    serde_json::from_value(params).map_err(|e| (INVALID_PARAMS, e.to_string()))
+}
+
+/// Serves requests read from `input`, writing responses and events to
+/// `output`, until `input` ends and every queued job has run.
+pub fn serve(input: impl BufRead, output: impl Write + Send + 'static) {
+    let output: Output = Arc::new(Mutex::new(Box::new(output)));
+    let events = output.clone();
+    let queue = JobQueue::new(move |event| {
+        // the event's tag becomes the notification's method
+        let mut params = json!(event);
+        let method = params
+            .as_object_mut()
+            .and_then(|params| params.remove("event"))
+            .unwrap_or_default();
+        send(
+            &events,
+            json!({ "jsonrpc": "2.0", "method": method, "params": params }),
+        );
+    });
+    let server = Server { queue, output };
+
+    for line in input.lines() {
+        match line {
+            Ok(line) if line.trim().is_empty() => {}
+            Ok(line) => server.handle(&line),
+            Err(e) => {
+                warn!("Failed to read request: {}", e);
+                break;
+            }
+        }
+    }
+    server.queue.finish();
+}
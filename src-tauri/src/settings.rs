@@ -0,0 +1,309 @@
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tracing::warn;
+
+use crate::hooks::Hooks;
+use crate::output::OutputOptions;
+use crate::postaction::PostActions;
+use crate::processed::ProcessedPolicy;
+use crate::similarity::{ColorSpace, Metric};
+use crate::video_fixer::{FrameFormat, VideoCodec};
+use crate::watch::WatchSettings;
+use crate::webhook::WebhookSettings;
+
+/// Version written by this build. Files from older builds are migrated on
+/// load; see [`migrate`].
+pub const SETTINGS_VERSION: u32 = 3;
+
+/// Default similarity above which a frame counts as dead.
+pub const DEFAULT_THRESHOLD: f32 = 0.95;
+
+/// User settings that are persisted across sessions.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AppSettings {
+    pub version: u32,
+    /// Frames scoring above this against their successor are removed.
+    pub threshold: f32,
+    /// Below `threshold`: runs of dead frames last while frames score above
+    /// this. No hysteresis when unset.
+    pub exit_threshold: Option<f32>,
+    /// How consecutive frames are compared.
+    pub metric: Metric,
+    /// The channels frames are compared in.
+    pub color_space: ColorSpace,
+    /// ONNX model the learned metric of `onnx` builds classifies frame
+    /// pairs with.
+    pub learned_model: Option<PathBuf>,
+    /// Detector plugins every job runs, by name; see
+    /// [`plugins`](crate::plugins). Ignored by builds without them.
+    pub enabled_plugins: Vec<String>,
+    /// Codec of the processed video.
+    pub codec: VideoCodec,
+    /// Format of the intermediate frames.
+    pub frame_format: FrameFormat,
+    /// Where processed videos are written and how they are named.
+    pub output: OutputOptions,
+    /// Directory used for intermediate frames; the OS temp dir when unset.
+    pub temp_dir: Option<PathBuf>,
+    /// Comparison and ffmpeg thread count; all cores when unset.
+    pub threads: Option<usize>,
+    /// Maximum number of ffmpeg processes running at once; unlimited when unset.
+    pub max_ffmpeg_processes: Option<usize>,
+    /// Encodes that may run at once across jobs; one per four threads when
+    /// unset.
+    pub max_concurrent_encodes: Option<usize>,
+    /// Frame pairs each comparison run scores; tuned per job when unset.
+    pub comparison_batch_size: Option<usize>,
+    /// GPUs hardware encodes are spread across, by NVENC index; GPU 0 when
+    /// empty.
+    pub nvenc_gpus: Vec<u32>,
+    /// Hardware encodes each GPU may run at once. Consumer NVIDIA drivers
+    /// limit the number of NVENC sessions.
+    pub nvenc_sessions_per_gpu: usize,
+    /// Run ffmpeg and comparison threads at reduced OS priority.
+    pub low_priority: bool,
+    /// Use an ffmpeg found in PATH instead of the embedded one.
+    pub prefer_system_ffmpeg: bool,
+    /// A specific ffmpeg binary to use, taking precedence over the others.
+    pub ffmpeg_path: Option<PathBuf>,
+    /// Proxy URL for downloading ffmpeg in `download-ffmpeg` builds.
+    pub download_proxy: Option<String>,
+    /// Seconds ffmpeg may go without output before it is killed; 0 disables
+    /// the timeout and unset uses the default.
+    pub ffmpeg_timeout_secs: Option<u64>,
+    /// How often a transiently failing ffmpeg invocation is retried.
+    pub ffmpeg_retries: u32,
+    /// Memory each ffmpeg process may use, in MiB; address space on Unix.
+    /// Unlimited when unset.
+    pub ffmpeg_memory_limit_mb: Option<u64>,
+    /// Share of all processors ffmpeg may use, in percent. Windows only;
+    /// unlimited when unset.
+    pub ffmpeg_cpu_limit_percent: Option<u32>,
+    /// Cut ffmpeg off from the network when the input is not a URL. Linux
+    /// only.
+    pub isolate_ffmpeg_network: bool,
+    /// Suppress the desktop notification when a job finishes or fails.
+    pub mute_notifications: bool,
+    /// The watch folder and what is done with files dropped into it.
+    pub watch: WatchSettings,
+    /// Preset for videos opened with the app; the settings when unset.
+    pub default_preset: Option<String>,
+    /// Start processing opened videos right away instead of holding them in
+    /// the queue.
+    pub auto_start_opened: bool,
+    /// What to do with dropped files and folders that were processed
+    /// before.
+    pub if_processed: ProcessedPolicy,
+    /// What the app does after each job and once the queue drains.
+    pub post_actions: PostActions,
+    /// Where finished and failed jobs are reported over HTTP.
+    pub webhook: WebhookSettings,
+    /// Shell commands run before and after each job.
+    pub hooks: Hooks,
+}
+
+impl Default for AppSettings {
+    fn default() -> Self {
+        AppSettings {
+            version: SETTINGS_VERSION,
+            threshold: DEFAULT_THRESHOLD,
+            exit_threshold: None,
+            metric: Metric::default(),
+            color_space: ColorSpace::default(),
+            learned_model: None,
+            enabled_plugins: Vec::new(),
+            codec: VideoCodec::default(),
+            frame_format: FrameFormat::default(),
+            output: OutputOptions::default(),
+            temp_dir: None,
+            threads: None,
+            max_ffmpeg_processes: None,
+            max_concurrent_encodes: None,
+            comparison_batch_size: None,
+            nvenc_gpus: Vec::new(),
+            nvenc_sessions_per_gpu: 3,
+            low_priority: false,
+            prefer_system_ffmpeg: false,
+            ffmpeg_path: None,
+            download_proxy: None,
+            ffmpeg_timeout_secs: None,
+            ffmpeg_retries: 0,
+            ffmpeg_memory_limit_mb: None,
+            ffmpeg_cpu_limit_percent: None,
+            isolate_ffmpeg_network: true,
+            mute_notifications: false,
+            watch: WatchSettings::default(),
+            default_preset: None,
+            auto_start_opened: false,
+            if_processed: ProcessedPolicy::default(),
+            post_actions: PostActions::default(),
+            webhook: WebhookSettings::default(),
+            hooks: Hooks::default(),
+        }
+    }
+}
+
+/// The settings the engine and app of this process run with.
+static STORE: Lazy<Arc<SettingsStore>> = Lazy::new(Arc::default);
+
+/// Upgrades a settings document written by an older build in place. Files
+/// without a `version` field predate versioning and count as version 1.
+fn migrate(value: &mut Value) {
+    let Some(object) = value.as_object_mut() else {
+        return;
+    };
+    let mut version = object.get("version").and_then(Value::as_u64).unwrap_or(1);
+
+    if version < 2 {
+        // the work directory became `temp_dir`
+        if let Some(work_dir) = object.remove("work_dir") {
+            object.insert("temp_dir".into(), work_dir);
+        }
+        version = 2;
+    }
+
+    if version < 3 {
+        // the post-processing command became the `after_job` hook; where
+        // both were set, the command now runs once the hook is done
+        let command = object
+            .get_mut("post_actions")
+            .and_then(Value::as_object_mut)
+            .and_then(|actions| actions.remove("command"));
+        if let Some(Value::String(command)) = command {
+            let hooks = object
+                .entry("hooks")
+                .or_insert_with(|| Value::Object(Default::default()));
+            if let Some(hooks) = hooks.as_object_mut() {
+                let after_job = match hooks.get("after_job").and_then(Value::as_str) {
+                    Some(hook) => {
+                        let separator = if cfg!(windows) { " & " } else { "; " };
+                        format!("{}{}{}", hook, separator, command)
+                    }
+                    None => command,
+                };
+                hooks.insert("after_job".into(), after_job.into());
+            }
+        }
+        version = 3;
+    }
+
+    object.insert("version".into(), version.into());
+}
+
+impl AppSettings {
+    fn load(path: &Path) -> AppSettings {
+        let Ok(contents) = fs::read_to_string(path) else {
+            return AppSettings::default();
+        };
+        let parsed = serde_json::from_str::<Value>(&contents).and_then(|mut value| {
+            migrate(&mut value);
+            serde_json::from_value(value)
+        });
+        parsed.unwrap_or_else(|e| {
+            warn!("Ignoring malformed settings {}: {}", path.display(), e);
+            AppSettings::default()
+        })
+    }
+
+    fn save(&self, path: &Path) -> std::io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let contents = serde_json::to_string_pretty(self)?;
+        fs::write(path, contents)
+    }
+
+    /// Checks values the frontend could have sent out of range.
+    pub fn validate(&self) -> Result<(), String> {
+        if !(0.0..=1.0).contains(&self.threshold) {
+            return Err(format!(
+                "Threshold must be between 0 and 1, got {}",
+                self.threshold
+            ));
+        }
+        if let Some(exit) = self.exit_threshold {
+            if !(0.0..=self.threshold).contains(&exit) {
+                return Err(format!(
+                    "Exit threshold must be between 0 and the threshold, got {}",
+                    exit
+                ));
+            }
+        }
+        self.color_space.validate()?;
+        if self.threads == Some(0)
+            || self.max_ffmpeg_processes == Some(0)
+            || self.max_concurrent_encodes == Some(0)
+            || self.comparison_batch_size == Some(0)
+            || self.nvenc_sessions_per_gpu == 0
+        {
+            return Err("Thread and process limits must be at least 1".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Settings held in memory and, once a file is given, saved to it on every
+/// change. Without a file they start at the defaults and live in memory.
+#[derive(Default)]
+pub struct SettingsStore {
+    settings: Mutex<AppSettings>,
+    path: Mutex<Option<PathBuf>>,
+}
+
+impl SettingsStore {
+    /// Loads the settings file at `path` and remembers it for later saves.
+    pub fn load(&self, path: PathBuf) {
+        *self.settings.lock().unwrap() = AppSettings::load(&path);
+        *self.path.lock().unwrap() = Some(path);
+    }
+
+    pub fn current(&self) -> AppSettings {
+        self.settings.lock().unwrap().clone()
+    }
+
+    /// Applies `change` to the current settings and writes them to disk.
+    pub fn update(&self, change: impl FnOnce(&mut AppSettings)) -> std::io::Result<()> {
+        let mut settings = self.settings.lock().unwrap();
+        change(&mut settings);
+        settings.version = SETTINGS_VERSION;
+        match self.path.lock().unwrap().as_ref() {
+            Some(path) => settings.save(path),
+            None => Ok(()),
+        }
+    }
+
+    /// Applies `change` for the rest of the process without saving it, for
+    /// options given for a single run.
+    pub fn apply(&self, change: impl FnOnce(&mut AppSettings)) {
+        change(&mut self.settings.lock().unwrap());
+    }
+}
+
+/// The process's settings, shared with [`crate::state::AppState`].
+pub fn store() -> Arc<SettingsStore> {
+    STORE.clone()
+}
+
+/// Loads the settings file at `path` and remembers it for later saves.
+pub fn init(path: PathBuf) {
+    STORE.load(path);
+}
+
+pub fn current() -> AppSettings {
+    STORE.current()
+}
+
+/// Applies `change` to the current settings and writes them to disk.
+pub fn update(change: impl FnOnce(&mut AppSettings)) -> std::io::Result<()> {
+    STORE.update(change)
+}
+
+/// Applies `change` to the current settings without writing them to disk.
+pub fn apply(change: impl FnOnce(&mut AppSettings)) {
+    STORE.apply(change)
+}
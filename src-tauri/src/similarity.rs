@@ -0,0 +1,341 @@
+use image::{DynamicImage, GrayImage};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use wide::f32x8;
+
+const K1: f32 = 0.01;
+const L: f32 = 255.0;
+
+/// Frames at least this large are worth uploading to the GPU; below 4K the
+/// transfer costs more than the SIMD path takes to score the pair.
+#[cfg(feature = "gpu")]
+const GPU_MIN_PIXELS: u32 = 3840 * 2160;
+
+/// Per-pixel SSIM between two luma values. With a one pixel window the means
+/// are the pixels themselves and both variance terms vanish, leaving only the
+/// luminance component.
+#[inline]
+fn pixel_ssim(p1: f32, p2: f32, c1: f32) -> f32 {
+    (2.0 * p1 * p2 + c1) / (p1 * p1 + p2 * p2 + c1)
+}
+
+fn row_sum_scalar(row1: &[u8], row2: &[u8], c1: f32) -> f32 {
+    row1.iter()
+        .zip(row2)
+        .map(|(&p1, &p2)| pixel_ssim(p1 as f32, p2 as f32, c1))
+        .sum()
+}
+
+fn row_sum_simd(row1: &[u8], row2: &[u8], c1: f32) -> f32 {
+    let c1v = f32x8::splat(c1);
+    let two = f32x8::splat(2.0);
+    let mut acc = f32x8::ZERO;
+
+    let chunks1 = row1.chunks_exact(8);
+    let chunks2 = row2.chunks_exact(8);
+    let (rem1, rem2) = (chunks1.remainder(), chunks2.remainder());
+    for (a, b) in chunks1.zip(chunks2) {
+        let p1 = f32x8::from(std::array::from_fn::<f32, 8, _>(|i| a[i] as f32));
+        let p2 = f32x8::from(std::array::from_fn::<f32, 8, _>(|i| b[i] as f32));
+        acc += (two * p1 * p2 + c1v) / (p1 * p1 + p2 * p2 + c1v);
+    }
+
+    acc.reduce_add() + row_sum_scalar(rem1, rem2, c1)
+}
+
+fn mean_over_rows(
+    grey1: &GrayImage,
+    grey2: &GrayImage,
+    row_sum: fn(&[u8], &[u8], f32) -> f32,
+) -> Result<f32, Box<dyn std::error::Error>> {
+    if grey1.dimensions() != grey2.dimensions() {
+        return Err("images are different dimensions".into());
+    }
+
+    let (width, height) = grey1.dimensions();
+    if width == 0 || height == 0 {
+        return Err("images are empty".into());
+    }
+    let c1 = (K1 * L).powi(2);
+
+    // rows are summed in parallel but added up in order, as a parallel sum
+    // would add them in whatever order the threads finish and give a score
+    // a few ulps off from one run to the next
+    let row_sums: Vec<f32> = grey1
+        .as_raw()
+        .par_chunks_exact(width as usize)
+        .zip(grey2.as_raw().par_chunks_exact(width as usize))
+        .map(|(row1, row2)| row_sum(row1, row2, c1))
+        .collect();
+    let ssim_sum: f32 = row_sums.iter().sum();
+
+    Ok(ssim_sum / ((width * height) as f32))
+}
+
+fn row_sum_abs_diff(row1: &[u8], row2: &[u8], _c1: f32) -> f32 {
+    row1.iter()
+        .zip(row2)
+        .map(|(&p1, &p2)| 1.0 - p1.abs_diff(p2) as f32 / L)
+        .sum()
+}
+
+/// How two frames are compared. Every metric scores identical frames 1.0
+/// and falls towards 0.0 as they differ, so thresholds carry over.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Metric {
+    /// Luminance SSIM, see [`ssim_luma`].
+    #[default]
+    Ssim,
+    /// One minus the mean absolute pixel difference. Cheaper than SSIM and
+    /// more sensitive to small uniform brightness shifts.
+    MeanAbsDiff,
+    /// The probability a learned classifier gives that the frames are
+    /// duplicates; see [`learned`](crate::learned).
+    #[cfg(feature = "onnx")]
+    Learned,
+    /// A comparator registered under this name with
+    /// [`comparator::register`](crate::comparator::register).
+    Custom(&'static str),
+}
+
+impl Metric {
+    /// The metric as options and the command line name it.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Metric::Ssim => "ssim",
+            Metric::MeanAbsDiff => "mean-abs-diff",
+            #[cfg(feature = "onnx")]
+            Metric::Learned => "learned",
+            Metric::Custom(name) => name,
+        }
+    }
+
+    /// The built-in metric called `name`.
+    pub(crate) fn builtin(name: &str) -> Option<Metric> {
+        match name {
+            "ssim" => Some(Metric::Ssim),
+            "mean-abs-diff" => Some(Metric::MeanAbsDiff),
+            #[cfg(feature = "onnx")]
+            "learned" => Some(Metric::Learned),
+            _ => None,
+        }
+    }
+
+    /// The metric called `name`, built in or registered.
+    pub fn from_name(name: &str) -> Option<Metric> {
+        Metric::builtin(name)
+            .or_else(|| crate::comparator::get(name).map(|(name, _)| Metric::Custom(name)))
+    }
+}
+
+impl Serialize for Metric {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
+impl<'de> Deserialize<'de> for Metric {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Metric::from_name(&name)
+            .ok_or_else(|| serde::de::Error::custom(format!("unknown metric \"{}\"", name)))
+    }
+}
+
+/// Similarity of two equally sized luma images under `metric`.
+pub fn score(
+    metric: Metric,
+    grey1: &GrayImage,
+    grey2: &GrayImage,
+) -> Result<f32, Box<dyn std::error::Error>> {
+    match metric {
+        Metric::Ssim => ssim_luma(grey1, grey2),
+        Metric::MeanAbsDiff => mean_over_rows(grey1, grey2, row_sum_abs_diff),
+        #[cfg(feature = "onnx")]
+        Metric::Learned => crate::learned::score(grey1, grey2),
+        Metric::Custom(name) => match crate::comparator::get(name) {
+            Some((_, comparator)) => Ok(comparator.score(grey1, grey2)),
+            None => Err(format!("No comparator registered as \"{}\"", name).into()),
+        },
+    }
+}
+
+/// Which channels of a frame are compared, and how much each counts
+/// towards its score. Luma alone misses changes of colour at the same
+/// brightness, such as a status light turning from red to green.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum ColorSpace {
+    #[default]
+    Luma,
+    /// The luma and the blue and red difference chroma, weighted in that
+    /// order.
+    Yuv { weights: [f32; 3] },
+    /// Red, green and blue, weighted in that order.
+    Rgb { weights: [f32; 3] },
+}
+
+impl ColorSpace {
+    /// The channel weights, the luma's alone for [`ColorSpace::Luma`].
+    fn weights(&self) -> &[f32] {
+        match self {
+            ColorSpace::Luma => &[1.0],
+            ColorSpace::Yuv { weights } | ColorSpace::Rgb { weights } => weights,
+        }
+    }
+
+    /// Checks that no weight is negative and some channel is compared.
+    pub fn validate(&self) -> Result<(), String> {
+        let weights = self.weights();
+        if weights
+            .iter()
+            .any(|weight| !weight.is_finite() || *weight < 0.0)
+        {
+            return Err("Channel weights must be positive".to_string());
+        }
+        if weights.iter().all(|&weight| weight == 0.0) {
+            return Err("At least one channel needs a weight above 0".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Rec. 709 chroma of `rgb` with luma `y`, offset to centre on 128 as in
+/// full range YCbCr.
+fn chroma(rgb: [u8; 3], y: u8) -> [u8; 2] {
+    let difference = |c: u8, scale: f32| (128.0 + (c as f32 - y as f32) / scale).round() as u8;
+    [difference(rgb[2], 1.8556), difference(rgb[0], 1.5748)]
+}
+
+/// `sample` over black where `alpha` is transparent, or over the neutral
+/// 128 for chroma.
+fn premultiply(sample: u8, alpha: u8, neutral: u8) -> u8 {
+    let offset = sample as i32 - neutral as i32;
+    (neutral as i32 + (offset * alpha as i32 + offset.signum() * 127) / 255) as u8
+}
+
+/// A frame as it is compared: the channels of its [`ColorSpace`] with
+/// their weights and, for frames with transparency, its alpha. The channels
+/// are premultiplied, so changes hidden under transparent pixels do not
+/// count.
+pub struct Planes {
+    pub channels: Vec<(GrayImage, f32)>,
+    pub alpha: Option<GrayImage>,
+}
+
+impl Planes {
+    /// Splits `image` into its luma and alpha.
+    pub fn new(image: &DynamicImage) -> Planes {
+        Planes::in_color_space(image, ColorSpace::Luma)
+    }
+
+    /// Splits `image` into the channels of `color_space` and its alpha.
+    /// Channels weighted 0 are left out.
+    pub fn in_color_space(image: &DynamicImage, color_space: ColorSpace) -> Planes {
+        let has_alpha = image.color().has_alpha();
+        if color_space == ColorSpace::Luma && !has_alpha {
+            return Planes {
+                channels: vec![(image.to_luma8(), 1.0)],
+                alpha: None,
+            };
+        }
+        let (width, height) = (image.width(), image.height());
+        let weights = color_space.weights();
+        let pixels = (width * height) as usize;
+        let mut samples: Vec<Vec<u8>> = (0..4).map(|_| Vec::with_capacity(pixels)).collect();
+        let mut push = |channels: &[u8], alpha: u8, neutral: &[u8]| {
+            for (i, (&sample, &neutral)) in channels.iter().zip(neutral).enumerate() {
+                samples[i].push(premultiply(sample, alpha, neutral));
+            }
+            samples[3].push(alpha);
+        };
+        match color_space {
+            ColorSpace::Luma => {
+                for pixel in image.to_luma_alpha8().pixels() {
+                    let [luma, alpha] = pixel.0;
+                    push(&[luma], alpha, &[0]);
+                }
+            }
+            ColorSpace::Yuv { .. } => {
+                let luma = image.to_luma8();
+                for (pixel, y) in image.to_rgba8().pixels().zip(luma.pixels()) {
+                    let [r, g, b, alpha] = pixel.0;
+                    let [cb, cr] = chroma([r, g, b], y.0[0]);
+                    push(&[y.0[0], cb, cr], alpha, &[0, 128, 128]);
+                }
+            }
+            ColorSpace::Rgb { .. } => {
+                for pixel in image.to_rgba8().pixels() {
+                    let [r, g, b, alpha] = pixel.0;
+                    push(&[r, g, b], alpha, &[0, 0, 0]);
+                }
+            }
+        }
+        let plane = |samples: Vec<u8>| {
+            GrayImage::from_raw(width, height, samples).expect("plane has the frame dimensions")
+        };
+        let alpha = samples.pop().filter(|_| has_alpha).map(plane);
+        Planes {
+            channels: samples
+                .into_iter()
+                .zip(weights)
+                .filter(|(_, &weight)| weight > 0.0)
+                .map(|(samples, &weight)| (plane(samples), weight))
+                .collect(),
+            alpha,
+        }
+    }
+}
+
+/// Similarity of two frames under `metric`: the weighted mean of that of
+/// their channels, or that of their alpha where it is lower, so a change in
+/// transparency alone still counts.
+pub fn score_planes(
+    metric: Metric,
+    planes1: &Planes,
+    planes2: &Planes,
+) -> Result<f32, Box<dyn std::error::Error>> {
+    let (mut sum, mut total_weight) = (0.0, 0.0);
+    for ((channel1, weight), (channel2, _)) in planes1.channels.iter().zip(&planes2.channels) {
+        sum += weight * score(metric, channel1, channel2)?;
+        total_weight += weight;
+    }
+    if total_weight == 0.0 {
+        return Err("frames have no channels to compare".into());
+    }
+    let channels = sum / total_weight;
+    match (&planes1.alpha, &planes2.alpha) {
+        (Some(alpha1), Some(alpha2)) => Ok(channels.min(score(metric, alpha1, alpha2)?)),
+        _ => Ok(channels),
+    }
+}
+
+/// Mean per-pixel SSIM of two equally sized luma images.
+///
+/// Large frames are scored on the GPU when the `gpu` feature is enabled and a
+/// hardware adapter is present; everything else uses [`ssim_luma_simd`].
+pub fn ssim_luma(grey1: &GrayImage, grey2: &GrayImage) -> Result<f32, Box<dyn std::error::Error>> {
+    #[cfg(feature = "gpu")]
+    if grey1.width() * grey1.height() >= GPU_MIN_PIXELS {
+        if let Some(score) = crate::gpu::ssim_luma(grey1, grey2) {
+            return score;
+        }
+    }
+    ssim_luma_simd(grey1, grey2)
+}
+
+/// [`ssim_luma`] on the CPU, using SIMD lanes for the inner loop.
+pub fn ssim_luma_simd(
+    grey1: &GrayImage,
+    grey2: &GrayImage,
+) -> Result<f32, Box<dyn std::error::Error>> {
+    mean_over_rows(grey1, grey2, row_sum_simd)
+}
+
+/// Scalar reference implementation of [`ssim_luma`], kept for benchmarking.
+pub fn ssim_luma_scalar(
+    grey1: &GrayImage,
+    grey2: &GrayImage,
+) -> Result<f32, Box<dyn std::error::Error>> {
+    mean_over_rows(grey1, grey2, row_sum_scalar)
+}
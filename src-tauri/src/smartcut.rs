@@ -0,0 +1,342 @@
+//! Smart cut: the kept ranges are cut out of the source without decoding
+//! what does not need it. GOPs that lie entirely inside a kept range are
+//! stream-copied; only the partial GOPs at each cut point are re-encoded,
+//! with the source's codec, and the segments are joined without another
+//! encode.
+//!
+//! Like the other output modes only the video stream is written. Frame
+//! positions are derived from timestamps, so sources must have a constant
+//! frame rate.
+
+use std::ffi::OsString;
+use std::fmt::Write as _;
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+use crate::color::{self, ColorInfo};
+use crate::concurrency;
+use crate::control::{JobControl, Stage};
+use crate::cutlist;
+use crate::error::ProcessError;
+use crate::ffmpeg;
+use crate::paths;
+use crate::streams;
+use crate::supervisor;
+
+/// What the keyframe scan found out about the source's video stream.
+struct Probe {
+    codec: String,
+    pixel_format: Option<String>,
+    /// Signalling the re-encoded segments are tagged with, so they match
+    /// the copied ones.
+    color: Option<ColorInfo>,
+    /// Frame indices of the keyframes, ascending.
+    keyframes: Vec<usize>,
+}
+
+/// A piece of the output, in source frames.
+#[derive(Debug, PartialEq)]
+enum Segment {
+    Copy(Range<usize>),
+    Encode(Range<usize>),
+}
+
+/// How segments are stored and put back together.
+#[derive(Clone, Copy)]
+enum Join {
+    /// Raw bitstreams in this format, concatenated byte for byte. They carry
+    /// their parameter sets in-band, so re-encoded segments can differ from
+    /// the copied ones in profile or level. `bsf` converts copied packets.
+    Bytes {
+        format: &'static str,
+        bsf: Option<&'static str>,
+    },
+    /// Matroska files joined by the concat demuxer, for codecs whose
+    /// keyframes carry everything needed to decode them.
+    Demuxer,
+}
+
+/// Encoder arguments reproducing `codec` closely enough to sit between
+/// stream-copied GOPs, and how its segments are joined.
+fn encoder_for(codec: &str) -> Option<(&'static [&'static str], Join)> {
+    Some(match codec {
+        "h264" => (
+            &["-c:v", "libx264", "-crf", "16", "-preset", "fast"],
+            Join::Bytes {
+                format: "h264",
+                bsf: Some("h264_mp4toannexb"),
+            },
+        ),
+        "hevc" => (
+            &["-c:v", "libx265", "-crf", "18", "-preset", "fast"],
+            Join::Bytes {
+                format: "hevc",
+                bsf: Some("hevc_mp4toannexb"),
+            },
+        ),
+        "mpeg2video" => (
+            &["-c:v", "mpeg2video", "-q:v", "2"],
+            Join::Bytes {
+                format: "mpeg2video",
+                bsf: None,
+            },
+        ),
+        "vp9" => (
+            &["-c:v", "libvpx-vp9", "-crf", "20", "-b:v", "0"],
+            Join::Demuxer,
+        ),
+        "av1" => (
+            &["-c:v", "libaom-av1", "-crf", "20", "-cpu-used", "6"],
+            Join::Demuxer,
+        ),
+        _ => return None,
+    })
+}
+
+fn probe(
+    input: &Path,
+    stream: usize,
+    fps: f64,
+    control: &JobControl,
+) -> Result<Probe, ProcessError> {
+    // only keyframes are decoded, which makes this far cheaper than a full pass
+    let output = supervisor::run_cancellable(
+        || {
+            let mut command = ffmpeg::command()?;
+            command
+                .args(["-skip_frame", "nokey", "-i"])
+                .arg(paths::ffmpeg_arg(input))
+                .args(["-map", &streams::map(stream)])
+                .args(["-vf", "showinfo", "-f", "null", "-"]);
+            Ok(command)
+        },
+        control,
+    )
+    .map_err(|e| ProcessError::ffmpeg("Failed to find keyframes", e))?;
+
+    let stream = streams::reported_line(&output.stderr, stream)
+        .and_then(|line| line.split("Video: ").nth(1))
+        .unwrap_or_default();
+    let codec = stream
+        .split([' ', ','])
+        .next()
+        .unwrap_or_default()
+        .to_string();
+    let pixel_format = stream
+        .split(", ")
+        .nth(1)
+        .and_then(|format| format.split('(').next())
+        .map(str::to_string);
+
+    let times: Vec<f64> = output
+        .stderr
+        .lines()
+        .filter(|line| line.contains("iskey:1"))
+        .filter_map(|line| {
+            line.split("pts_time:")
+                .nth(1)?
+                .split_whitespace()
+                .next()?
+                .parse()
+                .ok()
+        })
+        .collect();
+    let start = times.first().copied().unwrap_or_default();
+    let keyframes = times
+        .iter()
+        .map(|time| ((time - start) * fps).round() as usize)
+        .collect();
+    Ok(Probe {
+        codec,
+        pixel_format,
+        color: color::reported(&output.stderr),
+        keyframes,
+    })
+}
+
+/// Splits each kept range at the keyframes inside it: whole GOPs are copied
+/// and the frames before the first and after the last keyframe re-encoded.
+fn plan(kept: &[Range<usize>], keyframes: &[usize], total: usize) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    for range in kept {
+        let inside: Vec<usize> = keyframes
+            .iter()
+            .copied()
+            .filter(|key| range.contains(key))
+            .collect();
+        let (Some(&first), Some(&last)) = (inside.first(), inside.last()) else {
+            segments.push(Segment::Encode(range.clone()));
+            continue;
+        };
+        // a GOP running to the end of the source or into the next keyframe
+        // is complete and can be copied up to the range's end
+        let copy_end = if range.end == total || keyframes.contains(&range.end) {
+            range.end
+        } else {
+            last
+        };
+        if range.start < first {
+            segments.push(Segment::Encode(range.start..first));
+        }
+        if first < copy_end {
+            segments.push(Segment::Copy(first..copy_end));
+        }
+        if copy_end < range.end {
+            segments.push(Segment::Encode(copy_end..range.end));
+        }
+    }
+    segments
+}
+
+/// Writes the frames of video stream `stream` of `input` not marked in
+/// `removed` to `output`. `work_dir` holds the segments until they are
+/// joined.
+pub fn cut(
+    input: &Path,
+    stream: usize,
+    removed: &[bool],
+    fps: f64,
+    output: &Path,
+    work_dir: &Path,
+    control: &JobControl,
+) -> Result<(), ProcessError> {
+    control.report(Stage::Encoding, 0, 0);
+    let _slot = concurrency::acquire_encode_slot();
+    let probe = probe(input, stream, fps, control)?;
+    let (encoder, join) = encoder_for(&probe.codec).ok_or_else(|| {
+        ProcessError::new(format!(
+            "Smart cut cannot re-encode {} video",
+            if probe.codec.is_empty() {
+                "this"
+            } else {
+                &probe.codec
+            }
+        ))
+    })?;
+
+    let segments = plan(
+        &cutlist::kept_ranges(removed),
+        &probe.keyframes,
+        removed.len(),
+    );
+    let copied: usize = segments
+        .iter()
+        .map(|segment| match segment {
+            Segment::Copy(range) => range.len(),
+            Segment::Encode(_) => 0,
+        })
+        .sum();
+    info!(
+        "Smart cut: {} segments, {} of {} kept frames copied",
+        segments.len(),
+        copied,
+        removed.iter().filter(|&&dead| !dead).count()
+    );
+
+    let mut files: Vec<PathBuf> = Vec::new();
+    for (index, segment) in segments.iter().enumerate() {
+        control.report(Stage::Encoding, index, segments.len());
+        let extension = match join {
+            Join::Bytes { format, .. } => format,
+            Join::Demuxer => "mkv",
+        };
+        let file = work_dir.join(format!("segment_{:04}.{}", index, extension));
+        let result = supervisor::run_cancellable(
+            || {
+                let mut command = ffmpeg::command()?;
+                match segment {
+                    Segment::Copy(range) => {
+                        // half a frame in, so rounding cannot land the seek
+                        // on the keyframe before
+                        let seek = (range.start as f64 + 0.5) / fps;
+                        // packets are in decode order, so the segment is
+                        // bounded by time rather than a packet count
+                        let duration = (range.len() as f64 - 0.5) / fps;
+                        command
+                            .args(["-ss", &format!("{:.6}", seek), "-i"])
+                            .arg(paths::ffmpeg_arg(input))
+                            .args(["-map", &streams::map(stream), "-c", "copy"])
+                            .args(["-t", &format!("{:.6}", duration)]);
+                        if let Join::Bytes { bsf: Some(bsf), .. } = join {
+                            command.args(["-bsf:v", bsf]);
+                        }
+                    }
+                    Segment::Encode(range) => {
+                        // half a frame early, as frames before the seek
+                        // point are dropped
+                        let seek = (range.start as f64 - 0.5).max(0.0) / fps;
+                        command
+                            .args(["-ss", &format!("{:.6}", seek), "-i"])
+                            .arg(paths::ffmpeg_arg(input))
+                            .args(["-map", &streams::map(stream)])
+                            .args(["-frames:v", &range.len().to_string()])
+                            .args(encoder);
+                        if let Some(pixel_format) = &probe.pixel_format {
+                            command.args(["-pix_fmt", pixel_format]);
+                        }
+                        if let Some(color) = &probe.color {
+                            command.args(color.encoder_args(encoder[1]));
+                        }
+                    }
+                }
+                if let Join::Bytes { format, .. } = join {
+                    command.args(["-f", format]);
+                }
+                command.args(["-an", "-y"]).arg(paths::ffmpeg_arg(&file));
+                Ok(command)
+            },
+            control,
+        );
+        result.map_err(|e| ProcessError::ffmpeg("Failed to cut segment", e))?;
+        files.push(file);
+    }
+
+    let list_path = work_dir.join("segments.ffconcat");
+    if let Join::Demuxer = join {
+        let mut list = String::from("ffconcat version 1.0\n");
+        for file in &files {
+            // the segments sit next to the list, and plain names cannot
+            // trip over quotes or encodings in the work directory's path
+            let name = file.file_name().unwrap_or_default().to_string_lossy();
+            let _ = writeln!(list, "file '{}'", name);
+        }
+        fs::write(&list_path, list)
+            .map_err(|e| ProcessError::new(format!("Failed to write segment list: {}", e)))?;
+    }
+
+    let result = supervisor::run_cancellable(
+        || {
+            let mut command = ffmpeg::command()?;
+            match join {
+                Join::Bytes { format, .. } => {
+                    // raw streams have no timestamps of their own
+                    let mut concat = OsString::from("concat:");
+                    for (index, file) in files.iter().enumerate() {
+                        if index > 0 {
+                            concat.push("|");
+                        }
+                        concat.push(paths::ffmpeg_arg(file));
+                    }
+                    command
+                        .args(["-f", format, "-framerate", &fps.to_string(), "-i"])
+                        .arg(concat);
+                }
+                Join::Demuxer => {
+                    command
+                        .args(["-f", "concat", "-safe", "0", "-i"])
+                        .arg(paths::ffmpeg_arg(&list_path));
+                }
+            }
+            command
+                .args(["-map", "0:v:0", "-c", "copy", "-an", "-y"])
+                .arg(paths::ffmpeg_arg(output));
+            Ok(command)
+        },
+        control,
+    );
+    result
+        .map(|_| ())
+        .map_err(|e| ProcessError::ffmpeg("Failed to join segments", e))
+}
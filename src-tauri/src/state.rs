@@ -0,0 +1,143 @@
+//! What the desktop app hands its commands through `tauri::Builder::manage`:
+//! the settings, the ffmpeg in use, presets, job history, the analysis
+//! cache, plugins and logs, the job queue, the watch folder and running
+//! totals of the work done. None of it needs Tauri, so it can be built and
+//! driven without a window, and every window shares the one instance.
+//!
+//! Jobs, the watch folder and `dfr-cli` reach the stores from deep inside
+//! the engine, so `AppState` holds the process's own ([`settings::store`],
+//! [`ffmpeg::locator`], [`presets::store`] and so on) rather than copies
+//! that would drift apart from them.
+
+use serde::Serialize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::analysis_cache::{self, AnalysisCache};
+use crate::ffmpeg::{self, FfmpegLocator};
+use crate::history::{self, History};
+use crate::logging::{self, LogStore};
+#[cfg(feature = "plugins")]
+use crate::plugins::{self, PluginDir};
+use crate::presets::{self, PresetStore};
+use crate::queue::JobQueue;
+use crate::settings::{self, SettingsStore};
+use crate::video_fixer::JobSummary;
+use crate::watch::WatchFolder;
+
+pub struct AppState {
+    pub settings: Arc<SettingsStore>,
+    pub ffmpeg: Arc<FfmpegLocator>,
+    pub presets: Arc<PresetStore>,
+    pub history: Arc<History>,
+    pub analysis_cache: Arc<AnalysisCache>,
+    #[cfg(feature = "plugins")]
+    pub plugins: Arc<PluginDir>,
+    pub logging: Arc<LogStore>,
+    pub queue: JobQueue,
+    pub watch: WatchFolder,
+    pub metrics: Metrics,
+    /// Jobs run outside the queue, by `process_video`.
+    pub in_flight: InFlight,
+}
+
+impl AppState {
+    pub fn new(queue: JobQueue) -> AppState {
+        AppState {
+            settings: settings::store(),
+            ffmpeg: ffmpeg::locator(),
+            presets: presets::store(),
+            history: history::store(),
+            analysis_cache: analysis_cache::cache(),
+            #[cfg(feature = "plugins")]
+            plugins: plugins::store(),
+            logging: logging::store(),
+            queue,
+            watch: WatchFolder::default(),
+            metrics: Metrics::default(),
+            in_flight: InFlight::default(),
+        }
+    }
+
+    /// The jobs quitting now would cut short: those queued or running in
+    /// the queue, those the watch folder has picked up and those run
+    /// directly.
+    pub fn active_jobs(&self) -> usize {
+        let queued = self
+            .queue
+            .jobs()
+            .iter()
+            .filter(|job| job.state.is_active())
+            .count();
+        let watch = self.watch.status();
+        let watched = watch.queued.len() + usize::from(watch.current.is_some());
+        queued + watched + self.in_flight.count()
+    }
+}
+
+/// How many jobs are running outside the queue and the watch folder.
+#[derive(Default)]
+pub struct InFlight(AtomicUsize);
+
+impl InFlight {
+    /// Counts a job until the returned guard is dropped.
+    pub fn enter(&self) -> InFlightGuard<'_> {
+        self.0.fetch_add(1, Ordering::SeqCst);
+        InFlightGuard(&self.0)
+    }
+
+    pub fn count(&self) -> usize {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+pub struct InFlightGuard<'a>(&'a AtomicUsize);
+
+impl Drop for InFlightGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Totals over the jobs run since the app started, from the queue, the
+/// watch folder and direct calls alike.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SessionMetrics {
+    pub jobs_finished: u64,
+    /// Jobs whose output existed and were left alone.
+    pub jobs_skipped: u64,
+    pub jobs_failed: u64,
+    pub frames_total: u64,
+    pub frames_removed: u64,
+    pub input_bytes: u64,
+    pub output_bytes: u64,
+    /// Time spent running jobs.
+    pub busy_secs: f64,
+}
+
+#[derive(Default)]
+pub struct Metrics(Mutex<SessionMetrics>);
+
+impl Metrics {
+    pub fn job_finished(&self, summary: &JobSummary) {
+        let mut metrics = self.0.lock().unwrap();
+        if summary.skipped {
+            metrics.jobs_skipped += 1;
+            return;
+        }
+        metrics.jobs_finished += 1;
+        metrics.frames_total += summary.frames_total as u64;
+        metrics.frames_removed += summary.frames_removed as u64;
+        metrics.input_bytes += summary.input_bytes;
+        metrics.output_bytes += summary.output_bytes;
+        metrics.busy_secs += summary.elapsed_secs;
+    }
+
+    pub fn job_failed(&self) {
+        self.0.lock().unwrap().jobs_failed += 1;
+    }
+
+    pub fn snapshot(&self) -> SessionMetrics {
+        self.0.lock().unwrap().clone()
+    }
+}
@@ -0,0 +1,134 @@
+//! The video streams of a source. Files can hold several, such as camera
+//! angles or an embedded cover picture; one is picked and every ffmpeg run
+//! of a job maps that same stream.
+
+use serde::Serialize;
+use std::path::Path;
+
+use crate::color::{self, ColorInfo};
+use crate::error::ProcessError;
+use crate::ffmpeg;
+use crate::paths;
+use crate::supervisor::{self, RunError};
+
+/// One video stream as ffmpeg reports it.
+#[derive(Debug, Clone, Serialize)]
+pub struct VideoStream {
+    /// Position among the source's video streams, as in ffmpeg's `0:v:N`.
+    pub index: usize,
+    pub codec: String,
+    /// The codec's profile, such as `High` or `HQ`, where ffmpeg reports
+    /// one.
+    pub profile: Option<String>,
+    /// The colour signalling the stream is tagged with; see
+    /// [`color::reported_in_stream`].
+    pub color: Option<ColorInfo>,
+    pub width: u32,
+    pub height: u32,
+    pub fps: Option<f64>,
+    pub title: Option<String>,
+    /// A still picture, such as cover art, rather than video.
+    pub attached_pic: bool,
+    /// Flagged as the stream players pick.
+    pub default: bool,
+}
+
+/// The ffmpeg stream specifier of video stream `index` of the first input.
+pub fn map(index: usize) -> String {
+    format!("0:v:{}", index)
+}
+
+/// The line ffmpeg reported for video stream `index` of the input in
+/// `stderr`, without the streams of any output.
+pub fn reported_line(stderr: &str, index: usize) -> Option<&str> {
+    stderr
+        .lines()
+        .take_while(|line| !line.starts_with("Output #"))
+        .filter(|line| line.contains("Stream #") && line.contains("Video:"))
+        .nth(index)
+}
+
+/// Lists the video streams of `input`.
+pub fn list(input: &Path) -> Result<Vec<VideoStream>, ProcessError> {
+    let result = supervisor::run(|| {
+        let mut command = ffmpeg::command()?;
+        command.arg("-i").arg(paths::ffmpeg_arg(input));
+        Ok(command)
+    });
+    // without an output ffmpeg lists the streams and gives up
+    let stderr = match result {
+        Ok(output) => output.stderr,
+        Err(RunError::Failed { stderr, .. }) if stderr.contains("Stream #") => stderr,
+        Err(e) => return Err(ProcessError::ffmpeg("Failed to read the streams", e)),
+    };
+    Ok(parse(&stderr))
+}
+
+/// The stream processed when none is chosen: the first that is not an
+/// embedded picture.
+pub fn default_index(streams: &[VideoStream]) -> usize {
+    streams
+        .iter()
+        .find(|stream| !stream.attached_pic)
+        .map_or(0, |stream| stream.index)
+}
+
+/// The frame size in a stream line ffmpeg reported.
+pub fn frame_size(line: &str) -> Option<(u32, u32)> {
+    line.split([',', ' ']).find_map(|part| {
+        // not to be confused with codec tags like 0x31637661
+        let (width, height) = part.split_once('x')?;
+        let width: u32 = width.parse().ok().filter(|&width| width > 0)?;
+        Some((width, height.parse().ok()?))
+    })
+}
+
+fn parse(stderr: &str) -> Vec<VideoStream> {
+    let mut streams: Vec<VideoStream> = Vec::new();
+    // metadata lines belong to the stream line above them
+    let mut in_video = false;
+    for line in stderr
+        .lines()
+        .take_while(|line| !line.starts_with("Output #"))
+    {
+        if line.contains("Stream #") {
+            in_video = false;
+            let Some(description) = line.split("Video: ").nth(1) else {
+                continue;
+            };
+            in_video = true;
+            let (width, height) = frame_size(description).unwrap_or_default();
+            // e.g. `prores (HQ) (apch / 0x68637061)`, of which the second
+            // parentheses hold the codec tag
+            let codec = description.split(", ").next().unwrap_or_default();
+            let profile = codec
+                .split_once(" (")
+                .and_then(|(_, rest)| rest.split_once(')'))
+                .map(|(profile, _)| profile)
+                .filter(|profile| !profile.contains(" / "));
+            streams.push(VideoStream {
+                index: streams.len(),
+                codec: codec.split(' ').next().unwrap_or_default().to_string(),
+                profile: profile.map(String::from),
+                color: color::reported_in_stream(line),
+                width,
+                height,
+                fps: description
+                    .split(", ")
+                    .find_map(|part| part.strip_suffix(" fps")?.parse().ok()),
+                title: None,
+                attached_pic: line.contains("(attached pic)"),
+                default: line.contains("(default)"),
+            });
+        } else if in_video {
+            if let Some((key, value)) = line.split_once(':') {
+                if key.trim().eq_ignore_ascii_case("title") {
+                    if let Some(stream) = streams.last_mut() {
+                        stream.title = Some(value.trim().to_string());
+                    }
+                }
+            }
+        }
+    }
+    streams
+}
@@ -0,0 +1,536 @@
+//! Runs ffmpeg children under supervision: output is captured, a child that
+//! stops making progress is killed, and transient failures are retried.
+
+use crate::concurrency;
+use crate::control::{CancellationToken, JobControl, PauseToken, Stage};
+use crate::error::{self, ProcessError};
+use crate::sandbox;
+use crate::settings;
+use std::fmt;
+use std::io::{self, Read};
+use std::process::{Child, ChildStdout, Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Set once the app is exiting.
+static SHUTTING_DOWN: AtomicBool = AtomicBool::new(false);
+
+/// Default for how long ffmpeg may go without writing any output. ffmpeg
+/// prints a stats line to stderr about twice a second while it works, so a
+/// silent child is stuck rather than slow.
+pub const DEFAULT_STALL_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Failures whose stderr contains one of these are worth another attempt.
+const TRANSIENT_MARKERS: &[&str] = &[
+    "Resource temporarily unavailable",
+    "Connection reset by peer",
+    "Connection timed out",
+    "Input/output error",
+];
+
+/// Called with the number of frames ffmpeg has written so far and, once
+/// its input has been described, how many to expect in all.
+pub type FrameCallback = Arc<dyn Fn(usize, Option<usize>) + Send + Sync>;
+
+#[derive(Clone)]
+pub struct Policy {
+    /// Kill the child when it produces no output for this long.
+    pub stall_timeout: Option<Duration>,
+    /// Additional attempts after a transient failure.
+    pub retries: u32,
+    /// Kill the child as soon as this is cancelled.
+    pub cancel: Option<CancellationToken>,
+    /// Suspend the child while this is paused.
+    pub pause: Option<PauseToken>,
+    /// Follow ffmpeg's stats line and report its frame counter here.
+    pub on_frames: Option<FrameCallback>,
+}
+
+impl Policy {
+    pub fn from_settings() -> Policy {
+        let settings = settings::current();
+        Policy {
+            stall_timeout: match settings.ffmpeg_timeout_secs {
+                Some(0) => None,
+                Some(secs) => Some(Duration::from_secs(secs)),
+                None => Some(DEFAULT_STALL_TIMEOUT),
+            },
+            retries: settings.ffmpeg_retries,
+            cancel: None,
+            pause: None,
+            on_frames: None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum RunError {
+    Spawn(io::Error),
+    /// There is no ffmpeg to run.
+    Unavailable(ProcessError),
+    Stalled {
+        after: Duration,
+        stderr: String,
+    },
+    Failed {
+        status: ExitStatus,
+        stderr: String,
+    },
+    Cancelled,
+}
+
+impl fmt::Display for RunError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RunError::Spawn(e) => write!(f, "failed to start ffmpeg: {}", e),
+            RunError::Unavailable(e) => write!(f, "{}", e.message),
+            RunError::Stalled { after, .. } => {
+                write!(
+                    f,
+                    "ffmpeg made no progress for {}s and was killed",
+                    after.as_secs()
+                )
+            }
+            RunError::Failed { status, .. } => write!(f, "ffmpeg exited with {}", status),
+            RunError::Cancelled => write!(f, "ffmpeg was cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for RunError {}
+
+impl RunError {
+    fn is_transient(&self) -> bool {
+        match self {
+            RunError::Spawn(e) => matches!(
+                e.kind(),
+                io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted
+            ),
+            RunError::Failed { stderr, .. } => TRANSIENT_MARKERS
+                .iter()
+                .any(|marker| stderr.contains(marker)),
+            // a stuck child will most likely get stuck again
+            RunError::Unavailable(_) | RunError::Stalled { .. } | RunError::Cancelled => false,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Output {
+    pub stdout: Vec<u8>,
+    pub stderr: String,
+}
+
+/// Kills every supervised child within a poll interval, cancellable or
+/// not, and refuses to start new ones, for when the app exits. They end as
+/// [`RunError::Cancelled`].
+pub fn shut_down() {
+    SHUTTING_DOWN.store(true, Ordering::SeqCst);
+}
+
+fn is_shutting_down() -> bool {
+    SHUTTING_DOWN.load(Ordering::SeqCst)
+}
+
+/// Runs the command built by `build` with the policy from the settings.
+pub fn run(build: impl FnMut() -> Result<Command, ProcessError>) -> Result<Output, RunError> {
+    run_with(build, &Policy::from_settings())
+}
+
+/// Like [`run`], but kills the child once the job is cancelled and
+/// suspends it while the job is paused.
+pub fn run_cancellable(
+    build: impl FnMut() -> Result<Command, ProcessError>,
+    control: &JobControl,
+) -> Result<Output, RunError> {
+    let policy = Policy {
+        cancel: Some(control.cancel.clone()),
+        pause: Some(control.pause.clone()),
+        ..Policy::from_settings()
+    };
+    run_with(build, &policy)
+}
+
+/// Like [`run_cancellable`], and reports ffmpeg's progress as `stage`. The
+/// frames to expect are `total`, or else read from the input's duration and
+/// frame rate.
+pub fn run_reporting(
+    build: impl FnMut() -> Result<Command, ProcessError>,
+    control: &JobControl,
+    stage: Stage,
+    total: Option<usize>,
+) -> Result<Output, RunError> {
+    let reporter = control.clone();
+    let policy = Policy {
+        cancel: Some(control.cancel.clone()),
+        pause: Some(control.pause.clone()),
+        on_frames: Some(Arc::new(move |frames, expected| {
+            reporter.report(stage, frames, total.or(expected).unwrap_or(0))
+        })),
+        ..Policy::from_settings()
+    };
+    run_with(build, &policy)
+}
+
+/// Runs the command built by `build`, rebuilding it for every retry.
+pub fn run_with(
+    mut build: impl FnMut() -> Result<Command, ProcessError>,
+    policy: &Policy,
+) -> Result<Output, RunError> {
+    let mut attempt = 0;
+    loop {
+        let command = build().map_err(RunError::Unavailable)?;
+        match run_once(command, policy) {
+            Err(e) if e.is_transient() && attempt < policy.retries => {
+                attempt += 1;
+                warn!(
+                    "ffmpeg failed ({}), retrying ({}/{})",
+                    e, attempt, policy.retries
+                );
+                thread::sleep(Duration::from_millis(500) * attempt);
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Follows ffmpeg's stderr for the frame counter of its stats line and,
+/// from the description of its input, the number of frames to expect.
+struct StatsParser {
+    line: Vec<u8>,
+    duration: Option<f64>,
+    fps: Option<f64>,
+    on_frames: FrameCallback,
+}
+
+impl StatsParser {
+    fn new(on_frames: FrameCallback) -> StatsParser {
+        StatsParser {
+            line: Vec::new(),
+            duration: None,
+            fps: None,
+            on_frames,
+        }
+    }
+
+    /// Stats lines end in a carriage return, everything else in a newline.
+    fn feed(&mut self, data: &[u8]) {
+        for &byte in data {
+            if byte == b'\r' || byte == b'\n' {
+                let line = String::from_utf8_lossy(&self.line).into_owned();
+                self.line.clear();
+                self.parse(&line);
+            } else {
+                self.line.push(byte);
+            }
+        }
+    }
+
+    fn parse(&mut self, line: &str) {
+        if let Some(stats) = line.strip_prefix("frame=") {
+            if let Some(frame) = stats.split_whitespace().next().and_then(|f| f.parse().ok()) {
+                let expected = self
+                    .duration
+                    .zip(self.fps)
+                    .map(|(duration, fps)| (duration * fps).round() as usize);
+                (self.on_frames)(frame, expected);
+            }
+        } else if let Some(duration) = line.trim_start().strip_prefix("Duration: ") {
+            // only the first input counts
+            if self.duration.is_none() {
+                self.duration = parse_duration(duration.split(',').next().unwrap_or_default());
+            }
+        } else if self.fps.is_none() && line.contains("Stream #") && line.contains("Video:") {
+            self.fps = line
+                .split(", ")
+                .find_map(|part| part.strip_suffix(" fps")?.parse().ok());
+        }
+    }
+}
+
+/// Seconds in an `HH:MM:SS.ss` duration.
+fn parse_duration(text: &str) -> Option<f64> {
+    let mut parts = text.trim().split(':');
+    let hours: f64 = parts.next()?.parse().ok()?;
+    let minutes: f64 = parts.next()?.parse().ok()?;
+    let seconds: f64 = parts.next()?.parse().ok()?;
+    Some(hours * 3600.0 + minutes * 60.0 + seconds)
+}
+
+/// Drains `source` on a separate thread, recording when it last produced
+/// data and passing what it reads to `parser`.
+fn drain(
+    mut source: impl Read + Send + 'static,
+    last_activity: Arc<Mutex<Instant>>,
+    mut parser: Option<StatsParser>,
+) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut collected = Vec::new();
+        let mut buf = [0u8; 8192];
+        while let Ok(n) = source.read(&mut buf) {
+            if n == 0 {
+                break;
+            }
+            collected.extend_from_slice(&buf[..n]);
+            if let Some(parser) = &mut parser {
+                parser.feed(&buf[..n]);
+            }
+            *last_activity.lock().unwrap() = Instant::now();
+        }
+        collected
+    })
+}
+
+fn kill(child: &mut Child) {
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+/// Stops or continues every thread of `child`.
+#[cfg(unix)]
+fn set_suspended(child: &Child, suspended: bool) {
+    let signal = if suspended {
+        libc::SIGSTOP
+    } else {
+        libc::SIGCONT
+    };
+    // SAFETY: the child has not been reaped, so its pid is still its own
+    unsafe {
+        libc::kill(child.id() as libc::pid_t, signal);
+    }
+}
+
+/// Stops or continues every thread of `child`. Windows has no signal for
+/// this, so each thread is suspended on its own.
+#[cfg(windows)]
+fn set_suspended(child: &Child, suspended: bool) {
+    use windows_sys::Win32::Foundation::{CloseHandle, INVALID_HANDLE_VALUE};
+    use windows_sys::Win32::System::Diagnostics::ToolHelp::{
+        CreateToolhelp32Snapshot, Thread32First, Thread32Next, TH32CS_SNAPTHREAD, THREADENTRY32,
+    };
+    use windows_sys::Win32::System::Threading::{
+        OpenThread, ResumeThread, SuspendThread, THREAD_SUSPEND_RESUME,
+    };
+
+    // SAFETY: every handle opened here is checked and closed again
+    unsafe {
+        let snapshot = CreateToolhelp32Snapshot(TH32CS_SNAPTHREAD, 0);
+        if snapshot == INVALID_HANDLE_VALUE {
+            return;
+        }
+        let mut entry: THREADENTRY32 = std::mem::zeroed();
+        entry.dwSize = std::mem::size_of::<THREADENTRY32>() as u32;
+        let mut more = Thread32First(snapshot, &mut entry) != 0;
+        while more {
+            if entry.th32OwnerProcessID == child.id() {
+                let thread = OpenThread(THREAD_SUSPEND_RESUME, 0, entry.th32ThreadID);
+                if !thread.is_null() {
+                    if suspended {
+                        SuspendThread(thread);
+                    } else {
+                        ResumeThread(thread);
+                    }
+                    CloseHandle(thread);
+                }
+            }
+            more = Thread32Next(snapshot, &mut entry) != 0;
+        }
+        CloseHandle(snapshot);
+    }
+}
+
+/// How a supervised child ended.
+enum Ended {
+    Exited(ExitStatus),
+    Cancelled,
+    Stalled(Duration),
+}
+
+/// Waits for `child` to exit, killing it when the job is cancelled or it
+/// stalls and suspending it while the job is paused.
+fn supervise(
+    child: &mut Child,
+    last_activity: &Mutex<Instant>,
+    policy: &Policy,
+) -> io::Result<Ended> {
+    let mut suspended = false;
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) => return Ok(Ended::Exited(status)),
+            Ok(None) => {}
+            Err(e) => {
+                kill(child);
+                return Err(e);
+            }
+        }
+        if is_shutting_down() || policy.cancel.as_ref().is_some_and(|c| c.is_cancelled()) {
+            kill(child);
+            return Ok(Ended::Cancelled);
+        }
+        let paused = policy.pause.as_ref().is_some_and(|p| p.is_paused());
+        if paused != suspended {
+            debug!("{} ffmpeg", if paused { "Suspending" } else { "Resuming" });
+            set_suspended(child, paused);
+            suspended = paused;
+            // a suspended child is silent, which is no sign of a stall
+            *last_activity.lock().unwrap() = Instant::now();
+        }
+        if let Some(timeout) = policy.stall_timeout.filter(|_| !suspended) {
+            if last_activity.lock().unwrap().elapsed() > timeout {
+                kill(child);
+                return Ok(Ended::Stalled(timeout));
+            }
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+fn collect_stderr(handle: thread::JoinHandle<Vec<u8>>) -> String {
+    String::from_utf8_lossy(&handle.join().unwrap_or_default()).into_owned()
+}
+
+/// The result of a child that exited with `status`.
+fn finish(status: ExitStatus, output: Output) -> Result<Output, RunError> {
+    debug!(
+        "ffmpeg exited with {}, stderr:\n{}",
+        status,
+        error::stderr_tail(&output.stderr, error::STDERR_TAIL_LINES).join("\n")
+    );
+    if status.success() {
+        Ok(output)
+    } else {
+        Err(RunError::Failed {
+            status,
+            stderr: output.stderr,
+        })
+    }
+}
+
+fn run_once(mut command: Command, policy: &Policy) -> Result<Output, RunError> {
+    let _slot = concurrency::acquire_process_slot();
+    if is_shutting_down() {
+        return Err(RunError::Cancelled);
+    }
+    debug!("Running {:?}", command);
+    let mut child = sandbox::spawn(
+        command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped()),
+    )
+    .map_err(RunError::Spawn)?;
+
+    let last_activity = Arc::new(Mutex::new(Instant::now()));
+    let stdout = drain(child.stdout.take().unwrap(), last_activity.clone(), None);
+    let stderr = drain(
+        child.stderr.take().unwrap(),
+        last_activity.clone(),
+        policy.on_frames.clone().map(StatsParser::new),
+    );
+
+    let status = match supervise(&mut child, &last_activity, policy).map_err(RunError::Spawn)? {
+        Ended::Exited(status) => status,
+        Ended::Cancelled => return Err(RunError::Cancelled),
+        Ended::Stalled(after) => {
+            return Err(RunError::Stalled {
+                after,
+                stderr: collect_stderr(stderr),
+            })
+        }
+    };
+    let output = Output {
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: collect_stderr(stderr),
+    };
+    finish(status, output)
+}
+
+/// ffmpeg's stdout, counting every read as a sign of progress: a child
+/// blocked on a full pipe while its reader works is not stalled.
+pub struct StreamedOutput {
+    inner: ChildStdout,
+    last_activity: Arc<Mutex<Instant>>,
+}
+
+impl Read for StreamedOutput {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        *self.last_activity.lock().unwrap() = Instant::now();
+        Ok(n)
+    }
+}
+
+/// Like [`run_reporting`], but hands ffmpeg's stdout to `consume` while it
+/// is written instead of collecting it, for output too large to hold.
+/// `consume` runs on a thread of its own; once it returns the pipe is closed,
+/// which ends the child should it still be writing. Its result is returned
+/// with ffmpeg's stderr. Not retried, as the output was already consumed.
+pub fn run_streaming<T: Send>(
+    mut command: Command,
+    control: &JobControl,
+    stage: Stage,
+    consume: impl FnOnce(StreamedOutput) -> T + Send,
+) -> Result<(T, String), RunError> {
+    let reporter = control.clone();
+    let policy = Policy {
+        cancel: Some(control.cancel.clone()),
+        pause: Some(control.pause.clone()),
+        on_frames: Some(Arc::new(move |frames, expected| {
+            reporter.report(stage, frames, expected.unwrap_or(0))
+        })),
+        ..Policy::from_settings()
+    };
+    let _slot = concurrency::acquire_process_slot();
+    if is_shutting_down() {
+        return Err(RunError::Cancelled);
+    }
+    debug!("Running {:?}", command);
+    let mut child = sandbox::spawn(
+        command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped()),
+    )
+    .map_err(RunError::Spawn)?;
+
+    let last_activity = Arc::new(Mutex::new(Instant::now()));
+    let stdout = StreamedOutput {
+        inner: child.stdout.take().unwrap(),
+        last_activity: last_activity.clone(),
+    };
+    let stderr = drain(
+        child.stderr.take().unwrap(),
+        last_activity.clone(),
+        policy.on_frames.clone().map(StatsParser::new),
+    );
+
+    let (ended, consumed) = thread::scope(|scope| {
+        let consumer = scope.spawn(move || consume(stdout));
+        let ended = supervise(&mut child, &last_activity, &policy);
+        (ended, consumer.join())
+    });
+    let consumed = consumed.unwrap_or_else(|panic| std::panic::resume_unwind(panic));
+    let status = match ended.map_err(RunError::Spawn)? {
+        Ended::Exited(status) => status,
+        Ended::Cancelled => return Err(RunError::Cancelled),
+        Ended::Stalled(after) => {
+            return Err(RunError::Stalled {
+                after,
+                stderr: collect_stderr(stderr),
+            })
+        }
+    };
+    let output = finish(
+        status,
+        Output {
+            stdout: Vec::new(),
+            stderr: collect_stderr(stderr),
+        },
+    )?;
+    Ok((consumed, output.stderr))
+}
@@ -0,0 +1,274 @@
+//! Videos with a known pattern of unique, duplicated and black frames,
+//! rendered by ffmpeg's filters, so it is known exactly which frames the
+//! analysis ought to remove. The integration tests assert removal counts on
+//! them, and users can run their settings on one before trusting them with
+//! real footage.
+//!
+//! A pattern is written as segments like `10u,4d,6b,10u`: ten unique
+//! frames, four copies of the last of them, six black frames and ten more
+//! unique ones. Unique frames are random noise, which no metric mistakes
+//! for its neighbours, and the whole clip is encoded losslessly so copies
+//! stay identical.
+
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+use std::path::Path;
+
+use crate::error::ProcessError;
+use crate::ffmpeg;
+use crate::paths;
+use crate::supervisor;
+
+/// A run of frames in a [`Pattern`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase", tag = "kind", content = "frames")]
+pub enum Segment {
+    /// Frames unlike each other and everything else in the clip.
+    Unique(usize),
+    /// Copies of the frame before; a pattern cannot start with one.
+    Duplicate(usize),
+    Black(usize),
+}
+
+impl Segment {
+    pub fn frames(self) -> usize {
+        match self {
+            Segment::Unique(frames) | Segment::Duplicate(frames) | Segment::Black(frames) => frames,
+        }
+    }
+}
+
+/// The segments of a synthetic clip and the way it is encoded.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Pattern {
+    pub segments: Vec<Segment>,
+    pub width: u32,
+    pub height: u32,
+    pub fps: u32,
+    /// Add a sine tone for the length of the clip.
+    pub audio: bool,
+}
+
+impl Default for Pattern {
+    fn default() -> Self {
+        Pattern {
+            segments: Vec::new(),
+            width: 320,
+            height: 240,
+            fps: 30,
+            audio: false,
+        }
+    }
+}
+
+/// What a frame of the clip shows: a unique frame by its number, or black.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Content {
+    Unique(usize),
+    Black,
+}
+
+impl Pattern {
+    /// A pattern of the default size and rate with the segments in `spec`,
+    /// comma separated counts each followed by `u` for unique, `d` for
+    /// duplicate or `b` for black frames.
+    pub fn parse(spec: &str) -> Result<Pattern, String> {
+        let segments = spec
+            .split(',')
+            .map(|segment| {
+                let segment = segment.trim();
+                let (count, kind) = segment.split_at(segment.len().saturating_sub(1));
+                let frames = count
+                    .parse()
+                    .map_err(|_| format!("invalid segment \"{}\"", segment))?;
+                match kind {
+                    "u" => Ok(Segment::Unique(frames)),
+                    "d" => Ok(Segment::Duplicate(frames)),
+                    "b" => Ok(Segment::Black(frames)),
+                    _ => Err(format!(
+                        "invalid segment \"{}\"; segments end in u, d or b",
+                        segment
+                    )),
+                }
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Pattern {
+            segments,
+            ..Pattern::default()
+        })
+    }
+
+    /// Length of the clip in frames.
+    pub fn frames(&self) -> usize {
+        self.segments.iter().map(|segment| segment.frames()).sum()
+    }
+
+    /// Which frames the analysis removes at its default settings: every
+    /// frame identical to the one after it.
+    pub fn expected_removed(&self) -> Vec<bool> {
+        let contents = self.contents();
+        let mut removed: Vec<bool> = contents.windows(2).map(|pair| pair[0] == pair[1]).collect();
+        if !contents.is_empty() {
+            removed.push(false);
+        }
+        removed
+    }
+
+    /// How many frames the analysis removes at its default settings.
+    pub fn expected_removed_count(&self) -> usize {
+        self.expected_removed()
+            .into_iter()
+            .filter(|&removed| removed)
+            .count()
+    }
+
+    fn contents(&self) -> Vec<Content> {
+        let mut contents = Vec::with_capacity(self.frames());
+        let mut unique = 0;
+        for segment in &self.segments {
+            match *segment {
+                Segment::Unique(frames) => {
+                    contents.extend((unique..unique + frames).map(Content::Unique));
+                    unique += frames;
+                }
+                Segment::Duplicate(frames) => {
+                    let last = contents.last().copied().unwrap_or(Content::Black);
+                    contents.extend(std::iter::repeat_n(last, frames));
+                }
+                Segment::Black(frames) => {
+                    contents.extend(std::iter::repeat_n(Content::Black, frames));
+                }
+            }
+        }
+        contents
+    }
+
+    fn validate(&self) -> Result<(), ProcessError> {
+        let even = self.width.is_multiple_of(2) && self.height.is_multiple_of(2);
+        if self.width < 16 || self.height < 16 || !even {
+            return Err(ProcessError::new(
+                "The clip must be at least 16x16 with even dimensions",
+            ));
+        }
+        if self.fps == 0 {
+            return Err(ProcessError::new("The frame rate must be at least 1"));
+        }
+        if self.segments.iter().any(|segment| segment.frames() == 0) {
+            return Err(ProcessError::new("Every segment needs at least one frame"));
+        }
+        match self.segments.first() {
+            None => Err(ProcessError::new("The pattern has no segments")),
+            Some(Segment::Duplicate(_)) => Err(ProcessError::new(
+                "The pattern cannot start with duplicates",
+            )),
+            Some(_) => Ok(()),
+        }
+    }
+
+    /// The filter graph rendering the clip as `[v]`. Each segment is a
+    /// chain of its own, cut from one stream of noise or from black, joined
+    /// by `concat` and retimed to a constant rate.
+    fn filter_graph(&self) -> String {
+        let size = format!("{}x{}", self.width, self.height);
+        let mut chains = Vec::new();
+        // Chains cut from the noise, which is split between them.
+        let mut noise = Vec::new();
+        let mut uniques = 0;
+        // The unique frame last shown, unless black came after it.
+        let mut last = None;
+        for (i, segment) in self.segments.iter().enumerate() {
+            // The noise frames the chain cuts and how often it repeats the
+            // last of them, or black.
+            let cut = match (*segment, last) {
+                (Segment::Unique(frames), _) => {
+                    let cut = (uniques, uniques + frames, 0);
+                    uniques += frames;
+                    last = Some(uniques - 1);
+                    Some(cut)
+                }
+                (Segment::Duplicate(frames), Some(frame)) => Some((frame, frame + 1, frames - 1)),
+                (Segment::Duplicate(_), None) => None,
+                (Segment::Black(_), _) => {
+                    last = None;
+                    None
+                }
+            };
+            chains.push(match cut {
+                Some((start, end, clones)) => {
+                    noise.push(i);
+                    format!(
+                        "[n{}]trim=start_frame={}:end_frame={},setpts=PTS-STARTPTS,\
+                         tpad=stop={}:stop_mode=clone[c{}]",
+                        i, start, end, clones, i
+                    )
+                }
+                None => format!(
+                    "color=black:s={}:r={},format=yuv420p,trim=end_frame={}[c{}]",
+                    size,
+                    self.fps,
+                    segment.frames(),
+                    i
+                ),
+            });
+        }
+
+        let mut graph = String::new();
+        if uniques > 0 {
+            let _ = write!(
+                graph,
+                "nullsrc=s={}:r={},trim=end_frame={},format=yuv420p,\
+                 geq=lum='random(1)*255':cb=128:cr=128,split={}",
+                size,
+                self.fps,
+                uniques,
+                noise.len()
+            );
+            for i in &noise {
+                let _ = write!(graph, "[n{}]", i);
+            }
+            graph.push(';');
+        }
+        for chain in &chains {
+            graph.push_str(chain);
+            graph.push(';');
+        }
+        for i in 0..chains.len() {
+            let _ = write!(graph, "[c{}]", i);
+        }
+        let _ = write!(
+            graph,
+            "concat=n={}:v=1:a=0,setpts=N/{}/TB,fps={}[v]",
+            chains.len(),
+            self.fps,
+            self.fps
+        );
+        graph
+    }
+
+    /// Renders the clip into `output`, losslessly encoded with x264.
+    pub fn generate(&self, output: &Path) -> Result<(), ProcessError> {
+        self.validate()?;
+        let graph = self.filter_graph();
+        let duration = format!("{}", self.frames() as f64 / self.fps as f64);
+        supervisor::run(|| {
+            let mut command = ffmpeg::command()?;
+            if self.audio {
+                command
+                    .args(["-f", "lavfi", "-t", &duration])
+                    .args(["-i", "sine=frequency=440:sample_rate=48000"]);
+            }
+            command.args(["-filter_complex", &graph, "-map", "[v]"]);
+            if self.audio {
+                command.args(["-map", "0:a", "-c:a", "aac"]);
+            }
+            command
+                .args(["-c:v", "libx264", "-qp", "0", "-preset", "ultrafast"])
+                .args(["-pix_fmt", "yuv420p", "-y"])
+                .arg(paths::ffmpeg_arg(output));
+            Ok(command)
+        })
+        .map(|_| ())
+        .map_err(|e| ProcessError::ffmpeg("Failed to generate the synthetic clip", e))
+    }
+}
@@ -0,0 +1,49 @@
+//! Timelapse mode, for long recordings that barely change, like a build
+//! site or a render left running: a frame is only kept once it differs
+//! enough from the last frame kept, and the kept frames play at a rate of
+//! their own.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Timelapse {
+    /// How much a frame must differ from the last kept frame to be kept, as
+    /// the mean change of its pixels in percent of the full range.
+    pub min_change_percent: f32,
+    /// Frame rate of the timelapse.
+    pub fps: f64,
+}
+
+impl Default for Timelapse {
+    fn default() -> Self {
+        Timelapse {
+            min_change_percent: 1.0,
+            fps: 30.0,
+        }
+    }
+}
+
+impl Timelapse {
+    /// The score under the mean absolute difference metric above which a
+    /// frame is dead.
+    pub fn threshold(&self) -> f32 {
+        1.0 - self.min_change_percent / 100.0
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if !(0.0..=100.0).contains(&self.min_change_percent) {
+            return Err(format!(
+                "The change a timelapse keeps frames for must be between 0 and 100%, got {}",
+                self.min_change_percent
+            ));
+        }
+        if !self.fps.is_finite() || self.fps <= 0.0 {
+            return Err(format!(
+                "The timelapse frame rate must be positive, got {}",
+                self.fps
+            ));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,102 @@
+//! The score series of each job, kept next to its transcript so the app can
+//! graph it after the job is done.
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use tracing::{debug, warn};
+
+use crate::cutlist;
+use crate::logging;
+use crate::video_fixer::{Analysis, ProcessOptions};
+
+/// Points in a timeline before it is downsampled.
+const MAX_POINTS: usize = 4000;
+
+/// What is stored per job: the full series.
+#[derive(Serialize, Deserialize)]
+struct StoredScores {
+    threshold: f32,
+    #[serde(default)]
+    exit_threshold: Option<f32>,
+    scores: Vec<f32>,
+    /// The frame the first score is for; see [`Analysis::first_scored`].
+    #[serde(default)]
+    first_scored: usize,
+    removed: Vec<(usize, usize)>,
+}
+
+/// Scores of frames `first..=last`, against their successors or, under a
+/// comparison window, the kept frames before them. Downsampled points cover
+/// several frames and carry their lowest and highest score, so no dip below
+/// or spike above the threshold disappears from the graph.
+#[derive(Debug, Clone, Serialize)]
+pub struct TimelinePoint {
+    pub first: usize,
+    pub last: usize,
+    pub min: f32,
+    pub max: f32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScoreTimeline {
+    pub threshold: f32,
+    /// The threshold runs of dead frames end below, under hysteresis.
+    pub exit_threshold: Option<f32>,
+    /// Number of frames scored, before downsampling.
+    pub pairs: usize,
+    pub points: Vec<TimelinePoint>,
+    /// Inclusive frame ranges that were removed.
+    pub removed: Vec<(usize, usize)>,
+}
+
+/// Stores the scores of the job `job_id` for [`load`].
+pub fn save(job_id: &str, analysis: &Analysis, options: &ProcessOptions) {
+    let Ok(path) = logging::job_file(job_id, "scores.json") else {
+        debug!("Job logs are not available, not storing scores");
+        return;
+    };
+    let stored = StoredScores {
+        threshold: options.threshold,
+        exit_threshold: options.exit_threshold,
+        scores: analysis.scores.clone(),
+        first_scored: analysis.first_scored,
+        removed: cutlist::removed_ranges(&analysis.removed)
+            .into_iter()
+            .map(|range| (range.start, range.end - 1))
+            .collect(),
+    };
+    let result = serde_json::to_vec(&stored)
+        .map_err(|e| e.to_string())
+        .and_then(|json| fs::write(&path, json).map_err(|e| e.to_string()));
+    if let Err(e) = result {
+        warn!("Failed to store scores in {}: {}", path.display(), e);
+    }
+}
+
+/// The score timeline of the job `job_id`, at most [`MAX_POINTS`] long.
+pub fn load(job_id: &str) -> Result<ScoreTimeline, String> {
+    let path = logging::job_file(job_id, "scores.json")?;
+    let json = fs::read(&path).map_err(|e| format!("No scores for job {}: {}", job_id, e))?;
+    let stored: StoredScores = serde_json::from_slice(&json)
+        .map_err(|e| format!("Invalid scores for job {}: {}", job_id, e))?;
+
+    let bucket = stored.scores.len().div_ceil(MAX_POINTS).max(1);
+    let points = stored
+        .scores
+        .chunks(bucket)
+        .enumerate()
+        .map(|(index, chunk)| TimelinePoint {
+            first: stored.first_scored + index * bucket,
+            last: stored.first_scored + index * bucket + chunk.len() - 1,
+            min: chunk.iter().copied().fold(f32::INFINITY, f32::min),
+            max: chunk.iter().copied().fold(f32::NEG_INFINITY, f32::max),
+        })
+        .collect();
+    Ok(ScoreTimeline {
+        threshold: stored.threshold,
+        exit_threshold: stored.exit_threshold,
+        pairs: stored.scores.len(),
+        points,
+        removed: stored.removed,
+    })
+}
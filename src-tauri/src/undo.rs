@@ -0,0 +1,152 @@
+//! Undoing removals: jobs run with `keep_removed` set their frames aside in
+//! their job directory instead of deleting them, and the directory stays
+//! after the job, so frames removed by mistake can be put back and the
+//! output encoded again without another analysis.
+//!
+//! Kept frames are under `kept/` and removed ones under `removed/`, both
+//! with their extracted names. Image sequence inputs are not copied; the
+//! manifest points at the original files.
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+use crate::control::JobControl;
+use crate::error::ProcessError;
+use crate::video_fixer::{self, ProcessOptions, Source};
+use crate::workspace::{self, JobDir, FRAMES_DIR, KEPT_DIR, MANIFEST, REMOVED_DIR};
+
+/// What is needed to encode a job again.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Manifest {
+    /// The job's input, which retimed audio is taken from again.
+    #[serde(default)]
+    pub input: Option<PathBuf>,
+    pub output: PathBuf,
+    pub options: ProcessOptions,
+    #[serde(flatten)]
+    pub source: Source,
+    /// Every frame in order, as the path it has while kept.
+    pub frames: Vec<PathBuf>,
+    pub removed: Vec<bool>,
+    /// The frames are the input's own files, which are never moved.
+    pub borrowed: bool,
+}
+
+impl Manifest {
+    /// Where frame `index` is now.
+    fn location(&self, dir: &Path, index: usize) -> PathBuf {
+        let frame = &self.frames[index];
+        if self.removed[index] && !self.borrowed {
+            dir.join(REMOVED_DIR)
+                .join(frame.file_name().unwrap_or_default())
+        } else {
+            frame.clone()
+        }
+    }
+}
+
+/// Outcome of [`restore_frames`].
+#[derive(Debug, Clone, Serialize)]
+pub struct Restored {
+    pub output: String,
+    pub frames_total: usize,
+    pub frames_removed: usize,
+}
+
+/// Creates the empty undo set in the directory of `job`.
+pub(crate) fn create(job: &JobDir) -> io::Result<PathBuf> {
+    let dir = job.path().to_path_buf();
+    fs::create_dir_all(dir.join(KEPT_DIR))?;
+    fs::create_dir_all(dir.join(REMOVED_DIR))?;
+    Ok(dir)
+}
+
+pub(crate) fn save(dir: &Path, manifest: &Manifest) -> io::Result<()> {
+    fs::write(dir.join(MANIFEST), serde_json::to_vec_pretty(manifest)?)
+}
+
+fn load(dir: &Path) -> Result<Manifest, String> {
+    let json = fs::read(dir.join(MANIFEST)).map_err(|e| format!("Nothing to undo: {}", e))?;
+    serde_json::from_slice(&json).map_err(|e| format!("Invalid undo manifest: {}", e))
+}
+
+/// Puts the removed frames at `indices` back and encodes the job's output
+/// again. Indices of frames that are not removed are ignored.
+pub fn restore_frames(job_id: &str, indices: &[usize]) -> Result<Restored, ProcessError> {
+    let dir = workspace::job_dir_path(job_id).map_err(ProcessError::new)?;
+    let mut manifest = load(&dir).map_err(ProcessError::new)?;
+
+    let mut restored = 0;
+    for &index in indices {
+        if index >= manifest.frames.len() {
+            return Err(ProcessError::new(format!("No frame {}", index)));
+        }
+        if !manifest.removed[index] {
+            continue;
+        }
+        if !manifest.borrowed {
+            let from = manifest.location(&dir, index);
+            fs::rename(&from, &manifest.frames[index]).map_err(|e| {
+                ProcessError::new(format!("Failed to restore {}: {}", from.display(), e))
+            })?;
+        }
+        manifest.removed[index] = false;
+        restored += 1;
+    }
+    // saved before encoding, so a failed encode does not lose track of the
+    // frames already moved back
+    save(&dir, &manifest)
+        .map_err(|e| ProcessError::new(format!("Failed to update undo manifest: {}", e)))?;
+
+    let kept: Vec<PathBuf> = (0..manifest.frames.len())
+        .filter(|&index| !manifest.removed[index])
+        .map(|index| manifest.location(&dir, index))
+        .collect();
+    info!(
+        "Restored {} frames of job {}, encoding {} again",
+        restored,
+        job_id,
+        manifest.output.display()
+    );
+    let mut source = manifest.source.clone();
+    if let Some(input) = &manifest.input {
+        source.audio = video_fixer::retimed_audio(
+            input,
+            manifest.frames.len(),
+            kept.len(),
+            &source,
+            &manifest.options,
+        );
+    }
+    let options = video_fixer::with_target_size(manifest.options.clone(), kept.len(), &source)?;
+    // the job's frames directory is gone; encoding gets a fresh one
+    let frames_dir = dir.join(FRAMES_DIR);
+    let _ = fs::remove_dir_all(&frames_dir);
+    fs::create_dir(&frames_dir).map_err(|e| {
+        ProcessError::new(format!("Failed to create {}: {}", frames_dir.display(), e))
+    })?;
+    let result = video_fixer::restitch(
+        &kept,
+        source,
+        &options,
+        &manifest.output,
+        &frames_dir,
+        &JobControl::default(),
+    );
+    let _ = fs::remove_dir_all(&frames_dir);
+    result?;
+    Ok(Restored {
+        output: manifest.output.to_string_lossy().into_owned(),
+        frames_total: manifest.frames.len(),
+        frames_removed: manifest.removed.iter().filter(|&&dead| dead).count(),
+    })
+}
+
+/// Deletes the frames kept for undoing `job_id`.
+pub fn discard(job_id: &str) -> Result<(), String> {
+    let dir = workspace::job_dir_path(job_id)?;
+    fs::remove_dir_all(&dir).map_err(|e| format!("Failed to remove {}: {}", dir.display(), e))
+}
@@ -1,73 +1,771 @@
 use image;
-use once_cell::sync::Lazy;
+use image::{imageops, DynamicImage, GrayImage, ImageBuffer, Rgba};
 use rayon::prelude::*;
-use std::env;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::ffi::OsString;
 use std::fs;
 use std::fs::File;
-use std::io::Cursor;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::BufWriter;
+use std::io::Write;
 use std::path::Path;
 use std::path::PathBuf;
-use std::process::Command;
-use std::process::Stdio;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
-use std::sync::Mutex;
-use tempfile::tempdir;
-
-const FFMPEG_EXECUTABLE: &[u8] = if cfg!(target_os = "windows") {
-    include_bytes!("resources/ffmpeg-windows.zst")
-} else if cfg!(target_os = "macos") {
-    include_bytes!("resources/ffmpeg-mac.zst")
-} else if cfg!(target_os = "linux") {
-    include_bytes!("resources/ffmpeg-linux.zst")
-} else {
-    include_bytes!("resources/ffmpeg-linux.zst")
-};
-
-static FFMPEG_PATH: Lazy<Mutex<Option<String>>> = Lazy::new(|| Mutex::new(None));
-
-fn extract_ffmpeg() -> std::io::Result<String> {
-    use zstd::stream::read::Decoder;
-    let temp_dir = env::temp_dir();
-    let ffmpeg_path = temp_dir.join("ffmpeg");
-
-    let compressed = Cursor::new(FFMPEG_EXECUTABLE);
-    let mut decoder = Decoder::new(compressed)?;
-    let mut out = File::create(&ffmpeg_path)?;
-    std::io::copy(&mut decoder, &mut out)?;
-
-    #[cfg(unix)]
-    {
-        use std::os::unix::fs::PermissionsExt;
-        let mut perms = out.metadata()?.permissions();
-        perms.set_mode(0o755);
-        std::fs::set_permissions(&ffmpeg_path, perms)?;
+use std::time::Instant;
+use tracing::{debug, error, info, warn, Span};
+
+use crate::analysis_cache;
+use crate::animation;
+use crate::blur;
+use crate::capabilities;
+use crate::coarse;
+use crate::color::{self, ColorInfo};
+use crate::comparator;
+use crate::concurrency;
+use crate::control::{FrameDecision, JobControl, Stage, StageClock, StageTimes};
+use crate::crop::{self, CropMode, CropRect};
+use crate::cutlist::{self, CutListFormat};
+use crate::duplicates;
+use crate::error::ProcessError;
+use crate::estimate::{self, SizeEstimate};
+use crate::export;
+use crate::ffmpeg;
+use crate::glitch::{self, GlitchAction};
+use crate::logging;
+use crate::mezzanine::Profiles;
+use crate::output::{self, Destination, OutputOptions};
+use crate::paths;
+use crate::pixel_format::{self, Chroma, PixelFormat};
+#[cfg(feature = "plugins")]
+use crate::plugins::Plugin;
+use crate::power;
+use crate::quality::{self, QualityReport};
+use crate::remote;
+use crate::representative::{self, Representative};
+use crate::retime::{self, Audio};
+#[cfg(feature = "scripting")]
+use crate::script::{self, Script};
+use crate::sections::{self, Section, SectionMap};
+use crate::sequence;
+use crate::settings;
+use crate::similarity::{self, ColorSpace, Metric, Planes};
+use crate::smartcut;
+use crate::streams;
+use crate::supervisor::{self, RunError};
+use crate::timelapse::Timelapse;
+use crate::timeline;
+use crate::undo;
+use crate::workspace::{self, JobDir};
+use crate::y4m;
+
+/// Format of the intermediate frames written during extraction.
+///
+/// - `Png`: lossless and the default. Slow to encode and decode, and large
+///   (roughly 3 MB per 1080p frame).
+///   Sources above 8 bits are stored as 16-bit PNG.
+/// - `WebP`: lossless WebP. Scores are identical to PNG, files are about a
+///   third smaller, but encoding is the slowest of all formats. 8-bit only.
+/// - `Jpeg(quality)`: quality 1-100. Fastest of the image formats and by far
+///   the smallest, but compression artifacts make identical frames score
+///   slightly below 1.0, so thresholds may need lowering. 8-bit only.
+/// - `Y4m`: a single raw stream in the source's bit depth and subsampling.
+///   No encode or decode cost at all, so it is the fastest option, but it is
+///   also the largest on disk.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "quality", rename_all = "lowercase")]
+pub enum FrameFormat {
+    #[default]
+    Png,
+    WebP,
+    Jpeg(u8),
+    Y4m,
+}
+
+impl FrameFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            FrameFormat::Png => "png",
+            FrameFormat::WebP => "webp",
+            FrameFormat::Jpeg(_) => "jpg",
+            FrameFormat::Y4m => "y4m",
+        }
+    }
+
+    /// Whether frames in this format can hold more than 8 bits per sample.
+    fn keeps_high_depth(&self) -> bool {
+        matches!(self, FrameFormat::Png | FrameFormat::Y4m)
+    }
+
+    /// Whether frames in this format can hold an alpha channel.
+    fn keeps_alpha(&self) -> bool {
+        matches!(self, FrameFormat::Png | FrameFormat::WebP)
+    }
+
+    /// ffmpeg output options for writing frames of `pixel_format` in this
+    /// format.
+    fn encoder_args(&self, pixel_format: Option<PixelFormat>) -> Vec<String> {
+        let high_depth = pixel_format.is_some_and(|format| format.is_high_depth());
+        let alpha = pixel_format.is_some_and(|format| format.alpha);
+        match self {
+            FrameFormat::Png => {
+                let pixel_format = match (high_depth, alpha) {
+                    (true, true) => "rgba64be",
+                    (true, false) => "rgb48be",
+                    (false, true) => "rgba",
+                    (false, false) => return vec![],
+                };
+                vec!["-pix_fmt".into(), pixel_format.into()]
+            }
+            FrameFormat::WebP => {
+                let mut args: Vec<String> = ["-c:v", "libwebp", "-lossless", "1"]
+                    .map(String::from)
+                    .to_vec();
+                if alpha {
+                    args.extend(["-pix_fmt".into(), "bgra".into()]);
+                }
+                args
+            }
+            FrameFormat::Jpeg(quality) => {
+                // ffmpeg's mjpeg qscale runs from 2 (best) to 31 (worst)
+                let quality = (*quality).clamp(1, 100) as u32;
+                let qscale = 2 + (100 - quality) * 29 / 99;
+                vec!["-q:v".into(), qscale.to_string()]
+            }
+            FrameFormat::Y4m => {
+                let mut args = vec![
+                    "-pix_fmt".into(),
+                    pixel_format
+                        .unwrap_or(PixelFormat::YUV420P)
+                        .opaque()
+                        .yuv_name(),
+                    "-f".into(),
+                    "yuv4mpegpipe".into(),
+                ];
+                if high_depth {
+                    // depths above 8 bits are an extension of the format
+                    args.extend(["-strict".into(), "-1".into()]);
+                }
+                args
+            }
+        }
     }
+}
 
-    Ok(ffmpeg_path.to_string_lossy().into_owned())
+/// Codec of the processed video.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VideoCodec {
+    #[default]
+    H264,
+    H265,
+    Vp9,
+    Av1,
+    /// Lossless FFV1 in Matroska, for archiving.
+    Ffv1,
+    /// Animated GIF with a palette generated from the whole clip.
+    Gif,
+    /// Animated WebP.
+    WebP,
+    /// Animated PNG, lossless.
+    Apng,
+    /// ProRes in QuickTime, an intermediate for editing. The 4444 profiles
+    /// keep transparency.
+    ProRes,
+    /// DNxHR in QuickTime, an intermediate for editing.
+    DnxHr,
 }
 
-fn get_ffmpeg_path() -> String {
-    let mut cached = FFMPEG_PATH.lock().unwrap();
-    if cached.is_none() {
-        match extract_ffmpeg() {
-            Ok(path) => *cached = Some(path),
-            Err(e) => {
-                eprintln!("Failed to extract ffmpeg!  :{}", e);
-                std::process::exit(1);
+impl VideoCodec {
+    /// Extension of the container the codec is written to.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            VideoCodec::Ffv1 => "mkv",
+            VideoCodec::Gif => "gif",
+            VideoCodec::WebP => "webp",
+            VideoCodec::Apng => "apng",
+            VideoCodec::ProRes | VideoCodec::DnxHr => "mov",
+            _ => "mp4",
+        }
+    }
+
+    /// ffmpeg output options selecting and tuning the encoder.
+    fn encoder_args(&self) -> &'static [&'static str] {
+        match self {
+            VideoCodec::H264 => &["-c:v", "libx264", "-preset", "fast"],
+            VideoCodec::H265 => &["-c:v", "libx265", "-preset", "fast"],
+            VideoCodec::Vp9 => &[
+                "-c:v",
+                "libvpx-vp9",
+                "-row-mt",
+                "1",
+                "-b:v",
+                "0",
+                "-crf",
+                "31",
+            ],
+            VideoCodec::Av1 => &["-c:v", "libaom-av1", "-cpu-used", "6", "-crf", "30"],
+            VideoCodec::Ffv1 => &["-c:v", "ffv1", "-level", "3", "-slices", "16"],
+            VideoCodec::Gif => &[
+                "-vf",
+                "split[a][b];[a]palettegen[p];[b][p]paletteuse",
+                "-c:v",
+                "gif",
+            ],
+            VideoCodec::WebP => &["-c:v", "libwebp_anim", "-quality", "80"],
+            VideoCodec::Apng => &["-c:v", "apng", "-f", "apng"],
+            VideoCodec::ProRes => &["-c:v", "prores_ks", "-vendor", "apl0"],
+            VideoCodec::DnxHr => &["-c:v", "dnxhd"],
+        }
+    }
+
+    /// ffmpeg output options selecting the profile of the codecs that have
+    /// them; see [`mezzanine`](crate::mezzanine).
+    fn profile_args(&self, profiles: &Profiles) -> Vec<&'static str> {
+        match self {
+            VideoCodec::ProRes => vec!["-profile:v", profiles.prores.name()],
+            VideoCodec::DnxHr => vec!["-profile:v", profiles.dnxhr.name()],
+            _ => Vec::new(),
+        }
+    }
+
+    /// The profile of `profiles` as ffmpeg reports it for a stream, for the
+    /// codecs that are encoded at one.
+    fn reported_profile(&self, profiles: &Profiles) -> Option<&'static str> {
+        match self {
+            VideoCodec::ProRes => Some(profiles.prores.reported_name()),
+            VideoCodec::DnxHr => Some(profiles.dnxhr.reported_name()),
+            _ => None,
+        }
+    }
+
+    /// The codec as ffmpeg reports it for a stream.
+    fn codec_name(&self) -> &'static str {
+        match self {
+            VideoCodec::H264 => "h264",
+            VideoCodec::H265 => "hevc",
+            VideoCodec::Vp9 => "vp9",
+            VideoCodec::Av1 => "av1",
+            VideoCodec::Ffv1 => "ffv1",
+            VideoCodec::Gif => "gif",
+            VideoCodec::WebP => "webp",
+            VideoCodec::Apng => "apng",
+            VideoCodec::ProRes => "prores",
+            VideoCodec::DnxHr => "dnxhd",
+        }
+    }
+
+    /// Name of the ffmpeg encoder in [`Self::encoder_args`].
+    fn encoder(&self) -> &'static str {
+        let args = self.encoder_args();
+        args.iter()
+            .position(|&arg| arg == "-c:v")
+            .map_or("", |index| args[index + 1])
+    }
+
+    /// Whether the codec writes an animation rather than a video, without
+    /// colour signalling.
+    fn is_animation(&self) -> bool {
+        matches!(self, VideoCodec::Gif | VideoCodec::WebP | VideoCodec::Apng)
+    }
+
+    /// Whether the codec's software encoder can encode in two passes.
+    fn takes_two_passes(&self) -> bool {
+        matches!(self, VideoCodec::H264 | VideoCodec::H265)
+    }
+
+    /// ffmpeg output options for pass `pass` of a two-pass encode at `kbps`
+    /// kbit/s, keeping statistics in files named after `passlog`; see
+    /// [`Self::takes_two_passes`].
+    fn two_pass_args(&self, kbps: u32, pass: u8, passlog: &Path) -> Vec<OsString> {
+        let mut args: Vec<OsString> = vec!["-b:v".into(), format!("{}k", kbps).into()];
+        match self {
+            VideoCodec::H264 => {
+                args.extend(["-pass".into(), pass.to_string().into()]);
+                args.extend(["-passlogfile".into(), paths::ffmpeg_arg(passlog)]);
+            }
+            VideoCodec::H265 => {
+                // the path is an x265 parameter, where : and = separate
+                let stats: String = passlog
+                    .to_string_lossy()
+                    .chars()
+                    .flat_map(|c| match c {
+                        '\\' | ':' | '=' | '\'' => vec!['\\', c],
+                        _ => vec![c],
+                    })
+                    .collect();
+                args.extend([
+                    "-x265-params".into(),
+                    format!("pass={}:stats={}", pass, stats).into(),
+                ]);
+            }
+            _ => {}
+        }
+        args
+    }
+
+    /// The encoder for audio in the codec's container; animations have none.
+    fn audio_encoder(&self) -> Option<&'static str> {
+        match self {
+            VideoCodec::Gif | VideoCodec::WebP | VideoCodec::Apng => None,
+            VideoCodec::Ffv1 => Some("flac"),
+            VideoCodec::ProRes | VideoCodec::DnxHr => Some("pcm_s16le"),
+            _ => Some("aac"),
+        }
+    }
+
+    /// Whether the codec, in the profile it is encoded with, can carry an
+    /// alpha channel.
+    pub fn keeps_alpha(&self, profiles: &Profiles) -> bool {
+        match self {
+            VideoCodec::Gif | VideoCodec::WebP | VideoCodec::Apng | VideoCodec::Ffv1 => true,
+            VideoCodec::ProRes => profiles.prores.is_444(),
+            _ => false,
+        }
+    }
+
+    /// The NVENC encoder for the codec, if NVIDIA GPUs can encode it.
+    fn nvenc_encoder(&self) -> Option<&'static str> {
+        match self {
+            VideoCodec::H264 => Some("h264_nvenc"),
+            VideoCodec::H265 => Some("hevc_nvenc"),
+            VideoCodec::Av1 => Some("av1_nvenc"),
+            _ => None,
+        }
+    }
+
+    /// Pixel format the encoder is fed: the source's bit depth and
+    /// subsampling as far as the encoder takes them, or 8-bit 4:2:0 when the
+    /// source's is unknown, with its alpha channel where the codec has one.
+    /// GIF picks its own palette, with a transparent entry, and ProRes and
+    /// DNxHR take the format of their profile.
+    fn pixel_format(&self, source: Option<PixelFormat>, profiles: &Profiles) -> Option<String> {
+        let mut source = source.unwrap_or(PixelFormat::YUV420P);
+        if !self.keeps_alpha(profiles) {
+            source = source.opaque();
+        }
+        let max_depth = match self {
+            VideoCodec::Gif => return None,
+            VideoCodec::Apng => {
+                let name = match (source.is_high_depth(), source.alpha) {
+                    (true, true) => "rgba64be",
+                    (true, false) => "rgb48be",
+                    (false, true) => "rgba",
+                    (false, false) => "rgb24",
+                };
+                return Some(name.into());
+            }
+            VideoCodec::WebP if source.alpha => return Some("yuva420p".into()),
+            VideoCodec::WebP => return Some("yuv420p".into()),
+            VideoCodec::ProRes => {
+                return Some(profiles.prores.pixel_format(source.alpha).yuv_name())
+            }
+            VideoCodec::DnxHr => return Some(profiles.dnxhr.pixel_format().yuv_name()),
+            VideoCodec::H264 => 10,
+            VideoCodec::H265 | VideoCodec::Vp9 | VideoCodec::Av1 => 12,
+            // ffmpeg has no 12-bit 4:2:0 with alpha
+            VideoCodec::Ffv1
+                if source.alpha && source.bit_depth == 12 && source.chroma == Chroma::Yuv420 =>
+            {
+                return Some(
+                    PixelFormat {
+                        bit_depth: 16,
+                        ..source
+                    }
+                    .yuv_name(),
+                )
             }
+            VideoCodec::Ffv1 => 16,
+        };
+        Some(source.capped(max_depth).yuv_name())
+    }
+
+    /// Pixel format NVENC is fed. It takes 4:2:0 and, for H.264 and H.265,
+    /// 4:4:4, and encodes H.264 at 8 bits only.
+    fn nvenc_pixel_format(&self, source: Option<PixelFormat>) -> &'static str {
+        let source = source.unwrap_or(PixelFormat::YUV420P);
+        let full_chroma = source.chroma == Chroma::Yuv444 && *self != VideoCodec::Av1;
+        let high_depth = source.is_high_depth() && *self != VideoCodec::H264;
+        match (full_chroma, high_depth) {
+            (true, true) => "yuv444p16le",
+            (true, false) => "yuv444p",
+            (false, true) => "p010le",
+            (false, false) => "yuv420p",
+        }
+    }
+
+    /// Muxer options making an animation play `plays` times, 0 for forever.
+    fn loop_args(&self, plays: u16) -> Vec<String> {
+        let (option, value) = match self {
+            // GIF counts repeats after the first play and -1 for none
+            VideoCodec::Gif => ("-loop", if plays == 0 { 0 } else { plays as i32 - 1 }),
+            VideoCodec::WebP => ("-loop", plays as i32),
+            VideoCodec::Apng => ("-plays", plays as i32),
+            _ => return Vec::new(),
+        };
+        vec![option.to_string(), value.to_string()]
+    }
+}
+
+/// Image format of the sequence written instead of a video.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SequenceFormat {
+    Png,
+    /// Deflate-compressed TIFF.
+    Tiff,
+    /// Half-float OpenEXR, for compositing packages.
+    Exr,
+}
+
+impl SequenceFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            SequenceFormat::Png => "png",
+            SequenceFormat::Tiff => "tif",
+            SequenceFormat::Exr => "exr",
+        }
+    }
+
+    fn encoder_args(&self) -> &'static [&'static str] {
+        match self {
+            SequenceFormat::Png => &["-c:v", "png"],
+            SequenceFormat::Tiff => &["-c:v", "tiff", "-compression_algo", "deflate"],
+            SequenceFormat::Exr => &["-c:v", "exr", "-compression", "zip16", "-format", "half"],
+        }
+    }
+}
+
+/// Deinterlacing of the frames as they are extracted. Combing in interlaced
+/// frames changes between fields and hides true duplicates from the
+/// comparison. Either filter keeps one frame per frame.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Deinterlace {
+    /// `bwdif` on the frames of sources reporting an interlaced field
+    /// order.
+    #[default]
+    Auto,
+    Off,
+    /// Deinterlace every frame with `yadif`, the faster filter.
+    Yadif,
+    /// Deinterlace every frame with `bwdif`, which keeps more detail.
+    Bwdif,
+}
+
+impl Deinterlace {
+    /// The filter for a source that is `interlaced` or not, if any.
+    fn filter(&self, interlaced: bool) -> Option<&'static str> {
+        match self {
+            // flagged frames only, for sources that mix in progressive ones
+            Deinterlace::Auto if interlaced => Some("bwdif=mode=send_frame:deint=interlaced"),
+            Deinterlace::Auto | Deinterlace::Off => None,
+            Deinterlace::Yadif => Some("yadif"),
+            Deinterlace::Bwdif => Some("bwdif=mode=send_frame"),
+        }
+    }
+}
+
+/// Per-job processing options. Fields the frontend leaves out fall back to
+/// the user's settings.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProcessOptions {
+    pub frame_format: FrameFormat,
+    pub threshold: f32,
+    /// Once a frame is dead, the frames after it stay dead while they score
+    /// above this rather than `threshold`, so scores wavering around
+    /// `threshold` do not break a frozen span into pieces. Below
+    /// `threshold`; without it there is no hysteresis.
+    pub exit_threshold: Option<f32>,
+    pub metric: Metric,
+    /// The channels frames are compared in; the luma alone by default.
+    /// Applies to image frames; y4m frames, and with them `low_memory`,
+    /// are always compared by their luma.
+    pub color_space: ColorSpace,
+    /// How many of the last kept frames each frame is compared with. At 1 a
+    /// frame is only compared with its successor; more catch dead frames
+    /// that alternate between near-identical images, as in A B A B.
+    pub comparison_window: usize,
+    /// Compare only every this many frames at first, and frame by frame
+    /// only around the dead spans that turns up; see [`coarse`]. Applies
+    /// to image frames compared with their successors, not to y4m frames,
+    /// `low_memory`, a comparison window or `encoder_duplicates`.
+    pub analysis_step: Option<usize>,
+    /// Removed spans with fewer than this many kept frames between them
+    /// are merged into one, removing the frames in between; at 0 or 1
+    /// spans are left as found.
+    pub merge_gap: usize,
+    /// Which frame of each run of alike frames is kept; see
+    /// [`representative`]. By default the one the comparison keeps.
+    pub representative: Option<Representative>,
+    /// Also remove the frames whose sharpness is below this, whether dead
+    /// or not; see [`blur`].
+    pub blur_threshold: Option<f64>,
+    /// Remove single frames unlike both their neighbours, or replace them
+    /// with a blend of the two; see [`glitch`]. Replaced frames only make
+    /// it into encoded videos and image sequences.
+    pub glitches: Option<GlitchAction>,
+    /// A Rhai script that has the last word on each frame; see
+    /// [`script`](crate::script). Needs the `scripting` feature.
+    pub script: Option<PathBuf>,
+    /// Detector plugins run before the script, besides those enabled in
+    /// the app; see [`plugins`](crate::plugins). Needs the `plugins`
+    /// feature.
+    pub plugins: Vec<PathBuf>,
+    pub codec: VideoCodec,
+    /// Profiles of ProRes and DNxHR outputs; see [`mezzanine`](crate::mezzanine).
+    pub mezzanine: Profiles,
+    /// Encode H.264 or H.265 in two passes at this bitrate, in kbit/s,
+    /// rather than at constant quality, to hit an output size such as an
+    /// upload limit. Always in software.
+    pub target_bitrate: Option<u32>,
+    /// Encode H.264 or H.265 in two passes at the bitrate that keeps the
+    /// output within this many megabytes of 1,000,000 bytes, worked out
+    /// from what is left after removal. Takes the place of
+    /// `target_bitrate`.
+    pub target_size_mb: Option<f64>,
+    /// Frame rate of the output; the source's when unset, or 30 for a
+    /// directory of images.
+    pub framerate: Option<f64>,
+    /// Play the kept frames at this frame rate in place of `framerate`, and
+    /// keep the source's audio with its tempo changed to last as long as
+    /// the kept frames; see [`retime`]. Only encoded videos are retimed.
+    pub retime: Option<f64>,
+    pub output: OutputOptions,
+    /// Write the kept frames as numbered images in this format, into a
+    /// directory named by the output template, instead of encoding a video.
+    pub image_sequence: Option<SequenceFormat>,
+    /// Write a cut list or marker list in this format instead of any video
+    /// or images. Takes precedence over `image_sequence`.
+    pub cut_list: Option<CutListFormat>,
+    /// Cut the kept ranges out of the source, stream-copying whole GOPs and
+    /// re-encoding only around cut points, instead of encoding with `codec`.
+    /// The output keeps the source's container; see [`smartcut`].
+    pub smart_cut: bool,
+    /// Also write `<output>.timestamps.json`, mapping every output frame to
+    /// the source frame and time it came from.
+    pub timestamp_map: bool,
+    /// After encoding, compare the output with the source frames it kept and
+    /// report SSIM, plus VMAF when ffmpeg has libvmaf.
+    pub verify_quality: bool,
+    /// Estimate the output size in [`analyze`] with a short test encode of
+    /// the kept frames instead of the codec's typical bitrate.
+    pub test_encode: bool,
+    /// Set removed frames aside instead of deleting them, so they can be
+    /// put back with [`undo::restore_frames`]. Needs an image frame format.
+    pub keep_removed: bool,
+    /// Also copy every removed frame into this directory, named by its
+    /// index and time in the source; see [`export`].
+    pub export_removed: Option<PathBuf>,
+    /// Encode H.264, H.265 and AV1 videos with NVENC when ffmpeg has it,
+    /// on the GPUs and within the session limits of the settings.
+    pub hardware_encode: bool,
+    /// Decode the source on the GPU while extracting frames, where ffmpeg
+    /// has an accelerator for this system: CUDA or D3D11VA on Windows,
+    /// VideoToolbox on macOS, CUDA or VAAPI elsewhere. Falls back to
+    /// software when the accelerator fails.
+    pub hardware_decode: bool,
+    /// Bound memory use for very large sources: frames are decoded as a
+    /// stream and compared a pair at a time, and ffmpeg runs on few threads.
+    /// Frames are never stored as image files, which `keep_removed` needs.
+    pub low_memory: bool,
+    /// Give the same output, byte for byte, and the same removed frames on
+    /// every run with the same input and options: hardware encoding and
+    /// decoding are off, the encoder runs on
+    /// [`DETERMINISTIC_THREADS`](concurrency::DETERMINISTIC_THREADS) threads
+    /// however busy the machine is, and ffmpeg writes no version strings or
+    /// random IDs into the output. Scores are the same on every run anyway.
+    pub deterministic: bool,
+    /// Take the analysis of an earlier job on the same file with the same
+    /// options, where one is cached, rather than compare the frames again;
+    /// see [`analysis_cache`].
+    pub reuse_analysis: bool,
+    /// Encode at 8 bits even when the source has more. Subsampling is still
+    /// kept.
+    pub force_8bit: bool,
+    /// Deinterlace the frames as they are extracted.
+    pub deinterlace: Deinterlace,
+    /// Detect black bars and leave them out of the comparison, or crop
+    /// them from the output as well.
+    pub crop: CropMode,
+    /// Which of the source's video streams is processed, counted among its
+    /// video streams only; see [`streams::list`]. By default the first that
+    /// is not an embedded picture.
+    pub video_stream_index: Option<usize>,
+    /// Download an HTTP(S) or SMB input into the job directory before
+    /// processing, rather than reading it over the network on every pass;
+    /// see [`remote`].
+    pub cache_remote: bool,
+    /// Time ranges analysed with their own threshold or metric instead of
+    /// `threshold` and `metric`; see [`sections`].
+    pub sections: Vec<Section>,
+    /// Build a timelapse: keep only frames that differ enough from the last
+    /// one kept, under the mean absolute difference, and play them at the
+    /// timelapse's frame rate. Replaces `threshold`, `metric`, `framerate`
+    /// and `retime`; see [`timelapse`](crate::timelapse).
+    pub timelapse: Option<Timelapse>,
+    /// Remove only the frames the encoder repeated, as screen recorders do
+    /// when a capture tick brings no new frame, rather than every frame the
+    /// comparison finds alike; see [`duplicates`]. Image sequences and
+    /// animated WebP are compared as usual.
+    pub encoder_duplicates: bool,
+    /// Name of the preset these options came from, used in output names.
+    pub preset: Option<String>,
+}
+
+impl ProcessOptions {
+    /// The video stream processed; see [`Self::video_stream_index`].
+    fn video_stream(&self) -> usize {
+        self.video_stream_index.unwrap_or(0)
+    }
+
+    /// Frame rate the kept frames play at, when not the source's.
+    fn output_framerate(&self) -> Option<f64> {
+        self.retime.or(self.framerate)
+    }
+}
+
+/// How frames are compared and judged dead, frame by frame: by the
+/// options' metric and thresholds, except in sections with their own.
+struct Detection<'a> {
+    options: &'a ProcessOptions,
+    sections: SectionMap<'a>,
+    /// Frames the encoder repeated, which are the dead ones when known.
+    repeats: Option<&'a [bool]>,
+}
+
+impl<'a> Detection<'a> {
+    /// Detection for the frames of `source`, placed in time by its frame
+    /// rate, or by the output frame rate when that is not known.
+    fn new(options: &'a ProcessOptions, source: &'a Source) -> Detection<'a> {
+        Detection {
+            options,
+            sections: SectionMap::new(&options.sections, source_fps(source, options)),
+            repeats: source.repeats.as_deref(),
+        }
+    }
+
+    /// Whether frames are compared with the last kept frames rather than
+    /// their successors: under a comparison window, and in timelapse mode,
+    /// where change builds up slowly across many frames.
+    fn windowed(&self) -> bool {
+        self.options.comparison_window > 1 || self.options.timelapse.is_some()
+    }
+
+    /// How many kept frames a frame is compared with.
+    fn window(&self) -> usize {
+        self.options.comparison_window.max(1)
+    }
+
+    /// The frame the first score is for; see [`Analysis::first_scored`].
+    fn first_scored(&self) -> usize {
+        self.windowed() as usize
+    }
+
+    fn metric(&self, frame: usize) -> Metric {
+        self.sections
+            .at(frame)
+            .and_then(|section| section.metric)
+            .unwrap_or(self.options.metric)
+    }
+
+    fn threshold(&self, frame: usize) -> f32 {
+        self.sections
+            .at(frame)
+            .and_then(|section| section.threshold)
+            .unwrap_or(self.options.threshold)
+    }
+
+    /// Whether frame `frame` scoring `score` is dead, given whether the
+    /// frame before it was. `None` when that matters and is not known,
+    /// which is only ever between the exit threshold and the threshold.
+    fn is_dead(&self, frame: usize, score: f32, previous_dead: Option<bool>) -> Option<bool> {
+        if self.repeats.is_some() {
+            return Some(self.is_repeat(frame));
+        }
+        if score > self.threshold(frame) {
+            return Some(true);
+        }
+        match self.options.exit_threshold {
+            Some(exit) if score > exit => previous_dead,
+            _ => Some(false),
+        }
+    }
+
+    /// Whether frame `frame` is known to be a repeat the encoder wrote.
+    fn is_repeat(&self, frame: usize) -> bool {
+        self.repeats
+            .and_then(|repeats| repeats.get(frame).copied())
+            .unwrap_or(false)
+    }
+}
+
+/// The frame rate `source` is placed in time by.
+fn source_fps(source: &Source, options: &ProcessOptions) -> f64 {
+    source.fps.or(options.framerate).unwrap_or(30.0)
+}
+
+impl Default for ProcessOptions {
+    fn default() -> Self {
+        let settings = settings::current();
+        ProcessOptions {
+            frame_format: settings.frame_format,
+            threshold: settings.threshold,
+            exit_threshold: settings.exit_threshold,
+            metric: settings.metric,
+            color_space: settings.color_space,
+            comparison_window: 1,
+            analysis_step: None,
+            merge_gap: 0,
+            representative: None,
+            blur_threshold: None,
+            glitches: None,
+            script: None,
+            plugins: Vec::new(),
+            codec: settings.codec,
+            mezzanine: Profiles::default(),
+            target_bitrate: None,
+            target_size_mb: None,
+            framerate: None,
+            retime: None,
+            output: settings.output,
+            image_sequence: None,
+            cut_list: None,
+            smart_cut: false,
+            timestamp_map: false,
+            verify_quality: false,
+            test_encode: false,
+            keep_removed: false,
+            export_removed: None,
+            hardware_encode: false,
+            hardware_decode: false,
+            low_memory: false,
+            deterministic: false,
+            reuse_analysis: true,
+            force_8bit: false,
+            deinterlace: Deinterlace::Auto,
+            crop: CropMode::Off,
+            video_stream_index: None,
+            cache_remote: false,
+            sections: Vec::new(),
+            timelapse: None,
+            encoder_duplicates: false,
+            preset: None,
         }
     }
-    cached.clone().unwrap()
 }
 
-fn collect_files(path: &Path) -> Vec<PathBuf> {
+fn collect_files(path: &Path, extension: &str) -> Vec<PathBuf> {
     if !path.exists() {
         return Vec::new();
     }
 
     if path.is_file() {
         if let Some(ext) = path.extension() {
-            if ext == "png" {
+            if ext == extension {
                 return vec![path.to_path_buf()];
             }
         }
@@ -82,9 +780,9 @@ fn collect_files(path: &Path) -> Vec<PathBuf> {
                     if let Ok(entry) = entry {
                         let path = entry.path();
                         if path.is_dir() {
-                            collect_files(&path)
+                            collect_files(&path, extension)
                         } else if path.is_file()
-                            && path.extension().map_or(false, |ext| ext == "png")
+                            && path.extension().is_some_and(|ext| ext == extension)
                         {
                             vec![path]
                         } else {
@@ -100,198 +798,3059 @@ fn collect_files(path: &Path) -> Vec<PathBuf> {
     }
 }
 
-fn stitch_frames_into_video(folder: &str, output_file: &str) {
-    let ffmpeg_path = get_ffmpeg_path();
-
-    let input_pattern = format!("{}/frame_%04d.png", folder);
-
-    let status = Command::new(ffmpeg_path)
-        .args(&[
-            "-framerate",
-            "30",
-            "-i",
-            &input_pattern,
-            "-c:v",
-            "libx264",
-            "-preset",
-            "fast",
-            "-threads",
-            "0",
-            "-pix_fmt",
-            "yuv420p",
-            output_file,
-        ])
-        .status()
-        .expect("Failed to stitch frames into video");
-
-    if !status.success() {
-        eprintln!("FFmpeg failed to stitch video");
-    }
-}
-
-fn generate_frames(input_file: &str) -> (String, tempfile::TempDir) {
-    let temp_dir = tempdir().expect("Failed to create temp directory");
-    let output_pattern = temp_dir.path().join("frame_%04d.png");
-    let ffmpeg_path = get_ffmpeg_path();
-
-    let output_pattern_str = output_pattern.to_str().unwrap();
-
-    Command::new(ffmpeg_path)
-        .args(&["-i", input_file, output_pattern_str])
-        .args(["-threads", "0"])
-        .output()
-        .expect("Failed to execute ffmpeg");
-
-    (
-        output_pattern
-            .parent()
-            .unwrap()
-            .to_str()
-            .unwrap()
-            .to_string(),
-        temp_dir,
-    )
+/// Accelerators decoding may use on this platform, in order of preference.
+#[cfg(windows)]
+const HWACCELS: &[&str] = &["cuda", "d3d11va"];
+#[cfg(target_os = "macos")]
+const HWACCELS: &[&str] = &["videotoolbox"];
+#[cfg(not(any(windows, target_os = "macos")))]
+const HWACCELS: &[&str] = &["cuda", "vaapi"];
+
+/// The accelerator to decode with, when `options` ask for hardware
+/// decoding and ffmpeg has one for this system.
+fn hardware_decoder(options: &ProcessOptions) -> Option<&'static str> {
+    if !options.hardware_decode || options.deterministic {
+        return None;
+    }
+    let capabilities = capabilities::get_ffmpeg_capabilities().unwrap_or_default();
+    let hwaccel = HWACCELS
+        .iter()
+        .copied()
+        .find(|&name| capabilities.has_hwaccel(name));
+    match hwaccel {
+        Some(name) => info!("Decoding with {}", name),
+        None => info!("ffmpeg has no hardware decoder for this system, decoding in software"),
+    }
+    hwaccel
+}
+
+/// The NVENC encoder to encode with, when `options` ask for hardware
+/// encoding and the codec and ffmpeg support it.
+fn hardware_encoder(options: &ProcessOptions) -> Option<&'static str> {
+    if !options.hardware_encode || options.image_sequence.is_some() {
+        return None;
+    }
+    if options.deterministic {
+        info!("Deterministic encodes run in software");
+        return None;
+    }
+    if options.target_bitrate.is_some() {
+        info!("Two-pass encodes run in software");
+        return None;
+    }
+    let encoder = options.codec.nvenc_encoder()?;
+    let available = capabilities::get_ffmpeg_capabilities()
+        .map(|capabilities| capabilities.has_encoder(encoder))
+        .unwrap_or(false);
+    if !available {
+        info!("ffmpeg has no {}, encoding in software", encoder);
+        return None;
+    }
+    Some(encoder)
+}
+
+/// Encodes the `frame_<number>.<ext>` files numbered with `digits` digits,
+/// or the kept y4m stream, in `folder` into `output_file`.
+fn stitch_frames_into_video(
+    folder: &Path,
+    extension: &str,
+    digits: usize,
+    source: &Source,
+    options: &ProcessOptions,
+    output_file: &Path,
+    control: &JobControl,
+) -> Result<(), ProcessError> {
+    control.report(Stage::Encoding, 0, 0);
+    let hardware = hardware_encoder(options);
+    // a hardware encode barely loads the CPU, so it takes a GPU session
+    // rather than an encode slot
+    let gpu = hardware.map(|_| concurrency::acquire_gpu_session());
+    let _slot = gpu.is_none().then(concurrency::acquire_encode_slot);
+    let mut threads = concurrency::thread_count();
+    if options.low_memory {
+        threads = threads.min(concurrency::LOW_MEMORY_THREADS);
+    }
+    if options.deterministic {
+        threads = concurrency::DETERMINISTIC_THREADS;
+    }
+    let threads = threads.to_string();
+    // image frames are RGB, converted from and back to YUV with the source's
+    // matrix and range
+    let y4m = extension == FrameFormat::Y4m.extension();
+    // retimed audio goes into videos only
+    let audio = source
+        .audio
+        .as_ref()
+        .filter(|_| options.image_sequence.is_none())
+        .zip(options.codec.audio_encoder());
+    let filter = source
+        .color
+        .as_ref()
+        .and_then(|color| match options.image_sequence {
+            Some(_) if y4m => color.decode_filter(),
+            None if !y4m && !options.codec.is_animation() => color.encode_filter(),
+            _ => None,
+        });
+    let target_bitrate = options
+        .target_bitrate
+        .filter(|_| options.image_sequence.is_none());
+    let passlog = folder.join(PASSLOG);
+    // `pass` is the pass of a two-pass encode, of which the first only
+    // writes statistics
+    let encode = |pass: Option<u8>| {
+        let first_pass = pass == Some(1);
+        supervisor::run_reporting(
+            || {
+                let mut command = ffmpeg::command()?;
+                if y4m {
+                    // the y4m header carries the frame rate unless it is overridden
+                    if let Some(fps) = options.output_framerate() {
+                        command.args(["-r", &fps.to_string()]);
+                    }
+                    command
+                        .arg("-i")
+                        .arg(paths::ffmpeg_arg(folder.join(KEPT_Y4M)));
+                } else {
+                    let input_pattern = folder.join(sequence::frame_pattern(digits, extension));
+                    let fps = options
+                        .output_framerate()
+                        .or(source.fps)
+                        .unwrap_or(30.0)
+                        .to_string();
+                    command
+                        .args(["-framerate", &fps, "-i"])
+                        .arg(paths::ffmpeg_arg(input_pattern));
+                }
+                if let Some((audio, encoder)) = audio.filter(|_| !first_pass) {
+                    command
+                        .arg("-i")
+                        .arg(paths::ffmpeg_arg(&audio.input))
+                        // sources without audio are fine
+                        .args(["-map", "0:v", "-map", "1:a:0?"])
+                        .args(["-af", &audio.filter(), "-c:a", encoder]);
+                }
+                command.arg("-y");
+                if options.deterministic {
+                    command.args(BITEXACT_ARGS);
+                }
+                if let Some(filter) = &filter {
+                    command.args(["-vf", filter]);
+                }
+                match options.image_sequence {
+                    // `output_file` is the numbered file pattern in this case
+                    Some(sequence) => command
+                        .args(sequence.encoder_args())
+                        .args(["-threads", &threads, "-start_number", "1"])
+                        .arg(paths::ffmpeg_arg(output_file)),
+                    None => {
+                        match (hardware, &gpu) {
+                            (Some(encoder), Some(gpu)) => command.args([
+                                "-c:v",
+                                encoder,
+                                "-preset",
+                                "p4",
+                                "-gpu",
+                                &gpu.gpu().to_string(),
+                            ]),
+                            _ => command
+                                .args(options.codec.encoder_args())
+                                .args(options.codec.profile_args(&options.mezzanine))
+                                .args(["-threads", &threads]),
+                        };
+                        let mut encoder_args = Vec::new();
+                        if let (Some(kbps), Some(pass)) = (target_bitrate, pass) {
+                            encoder_args = options.codec.two_pass_args(kbps, pass, &passlog);
+                        }
+                        let pixel_format = match hardware {
+                            Some(_) => {
+                                Some(options.codec.nvenc_pixel_format(source.pixel_format).into())
+                            }
+                            None => options
+                                .codec
+                                .pixel_format(source.pixel_format, &options.mezzanine),
+                        };
+                        if let Some(pixel_format) = pixel_format {
+                            command.args(["-pix_fmt", &pixel_format]);
+                        }
+                        if source.pixel_format.is_some_and(|format| format.alpha)
+                            && (hardware.is_some()
+                                || !options.codec.keeps_alpha(&options.mezzanine))
+                        {
+                            warn!(
+                                "{} drops the source's transparency; prores 4444, ffv1, webp, apng and gif keep it",
+                                hardware.unwrap_or(options.codec.encoder())
+                            );
+                        }
+                        if let Some(color) = &source.color {
+                            if options.codec.is_animation() {
+                                if color.is_hdr() {
+                                    warn!(
+                                        "{} cannot carry HDR, the output will look washed out",
+                                        options.codec.extension()
+                                    );
+                                }
+                            } else {
+                                encoder_args.extend(
+                                    color
+                                        .encoder_args(hardware.unwrap_or(options.codec.encoder()))
+                                        .into_iter()
+                                        .map(OsString::from),
+                                );
+                            }
+                        }
+                        command.args(merge_x265_params(encoder_args));
+                        if first_pass {
+                            command.args(["-an", "-f", "null", "-"])
+                        } else {
+                            // animations keep the source's looping, or loop forever
+                            command
+                                .args(options.codec.loop_args(source.plays.unwrap_or(0)))
+                                .arg(paths::ffmpeg_arg(output_file))
+                        }
+                    }
+                };
+                Ok(command)
+            },
+            control,
+            Stage::Encoding,
+            None,
+        )
+    };
+    let result = match target_bitrate {
+        Some(kbps) => {
+            info!("Encoding at {} kbit/s in two passes", kbps);
+            let result = encode(Some(1)).and_then(|_| encode(Some(2)));
+            remove_passlogs(folder);
+            result
+        }
+        None => encode(None),
+    };
+
+    let context = if options.image_sequence.is_some() {
+        "Failed to write image sequence"
+    } else {
+        "Failed to stitch video"
+    };
+    result
+        .map(|_| ())
+        .map_err(|e| ProcessError::ffmpeg(context, e))
+}
+
+/// Keeps ffmpeg's version out of the muxed and encoded streams and the
+/// muxers from writing random IDs, such as Matroska's segment UID.
+const BITEXACT_ARGS: [&str; 6] = [
+    "-fflags",
+    "+bitexact",
+    "-flags:v",
+    "+bitexact",
+    "-flags:a",
+    "+bitexact",
+];
+
+/// `args` with the values of every `-x265-params` joined into the first, as
+/// ffmpeg only keeps the last.
+fn merge_x265_params(args: Vec<OsString>) -> Vec<OsString> {
+    let mut merged: Vec<OsString> = Vec::with_capacity(args.len());
+    let mut params = None;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg != "-x265-params" {
+            merged.push(arg);
+            continue;
+        }
+        let Some(value) = args.next() else {
+            break;
+        };
+        match params {
+            Some(index) => {
+                let existing: &mut OsString = &mut merged[index];
+                existing.push(":");
+                existing.push(value);
+            }
+            None => {
+                merged.push(arg);
+                params = Some(merged.len());
+                merged.push(value);
+            }
+        }
+    }
+    merged
+}
+
+/// Deletes the statistics a two-pass encode left in `folder`.
+fn remove_passlogs(folder: &Path) {
+    for entry in fs::read_dir(folder).into_iter().flatten().flatten() {
+        if entry.file_name().to_string_lossy().starts_with(PASSLOG) {
+            let _ = fs::remove_file(entry.path());
+        }
+    }
+}
+
+/// What extraction and the encoder need to know about the source.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct Source {
+    /// The video stream the frames come from; see
+    /// [`ProcessOptions::video_stream_index`].
+    pub stream: usize,
+    /// Frame rate, where known.
+    pub fps: Option<f64>,
+    /// How often an animated source plays; see [`animation::plays`].
+    pub plays: Option<u16>,
+    /// Bit depth and subsampling of the frames kept from the source, where
+    /// known; see [`kept_pixel_format`].
+    pub pixel_format: Option<PixelFormat>,
+    /// Colour signalling the output is tagged with; see [`color`].
+    pub color: Option<ColorInfo>,
+    /// Filter deinterlacing the frames on extraction; see [`Deinterlace`].
+    pub deinterlace: Option<String>,
+    /// Black bars cropped from the frames on extraction; see [`crop`].
+    pub crop: Option<CropRect>,
+    /// Black bars the frames still have, left out of the comparison.
+    pub compare_crop: Option<CropRect>,
+    /// Whether each frame is a repeat the encoder wrote, when only those
+    /// are removed; see [`ProcessOptions::encoder_duplicates`].
+    #[serde(skip)]
+    pub repeats: Option<Vec<bool>>,
+    /// Audio encoded along with the kept frames; see [`retimed_audio`].
+    #[serde(skip)]
+    pub audio: Option<Audio>,
+    /// Accelerator the frames are decoded with on extraction; see
+    /// [`ProcessOptions::hardware_decode`].
+    pub hwaccel: Option<String>,
+}
+
+impl Source {
+    /// The filters frames extracted as `format` go through: deinterlacing,
+    /// cropping, then the colour conversion.
+    fn extraction_filter(&self, format: FrameFormat) -> Option<String> {
+        let color = self.color.as_ref().and_then(|color| match format {
+            FrameFormat::Y4m => color.range_filter(),
+            _ => color.decode_filter(),
+        });
+        let filters: Vec<String> = self
+            .deinterlace
+            .iter()
+            .cloned()
+            .chain(self.crop.map(|crop| crop.filter()))
+            .chain(color)
+            .collect();
+        (!filters.is_empty()).then(|| filters.join(","))
+    }
+}
+
+/// Probes `input_file` for what the encoder needs to know when its frames
+/// are extracted as `format`. The frame rate is left to the extraction.
+fn probe_source(
+    input_file: &Path,
+    format: FrameFormat,
+    plays: Option<u16>,
+    options: &ProcessOptions,
+) -> Source {
+    // a frame is decoded, as containers like Matroska leave the HDR
+    // metadata to the bitstream
+    let stderr = supervisor::run(|| {
+        let mut command = ffmpeg::command()?;
+        command.arg("-i").arg(paths::ffmpeg_arg(input_file)).args([
+            "-map",
+            &streams::map(options.video_stream()),
+            "-frames:v",
+            "1",
+            "-vf",
+            "showinfo",
+            "-f",
+            "null",
+            "-",
+        ]);
+        Ok(command)
+    })
+    .map(|output| output.stderr)
+    .unwrap_or_default();
+    let color = color::reported(&stderr);
+    if color.as_ref().is_some_and(ColorInfo::is_hdr) {
+        info!("HDR source, passing its colour metadata on to the encoder");
+    }
+    let stream = options.video_stream();
+    let interlaced = reported_interlaced(&stderr, stream);
+    let deinterlace = options.deinterlace.filter(interlaced);
+    if let Some(filter) = deinterlace {
+        info!("Deinterlacing frames with {}", filter);
+    }
+    let bars = match options.crop {
+        CropMode::Off => None,
+        CropMode::Compare | CropMode::Output => crop::detect(input_file, stream),
+    };
+    let (crop, compare_crop) = match options.crop {
+        CropMode::Output => (bars, None),
+        _ => (None, bars),
+    };
+    Source {
+        stream,
+        fps: reported_fps(&stderr, stream),
+        plays,
+        pixel_format: kept_pixel_format(pixel_format::reported(&stderr, stream), format, options),
+        color,
+        deinterlace: deinterlace.map(String::from),
+        crop,
+        compare_crop,
+        repeats: None,
+        audio: None,
+        hwaccel: hardware_decoder(options).map(String::from),
+    }
+}
+
+/// Finds the frames the encoder repeated in `source`, when only those are
+/// removed.
+fn find_repeats(
+    input_file: &Path,
+    source: &mut Source,
+    options: &ProcessOptions,
+    control: &JobControl,
+) -> Result<(), ProcessError> {
+    if !options.encoder_duplicates {
+        return Ok(());
+    }
+    info!("Looking for frames the encoder repeated");
+    source.repeats = Some(duplicates::detect(
+        input_file,
+        source.stream,
+        source_fps(source, options),
+        source.deinterlace.as_deref(),
+        control,
+    )?);
+    Ok(())
+}
+
+/// Whether video stream `stream` ffmpeg reported in `stderr` has an
+/// interlaced field order, such as `top first`, rather than `progressive`
+/// or none.
+fn reported_interlaced(stderr: &str, stream: usize) -> bool {
+    streams::reported_line(stderr, stream).is_some_and(|line| line.contains(" first"))
+}
+
+/// The pixel format frames of the `probed` format keep when extracted as
+/// `format`: the same, unless `force_8bit` or an 8-bit frame format drops
+/// its depth, or a frame format without alpha its transparency.
+fn kept_pixel_format(
+    probed: Option<PixelFormat>,
+    format: FrameFormat,
+    options: &ProcessOptions,
+) -> Option<PixelFormat> {
+    let mut probed = probed?;
+    if probed.alpha && !format.keeps_alpha() {
+        warn!(
+            "{} frames have no alpha channel, so the source's transparency is dropped",
+            format.extension()
+        );
+        probed = probed.opaque();
+    }
+    if !probed.is_high_depth() {
+        return Some(probed);
+    }
+    if options.force_8bit {
+        return Some(probed.eight_bit());
+    }
+    if !format.keeps_high_depth() {
+        warn!(
+            "{} frames are 8-bit, so the {}-bit source is encoded at 8 bits",
+            format.extension(),
+            probed.bit_depth
+        );
+        return Some(probed.eight_bit());
+    }
+    debug!("Keeping the source's {} samples", probed.yuv_name());
+    Some(probed)
+}
+
+/// The frame rate of video stream `stream` ffmpeg reported in `stderr`.
+fn reported_fps(stderr: &str, stream: usize) -> Option<f64> {
+    streams::reported_line(stderr, stream)?
+        .split(", ")
+        .find_map(|part| part.strip_suffix(" fps")?.parse().ok())
+}
+
+/// Name of the extracted stream when frames are stored as y4m.
+const FRAMES_Y4M: &str = "frames.y4m";
+/// Name of the y4m stream holding only the frames that survived analysis.
+const KEPT_Y4M: &str = "kept.y4m";
+/// Prefix of the statistics files of a two-pass encode.
+const PASSLOG: &str = "passlog";
+
+fn generate_frames(
+    input_file: &Path,
+    format: FrameFormat,
+    source: &Source,
+    dir: &Path,
+    control: &JobControl,
+) -> Result<Option<f64>, ProcessError> {
+    control.report(Stage::Extracting, 0, 0);
+    let output_pattern = if format == FrameFormat::Y4m {
+        dir.join(FRAMES_Y4M)
+    } else {
+        dir.join(sequence::frame_pattern(
+            sequence::FRAME_DIGITS,
+            format.extension(),
+        ))
+    };
+
+    let threads = concurrency::thread_count().to_string();
+    let filter = source.extraction_filter(format);
+    let extract = |hwaccel: Option<&str>| {
+        supervisor::run_reporting(
+            || {
+                let mut command = ffmpeg::command()?;
+                if let Some(hwaccel) = hwaccel {
+                    command.args(["-hwaccel", hwaccel]);
+                }
+                command
+                    .args(["-threads", &threads, "-i"])
+                    .arg(paths::ffmpeg_arg(input_file))
+                    .args(["-map", &streams::map(source.stream)]);
+                if let Some(filter) = &filter {
+                    command.args(["-vf", filter]);
+                }
+                command
+                    .args(format.encoder_args(source.pixel_format))
+                    .arg(paths::ffmpeg_arg(&output_pattern));
+                Ok(command)
+            },
+            control,
+            Stage::Extracting,
+            None,
+        )
+    };
+    let result = match extract(source.hwaccel.as_deref()) {
+        Err(e)
+            if source.hwaccel.is_some()
+                && !matches!(e, RunError::Cancelled | RunError::Unavailable(_)) =>
+        {
+            warn!("Hardware decoding failed, decoding in software: {}", e);
+            extract(None)
+        }
+        result => result,
+    };
+    let output = result.map_err(|e| ProcessError::ffmpeg("Failed to extract frames", e))?;
+
+    Ok(reported_fps(&output.stderr, source.stream))
+}
+
+/// The planes of the image at `path` in `color_space`, within `crop` if
+/// given.
+fn load_planes(
+    path: &Path,
+    crop: Option<CropRect>,
+    color_space: ColorSpace,
+) -> Result<Planes, Box<dyn std::error::Error>> {
+    let mut image =
+        image::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    if let Some(crop) = crop {
+        image = image.crop_imm(crop.x, crop.y, crop.width, crop.height);
+    }
+    Ok(Planes::in_color_space(&image, color_space))
+}
+
+/// The pixel format of the image at `path`, which the frames of an image
+/// sequence share.
+fn image_pixel_format(path: &Path) -> Option<PixelFormat> {
+    use image::ImageDecoder;
+
+    let color = image::ImageReader::open(path)
+        .ok()?
+        .with_guessed_format()
+        .ok()?
+        .into_decoder()
+        .ok()?
+        .color_type();
+    Some(PixelFormat {
+        bit_depth: if color.bytes_per_pixel() > color.channel_count() {
+            16
+        } else {
+            8
+        },
+        chroma: Chroma::Yuv444,
+        alpha: color.has_alpha(),
+    })
+}
+
+/// Similarity of every frame to its successor, in frame order.
+///
+/// Frames are split into runs of `batch_size` pairs that are scored in
+/// parallel. Within a run each frame is decoded once and its luma buffer is
+/// carried forward to the next pair; only the first frame of each run is
+/// decoded a second time, by the run before it.
+///
+/// Each frame's fate is passed to `control` as soon as it is scored. Under
+/// hysteresis that can depend on the frames of the run before, so frames
+/// at the start of a run that score between the two thresholds wait until
+/// every run is done. On cancellation runs stop early and the scores are
+/// incomplete; callers check `control` afterwards. Only the part of the
+/// frames within `crop` is compared, if given. Frames with a score in
+/// `known` are not compared again, nor decoded unless the next frame is.
+fn score_consecutive_frames(
+    frames: &[PathBuf],
+    known: &[Option<f32>],
+    batch_size: usize,
+    detection: &Detection,
+    crop: Option<CropRect>,
+    control: &JobControl,
+) -> Vec<f32> {
+    let pair_count = frames.len().saturating_sub(1);
+    control.report(Stage::Analyzing, 0, pair_count);
+    let done = AtomicUsize::new(0);
+    let color_space = detection.options.color_space;
+    let run_starts: Vec<usize> = (0..pair_count).step_by(batch_size.max(1)).collect();
+
+    let runs: Vec<(Vec<f32>, Vec<usize>)> = run_starts
+        .par_iter()
+        .map(|&start| {
+            let end = (start + batch_size).min(pair_count);
+            // the frame being scored, once decoded
+            let mut previous: Option<Option<Planes>> = None;
+            let mut run_scores = Vec::with_capacity(end - start);
+            let mut undecided = Vec::new();
+            let mut dead = (start == 0).then_some(false);
+            for frame in start..end {
+                control.wait_while_paused();
+                if control.cancel.is_cancelled() {
+                    break;
+                }
+                let score = match known.get(frame).copied().flatten() {
+                    Some(score) => {
+                        previous = None;
+                        score
+                    }
+                    None => {
+                        let prev = previous
+                            .take()
+                            .unwrap_or_else(|| load_planes(&frames[frame], crop, color_space).ok());
+                        let current = load_planes(&frames[frame + 1], crop, color_space).ok();
+                        let score = match (&prev, &current) {
+                            (Some(prev), Some(cur)) => {
+                                similarity::score_planes(detection.metric(frame), prev, cur)
+                                    .unwrap_or(0.0)
+                            }
+                            _ => 0.0,
+                        };
+                        previous = Some(current);
+                        score
+                    }
+                };
+                dead = detection.is_dead(frame, score, dead);
+                match dead {
+                    Some(removed) => control.decide(FrameDecision {
+                        frame,
+                        score,
+                        removed,
+                    }),
+                    None => undecided.push(frame),
+                }
+                run_scores.push(score);
+                let done = done.fetch_add(1, Ordering::Relaxed) + 1;
+                control.report(Stage::Analyzing, done, pair_count);
+            }
+            (run_scores, undecided)
+        })
+        .collect();
+
+    let (runs, undecided): (Vec<_>, Vec<_>) = runs.into_iter().unzip();
+    let scores = runs.concat();
+    let undecided = undecided.concat();
+    if !undecided.is_empty() {
+        let dead = dead_frames(&scores, detection);
+        for frame in undecided {
+            control.decide(FrameDecision {
+                frame,
+                score: scores[frame],
+                removed: dead[frame],
+            });
+        }
+    }
+    control.flush_decisions();
+
+    scores
 }
 
-fn compare_images_ssim_ffmpeg(image1: &str, image2: &str) -> f32 {
-    let output = Command::new(get_ffmpeg_path())
-        .arg("-i")
-        .arg(image1)
-        .arg("-i")
-        .arg(image2)
-        .arg("-filter_complex")
-        .arg("ssim")
-        .arg("-f")
-        .arg("null")
-        .stderr(Stdio::piped())
-        .output()
-        .expect("Failed to execute FFmpeg");
-
-    let result = String::from_utf8_lossy(&output.stderr);
-
-    // Parse the SSIM score from FFmpeg output
-    // SSIM output looks like: "SSIM: All: 0.978"
-    if let Some(ssim_value) = result.split("All: ").nth(1) {
-        let ssim_score: f32 = ssim_value
-            .split_whitespace()
-            .next()
-            .unwrap_or("0")
-            .parse()
-            .unwrap_or(0.0);
-        return ssim_score;
-    }
-
-    0.0
-}
-
-fn compare_images_ssim_crate(
-    image1: &str,
-    image2: &str,
-) -> Result<f32, Box<dyn std::error::Error>> {
-    let image1 = image::open(image1).map_err(|e| format!("Failed to open first image: {}", e))?;
-    let image2 = image::open(image2).map_err(|e| format!("Failed to open second image: {}", e))?;
-
-    let grey1 = image1.to_luma8();
-    let grey2 = image2.to_luma8();
-
-    if grey1.dimensions() != grey2.dimensions() {
-        return Err("images are different dimensions".into());
-    }
-
-    let (width, height) = grey1.dimensions();
-
-    let ssim_sum: f32 = (0..height)
-        .into_par_iter()
-        .map(|y| {
-            let k1 = 0.01;
-            let k2 = 0.03;
-            let l = 255.0;
-            let c1 = (k1 * l as f32).powi(2);
-            let c2 = (k2 * l as f32).powi(2);
-
-            let mut row_sum = 0.0;
-            for x in 0..width {
-                let p1 = grey1.get_pixel(x, y)[0] as f32;
-                let p2 = grey2.get_pixel(x, y)[0] as f32;
-
-                //means
-                let mu1 = p1;
-                let mu2 = p2;
-
-                //variance and covariance
-                let sigma1_sq = (p1 - mu1).powi(2);
-                let sigma2_sq = (p2 - mu2).powi(2);
-                let sigma12 = (p1 - mu1) * (p2 - mu2);
-
-                //calculate ssim
-                let num = (2.0 * mu1 * mu2 + c1) * (2.0 * sigma12 + c2);
-                let den = (mu1.powi(2) + mu2.powi(2) + c1) * (sigma1_sq + sigma2_sq + c2);
-                row_sum += num / den;
-            }
-            row_sum
+/// The scores of the frames comparing every `step`th frame settles, for
+/// [`score_consecutive_frames`] to take as known; see [`coarse`]. Samples
+/// are compared a pair at a time in parallel, stopping early on
+/// cancellation.
+fn score_samples(
+    frames: &[PathBuf],
+    step: usize,
+    detection: &Detection,
+    crop: Option<CropRect>,
+    control: &JobControl,
+) -> Vec<Option<f32>> {
+    let samples = coarse::samples(frames.len(), step);
+    let gap_count = samples.len().saturating_sub(1);
+    control.report(Stage::Analyzing, 0, gap_count);
+    let done = AtomicUsize::new(0);
+    let gaps: Vec<(f32, Option<bool>)> = samples
+        .par_windows(2)
+        .map(|pair| {
+            control.wait_while_paused();
+            if control.cancel.is_cancelled() {
+                return (0.0, None);
+            }
+            let (first, second) = (pair[0], pair[1]);
+            let score = match (
+                load_planes(&frames[first], crop, detection.options.color_space),
+                load_planes(&frames[second], crop, detection.options.color_space),
+            ) {
+                (Ok(first_planes), Ok(second_planes)) => {
+                    similarity::score_planes(detection.metric(first), &first_planes, &second_planes)
+                        .unwrap_or(0.0)
+                }
+                _ => 0.0,
+            };
+            let done = done.fetch_add(1, Ordering::Relaxed) + 1;
+            control.report(Stage::Analyzing, done, gap_count);
+            (score, detection.is_dead(first, score, None))
         })
-        .sum();
+        .collect();
+    let known = coarse::known_scores(frames.len(), step, &gaps);
+    info!(
+        "Sampled every {} frames, comparing {} of {} frames one by one",
+        step,
+        known.iter().filter(|score| score.is_none()).count(),
+        known.len()
+    );
+    known
+}
 
-    let ssim = ssim_sum / ((width * height) as f32);
-    Ok(ssim)
+/// Whether each scored frame is dead, deciding them in order so that
+/// hysteresis carries from one to the next.
+fn dead_frames(scores: &[f32], detection: &Detection) -> Vec<bool> {
+    let mut dead = false;
+    scores
+        .iter()
+        .enumerate()
+        .map(|(index, &score)| {
+            let frame = index + detection.first_scored();
+            dead = detection.is_dead(frame, score, Some(dead)).unwrap_or(dead);
+            dead
+        })
+        .collect()
 }
 
-pub async fn process_video(input_file: &str) {
-    let (frames_folder, _temp_dir) = generate_frames(input_file);
-    let frames_vec: Vec<PathBuf> = collect_files(Path::new(&frames_folder));
+/// The last frames kept, most recent first, that a frame is compared with
+/// under a comparison window.
+struct KeptFrames<T> {
+    frames: VecDeque<T>,
+    size: usize,
+}
 
-    // Define batch size for comparing frames
-    let batch_size = 10; // Adjust this based on your system's capabilities
-    let bad_frames = Arc::new(Mutex::new(Vec::with_capacity(frames_vec.len())));
+impl<T> KeptFrames<T> {
+    fn new(size: usize) -> KeptFrames<T> {
+        KeptFrames {
+            frames: VecDeque::with_capacity(size),
+            size,
+        }
+    }
 
-    // Process frames in batches
-    frames_vec.par_chunks(batch_size).for_each(|chunk| {
-        // Local vector to store results for this batch
-        let mut local_results = Vec::with_capacity(chunk.len());
+    /// The score of a frame against the kept frame it matches under
+    /// `threshold`, or the best of them when it matches none. The first
+    /// frame has nothing to match and scores 0.0.
+    fn score(&self, threshold: f32, score: impl Fn(&T) -> f32) -> f32 {
+        let mut best = 0.0f32;
+        for kept in &self.frames {
+            best = best.max(score(kept));
+            if best > threshold {
+                break;
+            }
+        }
+        best
+    }
 
-        // Compare each frame with the next one within this batch
-        for i in 0..chunk.len().saturating_sub(1) {
-            let image1 = &chunk[i];
-            let image2 = &chunk[i + 1];
-            let score =
-                compare_images_ssim_crate(&image1.to_string_lossy(), &image2.to_string_lossy())
-                    .unwrap_or(0.0);
-            local_results.push(score > 0.95);
+    fn push(&mut self, frame: T) {
+        if self.frames.len() == self.size {
+            self.frames.pop_back();
         }
+        self.frames.push_front(frame);
+    }
+}
 
-        // Last frame in batch can't be compared within batch
-        if !chunk.is_empty() && chunk.len() < batch_size {
-            local_results.push(false);
+/// Similarity of every frame after the first to the last
+/// `comparison_window` frames kept before it; see [`KeptFrames::score`].
+///
+/// Which frames are kept depends on every decision before, so frames are
+/// decided in order. They are decoded ahead in parallel, `batch_size` at a
+/// time. Decisions go to `control` as they are made and cancellation leaves
+/// the scores incomplete, as in [`score_consecutive_frames`].
+fn score_against_kept_frames(
+    frames: &[PathBuf],
+    batch_size: usize,
+    detection: &Detection,
+    crop: Option<CropRect>,
+    control: &JobControl,
+) -> Vec<f32> {
+    let Some((first, rest)) = frames.split_first() else {
+        return Vec::new();
+    };
+    control.report(Stage::Analyzing, 0, rest.len());
+    let mut window = KeptFrames::new(detection.window());
+    let color_space = detection.options.color_space;
+    if let Ok(planes) = load_planes(first, crop, color_space) {
+        window.push(planes);
+    }
+    let mut scores = Vec::with_capacity(rest.len());
+    let mut dead = false;
+    for batch in rest.chunks(batch_size.max(1)) {
+        control.wait_while_paused();
+        if control.cancel.is_cancelled() {
+            break;
+        }
+        let decoded: Vec<Option<Planes>> = batch
+            .par_iter()
+            .map(|frame| load_planes(frame, crop, color_space).ok())
+            .collect();
+        for current in decoded {
+            let frame = scores.len() + 1;
+            let metric = detection.metric(frame);
+            let score = match &current {
+                Some(current) => window.score(detection.threshold(frame), |kept| {
+                    similarity::score_planes(metric, kept, current).unwrap_or(0.0)
+                }),
+                None => 0.0,
+            };
+            dead = detection.is_dead(frame, score, Some(dead)).unwrap_or(dead);
+            control.decide(FrameDecision {
+                frame,
+                score,
+                removed: dead,
+            });
+            scores.push(score);
+            if let (false, Some(current)) = (dead, current) {
+                window.push(current);
+            }
         }
+        control.report(Stage::Analyzing, scores.len(), rest.len());
+    }
+    control.flush_decisions();
 
-        // Add local results to overall results
-        let mut bad_frames_guard = bad_frames.lock().unwrap();
-        bad_frames_guard.extend(local_results);
-    });
+    scores
+}
+
+/// Streams the extracted y4m through the comparison and writes the frames
+/// that are kept to [`KEPT_Y4M`]. Returns the score of every frame against
+/// its successor, stopping early on cancellation.
+fn remove_dead_frames_y4m(
+    folder: &Path,
+    detection: &Detection,
+    crop: Option<CropRect>,
+    control: &JobControl,
+) -> std::io::Result<Vec<f32>> {
+    let input = File::open(folder.join(FRAMES_Y4M))?;
+    let stream_len = input.metadata()?.len() as usize;
+    let reader = y4m::Y4mReader::new(BufReader::new(input))?;
+    // every frame is its planes behind a bare "FRAME\n" marker
+    let pair_count =
+        ((stream_len - reader.header().len()) / (reader.frame_size() + 6)).saturating_sub(1);
+    control.report(Stage::Analyzing, 0, pair_count);
+    filter_y4m(
+        reader,
+        Some(pair_count),
+        &folder.join(KEPT_Y4M),
+        detection,
+        crop,
+        control,
+    )
+}
+
+/// Compares the frames of `reader` in order and writes those kept to
+/// `kept`. Progress is reported against `pair_count` when it is known;
+/// for streamed input ffmpeg's frame counter is reported instead.
+fn filter_y4m<R: BufRead>(
+    mut reader: y4m::Y4mReader<R>,
+    pair_count: Option<usize>,
+    kept: &Path,
+    detection: &Detection,
+    crop: Option<CropRect>,
+    control: &JobControl,
+) -> std::io::Result<Vec<f32>> {
+    let mut output = BufWriter::new(File::create(kept)?);
+    output.write_all(reader.header())?;
+    if detection.windowed() {
+        return filter_y4m_against_kept(reader, pair_count, output, detection, crop, control);
+    }
+
+    // A frame is dead when it matches its successor, so each frame is held
+    // back until the next one has been read.
+    let mut previous: Option<(Vec<u8>, GrayImage)> = None;
+    let mut scores = Vec::new();
+    let mut dead = false;
+    while let Some(frame) = reader.next_frame()? {
+        control.wait_while_paused();
+        if control.cancel.is_cancelled() {
+            return Ok(scores);
+        }
+        let mut luma = reader.luma(&frame);
+        if let Some(crop) = crop {
+            luma = imageops::crop_imm(&luma, crop.x, crop.y, crop.width, crop.height).to_image();
+        }
+        if let Some((prev_frame, prev_luma)) = previous {
+            let frame = scores.len();
+            let score =
+                similarity::score(detection.metric(frame), &prev_luma, &luma).unwrap_or(0.0);
+            dead = detection.is_dead(frame, score, Some(dead)).unwrap_or(dead);
+            if !dead {
+                y4m::write_frame(&mut output, &prev_frame)?;
+            }
+            control.decide(FrameDecision {
+                frame,
+                score,
+                removed: dead,
+            });
+            scores.push(score);
+            if let Some(pair_count) = pair_count {
+                control.report(Stage::Analyzing, scores.len(), pair_count);
+            }
+        }
+        previous = Some((frame, luma));
+    }
+    if let Some((last_frame, _)) = previous {
+        if !detection.is_repeat(scores.len()) {
+            y4m::write_frame(&mut output, &last_frame)?;
+        }
+    }
+    control.flush_decisions();
+
+    output.flush()?;
+    Ok(scores)
+}
 
-    let mut bad_frames = bad_frames.lock().unwrap();
-    // Ensure we have a result for each frame (except the last one)
-    while bad_frames.len() < frames_vec.len() - 1 {
-        bad_frames.push(false);
+/// [`filter_y4m`] under a comparison window: every frame is compared with
+/// the last kept frames before it and written out as soon as it is kept.
+fn filter_y4m_against_kept<R: BufRead>(
+    mut reader: y4m::Y4mReader<R>,
+    pair_count: Option<usize>,
+    mut output: BufWriter<File>,
+    detection: &Detection,
+    crop: Option<CropRect>,
+    control: &JobControl,
+) -> std::io::Result<Vec<f32>> {
+    let mut window = KeptFrames::new(detection.window());
+    let mut scores = Vec::new();
+    let mut dead = false;
+    let mut frame = 0;
+    while let Some(data) = reader.next_frame()? {
+        control.wait_while_paused();
+        if control.cancel.is_cancelled() {
+            return Ok(scores);
+        }
+        let mut luma = reader.luma(&data);
+        if let Some(crop) = crop {
+            luma = imageops::crop_imm(&luma, crop.x, crop.y, crop.width, crop.height).to_image();
+        }
+        let metric = detection.metric(frame);
+        let score = window.score(detection.threshold(frame), |kept| {
+            similarity::score(metric, kept, &luma).unwrap_or(0.0)
+        });
+        if frame > 0 {
+            dead = detection.is_dead(frame, score, Some(dead)).unwrap_or(dead);
+        }
+        if !dead {
+            y4m::write_frame(&mut output, &data)?;
+            window.push(luma);
+        }
+        if frame > 0 {
+            control.decide(FrameDecision {
+                frame,
+                score,
+                removed: dead,
+            });
+            scores.push(score);
+            if let Some(pair_count) = pair_count {
+                control.report(Stage::Analyzing, scores.len(), pair_count);
+            }
+        }
+        frame += 1;
     }
-    // Add false for the last frame
-    bad_frames.push(false);
+    control.flush_decisions();
+
+    output.flush()?;
+    Ok(scores)
+}
 
-    // Remove bad frames
-    for (index, value) in frames_vec.iter().enumerate() {
-        if bad_frames[index] {
-            if let Err(e) = fs::remove_file(value) {
-                eprintln!("Failed to remove file {}: {}", value.display(), e);
+/// Per-frame results of analysing a video.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Analysis {
+    /// Similarity of every frame to its successor or, under a comparison
+    /// window, of every frame after the first to the last frames kept
+    /// before it; one shorter than `removed`.
+    pub scores: Vec<f32>,
+    /// The frame the first score is for: 0, or 1 under a comparison window.
+    pub first_scored: usize,
+    /// Whether each frame is dead and gets removed.
+    pub removed: Vec<bool>,
+    /// Predicted size of the processed video, where one can be made.
+    pub estimated_size: Option<SizeEstimate>,
+}
+
+impl Analysis {
+    fn new(scores: Vec<f32>, detection: &Detection) -> Analysis {
+        // A frame is dead when it matches the next one, so the last frame is
+        // always kept; under a window it is dead when it matches an earlier
+        // one, so the first is
+        let mut removed = dead_frames(&scores, detection);
+        let windowed = detection.windowed();
+        if !scores.is_empty() {
+            if windowed {
+                removed.insert(0, false);
+            } else {
+                removed.push(false);
+            }
+        }
+        if detection.repeats.is_some() {
+            for (frame, dead) in removed.iter_mut().enumerate() {
+                *dead = detection.is_repeat(frame);
             }
         }
+        let first_scored = detection.first_scored();
+        for (index, &dead) in removed.iter().enumerate() {
+            let score = index
+                .checked_sub(first_scored)
+                .and_then(|index| scores.get(index));
+            match score {
+                Some(score) => debug!(
+                    "frame {} score {:.4} {}",
+                    index,
+                    score,
+                    if dead { "removed" } else { "kept" }
+                ),
+                None => debug!(
+                    "frame {} kept ({} frame)",
+                    index,
+                    if windowed { "first" } else { "last" }
+                ),
+            }
+        }
+        Analysis {
+            scores,
+            removed,
+            first_scored,
+            estimated_size: None,
+        }
     }
 
-    let output_video = format!(
-        "{}_processed.mp4",
-        Path::new(input_file).file_stem().unwrap().to_str().unwrap()
-    );
-    stitch_frames_into_video(&frames_folder, &output_video);
+    /// The score of frame `frame`, if it has one.
+    pub fn score(&self, frame: usize) -> Option<f32> {
+        let index = frame.checked_sub(self.first_scored)?;
+        self.scores.get(index).copied()
+    }
+
+    pub fn frames_total(&self) -> usize {
+        self.removed.len()
+    }
+
+    pub fn frames_removed(&self) -> usize {
+        self.removed.iter().filter(|&&dead| dead).count()
+    }
+}
+
+/// Frame files scored by [`analyze_frames`].
+struct Frames {
+    /// The frames in order; empty for y4m, whose kept frames have already
+    /// been written to [`KEPT_Y4M`].
+    files: Vec<PathBuf>,
+    extension: String,
+    /// The files are the user's image sequence rather than extracted frames
+    /// and must be left untouched.
+    borrowed: bool,
+    source: Source,
+    /// The job's frames directory, where they are encoded from.
+    dir: PathBuf,
+    /// Some frames were replaced after extraction, so the frames no longer
+    /// match the source even when none are removed.
+    replaced: bool,
 }
 
-#[tokio::main]
-async fn main() {}
+/// Extracts and scores the frames of `input_file`, or scores them in place
+/// when `input_file` is a directory holding an image sequence, then deals
+/// with glitches, picks the frame kept of each run, removes blurred frames,
+/// merges the removed spans close together and lets the plugins and the
+/// script decide. A `cached` analysis of the same frames is taken as it is,
+/// where the frames are extracted as images to be encoded from.
+fn analyze_frames(
+    input_file: &Path,
+    options: &ProcessOptions,
+    dir: &Path,
+    cached: Option<Analysis>,
+    control: &JobControl,
+) -> Result<(Analysis, Frames), ProcessError> {
+    options.color_space.validate().map_err(ProcessError::new)?;
+    // loaded first, so a broken plugin or script fails the job before the
+    // analysis
+    #[cfg(feature = "plugins")]
+    let plugins = {
+        let mut paths = options.plugins.clone();
+        for path in crate::plugins::enabled() {
+            if !paths.contains(&path) {
+                paths.push(path);
+            }
+        }
+        paths
+            .iter()
+            .map(|path| Plugin::load(path))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(ProcessError::new)?
+    };
+    #[cfg(not(feature = "plugins"))]
+    if !options.plugins.is_empty() {
+        return Err(ProcessError::new(
+            "This build cannot run plugins; it needs the plugins feature",
+        ));
+    }
+    #[cfg(feature = "scripting")]
+    let script = match &options.script {
+        Some(path) => Some(Script::load(path).map_err(ProcessError::new)?),
+        None => None,
+    };
+    #[cfg(not(feature = "scripting"))]
+    if options.script.is_some() {
+        return Err(ProcessError::new(
+            "This build cannot run scripts; it needs the scripting feature",
+        ));
+    }
+    // frames streamed as y4m are filtered as they are compared, and
+    // replaced glitches have to be blended anew
+    let reusable = !options.low_memory
+        && options.frame_format != FrameFormat::Y4m
+        && options.glitches != Some(GlitchAction::Replace);
+    let cached = cached.filter(|_| reusable);
+    let (mut analysis, mut frames) =
+        score_input(input_file, options, dir, cached.as_ref(), control)?;
+    if cached.is_some_and(|cached| cached.frames_total() == frames.files.len()) {
+        return Ok((analysis, frames));
+    }
+    if let Some(action) = options.glitches {
+        handle_glitches(&mut analysis, &mut frames, action, options, control)?;
+    }
+    if let Some(representative) = options.representative {
+        choose_representatives(&mut analysis, &frames, representative, control)?;
+    }
+    if let Some(threshold) = options.blur_threshold {
+        remove_blurred_frames(&mut analysis, &frames, threshold, control)?;
+    }
+    merge_removed_spans(&mut analysis, &frames, options.merge_gap, control)?;
+    #[cfg(feature = "plugins")]
+    if !plugins.is_empty() {
+        apply_plugins(&mut analysis, &frames, plugins, control)?;
+    }
+    #[cfg(feature = "scripting")]
+    if let Some(script) = script {
+        apply_script(&mut analysis, &frames, script, control)?;
+    }
+    Ok((analysis, frames))
+}
+
+/// Finds the glitches among the kept frames and removes them or replaces
+/// them with a blend of their neighbours; see [`glitch`]. y4m frames are
+/// looked at in the extracted stream, which low memory jobs do not keep.
+fn handle_glitches(
+    analysis: &mut Analysis,
+    frames: &mut Frames,
+    action: GlitchAction,
+    options: &ProcessOptions,
+    control: &JobControl,
+) -> Result<(), ProcessError> {
+    let stream = frames.dir.join(FRAMES_Y4M);
+    let y4m = frames.files.is_empty();
+    if y4m && !stream.exists() {
+        warn!("Low memory jobs keep no frames to look for glitches in");
+        return Ok(());
+    }
+    // the scores are of every frame against its successor, unless frames
+    // were compared with kept ones or filled in from samples
+    let candidates = if analysis.first_scored == 0 && options.analysis_step.is_none() {
+        glitch::candidates(&analysis.scores)
+    } else {
+        (1..analysis.removed.len().saturating_sub(1)).collect()
+    };
+    let candidates: Vec<usize> = candidates
+        .into_iter()
+        .filter(|&frame| !analysis.removed[frame])
+        .collect();
+    if candidates.is_empty() {
+        return Ok(());
+    }
+    control.report(Stage::Analyzing, 0, 0);
+    let replace = action == GlitchAction::Replace;
+    let crop = frames.source.compare_crop;
+    let error = |e: std::io::Error| ProcessError::new(format!("Failed to handle glitches: {}", e));
+    let (glitches, mut blends) = if y4m {
+        y4m_glitches(&stream, &candidates, options.metric, crop, replace, control).map_err(error)?
+    } else {
+        let files = &frames.files;
+        let glitches: Vec<usize> = concurrency::thread_pool().install(|| {
+            candidates
+                .par_iter()
+                .copied()
+                .filter(|&frame| {
+                    let planes =
+                        |frame: usize| load_planes(&files[frame], crop, options.color_space).ok();
+                    let (Some(before), Some(glitch), Some(after)) =
+                        (planes(frame - 1), planes(frame), planes(frame + 1))
+                    else {
+                        return false;
+                    };
+                    let score = |a: &Planes, b: &Planes| {
+                        similarity::score_planes(options.metric, a, b).unwrap_or(0.0)
+                    };
+                    glitch::is_glitch(
+                        score(&before, &glitch),
+                        score(&glitch, &after),
+                        score(&before, &after),
+                    )
+                })
+                .collect()
+        });
+        (glitches, HashMap::new())
+    };
+    control.check()?;
+    info!("{} glitched frames", glitches.len());
+    if glitches.is_empty() {
+        return Ok(());
+    }
+
+    if replace && !y4m {
+        let quality = match options.frame_format {
+            FrameFormat::Jpeg(quality) => quality,
+            _ => 95,
+        };
+        for &frame in &glitches {
+            let target = if frames.borrowed {
+                frames
+                    .dir
+                    .join(format!("glitch_{}.{}", frame, frames.extension))
+            } else {
+                frames.files[frame].clone()
+            };
+            write_blend(
+                &frames.files[frame - 1],
+                &frames.files[frame + 1],
+                &target,
+                quality,
+            )
+            .map_err(|e| {
+                ProcessError::new(format!("Failed to replace glitched frame {}: {}", frame, e))
+            })?;
+            frames.files[frame] = target;
+        }
+        frames.replaced = true;
+        return Ok(());
+    }
+
+    // the index of each frame among those in the kept y4m
+    let mut kept_index = HashMap::new();
+    let mut kept = 0;
+    for (frame, &dead) in analysis.removed.iter().enumerate() {
+        if !dead {
+            kept_index.insert(frame, kept);
+            kept += 1;
+        }
+    }
+    let glitched: HashSet<usize> = glitches.iter().map(|frame| kept_index[frame]).collect();
+    if replace {
+        let mut blends: HashMap<usize, Vec<u8>> = blends
+            .drain()
+            .map(|(frame, blend)| (kept_index[&frame], blend))
+            .collect();
+        frames.replaced = true;
+        return rewrite_kept_y4m(&frames.dir, |index, frame| {
+            Some(blends.remove(&index).unwrap_or(frame))
+        })
+        .map_err(error);
+    }
+    for &frame in &glitches {
+        analysis.removed[frame] = true;
+        control.decide(FrameDecision {
+            frame,
+            score: analysis.score(frame).unwrap_or(0.0),
+            removed: true,
+        });
+    }
+    control.flush_decisions();
+    if y4m {
+        rewrite_kept_y4m(&frames.dir, |index, frame| {
+            (!glitched.contains(&index)).then_some(frame)
+        })
+        .map_err(error)?;
+    }
+    Ok(())
+}
+
+/// Raw y4m frames, by the index of the frame each replaces.
+type Blends = HashMap<usize, Vec<u8>>;
+
+/// The glitches among `candidates` in the y4m stream at `path`, compared
+/// within `crop`, and with `blend` the mean of the neighbours of each.
+fn y4m_glitches(
+    path: &Path,
+    candidates: &[usize],
+    metric: Metric,
+    crop: Option<CropRect>,
+    blend: bool,
+    control: &JobControl,
+) -> std::io::Result<(Vec<usize>, Blends)> {
+    let mut reader = y4m::Y4mReader::new(BufReader::new(File::open(path)?))?;
+    let candidates: HashSet<usize> = candidates.iter().copied().collect();
+    let last = candidates.iter().max().copied().unwrap_or(0);
+    let mut window: VecDeque<(Vec<u8>, GrayImage)> = VecDeque::with_capacity(3);
+    let (mut glitches, mut blends) = (Vec::new(), HashMap::new());
+    let mut index = 0;
+    while index <= last + 1 {
+        if control.cancel.is_cancelled() {
+            break;
+        }
+        let Some(frame) = reader.next_frame()? else {
+            break;
+        };
+        let mut luma = reader.luma(&frame);
+        if let Some(crop) = crop {
+            luma = imageops::crop_imm(&luma, crop.x, crop.y, crop.width, crop.height).to_image();
+        }
+        if window.len() == 3 {
+            window.pop_front();
+        }
+        window.push_back((frame, luma));
+        if index >= 2 && candidates.contains(&(index - 1)) {
+            let score = |a: usize, b: usize| {
+                similarity::score(metric, &window[a].1, &window[b].1).unwrap_or(0.0)
+            };
+            if glitch::is_glitch(score(0, 1), score(1, 2), score(0, 2)) {
+                glitches.push(index - 1);
+                if blend {
+                    blends.insert(index - 1, reader.blend(&window[0].0, &window[2].0));
+                }
+            }
+        }
+        index += 1;
+    }
+    Ok((glitches, blends))
+}
+
+/// Writes the mean of the images at `before` and `after` to `target`, at
+/// the bit depth and with the channels of `before`. JPEG is written at
+/// `jpeg_quality`.
+fn write_blend(
+    before: &Path,
+    after: &Path,
+    target: &Path,
+    jpeg_quality: u8,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let before = image::open(before)?;
+    let after = image::open(after)?;
+    if (before.width(), before.height()) != (after.width(), after.height()) {
+        return Err("the frames either side differ in size".into());
+    }
+    let color = before.color();
+    let high_depth = color.bytes_per_pixel() > color.channel_count();
+    let blend = if high_depth {
+        let (a, b) = (before.to_rgba16(), after.to_rgba16());
+        DynamicImage::ImageRgba16(ImageBuffer::from_fn(a.width(), a.height(), |x, y| {
+            let (p, q) = (a.get_pixel(x, y), b.get_pixel(x, y));
+            Rgba(std::array::from_fn(|c| {
+                (p[c] as u32 + q[c] as u32).div_ceil(2) as u16
+            }))
+        }))
+    } else {
+        let (a, b) = (before.to_rgba8(), after.to_rgba8());
+        DynamicImage::ImageRgba8(ImageBuffer::from_fn(a.width(), a.height(), |x, y| {
+            let (p, q) = (a.get_pixel(x, y), b.get_pixel(x, y));
+            Rgba(std::array::from_fn(|c| {
+                (p[c] as u16 + q[c] as u16).div_ceil(2) as u8
+            }))
+        }))
+    };
+    let blend = match (color.has_alpha(), high_depth) {
+        (true, _) => blend,
+        (false, true) => DynamicImage::ImageRgb16(blend.to_rgb16()),
+        (false, false) => DynamicImage::ImageRgb8(blend.to_rgb8()),
+    };
+    let is_jpeg = target
+        .extension()
+        .is_some_and(|extension| extension == "jpg" || extension == "jpeg");
+    if is_jpeg {
+        let file = BufWriter::new(File::create(target)?);
+        blend.write_with_encoder(image::codecs::jpeg::JpegEncoder::new_with_quality(
+            file,
+            jpeg_quality,
+        ))?;
+    } else {
+        blend.save(target)?;
+    }
+    Ok(())
+}
+
+/// Keeps the frame of each run of alike frames that `representative` asks
+/// for, in place of the one the comparison kept. y4m frames are written to
+/// [`KEPT_Y4M`] again from the extracted stream, which low memory jobs do
+/// not keep, so theirs are left as they are.
+fn choose_representatives(
+    analysis: &mut Analysis,
+    frames: &Frames,
+    representative: Representative,
+    control: &JobControl,
+) -> Result<(), ProcessError> {
+    let kept_first = analysis.first_scored > 0 || frames.source.repeats.is_some();
+    let runs = representative::runs(&analysis.removed, kept_first);
+    if runs.is_empty() {
+        return Ok(());
+    }
+    let stream = frames.dir.join(FRAMES_Y4M);
+    let y4m = frames.files.is_empty();
+    if y4m && !stream.exists() {
+        warn!("Low memory jobs keep no frames to choose from, so runs keep their usual frame");
+        return Ok(());
+    }
+    let write_error =
+        |e: std::io::Error| ProcessError::new(format!("Failed to choose kept frames: {}", e));
+    let crop = frames.source.compare_crop;
+
+    let sharpness: HashMap<usize, f64> = match representative {
+        Representative::Sharpest => {
+            let in_runs: Vec<usize> = runs.iter().flat_map(|run| run.clone()).collect();
+            control.report(Stage::Analyzing, 0, 0);
+            let sharpness = if y4m {
+                y4m_sharpness(&stream, &in_runs, crop, control).map_err(write_error)?
+            } else {
+                concurrency::thread_pool().install(|| {
+                    in_runs
+                        .par_iter()
+                        .filter_map(|&frame| {
+                            Some((frame, image_sharpness(&frames.files[frame], crop)?))
+                        })
+                        .collect()
+                })
+            };
+            control.check()?;
+            sharpness
+        }
+        Representative::First | Representative::Last => HashMap::new(),
+    };
+
+    let mut changed = 0;
+    for run in &runs {
+        let keep = representative.pick(run, |frame| sharpness.get(&frame).copied().unwrap_or(0.0));
+        for frame in run.clone() {
+            let dead = frame != keep;
+            if analysis.removed[frame] != dead {
+                analysis.removed[frame] = dead;
+                control.decide(FrameDecision {
+                    frame,
+                    score: analysis.score(frame).unwrap_or(0.0),
+                    removed: dead,
+                });
+                changed += 1;
+            }
+        }
+    }
+    control.flush_decisions();
+    debug!(
+        "Chose the kept frame of {} runs, {} frames changed",
+        runs.len(),
+        changed
+    );
+    if y4m && changed > 0 {
+        write_kept_y4m(&frames.dir, &analysis.removed).map_err(write_error)?;
+    }
+    Ok(())
+}
+
+/// The sharpness of the part within `crop` of the image at `path`, if it
+/// can be read; see [`blur`].
+fn image_sharpness(path: &Path, crop: Option<CropRect>) -> Option<f64> {
+    let mut image = image::open(path).ok()?;
+    if let Some(crop) = crop {
+        image = image.crop_imm(crop.x, crop.y, crop.width, crop.height);
+    }
+    Some(blur::sharpness(&image.to_luma8()))
+}
+
+/// The sharpness of each of `frames`, in order, of the y4m stream at
+/// `path`, within `crop`.
+fn y4m_sharpness(
+    path: &Path,
+    frames: &[usize],
+    crop: Option<CropRect>,
+    control: &JobControl,
+) -> std::io::Result<HashMap<usize, f64>> {
+    let mut reader = y4m::Y4mReader::new(BufReader::new(File::open(path)?))?;
+    let mut sharpness = HashMap::with_capacity(frames.len());
+    let mut wanted = frames.iter().peekable();
+    let mut index = 0;
+    while let Some(&&next) = wanted.peek() {
+        if control.cancel.is_cancelled() {
+            break;
+        }
+        let Some(frame) = reader.next_frame()? else {
+            break;
+        };
+        if index == next {
+            let mut luma = reader.luma(&frame);
+            if let Some(crop) = crop {
+                luma =
+                    imageops::crop_imm(&luma, crop.x, crop.y, crop.width, crop.height).to_image();
+            }
+            sharpness.insert(index, blur::sharpness(&luma));
+            wanted.next();
+        }
+        index += 1;
+    }
+    Ok(sharpness)
+}
+
+/// Writes the frames of [`FRAMES_Y4M`] that are not `removed` to
+/// [`KEPT_Y4M`].
+fn write_kept_y4m(dir: &Path, removed: &[bool]) -> std::io::Result<()> {
+    let mut reader = y4m::Y4mReader::new(BufReader::new(File::open(dir.join(FRAMES_Y4M))?))?;
+    let mut output = BufWriter::new(File::create(dir.join(KEPT_Y4M))?);
+    output.write_all(reader.header())?;
+    let mut index = 0;
+    while let Some(frame) = reader.next_frame()? {
+        if !removed.get(index).copied().unwrap_or(false) {
+            y4m::write_frame(&mut output, &frame)?;
+        }
+        index += 1;
+    }
+    output.flush()
+}
+
+/// Removes the runs of fewer than `gap` kept frames between two removed
+/// spans, so that frames the comparison happened to keep do not break a
+/// long frozen section into stutters. y4m frames, whose kept frames are
+/// already written to [`KEPT_Y4M`], are written again without them.
+fn merge_removed_spans(
+    analysis: &mut Analysis,
+    frames: &Frames,
+    gap: usize,
+    control: &JobControl,
+) -> Result<(), ProcessError> {
+    let was_removed = analysis.removed.clone();
+    let merged = cutlist::merge_removed(&mut analysis.removed, gap);
+    if merged.is_empty() {
+        return Ok(());
+    }
+    info!(
+        "Merged removed spans less than {} frames apart, removing {} more frames",
+        gap,
+        merged.len()
+    );
+    for &frame in &merged {
+        control.decide(FrameDecision {
+            frame,
+            score: analysis.score(frame).unwrap_or(0.0),
+            removed: true,
+        });
+    }
+    control.flush_decisions();
+    if !frames.files.is_empty() {
+        return Ok(());
+    }
+
+    // whether each frame in the kept y4m stays
+    let keep: Vec<bool> = was_removed
+        .iter()
+        .zip(&analysis.removed)
+        .filter(|(&was, _)| !was)
+        .map(|(_, &dead)| !dead)
+        .collect();
+    rewrite_kept_y4m(&frames.dir, |index, frame| {
+        keep.get(index).copied().unwrap_or(true).then_some(frame)
+    })
+    .map_err(|e| ProcessError::new(format!("Failed to merge removed spans: {}", e)))
+}
+
+/// Lets `script` keep or remove each frame; see [`script`](crate::script).
+#[cfg(feature = "scripting")]
+fn apply_script(
+    analysis: &mut Analysis,
+    frames: &Frames,
+    mut script: Script,
+    control: &JobControl,
+) -> Result<(), ProcessError> {
+    let fps = frames.source.fps;
+    overrule(
+        analysis,
+        frames,
+        "the script",
+        control,
+        |frame, score, removed| {
+            script
+                .decide(&script::Frame {
+                    index: frame,
+                    time: fps.map(|fps| frame as f64 / fps),
+                    score,
+                    removed,
+                })
+                .map_err(ProcessError::new)
+        },
+    )
+}
+
+/// Lets each of `plugins` in turn keep or remove each frame; see
+/// [`plugins`](crate::plugins). y4m frames are read from [`FRAMES_Y4M`],
+/// which low memory jobs do not keep.
+#[cfg(feature = "plugins")]
+fn apply_plugins(
+    analysis: &mut Analysis,
+    frames: &Frames,
+    mut plugins: Vec<Plugin>,
+    control: &JobControl,
+) -> Result<(), ProcessError> {
+    let stream = frames.dir.join(FRAMES_Y4M);
+    let mut reader = match frames.files.is_empty() {
+        true if !stream.exists() => {
+            warn!("Low memory jobs keep no frames for plugins to look at, so none run");
+            return Ok(());
+        }
+        true => Some(
+            File::open(&stream)
+                .and_then(|file| y4m::Y4mReader::new(BufReader::new(file)))
+                .map_err(|e| ProcessError::new(format!("Failed to read frames: {}", e)))?,
+        ),
+        false => None,
+    };
+    let crop = frames.source.compare_crop;
+    let mut luma_of = |frame: usize| -> Result<GrayImage, ProcessError> {
+        let luma = match &mut reader {
+            Some(reader) => {
+                let data = reader.next_frame().ok().flatten().ok_or_else(|| {
+                    ProcessError::new(format!("Frame {} is missing from the stream", frame))
+                })?;
+                reader.luma(&data)
+            }
+            None => image::open(&frames.files[frame])
+                .map_err(|e| ProcessError::new(format!("Failed to read frame {}: {}", frame, e)))?
+                .to_luma8(),
+        };
+        Ok(match crop {
+            Some(crop) => {
+                imageops::crop_imm(&luma, crop.x, crop.y, crop.width, crop.height).to_image()
+            }
+            None => luma,
+        })
+    };
+    let names: Vec<&str> = plugins.iter().map(|plugin| plugin.name()).collect();
+    let who = format!("plugins {}", names.join(", "));
+    let total = analysis.removed.len();
+    let mut next: Option<GrayImage> = None;
+    overrule(
+        analysis,
+        frames,
+        &who,
+        control,
+        |frame, score, mut removed| {
+            let luma = match next.take() {
+                Some(luma) => luma,
+                None => luma_of(frame)?,
+            };
+            let successor = match frame + 1 < total {
+                true => luma_of(frame + 1)?,
+                false => luma.clone(),
+            };
+            let mut decision = None;
+            for plugin in &mut plugins {
+                if let Some(dead) = plugin
+                    .decide(frame, &luma, &successor, score, removed)
+                    .map_err(ProcessError::new)?
+                {
+                    removed = dead;
+                    decision = Some(dead);
+                }
+            }
+            next = Some(successor);
+            Ok(decision)
+        },
+    )
+}
+
+/// Has `decide` keep or remove each frame, given its score and whether it
+/// is removed so far. y4m frames it keeps that were removed come from [`FRAMES_Y4M`], so
+/// low memory jobs, which do not keep it, cannot have them back. `who`
+/// names what decides in the log.
+#[cfg(any(feature = "scripting", feature = "plugins"))]
+fn overrule(
+    analysis: &mut Analysis,
+    frames: &Frames,
+    who: &str,
+    control: &JobControl,
+    mut decide: impl FnMut(usize, Option<f32>, bool) -> Result<Option<bool>, ProcessError>,
+) -> Result<(), ProcessError> {
+    let y4m = frames.files.is_empty();
+    let can_restore = !y4m || frames.dir.join(FRAMES_Y4M).exists();
+    let was_removed = analysis.removed.clone();
+    let mut changed = 0;
+    let mut not_restored = 0;
+    for frame in 0..analysis.removed.len() {
+        if frame % 1000 == 0 {
+            control.check()?;
+        }
+        let removed = analysis.removed[frame];
+        match decide(frame, analysis.score(frame), removed)? {
+            Some(dead) if dead == removed => {}
+            Some(false) if !can_restore => not_restored += 1,
+            Some(dead) => {
+                analysis.removed[frame] = dead;
+                control.decide(FrameDecision {
+                    frame,
+                    score: analysis.score(frame).unwrap_or(0.0),
+                    removed: dead,
+                });
+                changed += 1;
+            }
+            None => {}
+        }
+    }
+    control.flush_decisions();
+    info!("Frames changed by {}: {}", who, changed);
+    if not_restored > 0 {
+        warn!(
+            "Low memory jobs keep no removed frames, so {} frames kept by {} stay removed",
+            not_restored, who
+        );
+    }
+    if y4m && changed > 0 {
+        update_kept_y4m(&frames.dir, &was_removed, &analysis.removed)
+            .map_err(|e| ProcessError::new(format!("Failed to apply decisions: {}", e)))?;
+    }
+    Ok(())
+}
+
+/// Writes [`KEPT_Y4M`] again for frames that were `was_removed` and are
+/// now `removed`. The frames still kept are taken from it, so frames
+/// replaced there stay replaced, and those kept again from [`FRAMES_Y4M`],
+/// which is only read if there are any.
+#[cfg(any(feature = "scripting", feature = "plugins"))]
+fn update_kept_y4m(dir: &Path, was_removed: &[bool], removed: &[bool]) -> std::io::Result<()> {
+    let restores = was_removed
+        .iter()
+        .zip(removed)
+        .any(|(&was, &dead)| was && !dead);
+    let mut stream = match restores {
+        true => Some(y4m::Y4mReader::new(BufReader::new(File::open(
+            dir.join(FRAMES_Y4M),
+        )?))?),
+        false => None,
+    };
+    let kept = dir.join(KEPT_Y4M);
+    let rewritten = dir.join("rewritten.y4m");
+    let mut reader = y4m::Y4mReader::new(BufReader::new(File::open(&kept)?))?;
+    let mut output = BufWriter::new(File::create(&rewritten)?);
+    output.write_all(reader.header())?;
+    for (&was, &dead) in was_removed.iter().zip(removed) {
+        let original = match &mut stream {
+            Some(stream) => stream.next_frame()?,
+            None => None,
+        };
+        let frame = match was {
+            true => original,
+            false => reader.next_frame()?,
+        };
+        if let (false, Some(frame)) = (dead, frame) {
+            y4m::write_frame(&mut output, &frame)?;
+        }
+    }
+    output.flush()?;
+    drop(output);
+    fs::rename(&rewritten, &kept)
+}
+
+/// Writes [`KEPT_Y4M`] again with each of its frames as `edit` returns it,
+/// given its index among the frames in it, leaving out those it returns
+/// `None` for.
+fn rewrite_kept_y4m(
+    dir: &Path,
+    mut edit: impl FnMut(usize, Vec<u8>) -> Option<Vec<u8>>,
+) -> std::io::Result<()> {
+    let kept = dir.join(KEPT_Y4M);
+    let rewritten = dir.join("rewritten.y4m");
+    let mut reader = y4m::Y4mReader::new(BufReader::new(File::open(&kept)?))?;
+    let mut output = BufWriter::new(File::create(&rewritten)?);
+    output.write_all(reader.header())?;
+    let mut index = 0;
+    while let Some(frame) = reader.next_frame()? {
+        if let Some(frame) = edit(index, frame) {
+            y4m::write_frame(&mut output, &frame)?;
+        }
+        index += 1;
+    }
+    output.flush()?;
+    drop(output);
+    fs::rename(&rewritten, &kept)
+}
+
+/// Removes the kept frames less sharp than `threshold`; see [`blur`].
+/// y4m frames are measured in [`KEPT_Y4M`], which low memory jobs have as
+/// well.
+fn remove_blurred_frames(
+    analysis: &mut Analysis,
+    frames: &Frames,
+    threshold: f64,
+    control: &JobControl,
+) -> Result<(), ProcessError> {
+    let kept: Vec<usize> = (0..analysis.removed.len())
+        .filter(|&frame| !analysis.removed[frame])
+        .collect();
+    let crop = frames.source.compare_crop;
+    control.report(Stage::Analyzing, 0, 0);
+    // frames that cannot be read are left alone
+    let blurred: Vec<bool> = if frames.files.is_empty() {
+        let ordinals: Vec<usize> = (0..kept.len()).collect();
+        let sharpness = y4m_sharpness(&frames.dir.join(KEPT_Y4M), &ordinals, crop, control)
+            .map_err(|e| ProcessError::new(format!("Failed to measure sharpness: {}", e)))?;
+        (0..kept.len())
+            .map(|index| sharpness.get(&index).is_some_and(|&s| s < threshold))
+            .collect()
+    } else {
+        concurrency::thread_pool().install(|| {
+            kept.par_iter()
+                .map(|&frame| {
+                    image_sharpness(&frames.files[frame], crop).is_some_and(|s| s < threshold)
+                })
+                .collect()
+        })
+    };
+    control.check()?;
+    let count = blurred.iter().filter(|&&blurred| blurred).count();
+    info!("{} frames are less sharp than {}", count, threshold);
+    if count == 0 {
+        return Ok(());
+    }
+    for (&frame, _) in kept.iter().zip(&blurred).filter(|(_, &blurred)| blurred) {
+        analysis.removed[frame] = true;
+        control.decide(FrameDecision {
+            frame,
+            score: analysis.score(frame).unwrap_or(0.0),
+            removed: true,
+        });
+    }
+    control.flush_decisions();
+    if frames.files.is_empty() {
+        rewrite_kept_y4m(&frames.dir, |index, frame| {
+            (!blurred.get(index).copied().unwrap_or(false)).then_some(frame)
+        })
+        .map_err(|e| ProcessError::new(format!("Failed to remove blurred frames: {}", e)))?;
+    }
+    Ok(())
+}
+
+fn score_input(
+    input_file: &Path,
+    options: &ProcessOptions,
+    dir: &Path,
+    cached: Option<&Analysis>,
+    control: &JobControl,
+) -> Result<(Analysis, Frames), ProcessError> {
+    sections::validate(&options.sections).map_err(ProcessError::new)?;
+    if let Some(step) = options.analysis_step {
+        coarse::validate(step).map_err(ProcessError::new)?;
+    }
+    if let Some(threshold) = options.blur_threshold {
+        blur::validate(threshold).map_err(ProcessError::new)?;
+    }
+    let metrics: Vec<Metric> = std::iter::once(options.metric)
+        .chain(options.sections.iter().filter_map(|section| section.metric))
+        .collect();
+    for metric in &metrics {
+        if let Metric::Custom(name) = metric {
+            comparator::get(name).ok_or_else(|| {
+                ProcessError::new(format!("No comparator registered as \"{}\"", name))
+            })?;
+        }
+    }
+    #[cfg(feature = "onnx")]
+    if metrics.contains(&Metric::Learned) {
+        crate::learned::ready().map_err(ProcessError::new)?;
+    }
+    if let Some(fps) = options.retime {
+        retime::validate(fps).map_err(ProcessError::new)?;
+    }
+    let encodes_video =
+        options.cut_list.is_none() && !options.smart_cut && options.image_sequence.is_none();
+    if let Some(mb) = options.target_size_mb {
+        if !mb.is_finite() || mb <= 0.0 {
+            return Err(ProcessError::new(format!(
+                "The target size must be positive, got {} MB",
+                mb
+            )));
+        }
+    }
+    let two_pass = options.target_bitrate.is_some() || options.target_size_mb.is_some();
+    if two_pass && encodes_video && !options.codec.takes_two_passes() {
+        return Err(ProcessError::new(
+            "Encoding at a target bitrate or size needs h264 or h265",
+        ));
+    }
+    if input_file.is_dir() {
+        let sequence = sequence::detect(input_file).map_err(ProcessError::new)?;
+        info!(
+            "Using image sequence {} ({} frames)",
+            sequence.pattern,
+            sequence.len()
+        );
+        let source = Source {
+            pixel_format: sequence
+                .frames
+                .first()
+                .and_then(|frame| image_pixel_format(frame)),
+            ..Source::default()
+        };
+        let frames = Frames {
+            files: sequence.frames,
+            extension: sequence.extension,
+            borrowed: true,
+            source,
+            dir: dir.to_path_buf(),
+            replaced: false,
+        };
+        return score_frames(frames, options, cached, control);
+    }
+
+    let plays = animation::plays(input_file);
+    if animation::is_animated_webp(input_file) {
+        // ffmpeg has no animated WebP decoder, so the frames are decoded here
+        control.report(Stage::Extracting, 0, 0);
+        let fps = animation::decode_webp(input_file, dir, control)?;
+        let mut files = collect_files(dir, "png");
+        files.sort();
+        let frames = Frames {
+            files,
+            extension: "png".to_string(),
+            borrowed: false,
+            source: Source {
+                fps: Some(fps),
+                plays,
+                pixel_format: Some(PixelFormat {
+                    alpha: true,
+                    ..PixelFormat::YUV420P
+                }),
+                ..Source::default()
+            },
+            dir: dir.to_path_buf(),
+            replaced: false,
+        };
+        return score_frames(frames, options, cached, control);
+    }
+
+    let streamed = options.low_memory || options.frame_format == FrameFormat::Y4m;
+    if options.analysis_step.is_some() && streamed {
+        warn!("y4m frames are compared as they stream, so every frame is compared");
+    }
+    if options.color_space != ColorSpace::Luma && streamed {
+        warn!("y4m frames are compared by their luma alone");
+    }
+    if options.low_memory {
+        let mut source = probe_source(input_file, FrameFormat::Y4m, plays, options);
+        find_repeats(input_file, &mut source, options, control)?;
+        return stream_frames(input_file, source, options, dir, control);
+    }
+
+    let format = options.frame_format;
+    let mut source = probe_source(input_file, format, plays, options);
+    find_repeats(input_file, &mut source, options, control)?;
+    source.fps = generate_frames(input_file, format, &source, dir, control)?;
+
+    if format == FrameFormat::Y4m {
+        let span = Span::current();
+        let detection = Detection::new(options, &source);
+        let scores = concurrency::thread_pool()
+            .install(|| {
+                span.in_scope(|| {
+                    remove_dead_frames_y4m(dir, &detection, source.compare_crop, control)
+                })
+            })
+            .map_err(|e| ProcessError::new(format!("Failed to filter frames: {}", e)))?;
+        control.check()?;
+        let analysis = Analysis::new(scores, &detection);
+        let frames = Frames {
+            files: Vec::new(),
+            extension: format.extension().to_string(),
+            borrowed: false,
+            source,
+            dir: dir.to_path_buf(),
+            replaced: false,
+        };
+        return Ok((analysis, frames));
+    }
+
+    let mut files: Vec<PathBuf> = collect_files(dir, format.extension());
+
+    // Collection order is arbitrary but frames must be compared in sequence
+    files.sort();
+
+    let frames = Frames {
+        files,
+        extension: format.extension().to_string(),
+        borrowed: false,
+        source,
+        dir: dir.to_path_buf(),
+        replaced: false,
+    };
+    score_frames(frames, options, cached, control)
+}
+
+/// Decodes `input_file` as a y4m stream piped straight into the
+/// comparison, so only the frames being compared are held in memory and no
+/// extracted frames are stored. Used in `low_memory` mode.
+fn stream_frames(
+    input_file: &Path,
+    mut source: Source,
+    options: &ProcessOptions,
+    dir: &Path,
+    control: &JobControl,
+) -> Result<(Analysis, Frames), ProcessError> {
+    control.report(Stage::Analyzing, 0, 0);
+    let kept = dir.join(KEPT_Y4M);
+    let span = Span::current();
+    // the stream's frame rate is only reported once it is done, so the one
+    // probed beforehand places the sections
+    let detection = Detection::new(options, &source);
+    let decode = |hwaccel: Option<&str>| {
+        let mut command = ffmpeg::command().map_err(RunError::Unavailable)?;
+        if let Some(hwaccel) = hwaccel {
+            command.args(["-hwaccel", hwaccel]);
+        }
+        command
+            .args([
+                "-threads",
+                &concurrency::LOW_MEMORY_THREADS.to_string(),
+                "-i",
+            ])
+            .arg(paths::ffmpeg_arg(input_file))
+            .args(["-map", &streams::map(source.stream)]);
+        if let Some(filter) = source.extraction_filter(FrameFormat::Y4m) {
+            command.args(["-vf", &filter]);
+        }
+        command
+            .args(FrameFormat::Y4m.encoder_args(source.pixel_format))
+            .arg("-");
+        supervisor::run_streaming(command, control, Stage::Analyzing, |stdout| {
+            span.in_scope(|| {
+                let reader = y4m::Y4mReader::new(BufReader::new(stdout))?;
+                filter_y4m(
+                    reader,
+                    None,
+                    &kept,
+                    &detection,
+                    source.compare_crop,
+                    control,
+                )
+                .inspect_err(|e| warn!("Failed to filter the frame stream: {}", e))
+            })
+        })
+    };
+    let result = match decode(source.hwaccel.as_deref()) {
+        Err(e)
+            if source.hwaccel.is_some()
+                && !matches!(e, RunError::Cancelled | RunError::Unavailable(_)) =>
+        {
+            warn!("Hardware decoding failed, decoding in software: {}", e);
+            decode(None)
+        }
+        result => result,
+    };
+    let (scores, stderr) =
+        result.map_err(|e| ProcessError::ffmpeg("Failed to decode frames", e))?;
+    let scores =
+        scores.map_err(|e| ProcessError::new(format!("Failed to filter frames: {}", e)))?;
+    control.check()?;
+
+    let analysis = Analysis::new(scores, &detection);
+    source.fps = reported_fps(&stderr, source.stream).or(source.fps);
+    let frames = Frames {
+        files: Vec::new(),
+        extension: FrameFormat::Y4m.extension().to_string(),
+        borrowed: false,
+        source,
+        dir: dir.to_path_buf(),
+        replaced: false,
+    };
+    Ok((analysis, frames))
+}
+
+fn score_frames(
+    frames: Frames,
+    options: &ProcessOptions,
+    cached: Option<&Analysis>,
+    control: &JobControl,
+) -> Result<(Analysis, Frames), ProcessError> {
+    if let Some(cached) = cached {
+        if cached.frames_total() == frames.files.len() {
+            info!("Reusing the analysis of an earlier job");
+            return Ok((cached.clone(), frames));
+        }
+        warn!(
+            "The cached analysis is of {} frames, not {}; comparing them again",
+            cached.frames_total(),
+            frames.files.len()
+        );
+    }
+    let (width, height) = frames
+        .files
+        .first()
+        .and_then(|frame| image::image_dimensions(frame).ok())
+        .unwrap_or((0, 0));
+    let plan = concurrency::comparison_plan(
+        frames.files.len().saturating_sub(1),
+        width,
+        height,
+        options.low_memory,
+    );
+    debug!(
+        "Comparing on {} threads in batches of {}",
+        plan.pool.current_num_threads(),
+        plan.batch_size
+    );
+    let detection = Detection::new(options, &frames.source);
+    let known = match options.analysis_step {
+        Some(step) if !detection.windowed() && detection.repeats.is_none() => {
+            plan.pool.install(|| {
+                score_samples(
+                    &frames.files,
+                    step,
+                    &detection,
+                    frames.source.compare_crop,
+                    control,
+                )
+            })
+        }
+        Some(_) => {
+            warn!("Every frame is compared under a comparison window or against encoder repeats");
+            Vec::new()
+        }
+        None => Vec::new(),
+    };
+    control.check()?;
+    let scores = plan.pool.install(|| {
+        if detection.windowed() {
+            score_against_kept_frames(
+                &frames.files,
+                plan.batch_size,
+                &detection,
+                frames.source.compare_crop,
+                control,
+            )
+        } else {
+            score_consecutive_frames(
+                &frames.files,
+                &known,
+                plan.batch_size,
+                &detection,
+                frames.source.compare_crop,
+                control,
+            )
+        }
+    });
+    control.check()?;
+
+    let analysis = Analysis::new(scores, &detection);
+    Ok((analysis, frames))
+}
+
+/// Leaves the kept frames in the job directory as the unbroken sequence
+/// `frame_0001.<ext>`, `frame_0002.<ext>`, ... that the encoder reads, since
+/// it stops at the first gap in the numbering. Extracted frames are renamed
+/// in place; borrowed ones are linked, or copied where linking fails.
+/// Returns the digits the sequence is numbered with, which grow with the
+/// number of frames kept.
+fn collect_kept_frames(frames: &Frames, removed: &[bool]) -> Result<usize, ProcessError> {
+    let kept: Vec<&PathBuf> = frames
+        .files
+        .iter()
+        .zip(removed)
+        .filter_map(|(frame, &dead)| (!dead).then_some(frame))
+        .collect();
+    let digits = sequence::digits_for(kept.len());
+    for (index, frame) in kept.into_iter().enumerate() {
+        let target = frames
+            .dir
+            .join(sequence::frame_name(index + 1, digits, &frames.extension));
+        let result = if frames.borrowed {
+            fs::hard_link(frame, &target).or_else(|_| fs::copy(frame, &target).map(|_| ()))
+        } else if *frame == target {
+            Ok(())
+        } else {
+            fs::rename(frame, &target)
+        };
+        result.map_err(|e| {
+            ProcessError::new(format!("Failed to prepare {}: {}", frame.display(), e))
+        })?;
+    }
+    Ok(digits)
+}
+
+/// Runs `job` in a job directory of its own and a job span, so everything
+/// it logs ends up in the job's transcript, and tags a failure with the job
+/// ID. The job counts towards sharing the thread budget while it runs.
+fn in_job_span<T>(
+    input_file: &Path,
+    options: &ProcessOptions,
+    job: impl FnOnce(&JobDir) -> Result<T, ProcessError>,
+) -> Result<T, ProcessError> {
+    let job_id = logging::new_job_id();
+    let dir = workspace::create_job_dir(&job_id).map_err(|e| {
+        ProcessError::new(format!("Failed to create job directory: {}", e)).with_job_id(&job_id)
+    })?;
+    // dropped before the directory, which closes the transcript in it
+    let span = tracing::info_span!(
+        "job",
+        job_id = %job_id,
+        job_dir = %dir.path().display()
+    );
+    let _lease = concurrency::start_job();
+    span.in_scope(|| {
+        debug!(
+            "Options: {}",
+            serde_json::to_string(options).unwrap_or_default()
+        );
+        job(&dir).map_err(|e| {
+            if e.cancelled {
+                info!("Cancelled processing {}", input_file.display());
+            } else {
+                error!("Failed to process {}: {}", input_file.display(), e);
+            }
+            e.with_job_id(&job_id)
+        })
+    })
+}
+
+pub(crate) fn analyze(
+    input_file: &Path,
+    options: &ProcessOptions,
+    control: &JobControl,
+) -> Result<Analysis, ProcessError> {
+    in_job_span(input_file, options, |job| {
+        info!("Analysing {}", input_file.display());
+        let _awake = power::inhibit_sleep();
+        let remote = remote::open(input_file, options.cache_remote, job, control)?;
+        let input_file = remote.path();
+        // cached under the options asked for, as the queue looks them up
+        let requested = options;
+        let options = &with_timelapse(with_video_stream(input_file, options)?)?;
+        if let Some(analysis) = analysis_cache::lookup(input_file, requested) {
+            info!("Reusing the analysis of an earlier job");
+            timeline::save(job.id(), &analysis, options);
+            return Ok(analysis);
+        }
+        let (mut analysis, frames) =
+            analyze_frames(input_file, options, &job.frames(), None, control)?;
+        timeline::save(job.id(), &analysis, options);
+        analysis.estimated_size =
+            estimate_output_size(input_file, &analysis, &frames, options, control)?;
+        analysis_cache::store(input_file, requested, &analysis);
+        Ok(analysis)
+    })
+}
+
+/// Kept frames encoded by a test encode.
+const SAMPLE_FRAMES: usize = 60;
+
+/// Predicts the size of the video processing would write. `None` for cut
+/// lists and image sequences, and when the frame size cannot be read.
+fn estimate_output_size(
+    input_file: &Path,
+    analysis: &Analysis,
+    frames: &Frames,
+    options: &ProcessOptions,
+    control: &JobControl,
+) -> Result<Option<SizeEstimate>, ProcessError> {
+    let kept = analysis.frames_total() - analysis.frames_removed();
+    if options.cut_list.is_some() || options.image_sequence.is_some() {
+        return Ok(None);
+    }
+    if options.smart_cut {
+        // nearly all of the output is the source's own packets
+        return Ok(fs::metadata(input_file)
+            .ok()
+            .map(|metadata| estimate::scaled(metadata.len(), analysis.frames_total(), kept)));
+    }
+    let options = &with_target_size(options.clone(), kept, &frames.source)?;
+    if let Some(kbps) = options.target_bitrate {
+        let fps = options
+            .output_framerate()
+            .or(frames.source.fps)
+            .unwrap_or(30.0);
+        return Ok(Some(estimate::at_bitrate(kbps, kept as f64 / fps)));
+    }
+    if options.test_encode {
+        match encode_sample(analysis, frames, options, control) {
+            Ok((bytes, count)) => return Ok(Some(estimate::scaled(bytes, count, kept))),
+            Err(e) if e.cancelled => return Err(e),
+            Err(e) => warn!("Test encode failed, guessing the output size: {}", e),
+        }
+    }
+    let dimensions = if frames.files.is_empty() {
+        File::open(frames.dir.join(KEPT_Y4M))
+            .and_then(|file| y4m::Y4mReader::new(BufReader::new(file)))
+            .map(|reader| reader.dimensions())
+            .ok()
+    } else {
+        image::image_dimensions(&frames.files[0]).ok()
+    };
+    Ok(dimensions.map(|(width, height)| estimate::guess(options.codec, width, height, kept)))
+}
+
+/// Encodes up to [`SAMPLE_FRAMES`] consecutive kept frames from the middle
+/// of the video the way processing would, and returns the size of the
+/// result and how many frames it holds.
+fn encode_sample(
+    analysis: &Analysis,
+    frames: &Frames,
+    options: &ProcessOptions,
+    control: &JobControl,
+) -> Result<(u64, usize), ProcessError> {
+    let sample_dir = frames.dir.join("sample");
+    fs::create_dir_all(&sample_dir)
+        .map_err(|e| ProcessError::new(format!("Failed to create sample directory: {}", e)))?;
+    let write_error =
+        |e: std::io::Error| ProcessError::new(format!("Failed to write sample: {}", e));
+
+    let mut digits = sequence::FRAME_DIGITS;
+    let count = if frames.files.is_empty() {
+        // the kept frames are already in one y4m file
+        let file = File::open(frames.dir.join(KEPT_Y4M)).map_err(write_error)?;
+        let mut reader = y4m::Y4mReader::new(BufReader::new(file)).map_err(write_error)?;
+        let mut sample =
+            BufWriter::new(File::create(sample_dir.join(KEPT_Y4M)).map_err(write_error)?);
+        sample.write_all(reader.header()).map_err(write_error)?;
+        let mut count = 0;
+        while count < SAMPLE_FRAMES {
+            let Some(frame) = reader.next_frame().map_err(write_error)? else {
+                break;
+            };
+            y4m::write_frame(&mut sample, &frame).map_err(write_error)?;
+            count += 1;
+        }
+        sample.flush().map_err(write_error)?;
+        count
+    } else {
+        let kept: Vec<&PathBuf> = frames
+            .files
+            .iter()
+            .zip(&analysis.removed)
+            .filter_map(|(frame, &dead)| (!dead).then_some(frame))
+            .collect();
+        let count = kept.len().min(SAMPLE_FRAMES);
+        let start = (kept.len() - count) / 2;
+        digits = sequence::digits_for(count);
+        for (index, frame) in kept[start..start + count].iter().enumerate() {
+            let target =
+                sample_dir.join(sequence::frame_name(index + 1, digits, &frames.extension));
+            fs::hard_link(frame, &target)
+                .or_else(|_| fs::copy(frame, &target).map(|_| ()))
+                .map_err(write_error)?;
+        }
+        count
+    };
+
+    let output = frames
+        .dir
+        .join(format!("sample.{}", options.codec.extension()));
+    stitch_frames_into_video(
+        &sample_dir,
+        &frames.extension,
+        digits,
+        &frames.source,
+        options,
+        &output,
+        control,
+    )?;
+    let bytes = fs::metadata(&output).map_err(write_error)?.len();
+    debug!("Test encode of {} frames: {} bytes", count, bytes);
+    Ok((bytes, count))
+}
+
+/// Scores every frame of `input_file` without writing a video.
+pub async fn analyze_video(
+    input_file: &Path,
+    options: &ProcessOptions,
+) -> Result<Analysis, ProcessError> {
+    let input_file = input_file.to_path_buf();
+    let options = options.clone();
+    on_blocking_pool(move || analyze(&input_file, &options, &JobControl::default())).await
+}
+
+/// Encodes the image sequence `frame_0001.<ext>`, `frame_0002.<ext>`, ... in
+/// `folder` into `output_file`, with the format taken from
+/// `options.frame_format`. The numbers may have any width, as long as it is
+/// the same for every frame.
+pub fn stitch_frames(
+    folder: &Path,
+    options: &ProcessOptions,
+    output_file: &Path,
+) -> Result<(), ProcessError> {
+    if options.frame_format == FrameFormat::Y4m {
+        return Err(ProcessError::new("Only image sequences can be stitched"));
+    }
+    let sequence = sequence::detect(folder).map_err(ProcessError::new)?;
+    let source = Source {
+        pixel_format: sequence
+            .frames
+            .first()
+            .and_then(|frame| image_pixel_format(frame)),
+        ..Source::default()
+    };
+    stitch_frames_into_video(
+        folder,
+        options.frame_format.extension(),
+        sequence.pattern.matches('#').count(),
+        &source,
+        options,
+        output_file,
+        &JobControl::default(),
+    )
+}
+
+/// Outcome of a finished job, with the statistics the app charts.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct JobSummary {
+    pub output: String,
+    /// The output already existed and the collision policy skipped the job.
+    pub skipped: bool,
+    pub frames_total: usize,
+    pub frames_removed: usize,
+    /// Running time of the input and of the output, from their frame counts
+    /// and rates.
+    pub duration_before_secs: f64,
+    pub duration_after_secs: f64,
+    /// Size on disk of the input and of the output; for directories, of the
+    /// files in them.
+    pub input_bytes: u64,
+    pub output_bytes: u64,
+    pub elapsed_secs: f64,
+    pub stages: StageTimes,
+    /// Mean similarity of consecutive frames under the job's metric.
+    pub score_mean: Option<f32>,
+    /// ID of the job's transcript; see [`logging::LogStore::job_log`].
+    pub job_id: String,
+    /// How close the output is to the source; see [`quality::verify`].
+    pub quality: Option<QualityReport>,
+    /// How many times faster than the source a timelapse or a retimed
+    /// video plays.
+    pub speed_up: Option<f64>,
+    /// Nothing was removed, so the source's video stream was copied rather
+    /// than encoded again.
+    pub copied: bool,
+}
+
+pub(crate) fn process(
+    input_file: &Path,
+    options: &ProcessOptions,
+    control: &JobControl,
+) -> Result<JobSummary, ProcessError> {
+    in_job_span(input_file, options, |job| {
+        let _lock = workspace::lock_input(input_file).map_err(ProcessError::new)?;
+        run_job(input_file, options, job, control)
+    })
+}
+
+/// `options` with the video stream of `input_file` to process filled in,
+/// after checking that a chosen one exists. Image sequences and animated
+/// WebP have just the one.
+fn with_video_stream(
+    input_file: &Path,
+    options: &ProcessOptions,
+) -> Result<ProcessOptions, ProcessError> {
+    let mut options = options.clone();
+    if input_file.is_dir() || animation::is_animated_webp(input_file) {
+        return Ok(options);
+    }
+    let streams = streams::list(input_file)?;
+    let index = match options.video_stream_index {
+        Some(index) if index >= streams.len() => {
+            return Err(ProcessError::new(format!(
+                "There is no video stream {}, the source has {}",
+                index,
+                streams.len()
+            )))
+        }
+        Some(index) => index,
+        None => streams::default_index(&streams),
+    };
+    if streams.len() > 1 {
+        info!("Using video stream {} of {}", index, streams.len());
+    }
+    options.video_stream_index = Some(index);
+    Ok(options)
+}
+
+/// `options` with those that timelapse mode replaces filled in, after
+/// checking its own.
+fn with_timelapse(mut options: ProcessOptions) -> Result<ProcessOptions, ProcessError> {
+    let Some(timelapse) = options.timelapse else {
+        return Ok(options);
+    };
+    timelapse.validate().map_err(ProcessError::new)?;
+    info!(
+        "Building a {} fps timelapse of frames changing by over {}%",
+        timelapse.fps, timelapse.min_change_percent
+    );
+    options.threshold = timelapse.threshold();
+    options.exit_threshold = None;
+    options.metric = Metric::MeanAbsDiff;
+    options.framerate = Some(timelapse.fps);
+    options.retime = None;
+    Ok(options)
+}
+
+/// The audio of `input_file`, retimed to last as long as `frames_kept` of
+/// its `frames_total` frames at the frame rate `options.retime` asks for.
+/// Image sequences and animated WebP have none.
+pub(crate) fn retimed_audio(
+    input_file: &Path,
+    frames_total: usize,
+    frames_kept: usize,
+    source: &Source,
+    options: &ProcessOptions,
+) -> Option<Audio> {
+    let fps = options.retime?;
+    if input_file.is_dir() || animation::is_animated_webp(input_file) {
+        return None;
+    }
+    let tempo = retime::tempo(
+        frames_total as f64 / source_fps(source, options),
+        frames_kept as f64 / fps,
+    )?;
+    info!("Retiming the audio to {:.2}x its tempo", tempo);
+    Some(Audio {
+        input: input_file.to_path_buf(),
+        tempo,
+    })
+}
+
+/// `options` with the bitrate that fits `frames_kept` frames, and the
+/// source's retimed audio if any, in `options.target_size_mb`.
+pub(crate) fn with_target_size(
+    mut options: ProcessOptions,
+    frames_kept: usize,
+    source: &Source,
+) -> Result<ProcessOptions, ProcessError> {
+    let Some(mb) = options.target_size_mb else {
+        return Ok(options);
+    };
+    let fps = options.output_framerate().or(source.fps).unwrap_or(30.0);
+    let secs = frames_kept as f64 / fps;
+    let audio_kbps = match source.audio {
+        Some(_) => estimate::AAC_KBPS,
+        None => 0,
+    };
+    let kbps = estimate::bitrate_for_size((mb * 1_000_000.0) as u64, secs, audio_kbps).ok_or_else(
+        || ProcessError::new(format!("{:.1}s of video do not fit in {} MB", secs, mb)),
+    )?;
+    info!(
+        "Fitting {:.1}s of video in {} MB at {} kbit/s",
+        secs, mb, kbps
+    );
+    options.target_bitrate = Some(kbps);
+    Ok(options)
+}
+
+/// Removes dead frames from `input_file` and writes the processed video.
+pub async fn process_video(
+    input_file: &Path,
+    options: &ProcessOptions,
+) -> Result<JobSummary, ProcessError> {
+    let input_file = input_file.to_path_buf();
+    let options = options.clone();
+    on_blocking_pool(move || process(&input_file, &options, &JobControl::default())).await
+}
+
+/// Runs the blocking `job` on the tokio runtime's blocking pool, so the
+/// async wrappers leave the caller's worker thread free while ffmpeg and
+/// the comparison run. Needs to be called within a tokio runtime.
+async fn on_blocking_pool<T: Send + 'static>(
+    job: impl FnOnce() -> Result<T, ProcessError> + Send + 'static,
+) -> Result<T, ProcessError> {
+    tokio::task::spawn_blocking(job)
+        .await
+        .map_err(|e| ProcessError::new(format!("Job failed: {}", e)))?
+}
+
+/// Creates the directory an image sequence is written to, clearing out the
+/// frames of an earlier run that would otherwise outnumber the new ones.
+fn prepare_sequence_dir(dir: &Path, extension: &str) -> Result<(), ProcessError> {
+    fs::create_dir_all(dir)
+        .map_err(|e| ProcessError::new(format!("Failed to create {}: {}", dir.display(), e)))?;
+    for entry in fs::read_dir(dir).into_iter().flatten().flatten() {
+        let path = entry.path();
+        let is_frame = path.extension().is_some_and(|ext| ext == extension)
+            && entry.file_name().to_string_lossy().starts_with("frame_");
+        if is_frame {
+            if let Err(e) = fs::remove_file(&path) {
+                warn!("Failed to remove file {}: {}", path.display(), e);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Where processing `input` with `options` writes, before the collision
+/// policy is applied: the video, cut list or image sequence directory.
+pub(crate) fn output_path(input: &Path, options: &ProcessOptions) -> Result<PathBuf, ProcessError> {
+    let preset = options.preset.as_deref();
+    match (options.cut_list, options.image_sequence) {
+        (Some(cut_list), _) => output::target(input, &options.output, preset, cut_list.extension()),
+        (None, _) if options.smart_cut => {
+            output::target(input, &options.output, preset, &smart_cut_extension(input)?)
+        }
+        (None, Some(sequence)) => {
+            output::sequence_target(input, &options.output, preset, sequence.extension())
+        }
+        (None, None) => output::target(input, &options.output, preset, options.codec.extension()),
+    }
+}
+
+/// Smart cut output keeps the source's container.
+fn smart_cut_extension(input: &Path) -> Result<String, ProcessError> {
+    if input.is_dir() {
+        return Err(ProcessError::new("Smart cut needs a video input"));
+    }
+    Ok(input.extension().map_or_else(
+        || "mp4".to_string(),
+        |ext| ext.to_string_lossy().into_owned(),
+    ))
+}
+
+/// Copies the video stream of `input_file` into `output_file` as it is,
+/// for jobs that removed nothing. Returns `false`, having written nothing,
+/// when the output is to differ from the source in more than its frames:
+/// in codec, profile, colour signalling, frame rate, picture or bitrate, or
+/// in being bit-exact.
+fn copy_unchanged(
+    input_file: &Path,
+    frames: &Frames,
+    options: &ProcessOptions,
+    output_file: &Path,
+    control: &JobControl,
+) -> Result<bool, ProcessError> {
+    let source = &frames.source;
+    let changes_video = frames.borrowed
+        || frames.replaced
+        || options.image_sequence.is_some()
+        || options.output_framerate().is_some()
+        || options.target_bitrate.is_some()
+        || options.target_size_mb.is_some()
+        || options.force_8bit
+        || options.deterministic
+        || source.crop.is_some()
+        || source.deinterlace.is_some();
+    if changes_video || animation::is_animated_webp(input_file) {
+        return Ok(false);
+    }
+    let streams = streams::list(input_file)?;
+    let Some(stream) = streams.get(source.stream) else {
+        return Ok(false);
+    };
+    let same_profile = options
+        .codec
+        .reported_profile(&options.mezzanine)
+        .is_none_or(|profile| stream.profile.as_deref() == Some(profile));
+    // the tags the encoder would write, which the stream may lack
+    let tags = source.color.clone().map(|color| ColorInfo {
+        mastering_display: None,
+        content_light: None,
+        ..color
+    });
+    if stream.codec != options.codec.codec_name() || !same_profile || stream.color != tags {
+        return Ok(false);
+    }
+    info!("Nothing to remove, copying the video stream instead of encoding it");
+    let result = supervisor::run_reporting(
+        || {
+            let mut command = ffmpeg::command()?;
+            command
+                .arg("-i")
+                .arg(paths::ffmpeg_arg(input_file))
+                .args([
+                    "-map",
+                    &streams::map(source.stream),
+                    "-c",
+                    "copy",
+                    "-an",
+                    "-y",
+                ])
+                .arg(paths::ffmpeg_arg(output_file));
+            Ok(command)
+        },
+        control,
+        Stage::Encoding,
+        None,
+    );
+    match result {
+        Ok(_) => Ok(true),
+        Err(e @ RunError::Cancelled) => Err(ProcessError::ffmpeg("Failed to copy video", e)),
+        Err(e) => {
+            warn!(
+                "Failed to copy the video stream, encoding it instead: {}",
+                e
+            );
+            let _ = fs::remove_file(output_file);
+            Ok(false)
+        }
+    }
+}
+
+/// Moves `frame` into the `removed` or the kept folder of the undo
+/// directory `dir`. Kept frames are linked, as the encoder still needs them.
+fn set_aside(frame: &Path, dead: bool, dir: &Path) -> std::io::Result<()> {
+    let name = frame.file_name().unwrap_or_default();
+    if dead {
+        fs::rename(frame, dir.join(workspace::REMOVED_DIR).join(name))
+    } else {
+        let target = dir.join(workspace::KEPT_DIR).join(name);
+        fs::hard_link(frame, &target).or_else(|_| fs::copy(frame, &target).map(|_| ()))
+    }
+}
+
+/// Drops the dead frames, or sets them aside in the undo directory `keep`,
+/// and encodes the rest into `output_video`.
+fn encode_kept_frames(
+    analysis: &Analysis,
+    frames: &Frames,
+    options: &ProcessOptions,
+    output_video: &Path,
+    keep: Option<&Path>,
+    control: &JobControl,
+) -> Result<(), ProcessError> {
+    // Drop the dead frames, keeping the rest in order
+    let mut digits = sequence::FRAME_DIGITS;
+    if !frames.files.is_empty() {
+        if !frames.borrowed {
+            for (frame, &dead) in frames.files.iter().zip(&analysis.removed) {
+                let result = match keep {
+                    Some(dir) => set_aside(frame, dead, dir),
+                    None if dead => fs::remove_file(frame),
+                    None => Ok(()),
+                };
+                if let Err(e) = result {
+                    warn!("Failed to remove file {}: {}", frame.display(), e);
+                }
+            }
+        }
+        digits = collect_kept_frames(frames, &analysis.removed)?;
+    }
+
+    let output_file = match options.image_sequence {
+        Some(image_sequence) => output_video.join(sequence::frame_pattern(
+            sequence::digits_for(analysis.frames_total() - analysis.frames_removed()),
+            image_sequence.extension(),
+        )),
+        None => output_video.to_path_buf(),
+    };
+    stitch_frames_into_video(
+        &frames.dir,
+        &frames.extension,
+        digits,
+        &frames.source,
+        options,
+        &output_file,
+        control,
+    )
+}
+
+/// The undo directory to set this job's frames aside in, when
+/// `keep_removed` is on and the frames are files that can be kept.
+fn keep_removed_frames(job: &JobDir, frames: &Frames, options: &ProcessOptions) -> Option<PathBuf> {
+    if !options.keep_removed {
+        return None;
+    }
+    if frames.files.is_empty() {
+        warn!("Removed frames can only be kept with an image frame format");
+        return None;
+    }
+    match undo::create(job) {
+        Ok(dir) => Some(dir),
+        Err(e) => {
+            warn!(
+                "Failed to create undo directory, removed frames are deleted: {}",
+                e
+            );
+            None
+        }
+    }
+}
+
+/// Encodes `files`, in order, into `output` as a job with `options` would,
+/// working in `dir`. Used to encode a job again once frames were restored.
+pub(crate) fn restitch(
+    files: &[PathBuf],
+    source: Source,
+    options: &ProcessOptions,
+    output: &Path,
+    dir: &Path,
+    control: &JobControl,
+) -> Result<(), ProcessError> {
+    let extension = files
+        .first()
+        .and_then(|file| file.extension())
+        .ok_or_else(|| ProcessError::new("There are no frames to encode"))?
+        .to_string_lossy()
+        .into_owned();
+    if let Some(sequence) = options.image_sequence {
+        prepare_sequence_dir(output, sequence.extension())?;
+    }
+    let frames = Frames {
+        files: files.to_vec(),
+        extension,
+        // linked into the job directory, so the undo set stays intact
+        borrowed: true,
+        source,
+        dir: dir.to_path_buf(),
+        replaced: false,
+    };
+    let analysis = Analysis {
+        scores: Vec::new(),
+        removed: vec![false; files.len()],
+        first_scored: 0,
+        estimated_size: None,
+    };
+    encode_kept_frames(&analysis, &frames, options, output, None, control)
+}
+
+fn run_job(
+    input_file: &Path,
+    options: &ProcessOptions,
+    job: &JobDir,
+    control: &JobControl,
+) -> Result<JobSummary, ProcessError> {
+    let job_id = job.id();
+    let started = Instant::now();
+    // URLs are named after their file
+    let name = remote::naming_path(input_file);
+    let destination = output::resolve(output_path(&name, options)?, options.output.collision)?;
+    let output_video = match destination {
+        Destination::Write(path) => path,
+        Destination::Skip(path) => {
+            info!("{} already exists, skipping", path.display());
+            return Ok(JobSummary {
+                output: path.to_string_lossy().into_owned(),
+                skipped: true,
+                frames_total: 0,
+                frames_removed: 0,
+                elapsed_secs: started.elapsed().as_secs_f64(),
+                job_id: job_id.to_string(),
+                ..JobSummary::default()
+            });
+        }
+    };
+    if let Some(dir) = output_video
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+    {
+        fs::create_dir_all(dir)
+            .map_err(|e| ProcessError::new(format!("Failed to create {}: {}", dir.display(), e)))?;
+    }
+    let writes_sequence = options.cut_list.is_none() && !options.smart_cut;
+    if let (true, Some(sequence)) = (writes_sequence, options.image_sequence) {
+        prepare_sequence_dir(&output_video, sequence.extension())?;
+    }
+    info!(
+        "Processing {} into {}",
+        input_file.display(),
+        output_video.display()
+    );
+    let clock = Arc::new(StageClock::default());
+    let control = &control.timed(clock.clone());
+    let _awake = power::inhibit_sleep();
+    let remote = remote::open(input_file, options.cache_remote, job, control)?;
+    let source_file = remote.path();
+    let cached = analysis_cache::lookup(source_file, options);
+    let requested = options;
+    let options = &with_timelapse(with_video_stream(source_file, options)?)?;
+    let (analysis, mut frames) =
+        analyze_frames(source_file, options, &job.frames(), cached, control)?;
+    timeline::save(job_id, &analysis, options);
+    analysis_cache::store(source_file, requested, &analysis);
+
+    // times in the source are in its own frames, which --fps names for
+    // image sequences
+    let source_fps = frames.source.fps.or(options.framerate).unwrap_or(30.0);
+    // cut lists and smart cuts keep the source's timing
+    let keeps_timing = options.smart_cut || options.cut_list.is_some();
+    let output_fps = match options.output_framerate() {
+        Some(fps) if !keeps_timing => fps,
+        _ => source_fps,
+    };
+    if keeps_timing && options.retime.is_some() {
+        info!("Cut lists and smart cuts keep the source's timing, not retiming");
+    }
+    // before the frames are encoded, which deletes the removed ones
+    if let Some(dir) = &options.export_removed {
+        export::removed_frames(
+            source_file,
+            frames.source.stream,
+            &frames.files,
+            &analysis.removed,
+            source_fps,
+            dir,
+            control,
+        )?;
+    }
+    // a job that removed nothing need not encode at all
+    let copied = !keeps_timing
+        && analysis.frames_removed() == 0
+        && copy_unchanged(source_file, &frames, options, &output_video, control)?;
+    match options.cut_list {
+        _ if copied => {}
+        Some(format) => {
+            let sequence_frames = if frames.borrowed {
+                frames.files.as_slice()
+            } else {
+                &[]
+            };
+            cutlist::write(
+                format,
+                input_file,
+                sequence_frames,
+                &analysis.removed,
+                source_fps,
+                &output_video,
+            )
+            .map_err(|e| ProcessError::new(format!("Failed to write cut list: {}", e)))?;
+        }
+        None if options.smart_cut => smartcut::cut(
+            source_file,
+            frames.source.stream,
+            &analysis.removed,
+            source_fps,
+            &output_video,
+            &frames.dir,
+            control,
+        )?,
+        None => {
+            frames.source.audio = retimed_audio(
+                source_file,
+                analysis.frames_total(),
+                analysis.frames_total() - analysis.frames_removed(),
+                &frames.source,
+                options,
+            );
+            let options = &with_target_size(
+                options.clone(),
+                analysis.frames_total() - analysis.frames_removed(),
+                &frames.source,
+            )?;
+            let keep = keep_removed_frames(job, &frames, options);
+            encode_kept_frames(
+                &analysis,
+                &frames,
+                options,
+                &output_video,
+                keep.as_deref(),
+                control,
+            )?;
+            if let Some(dir) = keep {
+                let manifest = undo::Manifest {
+                    input: Some(input_file.to_path_buf()),
+                    output: output_video.clone(),
+                    options: options.clone(),
+                    source: frames.source.clone(),
+                    frames: frames
+                        .files
+                        .iter()
+                        .map(|frame| match frames.borrowed {
+                            true => frame.clone(),
+                            false => dir
+                                .join(workspace::KEPT_DIR)
+                                .join(frame.file_name().unwrap_or_default()),
+                        })
+                        .collect(),
+                    removed: analysis.removed.clone(),
+                    borrowed: frames.borrowed,
+                };
+                undo::save(&dir, &manifest).map_err(|e| {
+                    ProcessError::new(format!("Failed to write undo manifest: {}", e))
+                })?;
+                job.keep();
+                info!("Kept removed frames in {}", dir.display());
+            }
+        }
+    }
+
+    if options.timestamp_map && options.cut_list.is_none() {
+        let map = output_video.with_extension("timestamps.json");
+        cutlist::write_timestamp_map(
+            input_file,
+            &output_video,
+            &analysis.removed,
+            source_fps,
+            output_fps,
+            &map,
+        )
+        .map_err(|e| ProcessError::new(format!("Failed to write timestamp map: {}", e)))?;
+        info!("Wrote timestamp map {}", map.display());
+    }
+
+    let quality = if !options.verify_quality {
+        None
+    } else if options.cut_list.is_some() || options.image_sequence.is_some() || frames.borrowed {
+        info!("Quality verification needs a video source and output, skipping");
+        None
+    } else if animation::is_animated_webp(source_file) {
+        info!("ffmpeg cannot read animated WebP, skipping quality verification");
+        None
+    } else {
+        Some(quality::verify(
+            source_file,
+            &output_video,
+            frames.source.stream,
+            &analysis.removed,
+            frames.source.crop.filter(|_| !options.smart_cut),
+            control,
+        )?)
+    };
+
+    let elapsed_secs = started.elapsed().as_secs_f64();
+    info!(
+        "Removed {} of {} frames from {} in {:.1}s",
+        analysis.frames_removed(),
+        analysis.frames_total(),
+        input_file.display(),
+        elapsed_secs
+    );
+    let scores = &analysis.scores;
+    let frames_kept = analysis.frames_total() - analysis.frames_removed();
+    let duration_before_secs = analysis.frames_total() as f64 / source_fps;
+    let duration_after_secs = frames_kept as f64 / output_fps;
+    let retimed = options.retime.is_some() && !keeps_timing;
+    let speed_up = if options.timelapse.is_some() || retimed {
+        retime::tempo(duration_before_secs, duration_after_secs)
+    } else {
+        None
+    };
+    if let Some(speed_up) = speed_up {
+        info!("The output plays {:.1}x faster than the source", speed_up);
+    }
+    Ok(JobSummary {
+        skipped: false,
+        frames_total: analysis.frames_total(),
+        frames_removed: analysis.frames_removed(),
+        duration_before_secs,
+        duration_after_secs,
+        input_bytes: disk_size(source_file),
+        output_bytes: disk_size(&output_video),
+        output: output_video.to_string_lossy().into_owned(),
+        elapsed_secs,
+        stages: clock.finish(),
+        score_mean: (!scores.is_empty()).then(|| scores.iter().sum::<f32>() / scores.len() as f32),
+        job_id: job_id.to_string(),
+        quality,
+        speed_up,
+        copied,
+    })
+}
+
+/// Size of the file at `path`, or of the files directly inside it.
+fn disk_size(path: &Path) -> u64 {
+    match fs::read_dir(path) {
+        Ok(entries) => entries
+            .flatten()
+            .filter_map(|entry| entry.metadata().ok())
+            .filter(|metadata| metadata.is_file())
+            .map(|metadata| metadata.len())
+            .sum(),
+        Err(_) => fs::metadata(path).map_or(0, |metadata| metadata.len()),
+    }
+}
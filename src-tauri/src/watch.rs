@@ -0,0 +1,315 @@
+//! Watch folder: video files that appear in a folder are processed with a
+//! chosen preset, one at a time, and the originals can be moved to an
+//! `archive` subfolder afterwards.
+//!
+//! Only the folder itself is watched, not its subfolders, so processed
+//! videos (written to `processed` unless the preset names a directory) and
+//! archived originals are not picked up again.
+
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher as _};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{info, warn};
+
+use crate::control::{CancellationToken, JobControl};
+use crate::error::ProcessError;
+use crate::history;
+use crate::ingest::is_video;
+use crate::output;
+use crate::presets;
+use crate::processed::{self, Processed, ProcessedPolicy};
+use crate::video_fixer::{self, JobSummary, ProcessOptions};
+
+/// How long a new file's size must stay the same before it is queued, so
+/// files still being copied in are not processed half-written.
+const SETTLE_TIME: Duration = Duration::from_secs(3);
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// The persisted watch folder configuration.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WatchSettings {
+    /// Resume watching when the app starts.
+    pub enabled: bool,
+    pub folder: Option<PathBuf>,
+    /// Preset new files are processed with; the settings when unset.
+    pub preset: Option<String>,
+    /// Move each original to `<folder>/archive` once it has been processed.
+    pub archive_originals: bool,
+    /// What to do with files that were processed before, such as ones
+    /// copied in again.
+    pub if_processed: ProcessedPolicy,
+}
+
+/// What happened to a file in the watch folder.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum WatchEvent {
+    Queued {
+        input: PathBuf,
+    },
+    /// Left alone as it was processed before.
+    Skipped {
+        input: PathBuf,
+        processed: Processed,
+    },
+    Started {
+        input: PathBuf,
+    },
+    Finished {
+        input: PathBuf,
+        summary: JobSummary,
+    },
+    Failed {
+        input: PathBuf,
+        error: ProcessError,
+    },
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct WatchStatus {
+    pub active: bool,
+    pub folder: Option<PathBuf>,
+    /// Files waiting to be processed, in order.
+    pub queued: Vec<PathBuf>,
+    pub current: Option<PathBuf>,
+}
+
+struct ActiveWatch {
+    /// Dropping the watcher ends the event stream.
+    watcher: RecommendedWatcher,
+    stop: CancellationToken,
+    worker: thread::JoinHandle<()>,
+    status: Arc<Mutex<WatchStatus>>,
+}
+
+/// A watch folder that can be started, replaced and stopped. The app keeps
+/// one in its [`crate::state::AppState`].
+#[derive(Default)]
+pub struct WatchFolder {
+    active: Mutex<Option<ActiveWatch>>,
+}
+
+fn options_for(settings: &WatchSettings, folder: &Path) -> Result<ProcessOptions, String> {
+    let mut options = match &settings.preset {
+        Some(name) => presets::get(name)
+            .ok_or_else(|| format!("No preset named \"{}\"", name))?
+            .options(),
+        None => ProcessOptions::default(),
+    };
+    if options.output.dir.is_none() {
+        options.output.dir = Some(folder.join("processed"));
+    }
+    Ok(options)
+}
+
+impl WatchFolder {
+    /// Starts watching `settings.folder`, replacing any watch already running.
+    /// `on_event` is called from the worker thread.
+    pub fn start(
+        &self,
+        settings: &WatchSettings,
+        on_event: impl Fn(WatchEvent) + Send + 'static,
+    ) -> Result<(), String> {
+        let folder = settings
+            .folder
+            .clone()
+            .ok_or_else(|| "No watch folder set".to_string())?;
+        if !folder.is_dir() {
+            return Err(format!("{} is not a directory", folder.display()));
+        }
+        let options = options_for(settings, &folder)?;
+        self.stop();
+
+        let (events, changes) = mpsc::channel();
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| match event {
+                Ok(event) if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) => {
+                    for path in event.paths {
+                        let _ = events.send(path);
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => warn!("Watch error: {}", e),
+            })
+            .map_err(|e| format!("Failed to start watching: {}", e))?;
+        watcher
+            .watch(&folder, RecursiveMode::NonRecursive)
+            .map_err(|e| format!("Failed to watch {}: {}", folder.display(), e))?;
+
+        let stop = CancellationToken::new();
+        let status = Arc::new(Mutex::new(WatchStatus {
+            active: true,
+            folder: Some(folder.clone()),
+            ..WatchStatus::default()
+        }));
+        let worker = {
+            let stop = stop.clone();
+            let status = status.clone();
+            let archive = settings.archive_originals.then(|| folder.join("archive"));
+            let if_processed = settings.if_processed;
+            thread::spawn(move || {
+                Worker {
+                    options,
+                    archive,
+                    if_processed,
+                    stop,
+                    status,
+                    on_event: Box::new(on_event),
+                    processed: HashMap::new(),
+                }
+                .run(changes)
+            })
+        };
+        info!("Watching {}", folder.display());
+        *self.active.lock().unwrap() = Some(ActiveWatch {
+            watcher,
+            stop,
+            worker,
+            status,
+        });
+        Ok(())
+    }
+
+    /// Stops watching. A job that is running is cancelled and its file is
+    /// left in place.
+    pub fn stop(&self) {
+        let Some(active) = self.active.lock().unwrap().take() else {
+            return;
+        };
+        active.stop.cancel();
+        drop(active.watcher);
+        let _ = active.worker.join();
+        info!("Stopped watching");
+    }
+
+    pub fn status(&self) -> WatchStatus {
+        match self.active.lock().unwrap().as_ref() {
+            Some(active) => active.status.lock().unwrap().clone(),
+            None => WatchStatus::default(),
+        }
+    }
+}
+
+struct Worker {
+    options: ProcessOptions,
+    archive: Option<PathBuf>,
+    if_processed: ProcessedPolicy,
+    stop: CancellationToken,
+    status: Arc<Mutex<WatchStatus>>,
+    on_event: Box<dyn Fn(WatchEvent) + Send>,
+    /// Inputs processed so far, with their modification time at the end.
+    processed: HashMap<PathBuf, SystemTime>,
+}
+
+impl Worker {
+    fn run(mut self, changes: mpsc::Receiver<PathBuf>) {
+        // files seen but not yet settled: size at the last change and when
+        let mut settling: HashMap<PathBuf, (u64, Instant)> = HashMap::new();
+        let mut queue: VecDeque<PathBuf> = VecDeque::new();
+
+        while !self.stop.is_cancelled() {
+            let first = match changes.recv_timeout(POLL_INTERVAL) {
+                Ok(path) => Some(path),
+                Err(RecvTimeoutError::Timeout) => None,
+                Err(RecvTimeoutError::Disconnected) => break,
+            };
+            for path in first.into_iter().chain(changes.try_iter()) {
+                if is_video(&path) && !queue.contains(&path) && !self.is_processed(&path) {
+                    settling.entry(path).or_insert((u64::MAX, Instant::now()));
+                }
+            }
+
+            settling.retain(|path, (size, since)| {
+                let Ok(metadata) = fs::metadata(path) else {
+                    // deleted or renamed away before it settled
+                    return false;
+                };
+                if metadata.len() != *size {
+                    *size = metadata.len();
+                    *since = Instant::now();
+                    return true;
+                }
+                if since.elapsed() < SETTLE_TIME {
+                    return true;
+                }
+                if let Some(processed) = processed::skip(path, &self.options, self.if_processed) {
+                    if let Ok(modified) = metadata.modified() {
+                        self.processed.insert(path.clone(), modified);
+                    }
+                    (self.on_event)(WatchEvent::Skipped {
+                        input: path.clone(),
+                        processed,
+                    });
+                    return false;
+                }
+                queue.push_back(path.clone());
+                (self.on_event)(WatchEvent::Queued {
+                    input: path.clone(),
+                });
+                false
+            });
+            self.status.lock().unwrap().queued = queue.iter().cloned().collect();
+
+            // one job per pass keeps new files settling while the queue drains
+            if let Some(input) = queue.pop_front() {
+                self.process(input);
+            }
+        }
+    }
+
+    /// Whether `path` was processed already and has not changed since, so
+    /// events on an original that stays in the folder do not requeue it.
+    fn is_processed(&self, path: &Path) -> bool {
+        let modified = fs::metadata(path).and_then(|m| m.modified()).ok();
+        modified.is_some() && self.processed.get(path) == modified.as_ref()
+    }
+
+    fn process(&mut self, input: PathBuf) {
+        {
+            let mut status = self.status.lock().unwrap();
+            status.queued.retain(|queued| queued != &input);
+            status.current = Some(input.clone());
+        }
+        (self.on_event)(WatchEvent::Started {
+            input: input.clone(),
+        });
+        let mut control = JobControl::default();
+        control.cancel = self.stop.clone();
+        let result = video_fixer::process(&input, &self.options, &control);
+        self.status.lock().unwrap().current = None;
+
+        match result {
+            Ok(summary) => {
+                history::record(&input.to_string_lossy(), &self.options, &summary);
+                if let Ok(modified) = fs::metadata(&input).and_then(|m| m.modified()) {
+                    self.processed.insert(input.clone(), modified);
+                }
+                if let Some(archive) = &self.archive {
+                    if let Err(e) = archive_original(&input, archive) {
+                        warn!("Failed to archive {}: {}", input.display(), e);
+                    }
+                }
+                (self.on_event)(WatchEvent::Finished { input, summary });
+            }
+            // stopping the watch is not a failure of the file
+            Err(e) if e.cancelled => {}
+            Err(error) => (self.on_event)(WatchEvent::Failed { input, error }),
+        }
+    }
+}
+
+fn archive_original(input: &Path, archive: &Path) -> std::io::Result<()> {
+    fs::create_dir_all(archive)?;
+    let mut target = archive.join(input.file_name().unwrap_or_default());
+    if target.exists() {
+        target = output::next_free(&target);
+    }
+    fs::rename(input, target)
+}
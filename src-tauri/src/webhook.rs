@@ -0,0 +1,100 @@
+//! An HTTP POST to a user's endpoint whenever a job completes or fails, for
+//! automation setups that do not watch the app.
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::error::ProcessError;
+use crate::video_fixer::JobSummary;
+
+/// Pause before the first retry, doubled for each one after it.
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// The persisted webhook configuration.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WebhookSettings {
+    /// Endpoint the payloads are posted to; nothing is sent when unset.
+    pub url: Option<String>,
+    /// Seconds a single attempt may take, connecting included.
+    pub timeout_secs: u64,
+    /// How often a failed delivery is retried.
+    pub retries: u32,
+}
+
+impl Default for WebhookSettings {
+    fn default() -> Self {
+        WebhookSettings {
+            url: None,
+            timeout_secs: 10,
+            retries: 3,
+        }
+    }
+}
+
+/// Reports a finished job, in the background.
+pub fn job_finished(settings: &WebhookSettings, job: u64, input: &Path, summary: &JobSummary) {
+    let payload = json!({
+        "event": "job-finished",
+        "job": job,
+        "input": input,
+        "summary": summary,
+    });
+    post(settings, payload);
+}
+
+/// Reports a failed job, in the background.
+pub fn job_failed(settings: &WebhookSettings, job: u64, input: &Path, error: &ProcessError) {
+    let payload = json!({
+        "event": "job-failed",
+        "job": job,
+        "input": input,
+        "error": error,
+    });
+    post(settings, payload);
+}
+
+fn post(settings: &WebhookSettings, payload: serde_json::Value) {
+    let Some(url) = settings.url.clone() else {
+        return;
+    };
+    let agent = ureq::AgentBuilder::new()
+        .timeout(Duration::from_secs(settings.timeout_secs.max(1)))
+        .build();
+    let retries = settings.retries;
+    let body = payload.to_string();
+    thread::spawn(move || {
+        let mut delay = RETRY_DELAY;
+        for attempt in 0..=retries {
+            if attempt > 0 {
+                thread::sleep(delay);
+                delay *= 2;
+            }
+            let result = agent
+                .post(&url)
+                .set("Content-Type", "application/json")
+                .send_string(&body);
+            match result {
+                Ok(_) => {
+                    info!(
+                        "Posted {} to {}",
+                        payload["event"].as_str().unwrap_or_default(),
+                        url
+                    );
+                    return;
+                }
+                // the endpoint refused the payload itself; sending it again
+                // will not change that
+                Err(ureq::Error::Status(status, _)) if (400..500).contains(&status) => {
+                    warn!("Webhook {} rejected the payload with {}", url, status);
+                    return;
+                }
+                Err(e) => warn!("Webhook {} failed (attempt {}): {}", url, attempt + 1, e),
+            }
+        }
+    });
+}
@@ -0,0 +1,271 @@
+use crate::logging;
+use crate::settings;
+use fs2::FileExt;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::env;
+use std::fs;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime};
+use tracing::warn;
+
+/// Prefix of the per-job directories created in the work directory.
+const JOB_DIR_PREFIX: &str = "dead-frames-";
+/// Lock file held for as long as a job directory is in use.
+const JOB_LOCK: &str = ".lock";
+/// Job directories without a lock file (from builds before locking) are only
+/// considered stale once they are this old.
+const UNLOCKED_STALE_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Directory of the input locks, in the OS temp dir rather than the work
+/// directory so that instances set to different work directories still
+/// see each other's locks.
+const INPUT_LOCKS_DIR: &str = "dead-frames-inputs";
+
+/// Minimum free space required in the work directory. Extracted frames for
+/// even short clips easily run into gigabytes.
+const MIN_FREE_SPACE: u64 = 2 * 1024 * 1024 * 1024;
+
+/// Directory in which intermediate frames are written.
+pub fn work_dir() -> PathBuf {
+    settings::current().temp_dir.unwrap_or_else(env::temp_dir)
+}
+
+/// Checks that `path` can be used as a work directory: it must be a
+/// writable directory with at least [`MIN_FREE_SPACE`] bytes available.
+pub fn validate_work_dir(path: &Path) -> Result<(), String> {
+    if !path.exists() {
+        fs::create_dir_all(path)
+            .map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+    }
+    if !path.is_dir() {
+        return Err(format!("{} is not a directory", path.display()));
+    }
+
+    tempfile::tempfile_in(path)
+        .map_err(|e| format!("{} is not writable: {}", path.display(), e))?;
+
+    let available = fs2::available_space(path)
+        .map_err(|e| format!("Failed to query free space of {}: {}", path.display(), e))?;
+    if available < MIN_FREE_SPACE {
+        return Err(format!(
+            "{} has only {} MiB free, at least {} MiB is required",
+            path.display(),
+            available / (1024 * 1024),
+            MIN_FREE_SPACE / (1024 * 1024)
+        ));
+    }
+
+    Ok(())
+}
+
+/// Subdirectories and files of a job directory; see [`JobDir`].
+pub const FRAMES_DIR: &str = "frames";
+pub const DOWNLOAD_DIR: &str = "download";
+pub const KEPT_DIR: &str = "kept";
+pub const REMOVED_DIR: &str = "removed";
+pub const MANIFEST: &str = "job.json";
+pub const JOB_LOG: &str = "job.log";
+
+/// The directory everything one job writes goes into, named after the job:
+///
+/// - `frames/`: the extracted frames and whatever else is made from them
+/// - `download/`: a URL input's local copy
+/// - `kept/` and `removed/`: the frames set aside for undoing removals
+/// - `job.json`: the undo manifest
+/// - `job.log`: the job's transcript while it runs
+///
+/// Jobs never share a directory, and cleaning up after one is deleting its
+/// directory. It is locked while the job runs and removed when dropped,
+/// unless kept as an undo set.
+pub struct JobDir {
+    id: String,
+    path: PathBuf,
+    // released before the directory is removed
+    lock: Option<File>,
+    keep: AtomicBool,
+}
+
+impl JobDir {
+    /// The ID of the job the directory belongs to.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Where the job's frames are extracted and encoded from.
+    pub fn frames(&self) -> PathBuf {
+        self.path.join(FRAMES_DIR)
+    }
+
+    /// Leaves the undo set in place when the job is done; the frames and
+    /// the download still go.
+    pub fn keep(&self) {
+        self.keep.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Drop for JobDir {
+    fn drop(&mut self) {
+        logging::archive_job_log(&self.id, &self.path.join(JOB_LOG));
+        drop(self.lock.take());
+        let doomed = if self.keep.load(Ordering::Relaxed) {
+            vec![self.path.join(FRAMES_DIR), self.path.join(DOWNLOAD_DIR)]
+        } else {
+            vec![self.path.clone()]
+        };
+        for path in doomed.into_iter().filter(|path| path.exists()) {
+            if let Err(e) = fs::remove_dir_all(&path) {
+                warn!("Failed to remove {}: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+/// The directory of the job `job_id`, whether or not it exists.
+pub fn job_dir_path(job_id: &str) -> Result<PathBuf, String> {
+    if !logging::is_valid_job_id(job_id) {
+        return Err(format!("Invalid job ID: {}", job_id));
+    }
+    Ok(work_dir().join(format!("{}{}", JOB_DIR_PREFIX, job_id)))
+}
+
+/// Creates and locks the directory of the job `job_id` in the work
+/// directory.
+pub fn create_job_dir(job_id: &str) -> std::io::Result<JobDir> {
+    let path = job_dir_path(job_id)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    fs::create_dir_all(work_dir())?;
+    fs::create_dir(&path)?;
+    let lock = File::create(path.join(JOB_LOCK))?;
+    lock.lock_exclusive()?;
+    let dir = JobDir {
+        id: job_id.to_string(),
+        path,
+        lock: Some(lock),
+        keep: AtomicBool::new(false),
+    };
+    fs::create_dir(dir.frames())?;
+    Ok(dir)
+}
+
+/// Held while a job processes an input; see [`lock_input`].
+pub struct InputLock {
+    _file: File,
+}
+
+/// Locks `input` for the job processing it, so no other job, whether in
+/// this instance, another window's queue, the watch folder or `dfr-cli`,
+/// processes the same file at the same time. Fails when one already does.
+///
+/// Lock files are named after a hash of the canonical path and left in
+/// place; removing one could race with an instance about to lock it.
+pub fn lock_input(input: &Path) -> Result<InputLock, String> {
+    let canonical = fs::canonicalize(input).unwrap_or_else(|_| input.to_path_buf());
+    let hash: String = Sha256::digest(canonical.to_string_lossy().as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    let dir = env::temp_dir().join(INPUT_LOCKS_DIR);
+    let path = dir.join(hash).with_extension("lock");
+    let file = fs::create_dir_all(&dir)
+        .and_then(|()| File::create(&path))
+        .map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+    match file.try_lock_exclusive() {
+        Ok(()) => Ok(InputLock { _file: file }),
+        Err(e) if e.kind() == fs2::lock_contended_error().kind() => Err(format!(
+            "{} is already being processed by another job",
+            input.display()
+        )),
+        Err(e) => Err(format!("Failed to lock {}: {}", path.display(), e)),
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StaleDir {
+    pub path: PathBuf,
+    pub bytes: u64,
+}
+
+/// Job directories left behind by crashed runs.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct WorkspaceReport {
+    pub stale_dirs: Vec<StaleDir>,
+    pub reclaimable_bytes: u64,
+}
+
+fn dir_size(path: &Path) -> u64 {
+    fs::read_dir(path)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| match entry.metadata() {
+            Ok(meta) if meta.is_dir() => dir_size(&entry.path()),
+            Ok(meta) => meta.len(),
+            Err(_) => 0,
+        })
+        .sum()
+}
+
+/// Whether no running job, in this or another instance, owns `dir`, and it
+/// is not an undo set.
+fn is_stale(dir: &Path) -> bool {
+    if dir.join(MANIFEST).is_file() {
+        return false;
+    }
+    match File::open(dir.join(JOB_LOCK)) {
+        Ok(lock) => match lock.try_lock_exclusive() {
+            Ok(()) => {
+                let _ = lock.unlock();
+                true
+            }
+            Err(_) => false,
+        },
+        Err(_) => fs::metadata(dir)
+            .and_then(|meta| meta.modified())
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+            .is_some_and(|age| age > UNLOCKED_STALE_AGE),
+    }
+}
+
+/// Finds job directories in the work directory that no job is using.
+pub fn scan() -> WorkspaceReport {
+    let mut report = WorkspaceReport::default();
+    let Ok(entries) = fs::read_dir(work_dir()) else {
+        return report;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let is_job_dir = entry
+            .file_name()
+            .to_string_lossy()
+            .starts_with(JOB_DIR_PREFIX);
+        if is_job_dir && path.is_dir() && is_stale(&path) {
+            let bytes = dir_size(&path);
+            report.reclaimable_bytes += bytes;
+            report.stale_dirs.push(StaleDir { path, bytes });
+        }
+    }
+    report
+}
+
+/// Removes stale job directories and returns how many bytes were freed.
+pub fn cleanup() -> u64 {
+    scan()
+        .stale_dirs
+        .into_iter()
+        .filter_map(|stale| match fs::remove_dir_all(&stale.path) {
+            Ok(()) => Some(stale.bytes),
+            Err(e) => {
+                warn!("Failed to remove {}: {}", stale.path.display(), e);
+                None
+            }
+        })
+        .sum()
+}
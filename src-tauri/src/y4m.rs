@@ -0,0 +1,167 @@
+//! Minimal YUV4MPEG2 reader and writer used for the raw intermediate format.
+//!
+//! Streams above 8 bits, such as `C420p10`, store each sample in two
+//! little-endian bytes; their luma is scaled down to 8 bits for comparison.
+//! Limited range luma is stretched to full range, so it scores like the
+//! luma of RGB images.
+
+use image::GrayImage;
+use std::io::{self, BufRead, Write};
+
+pub struct Y4mReader<R> {
+    inner: R,
+    header: Vec<u8>,
+    width: u32,
+    height: u32,
+    bit_depth: u32,
+    /// `XCOLORRANGE=FULL`; streams without the tag are limited range.
+    full_range: bool,
+    frame_size: usize,
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+impl<R: BufRead> Y4mReader<R> {
+    pub fn new(mut inner: R) -> io::Result<Self> {
+        let mut header = Vec::new();
+        inner.read_until(b'\n', &mut header)?;
+        let line = String::from_utf8_lossy(&header).trim_end().to_string();
+        let mut tokens = line.split(' ');
+        if tokens.next() != Some("YUV4MPEG2") {
+            return Err(invalid("not a YUV4MPEG2 stream".into()));
+        }
+
+        let (mut width, mut height, mut colorspace) = (0u32, 0u32, "420");
+        let mut full_range = false;
+        for token in tokens {
+            let Some(tag) = token.chars().next() else {
+                return Err(invalid("empty header field".into()));
+            };
+            match (tag, &token[tag.len_utf8()..]) {
+                ('W', w) => width = w.parse().map_err(|_| invalid(format!("bad width {}", w)))?,
+                ('H', h) => {
+                    height = h
+                        .parse()
+                        .map_err(|_| invalid(format!("bad height {}", h)))?
+                }
+                ('C', c) => colorspace = c,
+                ('X', "COLORRANGE=FULL") => full_range = true,
+                _ => {}
+            }
+        }
+        if width == 0 || height == 0 {
+            return Err(invalid("missing frame dimensions".into()));
+        }
+
+        let (layout, bit_depth) = split_depth(colorspace);
+        let (w, h) = (width as usize, height as usize);
+        let (cw, ch) = (w.div_ceil(2), h.div_ceil(2));
+        let samples = match layout {
+            "420" | "420jpeg" | "420paldv" | "420mpeg2" => w * h + 2 * cw * ch,
+            "422" => w * h + 2 * cw * h,
+            "444" => 3 * w * h,
+            "mono" => w * h,
+            other => return Err(invalid(format!("unsupported colorspace {}", other))),
+        };
+        let frame_size = if bit_depth > 8 { 2 * samples } else { samples };
+
+        Ok(Y4mReader {
+            inner,
+            header,
+            width,
+            height,
+            bit_depth,
+            full_range,
+            frame_size,
+        })
+    }
+
+    /// Raw header line, including the trailing newline.
+    pub fn header(&self) -> &[u8] {
+        &self.header
+    }
+
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Size of a frame's raw planes in bytes.
+    pub fn frame_size(&self) -> usize {
+        self.frame_size
+    }
+
+    /// Reads the next frame's raw planes, or `None` at the end of the stream.
+    pub fn next_frame(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut line = Vec::new();
+        if self.inner.read_until(b'\n', &mut line)? == 0 {
+            return Ok(None);
+        }
+        if !line.starts_with(b"FRAME") {
+            return Err(invalid("missing FRAME marker".into()));
+        }
+        let mut frame = vec![0u8; self.frame_size];
+        self.inner.read_exact(&mut frame)?;
+        Ok(Some(frame))
+    }
+
+    /// The luma plane of a frame returned by [`Self::next_frame`], at 8 bits.
+    pub fn luma(&self, frame: &[u8]) -> GrayImage {
+        let luma_size = (self.width * self.height) as usize;
+        let mut luma: Vec<u8> = if self.bit_depth > 8 {
+            let shift = self.bit_depth - 8;
+            frame[..2 * luma_size]
+                .chunks_exact(2)
+                .map(|sample| (u16::from_le_bytes([sample[0], sample[1]]) >> shift) as u8)
+                .collect()
+        } else {
+            frame[..luma_size].to_vec()
+        };
+        if !self.full_range {
+            for sample in &mut luma {
+                *sample = ((sample.saturating_sub(16) as u32 * 255 + 109) / 219).min(255) as u8;
+            }
+        }
+        GrayImage::from_raw(self.width, self.height, luma)
+            .expect("luma plane has the frame dimensions")
+    }
+
+    /// The sample-wise mean of two frames returned by [`Self::next_frame`].
+    pub fn blend(&self, a: &[u8], b: &[u8]) -> Vec<u8> {
+        if self.bit_depth > 8 {
+            a.chunks_exact(2)
+                .zip(b.chunks_exact(2))
+                .flat_map(|(a, b)| {
+                    let a = u16::from_le_bytes([a[0], a[1]]) as u32;
+                    let b = u16::from_le_bytes([b[0], b[1]]) as u32;
+                    ((a + b).div_ceil(2) as u16).to_le_bytes()
+                })
+                .collect()
+        } else {
+            a.iter()
+                .zip(b)
+                .map(|(&a, &b)| (a as u16 + b as u16).div_ceil(2) as u8)
+                .collect()
+        }
+    }
+}
+
+/// Splits a colorspace such as `420p10` or `mono16` into its layout and bit
+/// depth; those without a depth are 8-bit.
+fn split_depth(colorspace: &str) -> (&str, u32) {
+    let layout = colorspace.trim_end_matches(|c: char| c.is_ascii_digit());
+    let depth = colorspace[layout.len()..].parse();
+    let layout = layout
+        .strip_suffix('p')
+        .or((layout == "mono").then_some(layout));
+    match (layout, depth) {
+        (Some(layout), Ok(depth)) if !layout.is_empty() => (layout, depth),
+        _ => (colorspace, 8),
+    }
+}
+
+pub fn write_frame<W: Write>(out: &mut W, frame: &[u8]) -> io::Result<()> {
+    out.write_all(b"FRAME\n")?;
+    out.write_all(frame)
+}
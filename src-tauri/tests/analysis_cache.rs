@@ -0,0 +1,125 @@
+//! Analyses are kept for a file queued again with the same options, and
+//! forgotten once the file or the options change.
+
+mod common;
+
+use common::Clip;
+use dead_frames_lib::analysis_cache;
+use dead_frames_lib::synthetic::Pattern;
+use dead_frames_lib::video_fixer::ProcessOptions;
+use dead_frames_lib::VideoFixer;
+use std::path::Path;
+use std::sync::OnceLock;
+use tempfile::TempDir;
+
+/// The app data dir the cache is kept in, shared by every test here as
+/// the cache is.
+fn init() -> &'static Path {
+    static DIR: OnceLock<TempDir> = OnceLock::new();
+    let dir = DIR.get_or_init(|| tempfile::tempdir().unwrap()).path();
+    analysis_cache::init(dir);
+    dir
+}
+
+/// Generates `spec` with the cache set up, or returns `None` when there is
+/// no ffmpeg to do it with.
+fn clip(spec: &str) -> Option<Clip> {
+    init();
+    common::clip(spec)
+}
+
+#[test]
+fn analysis_is_offered_again() {
+    let Some(clip) = clip("10u,4d,6b,10u") else {
+        return;
+    };
+    let options = ProcessOptions::default();
+    assert!(analysis_cache::peek(&clip.video, &options).is_none());
+
+    let first = VideoFixer::new(&clip.video)
+        .options(options.clone())
+        .analyze()
+        .unwrap();
+    let plan = analysis_cache::peek(&clip.video, &options).expect("a cached plan");
+    assert_eq!(plan.frames_total, clip.pattern.frames());
+    assert_eq!(plan.frames_removed, clip.pattern.expected_removed_count());
+
+    let second = VideoFixer::new(&clip.video)
+        .options(options.clone())
+        .analyze()
+        .unwrap();
+    assert_eq!(second.removed, first.removed);
+    assert_eq!(second.scores, first.scores);
+
+    let reanalyze = ProcessOptions {
+        reuse_analysis: false,
+        ..options.clone()
+    };
+    assert!(analysis_cache::peek(&clip.video, &reanalyze).is_none());
+    let stricter = ProcessOptions {
+        threshold: 0.5,
+        ..options
+    };
+    assert!(analysis_cache::peek(&clip.video, &stricter).is_none());
+}
+
+#[test]
+fn changed_file_is_analysed_again() {
+    let Some(clip) = clip("10u,4d,6b,10u") else {
+        return;
+    };
+    let options = ProcessOptions::default();
+    VideoFixer::new(&clip.video)
+        .options(options.clone())
+        .analyze()
+        .unwrap();
+    assert!(analysis_cache::peek(&clip.video, &options).is_some());
+
+    let pattern = Pattern::parse("8u,2d,8u").unwrap();
+    pattern.generate(&clip.video).unwrap();
+    assert!(analysis_cache::peek(&clip.video, &options).is_none());
+    let analysis = VideoFixer::new(&clip.video)
+        .options(options)
+        .analyze()
+        .unwrap();
+    assert_eq!(analysis.removed, pattern.expected_removed());
+}
+
+#[test]
+fn job_reuses_the_analysis() {
+    let Some(clip) = clip("6u,5d,3b,6u") else {
+        return;
+    };
+    let options = ProcessOptions::default();
+    VideoFixer::new(&clip.video)
+        .options(options.clone())
+        .analyze()
+        .unwrap();
+    let summary = VideoFixer::new(&clip.video)
+        .options(options)
+        .output_dir(clip.dir.path().join("out"))
+        .run()
+        .unwrap();
+    assert_eq!(summary.frames_total, clip.pattern.frames());
+    assert_eq!(
+        summary.frames_removed,
+        clip.pattern.expected_removed_count()
+    );
+}
+
+#[test]
+fn low_memory_analysis_is_kept_apart() {
+    let Some(clip) = clip("6u,5d,3b,6u") else {
+        return;
+    };
+    let low_memory = ProcessOptions {
+        low_memory: true,
+        ..ProcessOptions::default()
+    };
+    VideoFixer::new(&clip.video)
+        .options(low_memory.clone())
+        .analyze()
+        .unwrap();
+    assert!(analysis_cache::peek(&clip.video, &low_memory).is_some());
+    assert!(analysis_cache::peek(&clip.video, &ProcessOptions::default()).is_none());
+}
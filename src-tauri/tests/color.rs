@@ -0,0 +1,46 @@
+//! The colour signalling ffmpeg reports on a stream's line.
+
+use dead_frames_lib::color::{self, ColorInfo};
+
+fn tags(range: Option<&str>, matrix: &str, primaries: &str, transfer: &str) -> ColorInfo {
+    let name = |name: &str| (name != "unknown").then(|| name.to_string());
+    ColorInfo {
+        range: range.map(String::from),
+        matrix: name(matrix),
+        primaries: name(primaries),
+        transfer: name(transfer),
+        ..ColorInfo::default()
+    }
+}
+
+#[test]
+fn each_tag_is_read() {
+    let line = "  Stream #0:0[0x1](und): Video: h264 (High 10) (avc1 / 0x31637661), \
+                yuv420p10le(tv, bt2020nc/bt2020/smpte2084, progressive), 64x64, 25 fps";
+    assert_eq!(
+        color::reported_in_stream(line),
+        Some(tags(Some("tv"), "bt2020nc", "bt2020", "smpte2084"))
+    );
+}
+
+#[test]
+fn one_name_stands_for_all_three() {
+    let line = "  Stream #0:0: Video: h264 (High), yuv444p(tv, bt709, progressive), 64x64";
+    assert_eq!(
+        color::reported_in_stream(line),
+        Some(tags(Some("tv"), "bt709", "bt709", "bt709"))
+    );
+}
+
+#[test]
+fn unknown_tags_are_unspecified() {
+    let line = "  Stream #0:0: Video: dnxhd (DNXHR SQ) (AVdh / 0x68645641), \
+                yuv422p(tv, bt709/unknown/unknown), 256x128";
+    assert_eq!(
+        color::reported_in_stream(line),
+        Some(tags(Some("tv"), "bt709", "unknown", "unknown"))
+    );
+    let line =
+        "  Stream #0:0: Video: prores (XQ) (ap4x / 0x78347061), yuv444p12le(progressive), 64x64";
+    assert_eq!(color::reported_in_stream(line), None);
+}
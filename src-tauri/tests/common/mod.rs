@@ -0,0 +1,49 @@
+//! Synthetic clips for the tests that run the engine, shared by every test
+//! crate that generates one.
+
+// each test crate uses a part of it
+#![allow(dead_code)]
+
+use dead_frames_lib::ffmpeg;
+use dead_frames_lib::synthetic::Pattern;
+use std::path::PathBuf;
+use tempfile::TempDir;
+
+/// A generated clip, in a directory of its own that outputs can go into.
+pub struct Clip {
+    pub dir: TempDir,
+    pub video: PathBuf,
+    pub pattern: Pattern,
+}
+
+impl Clip {
+    /// Generates `pattern` as `clip.mp4` in a new directory.
+    pub fn new(pattern: Pattern) -> Clip {
+        let dir = tempfile::tempdir().unwrap();
+        let video = dir.path().join("clip.mp4");
+        pattern.generate(&video).unwrap();
+        Clip {
+            dir,
+            video,
+            pattern,
+        }
+    }
+}
+
+/// Whether there is an ffmpeg to run the engine with; a test without one
+/// says it is skipped.
+pub fn has_ffmpeg() -> bool {
+    match ffmpeg::get_ffmpeg_info() {
+        Ok(_) => true,
+        Err(e) => {
+            eprintln!("skipping, no ffmpeg: {}", e);
+            false
+        }
+    }
+}
+
+/// Generates `spec`, or returns `None` when there is no ffmpeg to do it
+/// with.
+pub fn clip(spec: &str) -> Option<Clip> {
+    has_ffmpeg().then(|| Clip::new(Pattern::parse(spec).unwrap()))
+}
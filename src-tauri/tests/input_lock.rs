@@ -0,0 +1,23 @@
+//! An input is processed by one job at a time, however it is named.
+
+use dead_frames_lib::workspace;
+
+#[test]
+fn input_is_locked_until_released() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("clip.mp4");
+    std::fs::write(&input, b"").unwrap();
+
+    let lock = workspace::lock_input(&input).unwrap();
+    let error = workspace::lock_input(&dir.path().join(".").join("clip.mp4"))
+        .err()
+        .expect("a second lock on the same file");
+    assert!(error.contains("already being processed"), "{}", error);
+
+    let other = dir.path().join("other.mp4");
+    std::fs::write(&other, b"").unwrap();
+    assert!(workspace::lock_input(&other).is_ok());
+
+    drop(lock);
+    assert!(workspace::lock_input(&input).is_ok());
+}
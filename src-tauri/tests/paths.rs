@@ -0,0 +1,57 @@
+//! Inputs and output folders whose names are not ASCII, or on Unix not
+//! Unicode at all, are processed like any other. The tests need ffmpeg, so
+//! they are ignored unless asked for: `cargo test --test paths -- --ignored`.
+
+use dead_frames_lib::synthetic::Pattern;
+use dead_frames_lib::VideoFixer;
+use std::ffi::OsString;
+use std::fs;
+use std::path::Path;
+
+/// Generates a clip at `input` and processes it into `output_dir`,
+/// returning the names of what is there afterwards. The names are read
+/// from the folder, as the job summary gives the output as text.
+fn process(input: &Path, output_dir: &Path) -> Vec<OsString> {
+    let pattern = Pattern::parse("6u,5d,3b,6u").unwrap();
+    pattern.generate(input).unwrap();
+    let summary = VideoFixer::new(input).output_dir(output_dir).run().unwrap();
+    assert_eq!(summary.frames_removed, pattern.expected_removed_count());
+    fs::read_dir(output_dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect()
+}
+
+/// `stem` with the default template's suffix.
+fn processed(stem: &[u8]) -> Vec<u8> {
+    [stem, b"_processed.mp4"].concat()
+}
+
+#[test]
+#[ignore = "needs ffmpeg"]
+fn unicode_names() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("视频 🎬 clip.mp4");
+    let output_dir = dir.path().join("処理済み ✓");
+    let names = process(&input, &output_dir);
+    let expected = processed("视频 🎬 clip".as_bytes());
+    assert_eq!(
+        names,
+        [OsString::from(String::from_utf8(expected).unwrap())]
+    );
+}
+
+#[cfg(unix)]
+#[test]
+#[ignore = "needs ffmpeg"]
+fn non_utf8_names() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join(OsStr::from_bytes(b"clip \xff\xfe.mp4"));
+    let output_dir = dir.path().join(OsStr::from_bytes(b"out \xe9"));
+    let names = process(&input, &output_dir);
+    let expected = processed(b"clip \xff\xfe");
+    assert_eq!(names, [OsStr::from_bytes(&expected)]);
+}
@@ -0,0 +1,325 @@
+//! The whole pipeline, extraction, analysis and stitching, run over
+//! synthetic clips, with the output checked by ffprobe: its streams, their
+//! codecs, frame counts and durations.
+//!
+//! ffprobe is taken from `FFPROBE`, from beside the ffmpeg the app uses or
+//! from `PATH`. As not every machine has one, the tests are ignored unless
+//! asked for: `cargo test --test pipeline -- --ignored`.
+
+mod common;
+
+use common::Clip;
+use dead_frames_lib::ffmpeg;
+use dead_frames_lib::output::CollisionPolicy;
+use dead_frames_lib::synthetic::Pattern;
+use dead_frames_lib::video_fixer::{FrameFormat, JobSummary, ProcessOptions, VideoCodec};
+use dead_frames_lib::VideoFixer;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// How far durations may be off, in seconds: a frame at 30 fps, or an AAC
+/// frame of padding.
+const TOLERANCE: f64 = 0.034;
+
+/// ffprobe's report on a file, the parts checked.
+#[derive(Debug, Deserialize)]
+struct Probe {
+    streams: Vec<Stream>,
+    format: Format,
+}
+
+#[derive(Debug, Deserialize)]
+struct Stream {
+    codec_type: String,
+    codec_name: String,
+    width: Option<u32>,
+    height: Option<u32>,
+    nb_read_frames: Option<String>,
+    duration: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Format {
+    duration: Option<String>,
+}
+
+impl Probe {
+    fn codecs(&self) -> Vec<(&str, &str)> {
+        self.streams
+            .iter()
+            .map(|stream| (stream.codec_type.as_str(), stream.codec_name.as_str()))
+            .collect()
+    }
+
+    fn stream(&self, codec_type: &str) -> &Stream {
+        self.streams
+            .iter()
+            .find(|stream| stream.codec_type == codec_type)
+            .unwrap_or_else(|| panic!("no {} stream in {:?}", codec_type, self.codecs()))
+    }
+
+    fn duration(&self) -> f64 {
+        parse(self.format.duration.as_deref())
+    }
+}
+
+impl Stream {
+    fn frames(&self) -> usize {
+        self.nb_read_frames
+            .as_deref()
+            .and_then(|frames| frames.parse().ok())
+            .expect("frame count")
+    }
+
+    fn duration(&self) -> f64 {
+        parse(self.duration.as_deref())
+    }
+}
+
+fn parse(duration: Option<&str>) -> f64 {
+    duration
+        .and_then(|duration| duration.parse().ok())
+        .expect("duration")
+}
+
+/// The ffprobe to check outputs with, if there is one.
+fn ffprobe() -> Option<PathBuf> {
+    let beside_ffmpeg = ffmpeg::get_ffmpeg_info()
+        .ok()
+        .map(|info| info.path.with_file_name("ffprobe"));
+    std::env::var_os("FFPROBE")
+        .map(PathBuf::from)
+        .into_iter()
+        .chain(beside_ffmpeg)
+        .chain(Some(PathBuf::from("ffprobe")))
+        .find(|ffprobe| {
+            Command::new(ffprobe)
+                .arg("-version")
+                .output()
+                .is_ok_and(|output| output.status.success())
+        })
+}
+
+fn probe(ffprobe: &Path, file: &Path) -> Probe {
+    let output = Command::new(ffprobe)
+        .args([
+            "-v",
+            "error",
+            "-count_frames",
+            "-show_streams",
+            "-show_format",
+        ])
+        .args(["-of", "json"])
+        .arg(file)
+        .output()
+        .expect("run ffprobe");
+    assert!(
+        output.status.success(),
+        "ffprobe failed on {}: {}",
+        file.display(),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    serde_json::from_slice(&output.stdout).expect("ffprobe's JSON")
+}
+
+/// A clip, ready to be processed into its own directory and probed.
+struct Fixture {
+    clip: Clip,
+    ffprobe: PathBuf,
+}
+
+impl Fixture {
+    /// Generates `pattern`. There must be an ffmpeg and an ffprobe to run
+    /// the test with.
+    fn new(pattern: Pattern) -> Fixture {
+        if let Err(e) = ffmpeg::get_ffmpeg_info() {
+            panic!("no ffmpeg: {}", e);
+        }
+        let ffprobe = ffprobe().expect("an ffprobe in FFPROBE, beside ffmpeg or on PATH");
+        Fixture {
+            clip: Clip::new(pattern),
+            ffprobe,
+        }
+    }
+
+    fn parse(spec: &str) -> Fixture {
+        Fixture::new(Pattern::parse(spec).unwrap())
+    }
+
+    /// Processes the clip with `options` and probes the output.
+    fn process(&self, options: ProcessOptions) -> (JobSummary, Probe) {
+        let summary = VideoFixer::new(&self.clip.video)
+            .options(options)
+            .output_dir(self.clip.dir.path().join("out"))
+            .collision(CollisionPolicy::Overwrite)
+            .run()
+            .unwrap();
+        let probe = probe(&self.ffprobe, Path::new(&summary.output));
+        (summary, probe)
+    }
+
+    fn frames_kept(&self) -> usize {
+        self.clip.pattern.frames() - self.clip.pattern.expected_removed_count()
+    }
+
+    fn secs(&self, frames: usize) -> f64 {
+        frames as f64 / self.clip.pattern.fps as f64
+    }
+}
+
+fn assert_close(actual: f64, expected: f64, what: &str) {
+    assert!(
+        (actual - expected).abs() <= TOLERANCE,
+        "{} is {:.3}s, expected {:.3}s",
+        what,
+        actual,
+        expected
+    );
+}
+
+#[test]
+#[ignore = "needs ffprobe"]
+fn dead_frames_are_cut_out() {
+    let fixture = Fixture::parse("10u,4d,6b,10u");
+    let (summary, probe) = fixture.process(ProcessOptions::default());
+    assert_eq!(summary.frames_total, 30);
+    assert_eq!(summary.frames_removed, 9);
+    assert_eq!(probe.codecs(), [("video", "h264")]);
+    let video = probe.stream("video");
+    assert_eq!((video.width, video.height), (Some(320), Some(240)));
+    assert_eq!(video.frames(), fixture.frames_kept());
+    assert_close(video.duration(), fixture.secs(21), "video");
+    assert_close(probe.duration(), fixture.secs(21), "output");
+}
+
+#[test]
+#[ignore = "needs ffprobe"]
+fn every_frame_format_stitches_the_same() {
+    let fixture = Fixture::parse("6u,5d,3b,6u");
+    for frame_format in [FrameFormat::Png, FrameFormat::Jpeg(90), FrameFormat::Y4m] {
+        let (_, probe) = fixture.process(ProcessOptions {
+            frame_format,
+            ..ProcessOptions::default()
+        });
+        let video = probe.stream("video");
+        assert_eq!(video.frames(), fixture.frames_kept(), "{:?}", frame_format);
+        assert_close(
+            video.duration(),
+            fixture.secs(fixture.frames_kept()),
+            "video",
+        );
+    }
+}
+
+#[test]
+#[ignore = "needs ffprobe"]
+fn low_memory_stitches_the_same() {
+    let fixture = Fixture::parse("6u,5d,3b,6u");
+    let (_, probe) = fixture.process(ProcessOptions {
+        low_memory: true,
+        ..ProcessOptions::default()
+    });
+    assert_eq!(probe.stream("video").frames(), fixture.frames_kept());
+}
+
+#[test]
+#[ignore = "needs ffprobe"]
+fn nothing_removed_keeps_every_frame() {
+    let fixture = Fixture::parse("20u");
+    let (summary, probe) = fixture.process(ProcessOptions::default());
+    assert_eq!(summary.frames_removed, 0);
+    let video = probe.stream("video");
+    assert_eq!(video.frames(), 20);
+    assert_close(video.duration(), fixture.secs(20), "video");
+}
+
+#[test]
+#[ignore = "needs ffprobe"]
+fn audio_is_dropped_unless_retimed() {
+    let fixture = Fixture::new(Pattern {
+        audio: true,
+        ..Pattern::parse("10u,4d,6b,10u").unwrap()
+    });
+    let (_, probe) = fixture.process(ProcessOptions::default());
+    assert_eq!(probe.codecs(), [("video", "h264")]);
+
+    let (_, probe) = fixture.process(ProcessOptions {
+        retime: Some(30.0),
+        ..ProcessOptions::default()
+    });
+    assert_eq!(probe.codecs(), [("video", "h264"), ("audio", "aac")]);
+    let kept = fixture.secs(fixture.frames_kept());
+    assert_eq!(probe.stream("video").frames(), fixture.frames_kept());
+    assert_close(probe.stream("video").duration(), kept, "video");
+    assert_close(probe.stream("audio").duration(), kept, "retimed audio");
+}
+
+#[test]
+#[ignore = "needs ffprobe"]
+fn codecs_and_containers() {
+    let fixture = Fixture::parse("6u,5d,3b,6u");
+    for (codec, name, extension) in [
+        (VideoCodec::H265, "hevc", "mp4"),
+        (VideoCodec::Vp9, "vp9", "mp4"),
+        (VideoCodec::Ffv1, "ffv1", "mkv"),
+        (VideoCodec::ProRes, "prores", "mov"),
+    ] {
+        let (summary, probe) = fixture.process(ProcessOptions {
+            codec,
+            ..ProcessOptions::default()
+        });
+        assert!(
+            summary.output.ends_with(extension),
+            "{:?} written to {}",
+            codec,
+            summary.output
+        );
+        assert_eq!(probe.codecs(), [("video", name)]);
+        assert_eq!(probe.stream("video").frames(), fixture.frames_kept());
+    }
+}
+
+#[test]
+#[ignore = "needs ffprobe"]
+fn deterministic_runs_are_identical() {
+    let fixture = Fixture::parse("6u,5d,3b,6u");
+    for codec in [VideoCodec::H264, VideoCodec::Ffv1] {
+        let options = ProcessOptions {
+            codec,
+            deterministic: true,
+            ..ProcessOptions::default()
+        };
+        let (first, _) = fixture.process(options.clone());
+        let first = std::fs::read(&first.output).unwrap();
+        let (second, _) = fixture.process(options);
+        let second = std::fs::read(&second.output).unwrap();
+        assert!(first == second, "{:?} output differs between runs", codec);
+    }
+}
+
+#[test]
+#[ignore = "needs ffprobe"]
+fn more_than_9999_frames_are_renumbered() {
+    // extracted with eight digits, the 10,010 kept frames are renumbered
+    // with five for the encoder
+    let fixture = Fixture::new(Pattern {
+        width: 16,
+        height: 16,
+        ..Pattern::parse("10010u,5d").unwrap()
+    });
+    let (summary, probe) = fixture.process(ProcessOptions::default());
+    assert_eq!(summary.frames_total, 10015);
+    assert_eq!(
+        summary.frames_removed,
+        fixture.clip.pattern.expected_removed_count()
+    );
+    let video = probe.stream("video");
+    assert!(video.frames() > 9999);
+    assert_eq!(video.frames(), fixture.frames_kept());
+    assert_close(
+        video.duration(),
+        fixture.secs(fixture.frames_kept()),
+        "video",
+    );
+}
@@ -0,0 +1,122 @@
+//! The analysis removes exactly the frames a synthetic clip repeats.
+
+mod common;
+
+use dead_frames_lib::ffmpeg;
+use dead_frames_lib::similarity::ColorSpace;
+use dead_frames_lib::synthetic::Pattern;
+use dead_frames_lib::video_fixer::{FrameFormat, ProcessOptions};
+use dead_frames_lib::VideoFixer;
+
+fn assert_exact(spec: &str, options: ProcessOptions) {
+    let Some(clip) = common::clip(spec) else {
+        return;
+    };
+    let removed = VideoFixer::new(&clip.video)
+        .options(options)
+        .analyze()
+        .unwrap()
+        .removed;
+    assert_eq!(
+        removed.len(),
+        clip.pattern.frames(),
+        "frame count of {}",
+        spec
+    );
+    assert_eq!(
+        removed,
+        clip.pattern.expected_removed(),
+        "frames removed from {}",
+        spec
+    );
+}
+
+#[test]
+fn unique_frames_are_kept() {
+    assert_exact("20u", ProcessOptions::default());
+}
+
+#[test]
+fn duplicates_and_black_are_removed() {
+    assert_exact("10u,4d,6b,10u", ProcessOptions::default());
+}
+
+#[test]
+fn black_at_either_end() {
+    assert_exact("5b,3u,2d,1b,1d,4u,3b", ProcessOptions::default());
+}
+
+#[test]
+fn single_duplicates() {
+    assert_exact("1u,1d,1u,1d,1u,1d,1u", ProcessOptions::default());
+}
+
+#[test]
+fn every_frame_format_agrees() {
+    for frame_format in [FrameFormat::Png, FrameFormat::Jpeg(90), FrameFormat::Y4m] {
+        let options = ProcessOptions {
+            frame_format,
+            ..ProcessOptions::default()
+        };
+        assert_exact("6u,5d,3b,6u", options);
+    }
+}
+
+#[test]
+fn low_memory_agrees() {
+    let options = ProcessOptions {
+        low_memory: true,
+        ..ProcessOptions::default()
+    };
+    assert_exact("6u,5d,3b,6u", options);
+}
+
+#[test]
+#[ignore = "needs ffmpeg"]
+fn chroma_changes_count_in_color() {
+    let dir = tempfile::tempdir().unwrap();
+    let video = dir.path().join("chroma.mp4");
+    // five frames of one colour, then five of another of the same luma
+    let flat = |cb, cr| {
+        format!(
+            "nullsrc=s=64x64:r=10,trim=end_frame=5,format=yuv444p,\
+             geq=lum=128:cb={}:cr={}",
+            cb, cr
+        )
+    };
+    let graph = format!(
+        "{}[a];{}[b];[a][b]concat=n=2:v=1:a=0",
+        flat(64, 200),
+        flat(200, 64)
+    );
+    let status = ffmpeg::command()
+        .unwrap()
+        .args(["-v", "error", "-f", "lavfi", "-i", &graph])
+        .args(["-c:v", "libx264", "-qp", "0", "-pix_fmt", "yuv444p", "-y"])
+        .arg(&video)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let kept = |color_space| {
+        let options = ProcessOptions {
+            frame_format: FrameFormat::Png,
+            color_space,
+            ..ProcessOptions::default()
+        };
+        let analysis = VideoFixer::new(&video).options(options).analyze().unwrap();
+        analysis.removed.iter().filter(|&&removed| !removed).count()
+    };
+    assert_eq!(kept(ColorSpace::Luma), 1);
+    let weights = [1.0; 3];
+    assert_eq!(kept(ColorSpace::Yuv { weights }), 2);
+    assert_eq!(kept(ColorSpace::Rgb { weights }), 2);
+}
+
+#[test]
+fn counts_match_the_pattern() {
+    let pattern = Pattern::parse("10u,4d,6b,10u").unwrap();
+    assert_eq!(pattern.frames(), 30);
+    assert_eq!(pattern.expected_removed_count(), 9);
+    assert!(Pattern::parse("4x").is_err());
+}
@@ -0,0 +1,22 @@
+//! Names of the frames the app writes, which widen past four digits with
+//! the number of frames.
+
+use dead_frames_lib::sequence::{self, FRAME_DIGITS};
+
+#[test]
+fn digits_grow_past_four() {
+    assert_eq!(sequence::digits_for(0), 4);
+    assert_eq!(sequence::digits_for(9999), 4);
+    assert_eq!(sequence::digits_for(10000), 5);
+}
+
+#[test]
+fn names_match_the_pattern() {
+    assert_eq!(sequence::frame_name(12, 4, "png"), "frame_0012.png");
+    assert_eq!(sequence::frame_name(10000, 5, "png"), "frame_10000.png");
+    assert_eq!(
+        sequence::frame_name(1, FRAME_DIGITS, "y4m"),
+        "frame_00000001.y4m"
+    );
+    assert_eq!(sequence::frame_pattern(5, "png"), "frame_%05d.png");
+}
@@ -0,0 +1,28 @@
+//! Settings files written by older builds load into the current settings.
+
+use dead_frames_lib::settings::{self, SETTINGS_VERSION};
+use std::fs;
+
+#[test]
+fn post_processing_command_becomes_the_after_job_hook() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("settings.json");
+    fs::write(
+        &path,
+        r#"{
+            "version": 2,
+            "post_actions": { "command": "notify-send done", "shutdown_when_done": true }
+        }"#,
+    )
+    .unwrap();
+    settings::init(path.clone());
+    let current = settings::current();
+    assert_eq!(current.hooks.after_job.as_deref(), Some("notify-send done"));
+    assert!(current.post_actions.shutdown_when_done);
+
+    settings::update(|_| {}).unwrap();
+    let saved: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(saved["version"], SETTINGS_VERSION);
+    assert!(saved["post_actions"].get("command").is_none());
+}
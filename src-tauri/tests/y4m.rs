@@ -0,0 +1,24 @@
+//! Malformed YUV4MPEG2 headers are errors, not panics.
+
+use dead_frames_lib::y4m::Y4mReader;
+use std::io::Cursor;
+
+fn header(line: &str) -> std::io::Result<(u32, u32)> {
+    Y4mReader::new(Cursor::new(line.as_bytes().to_vec())).map(|reader| reader.dimensions())
+}
+
+#[test]
+fn well_formed_header_is_read() {
+    assert_eq!(header("YUV4MPEG2 W64 H48 F30:1 C420\n").unwrap(), (64, 48));
+}
+
+#[test]
+fn doubled_space_is_an_error() {
+    assert!(header("YUV4MPEG2 W64  H48 C420\n").is_err());
+}
+
+#[test]
+fn multibyte_field_is_skipped() {
+    assert_eq!(header("YUV4MPEG2 W64 H48 é1 🎬 C420\n").unwrap(), (64, 48));
+    assert!(header("YUV4MPEG2 Wé H48\n").is_err());
+}
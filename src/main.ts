@@ -1,4 +1,5 @@
 import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
 
 let greetInputEl: HTMLInputElement | null;
 let greetMsgEl: HTMLElement | null;
@@ -12,6 +13,19 @@ async function greet() {
   }
 }
 
+// Closing the window while jobs are active is held back until the user
+// agrees; see `confirm_exit`.
+listen<{ active_jobs: number }>("exit-requested", async (event) => {
+  const jobs = event.payload.active_jobs;
+  const message =
+    jobs === 1
+      ? "A job is still queued or running. Quit anyway?"
+      : `${jobs} jobs are still queued or running. Quit anyway?`;
+  if (window.confirm(message)) {
+    await invoke("confirm_exit");
+  }
+});
+
 window.addEventListener("DOMContentLoaded", () => {
   greetInputEl = document.querySelector("#greet-input");
   greetMsgEl = document.querySelector("#greet-msg");